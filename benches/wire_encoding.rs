@@ -0,0 +1,111 @@
+//! JSON and CBOR message encoding on text and image payloads
+//!
+//! Run with `cargo bench --bench wire_encoding`. The encoded size of each
+//! message is printed before its timings.
+
+use std::hint::black_box;
+
+use clipsync::transport::codec::{self, WireEncoding};
+use clipsync::transport::protocol::{
+    ClipboardData, ClipboardFormat, Message, MessagePayload, MessageType,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+fn clipboard_message(format: ClipboardFormat, data: Vec<u8>) -> Message {
+    Message::new(
+        MessageType::ClipboardData,
+        MessagePayload::Clipboard(ClipboardData {
+            format,
+            data,
+            compression: None,
+            checksum: "0123456789abcdef0123456789abcdef".to_string(),
+            metadata: [
+                ("selection".to_string(), "clipboard".to_string()),
+                (
+                    "origin".to_string(),
+                    "6f1c2d3e-4b5a-4c6d-8e7f-9a0b1c2d3e4f".to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        }),
+    )
+}
+
+/// Prose-like text of `len` bytes
+fn text(len: usize) -> Vec<u8> {
+    b"The quick brown fox jumps over the lazy dog. "
+        .iter()
+        .copied()
+        .cycle()
+        .take(len)
+        .collect()
+}
+
+/// Incompressible bytes standing in for an encoded image
+fn image(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn payloads() -> Vec<(&'static str, Message)> {
+    let png = || ClipboardFormat::Image {
+        mime_type: "image/png".to_string(),
+    };
+
+    vec![
+        (
+            "text_1k",
+            clipboard_message(ClipboardFormat::Text, text(1024)),
+        ),
+        (
+            "text_64k",
+            clipboard_message(ClipboardFormat::Text, text(64 * 1024)),
+        ),
+        ("image_64k", clipboard_message(png(), image(64 * 1024))),
+        ("image_1m", clipboard_message(png(), image(1024 * 1024))),
+    ]
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for (name, message) in payloads() {
+        for encoding in WireEncoding::ALL {
+            let size = codec::encode(&message, encoding).unwrap().len();
+            println!("{}/{}: {} bytes", name, encoding, size);
+
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(encoding.as_str(), name),
+                &message,
+                |b, m| b.iter(|| codec::encode(black_box(m), encoding).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, message) in payloads() {
+        for encoding in WireEncoding::ALL {
+            let frame = codec::encode(&message, encoding).unwrap();
+
+            group.throughput(Throughput::Bytes(frame.len() as u64));
+            group.bench_with_input(BenchmarkId::new(encoding.as_str(), name), &frame, |b, f| {
+                b.iter(|| codec::decode(black_box(f)).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode);
+criterion_main!(benches);
@@ -12,7 +12,7 @@ fn main() {
 
     // Get the rustc version
     let rustc_version = Command::new("rustc")
-        .args(&["--version"])
+        .args(["--version"])
         .output()
         .ok()
         .and_then(|output| String::from_utf8(output.stdout).ok())
@@ -45,6 +45,7 @@ async fn main() -> anyhow::Result<()> {
             ssh_fingerprint: Some(public_key.fingerprint()),
             capabilities: vec!["sync".to_string()],
             device_name: Some("My Laptop".to_string()),
+            groups: vec![],
         },
         last_seen: chrono::Utc::now().timestamp(),
     };
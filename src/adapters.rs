@@ -5,30 +5,232 @@ use std::path::Path;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::clipboard::ClipboardSelection;
 use crate::config::Config;
 use crate::discovery::Discovery;
+use crate::transport::protocol::ClipboardFormat;
 
 // Adapter types for the new sync engine interface
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ClipboardData {
     Text(String),
+    Html(String),
+    Rtf(Vec<u8>),
+    Image { mime_type: String, data: Vec<u8> },
+    Binary { mime_type: String, data: Vec<u8> },
 }
 
-#[derive(Debug, Clone)]
+impl ClipboardData {
+    /// Build typed clipboard data from a MIME type and raw bytes
+    pub fn from_mime(mime_type: &str, data: Vec<u8>) -> Self {
+        let base = mime_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        match base.as_str() {
+            "text/plain" | "utf8_string" | "string" | "text" => match String::from_utf8(data) {
+                Ok(text) => ClipboardData::Text(text),
+                Err(e) => ClipboardData::Binary {
+                    mime_type: "application/octet-stream".to_string(),
+                    data: e.into_bytes(),
+                },
+            },
+            "text/html" => match String::from_utf8(data) {
+                Ok(html) => ClipboardData::Html(html),
+                Err(e) => ClipboardData::Binary {
+                    mime_type: base,
+                    data: e.into_bytes(),
+                },
+            },
+            "text/rtf" | "application/rtf" => ClipboardData::Rtf(data),
+            _ if base.starts_with("image/") => ClipboardData::Image {
+                mime_type: base,
+                data,
+            },
+            _ => ClipboardData::Binary {
+                mime_type: base,
+                data,
+            },
+        }
+    }
+
+    /// Build typed clipboard data from a transport payload
+    pub fn from_format(format: &ClipboardFormat, data: Vec<u8>) -> Self {
+        match format {
+            ClipboardFormat::Image { mime_type } => ClipboardData::Image {
+                mime_type: mime_type.clone(),
+                data,
+            },
+            ClipboardFormat::Binary { mime_type } => ClipboardData::Binary {
+                mime_type: mime_type.clone(),
+                data,
+            },
+            ClipboardFormat::Custom { format_name } => ClipboardData::Binary {
+                mime_type: format_name.clone(),
+                data,
+            },
+            other => Self::from_mime(&other.mime_type(), data),
+        }
+    }
+
+    /// MIME type describing this content
+    pub fn mime_type(&self) -> String {
+        match self {
+            ClipboardData::Text(_) => "text/plain".to_string(),
+            ClipboardData::Html(_) => "text/html".to_string(),
+            ClipboardData::Rtf(_) => "text/rtf".to_string(),
+            ClipboardData::Image { mime_type, .. } | ClipboardData::Binary { mime_type, .. } => {
+                mime_type.clone()
+            }
+        }
+    }
+
+    /// Transport format describing this content
+    pub fn format(&self) -> ClipboardFormat {
+        match self {
+            ClipboardData::Text(_) => ClipboardFormat::Text,
+            ClipboardData::Html(_) => ClipboardFormat::Html,
+            ClipboardData::Rtf(_) => ClipboardFormat::Rtf,
+            ClipboardData::Image { mime_type, .. } => ClipboardFormat::Image {
+                mime_type: mime_type.clone(),
+            },
+            ClipboardData::Binary { mime_type, .. } => ClipboardFormat::Binary {
+                mime_type: mime_type.clone(),
+            },
+        }
+    }
+
+    /// Raw bytes of the content
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            ClipboardData::Text(text) | ClipboardData::Html(text) => text.as_bytes(),
+            ClipboardData::Rtf(data)
+            | ClipboardData::Image { data, .. }
+            | ClipboardData::Binary { data, .. } => data,
+        }
+    }
+
+    /// Textual content, if this entry is text-like
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ClipboardData::Text(text) | ClipboardData::Html(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Size of the content in bytes
+    pub fn size(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    /// Checksum used to detect duplicate content
+    pub fn checksum(&self) -> String {
+        format!("{:x}", md5::compute(self.as_bytes()))
+    }
+
+    /// Short single-line description for listings
+    pub fn preview(&self, max_chars: usize) -> String {
+        match self.as_text() {
+            Some(text) => {
+                let prefix = if matches!(self, ClipboardData::Html(_)) {
+                    "[HTML] "
+                } else {
+                    ""
+                };
+                if text.chars().count() > max_chars {
+                    let truncated: String = text.chars().take(max_chars).collect();
+                    format!("{}{}...", prefix, truncated)
+                } else {
+                    format!("{}{}", prefix, text)
+                }
+            }
+            None => format!("[{}, {} bytes]", self.mime_type(), self.size()),
+        }
+    }
+
+    /// Convert into the clipboard layer's representation
+    pub fn to_content(&self) -> crate::clipboard::ClipboardContent {
+        crate::clipboard::ClipboardContent {
+            mime_type: self.mime_type(),
+            data: self.as_bytes().to_vec(),
+            timestamp: Utc::now().timestamp(),
+            concealed: false,
+        }
+    }
+}
+
+impl From<crate::clipboard::ClipboardContent> for ClipboardData {
+    fn from(content: crate::clipboard::ClipboardContent) -> Self {
+        Self::from_mime(&content.mime_type, content.data)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardEntry {
     pub id: Uuid,
     pub content: ClipboardData,
     pub timestamp: DateTime<Utc>,
     pub source: Uuid,
     pub checksum: String,
+    #[serde(default)]
+    pub selection: ClipboardSelection,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Position in the cross-node order of entries
+    #[serde(default)]
+    pub hlc: crate::sync::HybridTimestamp,
+    /// When every node forgets the entry, if it has a time to live
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Peer {
     pub id: Uuid,
     pub hostname: String,
     pub address: String,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Sync groups the peer announced
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+impl From<crate::history::HistoryEntry> for ClipboardEntry {
+    fn from(entry: crate::history::HistoryEntry) -> Self {
+        let content = ClipboardData::from_mime(&entry.content_type, entry.content);
+
+        Self {
+            id: entry.id,
+            content,
+            timestamp: DateTime::from_timestamp(entry.timestamp, 0).unwrap_or_else(Utc::now),
+            source: entry.origin_node,
+            checksum: entry.checksum,
+            selection: entry.selection,
+            pinned: entry.pinned,
+            // Entries recorded before history kept the clock stamp only
+            // have whole seconds
+            hlc: entry.hlc.unwrap_or_else(|| {
+                crate::sync::HybridTimestamp::from_wall(
+                    entry.timestamp.max(0) as u64 * 1000,
+                    entry.origin_node,
+                )
+            }),
+            expires_at: entry
+                .expires_at
+                .and_then(|expires_at| DateTime::from_timestamp(expires_at, 0)),
+        }
+    }
+}
+
+impl Peer {
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
 }
 
 pub struct AuthenticatedConnection {
@@ -64,17 +266,30 @@ impl HistoryManager {
         Ok(Self { inner })
     }
 
-    pub async fn add_entry(&self, entry: &ClipboardEntry) -> Result<()> {
-        let content = match &entry.content {
-            ClipboardData::Text(text) => text.as_bytes().to_vec(),
-        };
+    /// Open the history database configured in `config`, with its retention policy
+    pub async fn from_config(config: &Config) -> Result<Self> {
+        let retention = crate::history::RetentionPolicy::from(&config.clipboard);
+        let inner =
+            crate::history::ClipboardHistory::with_retention(&config.database_path(), retention)
+                .await?;
+        Ok(Self { inner })
+    }
 
+    /// Wrap an already opened history
+    pub fn from_history(inner: crate::history::ClipboardHistory) -> Self {
+        Self { inner }
+    }
+
+    pub async fn add_entry(&self, entry: &ClipboardEntry) -> Result<()> {
         let history_content = crate::history::ClipboardContent {
             id: entry.id,
-            content,
-            content_type: "text/plain".to_string(),
+            content: entry.content.as_bytes().to_vec(),
+            content_type: entry.content.mime_type(),
             timestamp: entry.timestamp.timestamp(),
             origin_node: entry.source,
+            selection: entry.selection,
+            expires_at: entry.expires_at.map(|expires_at| expires_at.timestamp()),
+            hlc: Some(entry.hlc),
         };
 
         self.inner.add(&history_content).await
@@ -82,57 +297,139 @@ impl HistoryManager {
 
     pub async fn get_recent_entries(&self, limit: usize) -> Result<Vec<ClipboardEntry>> {
         let entries = self.inner.get_recent(limit).await?;
+        Ok(entries.into_iter().map(ClipboardEntry::from).collect())
+    }
+
+    /// Summaries of entries created at or after `since`, newest first
+    pub async fn summaries_since(
+        &self,
+        since: i64,
+        limit: usize,
+    ) -> Result<Vec<crate::history::EntrySummary>> {
+        self.inner.summaries_since(since, limit).await
+    }
 
+    /// IDs and deadlines of entries with a time to live
+    pub async fn expiring(&self) -> Result<Vec<(Uuid, DateTime<Utc>)>> {
+        let entries = self.inner.expiring().await?;
         Ok(entries
             .into_iter()
-            .map(|entry| {
-                let content = match String::from_utf8(entry.content) {
-                    Ok(text) => ClipboardData::Text(text),
-                    Err(_) => ClipboardData::Text("[Binary Data]".to_string()),
-                };
+            .filter_map(|(id, expires_at)| Some((id, DateTime::from_timestamp(expires_at, 0)?)))
+            .collect())
+    }
 
-                ClipboardEntry {
-                    id: entry.id,
-                    content,
-                    timestamp: DateTime::from_timestamp(entry.timestamp, 0)
-                        .unwrap_or_else(Utc::now),
-                    source: entry.origin_node,
-                    checksum: entry.checksum,
-                }
+    /// Get the entries with the given IDs, newest first
+    pub async fn get_by_ids(&self, ids: &[Uuid]) -> Result<Vec<ClipboardEntry>> {
+        let entries = self.inner.get_by_ids(ids).await?;
+        Ok(entries.into_iter().map(ClipboardEntry::from).collect())
+    }
+
+    /// Add entries from another node, skipping ones already in history
+    ///
+    /// `checksum` must be the history (SHA-256) checksum of each entry.
+    /// Returns the number of added entries.
+    pub async fn merge(&self, entries: &[ClipboardEntry]) -> Result<usize> {
+        let entries: Vec<crate::history::HistoryEntry> = entries
+            .iter()
+            .map(|entry| crate::history::HistoryEntry {
+                id: entry.id,
+                content: entry.content.as_bytes().to_vec(),
+                content_type: entry.content.mime_type(),
+                content_size: entry.content.size() as u64,
+                timestamp: entry.timestamp.timestamp(),
+                origin_node: entry.source,
+                checksum: entry.checksum.clone(),
+                selection: entry.selection,
+                pinned: false,
+                expires_at: entry.expires_at.map(|expires_at| expires_at.timestamp()),
+                hlc: Some(entry.hlc),
             })
-            .collect())
+            .collect();
+
+        self.inner.merge(&entries).await
+    }
+
+    /// Pin or unpin an entry so retention pruning skips it
+    pub async fn set_pinned(&self, id: Uuid, pinned: bool) -> Result<bool> {
+        self.inner.set_pinned(id, pinned).await
+    }
+
+    /// Set when an entry expires; returns `false` if it doesn't exist
+    pub async fn set_expiry(&self, id: Uuid, expires_at: DateTime<Utc>) -> Result<bool> {
+        self.inner.set_expires_at(id, expires_at.timestamp()).await
     }
 
+    /// Delete an entry; returns `false` if it doesn't exist
+    pub async fn delete_entry(&self, id: Uuid) -> Result<bool> {
+        self.inner.delete(id).await
+    }
+
+    /// Look up an entry by its history (SHA-256) checksum
     pub async fn get_by_checksum(&self, checksum: &str) -> Result<Option<ClipboardEntry>> {
-        // Simplified implementation - would need to be enhanced
-        let entries = self.get_recent_entries(100).await?;
-        Ok(entries.into_iter().find(|e| e.checksum == checksum))
+        let entry = self.inner.get_by_checksum(checksum).await?;
+        Ok(entry.map(ClipboardEntry::from))
+    }
+
+    /// Look up the newest entry holding the same content
+    pub async fn get_by_content(&self, content: &ClipboardData) -> Result<Option<ClipboardEntry>> {
+        let checksum =
+            crate::history::encryption::Encryptor::compute_checksum(content.as_bytes());
+        self.get_by_checksum(&checksum).await
     }
 
     pub async fn search_entries(&self, search_term: &str, limit: usize) -> Result<Vec<ClipboardEntry>> {
-        let inner_entries = self.inner.search(search_term).await?;
-        
-        let entries = inner_entries
-            .into_iter()
-            .take(limit)
-            .map(|entry| {
-                let content = match String::from_utf8(entry.content) {
-                    Ok(text) => ClipboardData::Text(text),
-                    Err(_) => ClipboardData::Text("[Binary Data]".to_string()),
-                };
+        self.search(&crate::history::SearchQuery::new(search_term), limit)
+            .await
+    }
 
-                ClipboardEntry {
-                    id: entry.id,
-                    content,
-                    timestamp: DateTime::from_timestamp(entry.timestamp, 0)
-                        .unwrap_or_else(Utc::now),
-                    source: entry.origin_node,
-                    checksum: entry.checksum,
-                }
-            })
-            .collect();
+    /// Whether history is waiting for its passphrase
+    pub async fn is_locked(&self) -> bool {
+        self.inner.is_locked().await
+    }
+
+    /// Unlock passphrase-protected history
+    pub async fn unlock(&self, passphrase: &str) -> Result<()> {
+        self.inner.unlock(passphrase).await
+    }
+
+    /// Lock history until it is unlocked again
+    pub async fn lock(&self) -> Result<()> {
+        self.inner.lock().await
+    }
+
+    /// Set, change or remove (`new = None`) the history passphrase
+    pub async fn set_passphrase(&self, current: Option<&str>, new: Option<&str>) -> Result<()> {
+        self.inner.set_passphrase(current, new).await
+    }
+
+    /// Re-encrypt history under a fresh key
+    pub async fn rekey(&self, passphrase: Option<&str>) -> Result<usize> {
+        self.inner.rekey(passphrase).await
+    }
+
+    /// Whether the history key is passphrase-protected
+    pub fn is_protected(&self) -> Result<bool> {
+        self.inner.is_protected()
+    }
+
+    /// Export all entries to a passphrase-encrypted archive
+    pub async fn export(&self, path: &Path, passphrase: &str) -> Result<usize> {
+        self.inner.export(path, passphrase).await
+    }
+
+    /// Import an archive, skipping entries already in history
+    pub async fn import(&self, path: &Path, passphrase: &str) -> Result<(usize, usize)> {
+        self.inner.import(path, passphrase).await
+    }
 
-        Ok(entries)
+    /// Search history with filters, newest first
+    pub async fn search(
+        &self,
+        query: &crate::history::SearchQuery,
+        limit: usize,
+    ) -> Result<Vec<ClipboardEntry>> {
+        let entries = self.inner.search(query, limit).await?;
+        Ok(entries.into_iter().map(ClipboardEntry::from).collect())
     }
 }
 
@@ -157,10 +454,53 @@ impl ClipboardProviderWrapper {
         Ok(())
     }
 
+    pub async fn get_content(&self) -> Result<ClipboardData> {
+        let content = self.inner.get_content().await?;
+        Ok(ClipboardData::from(content))
+    }
+
+    pub async fn set_content(&self, content: &ClipboardData) -> Result<()> {
+        self.inner.set_content(&content.to_content()).await?;
+        Ok(())
+    }
+
+    pub async fn get_selection(&self, selection: ClipboardSelection) -> Result<ClipboardData> {
+        let content = self.inner.get_selection(selection).await?;
+        Ok(ClipboardData::from(content))
+    }
+
+    /// Content of a selection as the backend reported it, including whether
+    /// it is concealed
+    pub async fn read_selection(
+        &self,
+        selection: ClipboardSelection,
+    ) -> Result<crate::clipboard::ClipboardContent> {
+        Ok(self.inner.get_selection(selection).await?)
+    }
+
+    pub async fn set_selection(
+        &self,
+        content: &ClipboardData,
+        selection: ClipboardSelection,
+    ) -> Result<()> {
+        self.inner
+            .set_selection(&content.to_content(), selection)
+            .await?;
+        Ok(())
+    }
+
     pub async fn clear(&self) -> Result<()> {
         self.inner.clear().await?;
         Ok(())
     }
+
+    pub async fn watch(&self) -> Result<crate::clipboard::ClipboardWatcher> {
+        Ok(self.inner.watch().await?)
+    }
+
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
 }
 
 // ClipboardProvider getter function
@@ -245,6 +585,17 @@ impl PeerDiscovery {
             .txt_data
             .push(("pubkey".to_string(), public_key));
 
+        // Advertise optional features peers must opt in to
+        if self.config.primary_sync_enabled() {
+            service_info = service_info
+                .with_capabilities(vec![crate::discovery::CAP_PRIMARY_SELECTION.to_string()]);
+        }
+
+        // Announce sync group membership so peers route copies to us
+        if !self.config.sync.groups.is_empty() {
+            service_info = service_info.with_groups(&self.config.sync.groups);
+        }
+
         // Start discovery and announce
         let mut inner = self.inner.lock().await;
         inner.start().await?;
@@ -279,6 +630,8 @@ impl PeerDiscovery {
                                         .best_address()
                                         .map(|a| a.to_string())
                                         .unwrap_or_else(|| "unknown".to_string()),
+                                    capabilities: peer_info.metadata.capabilities.clone(),
+                                    groups: peer_info.metadata.groups.clone(),
                                 };
                                 let _ = event_tx.send(peer);
                             }
@@ -315,6 +668,11 @@ impl PeerDiscovery {
         Ok(())
     }
 
+    /// Apply the `[[peers]]` list from a reloaded config
+    pub async fn update_manual_peers(&self, peers: &[crate::config::PeerConfig]) -> Result<()> {
+        self.inner.lock().await.update_manual_peers(peers).await
+    }
+
     pub async fn subscribe(&self) -> Result<tokio::sync::broadcast::Receiver<Peer>> {
         Ok(self.event_tx.subscribe())
     }
@@ -352,6 +710,12 @@ impl Config {
         1000 // Default 1 second
     }
 
+    /// Whether the PRIMARY selection is synced as its own channel.
+    /// macOS has no PRIMARY selection, so the setting is ignored there.
+    pub fn primary_sync_enabled(&self) -> bool {
+        cfg!(not(target_os = "macos")) && self.clipboard.sync_primary
+    }
+
     pub fn database_path(&self) -> std::path::PathBuf {
         self.clipboard.history_db.clone()
     }
@@ -374,3 +738,77 @@ impl Config {
         Self::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipboard_data_from_mime() {
+        assert_eq!(
+            ClipboardData::from_mime("text/plain;charset=utf-8", b"hi".to_vec()),
+            ClipboardData::Text("hi".to_string())
+        );
+        assert_eq!(
+            ClipboardData::from_mime("text/html", b"<b>hi</b>".to_vec()),
+            ClipboardData::Html("<b>hi</b>".to_string())
+        );
+        assert_eq!(
+            ClipboardData::from_mime("application/rtf", b"{\\rtf1}".to_vec()),
+            ClipboardData::Rtf(b"{\\rtf1}".to_vec())
+        );
+        assert_eq!(
+            ClipboardData::from_mime("image/png", vec![0x89, 0x50]),
+            ClipboardData::Image {
+                mime_type: "image/png".to_string(),
+                data: vec![0x89, 0x50],
+            }
+        );
+        assert!(matches!(
+            ClipboardData::from_mime("text/plain", vec![0xff, 0xfe]),
+            ClipboardData::Binary { .. }
+        ));
+    }
+
+    #[test]
+    fn test_clipboard_data_format_round_trip() {
+        let samples = vec![
+            ClipboardData::Text("plain".to_string()),
+            ClipboardData::Html("<p>html</p>".to_string()),
+            ClipboardData::Rtf(b"{\\rtf1 rich}".to_vec()),
+            ClipboardData::Image {
+                mime_type: "image/png".to_string(),
+                data: vec![1, 2, 3],
+            },
+            ClipboardData::Binary {
+                mime_type: "application/x-custom".to_string(),
+                data: vec![4, 5, 6],
+            },
+        ];
+
+        for data in samples {
+            let decoded = ClipboardData::from_format(&data.format(), data.as_bytes().to_vec());
+            assert_eq!(decoded, data);
+
+            let content = data.to_content();
+            assert_eq!(ClipboardData::from(content), data);
+        }
+    }
+
+    #[test]
+    fn test_clipboard_data_preview() {
+        assert_eq!(ClipboardData::Text("short".to_string()).preview(10), "short");
+        assert_eq!(
+            ClipboardData::Text("héllo wörld".to_string()).preview(5),
+            "héllo..."
+        );
+        assert_eq!(
+            ClipboardData::Image {
+                mime_type: "image/png".to_string(),
+                data: vec![0; 4],
+            }
+            .preview(50),
+            "[image/png, 4 bytes]"
+        );
+    }
+}
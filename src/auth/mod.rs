@@ -61,6 +61,9 @@ pub trait Authenticator: Send + Sync {
     /// Get the local public key
     async fn get_public_key(&self) -> Result<PublicKey, AuthError>;
 
+    /// Sign a message with the local private key
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, AuthError>;
+
     /// Check if a peer is authorized
     async fn is_authorized(&self, peer_key: &PublicKey) -> Result<bool, AuthError>;
 }
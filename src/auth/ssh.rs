@@ -284,6 +284,15 @@ impl Authenticator for SshAuthenticator {
         Ok(key_pair.public_key())
     }
 
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, AuthError> {
+        let key_pair = self.key_pair.read().await;
+        let key_pair = key_pair
+            .as_ref()
+            .ok_or_else(|| AuthError::KeyError("No local key pair available".to_string()))?;
+
+        key_pair.sign(message)
+    }
+
     async fn is_authorized(&self, peer_key: &PublicKey) -> Result<bool, AuthError> {
         let authorized_keys = self.authorized_keys.read().await;
         Ok(authorized_keys.is_authorized(peer_key))
@@ -233,6 +233,16 @@ impl TrustManager {
             .collect()
     }
 
+    /// Fingerprint of the trusted key a peer authenticated with
+    pub async fn fingerprint_of(&self, peer_id: Uuid) -> Option<String> {
+        self.trust_cache
+            .read()
+            .await
+            .values()
+            .find(|s| s.is_trusted && s.peer_id == peer_id)
+            .map(|s| s.fingerprint.clone())
+    }
+
     /// CLI trust prompt implementation
     fn cli_trust_prompt(peer: &PeerInfo, fingerprint: &str) -> TrustDecision {
         use std::io::{self, Write};
@@ -3,7 +3,7 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent},
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -78,7 +78,7 @@ impl HistoryPicker {
 
         println!("ClipSync History Picker");
         println!("======================");
-        println!("Use ↑/↓ to navigate, Enter to select, Esc to exit");
+        println!("Use ↑/↓ to navigate, Enter to select, Ctrl+P to pin/unpin, Esc to exit");
 
         if !self.search_term.is_empty() {
             println!("Search: {}", self.search_term);
@@ -95,23 +95,20 @@ impl HistoryPicker {
                 "  "
             };
 
-            let content_preview = match &entry.content {
-                crate::adapters::ClipboardData::Text(text) => {
-                    let preview = if text.len() > 80 {
-                        format!("{}...", &text[..80])
-                    } else {
-                        text.clone()
-                    };
+            // Replace newlines with spaces for display
+            let content_preview = entry
+                .content
+                .preview(80)
+                .replace('\n', " ")
+                .replace('\r', "");
 
-                    // Replace newlines with spaces for display
-                    preview.replace('\n', " ").replace('\r', "")
-                }
-            };
+            let pin_marker = if entry.pinned { "* " } else { "" };
 
             println!(
-                "{}{} | {}",
+                "{}{} | {}{}",
                 prefix,
                 entry.timestamp.format("%m-%d %H:%M"),
+                pin_marker,
                 content_preview
             );
         }
@@ -126,10 +123,11 @@ impl HistoryPicker {
         } else {
             self.entries
                 .iter()
-                .filter(|entry| match &entry.content {
-                    crate::adapters::ClipboardData::Text(text) => text
-                        .to_lowercase()
-                        .contains(&self.search_term.to_lowercase()),
+                .filter(|entry| {
+                    entry.content.as_text().map_or(false, |text| {
+                        text.to_lowercase()
+                            .contains(&self.search_term.to_lowercase())
+                    })
                 })
                 .collect()
         }
@@ -152,6 +150,10 @@ impl HistoryPicker {
                 }
                 Ok(PickerAction::Continue)
             }
+            KeyCode::Char('p') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.toggle_pin().await?;
+                Ok(PickerAction::Continue)
+            }
             KeyCode::Char(c) => {
                 self.search_term.push(c);
                 self.selected_index = 0; // Reset selection when searching
@@ -166,16 +168,34 @@ impl HistoryPicker {
         }
     }
 
+    async fn toggle_pin(&mut self) -> Result<()> {
+        let Some((id, pinned)) = self
+            .get_filtered_entries()
+            .get(self.selected_index)
+            .map(|entry| (entry.id, !entry.pinned))
+        else {
+            return Ok(());
+        };
+
+        debug!("Setting pinned={} on entry {}", pinned, id);
+        if self.history.set_pinned(id, pinned).await? {
+            if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+                entry.pinned = pinned;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn copy_entry_to_clipboard(&self, entry: &ClipboardEntry) -> Result<()> {
         debug!("Copying entry {} to clipboard", entry.id);
 
         // In a real implementation, we would get the clipboard provider here
         // For now, we'll just print the selection
-        match &entry.content {
-            crate::adapters::ClipboardData::Text(text) => {
-                println!("\nSelected clipboard entry:");
-                println!("{}", text);
-            }
+        println!("\nSelected clipboard entry:");
+        match entry.content.as_text() {
+            Some(text) => println!("{}", text),
+            None => println!("{}", entry.content.preview(0)),
         }
 
         Ok(())
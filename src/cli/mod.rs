@@ -11,18 +11,29 @@ use chrono::{DateTime, Local};
 use serde_json;
 
 use crate::adapters::{
-    get_clipboard_provider, ClipboardProviderWrapper, HistoryManager, PeerDiscovery,
+    get_clipboard_provider, ClipboardEntry, ClipboardProviderWrapper, HistoryManager, Peer,
+    PeerDiscovery,
 };
 use crate::auth::{AuthorizedKey, AuthorizedKeys, PublicKey};
+use crate::clipboard::safety::{SafetyFilter, Verdict};
+use crate::clipboard::ClipboardSelection;
 use crate::config::Config;
+use crate::history::SearchQuery;
+#[cfg(target_os = "linux")]
+use crate::control::{self, ControlCommand, ControlReply};
 #[cfg(target_os = "linux")]
 use crate::daemon;
 // use crate::hotkey::HotKeyManager; // Removed - we work with system clipboard
 use crate::sync::{SyncEngine, TrustAwareSyncEngine};
-use crate::transport::{TransportConfig, TransportManager};
+use crate::progress::format_bytes;
+use crate::transport::{StreamDirection, Transfer, TransportConfig, TransportManager};
 
 pub mod commands;
 pub mod history_picker;
+pub mod passphrase;
+
+/// How often the daemon checks the config file for peer list changes
+const CONFIG_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
 
 #[derive(Parser)]
 #[command(name = "clipsync")]
@@ -58,14 +69,34 @@ pub enum Commands {
 
     #[command(about = "Show clipboard history")]
     History {
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+
         #[arg(short, long, default_value = "10")]
         limit: usize,
 
         #[arg(short, long)]
         interactive: bool,
 
+        /// Search query: words, prefix*, "exact phrase" or /regex/
         #[arg(long)]
         search: Option<String>,
+
+        /// Only entries from this node ID
+        #[arg(long)]
+        origin: Option<uuid::Uuid>,
+
+        /// Only entries of this MIME type, or a prefix such as image/*
+        #[arg(long = "type")]
+        content_type: Option<String>,
+
+        /// Only entries newer than a date (YYYY-MM-DD, RFC 3339) or age (e.g. 2d)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only entries older than a date (YYYY-MM-DD, RFC 3339) or age (e.g. 2d)
+        #[arg(long)]
+        until: Option<String>,
     },
 
     #[command(about = "Force synchronization with peers")]
@@ -78,7 +109,13 @@ pub enum Commands {
     },
 
     #[command(about = "Copy text to clipboard")]
-    Copy { text: String },
+    Copy {
+        text: String,
+
+        /// Clear the copy from every device after this long (e.g. 30s, 5m)
+        #[arg(long)]
+        ttl: Option<String>,
+    },
 
     #[command(about = "Get current clipboard content")]
     Paste,
@@ -104,6 +141,12 @@ pub enum Commands {
         action: AuthAction,
     },
 
+    #[command(about = "Sensitive-content rules")]
+    Safety {
+        #[command(subcommand)]
+        action: SafetyCommand,
+    },
+
     #[command(about = "Run connectivity diagnostics")]
     Doctor,
 
@@ -135,6 +178,58 @@ pub enum ConfigAction {
     Edit,
 }
 
+#[derive(Subcommand)]
+pub enum HistoryAction {
+    #[command(about = "Pin an entry so retention never prunes it")]
+    Pin {
+        /// Entry number as listed by `clipsync history`, or entry ID
+        entry: String,
+    },
+
+    #[command(about = "Unpin a previously pinned entry")]
+    Unpin {
+        /// Entry number as listed by `clipsync history`, or entry ID
+        entry: String,
+    },
+
+    #[command(about = "Export history to a passphrase-encrypted archive")]
+    Export {
+        /// Archive file to write
+        file: PathBuf,
+    },
+
+    #[command(about = "Import history from an archive created by `history export`")]
+    Import {
+        /// Archive file to read
+        file: PathBuf,
+    },
+
+    #[command(about = "Unlock passphrase-protected history in the running daemon")]
+    Unlock,
+
+    #[command(about = "Lock history in the running daemon until it is unlocked")]
+    Lock,
+
+    #[command(about = "Set or change the passphrase protecting the history key")]
+    Passphrase {
+        /// Remove the passphrase and store the key unprotected
+        #[arg(long)]
+        remove: bool,
+    },
+
+    #[command(about = "Re-encrypt all history entries under a new key")]
+    Rekey,
+}
+
+#[derive(Subcommand)]
+pub enum SafetyCommand {
+    #[command(about = "Show which safety rule, if any, matches some text")]
+    Test {
+        /// Text to check, as if it had been copied
+        text: String,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum AuthAction {
     #[command(about = "Add authorized device")]
@@ -207,12 +302,22 @@ impl CliHandler {
     async fn ensure_history(&mut self) -> Result<Arc<HistoryManager>> {
         if self.history.is_none() {
             info!("Initializing history manager");
-            let history = Arc::new(HistoryManager::new(&self.config.database_path()).await?);
+            let history = Arc::new(HistoryManager::from_config(&self.config).await?);
             self.history = Some(history);
         }
         Ok(self.history.as_ref().unwrap().clone())
     }
 
+    /// History manager, asking for the passphrase if history is locked
+    async fn unlocked_history(&mut self) -> Result<Arc<HistoryManager>> {
+        let history = self.ensure_history().await?;
+        if history.is_locked().await {
+            let passphrase = passphrase::read_passphrase("History passphrase: ")?;
+            history.unlock(&passphrase).await?;
+        }
+        Ok(history)
+    }
+
     /// Lazily initialize the clipboard provider when needed
     async fn ensure_clipboard(&mut self) -> Result<Arc<ClipboardProviderWrapper>> {
         if self.clipboard.is_none() {
@@ -243,16 +348,82 @@ impl CliHandler {
         Ok(self.transport.as_ref().unwrap().clone())
     }
 
+    /// Connected peers from the in-process engine or the running daemon
+    async fn connected_peers(&self) -> Result<Option<Vec<Peer>>> {
+        if let Some(sync_engine) = &self.sync_engine {
+            return Ok(Some(sync_engine.get_connected_peers().await));
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ControlReply::Peers(peers)) = control::request(ControlCommand::Peers).await? {
+                return Ok(Some(peers));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Fetch history from the running daemon, falling back to the local database
+    async fn fetch_history(
+        &mut self,
+        limit: usize,
+        search: Option<&SearchQuery>,
+    ) -> Result<Vec<ClipboardEntry>> {
+        #[cfg(target_os = "linux")]
+        {
+            let command = ControlCommand::History {
+                limit,
+                search: search.cloned(),
+            };
+            if let Some(ControlReply::History(entries)) = control::request(command).await? {
+                return Ok(entries);
+            }
+        }
+
+        let history = self.unlocked_history().await?;
+        match search {
+            Some(query) => history.search(query, limit).await,
+            None => history.get_recent_entries(limit).await,
+        }
+    }
+
     pub async fn handle_command(&mut self, command: Commands) -> Result<()> {
         match command {
             Commands::Start { foreground } => self.start_daemon(foreground).await,
             Commands::Stop => self.stop_daemon().await,
             Commands::Status => self.show_status().await,
-            Commands::History { limit, interactive, search } => {
+            Commands::History {
+                action: Some(action),
+                ..
+            } => self.handle_history_action(action).await,
+            Commands::History {
+                action: None,
+                limit,
+                interactive,
+                search,
+                origin,
+                content_type,
+                since,
+                until,
+            } => {
+                let filtered = search.is_some()
+                    || origin.is_some()
+                    || content_type.is_some()
+                    || since.is_some()
+                    || until.is_some();
+
                 if interactive {
                     self.show_interactive_history().await
-                } else if let Some(search_term) = search {
-                    self.search_history(&search_term, limit).await
+                } else if filtered {
+                    let query = SearchQuery {
+                        text: search.unwrap_or_default(),
+                        origin,
+                        content_type,
+                        since: since.as_deref().map(parse_time_bound).transpose()?,
+                        until: until.as_deref().map(parse_time_bound).transpose()?,
+                    };
+                    self.search_history(&query, limit).await
                 } else {
                     self.show_history(limit).await
                 }
@@ -265,13 +436,14 @@ impl CliHandler {
                     self.show_peers().await
                 }
             }
-            Commands::Copy { text } => self.copy_text(text).await,
+            Commands::Copy { text, ttl } => self.copy_text(text, ttl).await,
             Commands::Paste => self.paste_text().await,
             Commands::Restart => self.restart_daemon().await,
             Commands::Clear => self.clear_clipboard().await,
             Commands::Version => self.show_version().await,
             Commands::Config { action } => self.handle_config_action(action).await,
             Commands::Auth { action } => self.handle_auth_action(action).await,
+            Commands::Safety { action } => self.handle_safety_command(action),
             Commands::Doctor => self.run_diagnostics().await,
             Commands::Logs { limit, follow } => {
                 if follow {
@@ -354,6 +526,9 @@ impl CliHandler {
         // Ensure all components are initialized for daemon mode
         let clipboard = self.ensure_clipboard().await?;
         let history = self.ensure_history().await?;
+        if history.is_locked().await {
+            println!("History is locked; run 'clipsync history unlock' to access it");
+        }
         let discovery = self.ensure_discovery().await?;
         let transport = self.ensure_transport().await?;
 
@@ -381,11 +556,35 @@ impl CliHandler {
 
         self.sync_engine = Some(Arc::clone(&sync_engine));
 
+        // Expose the control socket so CLI invocations can reach this daemon
+        #[cfg(target_os = "linux")]
+        {
+            let server = control::ControlServer::bind(&control::get_socket_path()?)?;
+            let handler = Arc::new(control::DaemonControl::new(
+                Arc::clone(&sync_engine),
+                Arc::clone(&history),
+                Arc::clone(&clipboard),
+                self.config.node_id(),
+            ));
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(handler).await {
+                    error!("Control socket error: {}", e);
+                }
+            });
+        }
+
         // Start trust processing
         sync_engine
             .start_trust_processing(Arc::clone(&discovery))
             .await?;
 
+        // Pick up edits to the manual peer list without a restart
+        if let Some(config_path) = self.get_config_path_for_validation() {
+            let discovery = Arc::clone(&discovery);
+            let peers = self.config.peers.clone();
+            tokio::spawn(watch_manual_peers(config_path, peers, discovery));
+        }
+
         // Start services
         let sync_engine_task = Arc::clone(&sync_engine);
 
@@ -422,7 +621,10 @@ impl CliHandler {
 
         // Cleanup
         #[cfg(target_os = "linux")]
-        daemon::remove_pidfile()?;
+        {
+            control::remove_socket()?;
+            daemon::remove_pidfile()?;
+        }
         info!("ClipSync daemon stopped");
 
         Ok(())
@@ -521,14 +723,31 @@ impl CliHandler {
         if let Some(sync_engine) = &self.sync_engine {
             let peers = sync_engine.get_connected_peers().await;
             println!("  Connected Peers: {}", peers.len());
+            return Ok(());
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            match control::request(ControlCommand::Status).await {
+                Ok(Some(ControlReply::Status(status))) => {
+                    println!("  Connected Peers: {}", status.connected_peers);
+                    if status.history_locked {
+                        println!("  History: Locked (run 'clipsync history unlock')");
+                    }
+                    for transfer in &status.transfers {
+                        println!("  Transfer: {}", transfer_label(transfer));
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => println!("  Control socket: unreachable ({})", e),
+            }
         }
 
         Ok(())
     }
 
     async fn show_history(&mut self, limit: usize) -> Result<()> {
-        let history = self.ensure_history().await?;
-        let entries = history.get_recent_entries(limit).await?;
+        let entries = self.fetch_history(limit, None).await?;
 
         if entries.is_empty() {
             println!("No clipboard history found");
@@ -538,26 +757,205 @@ impl CliHandler {
         println!("Clipboard History (showing {} entries):", entries.len());
         for (i, entry) in entries.iter().enumerate() {
             println!(
-                "{}. [{}] {}",
+                "{}. [{}] {}{}",
                 i + 1,
                 entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
-                match &entry.content {
-                    crate::adapters::ClipboardData::Text(text) => {
-                        if text.len() > 50 {
-                            format!("{}...", &text[..50])
-                        } else {
-                            text.clone()
-                        }
-                    }
-                }
+                entry_label(entry),
+                entry.content.preview(50)
             );
         }
 
         Ok(())
     }
 
-    async fn show_interactive_history(&mut self) -> Result<()> {
+    async fn handle_history_action(&mut self, action: HistoryAction) -> Result<()> {
+        match action {
+            HistoryAction::Pin { entry } => self.set_entry_pinned(&entry, true).await,
+            HistoryAction::Unpin { entry } => self.set_entry_pinned(&entry, false).await,
+            HistoryAction::Export { file } => self.export_history(&file).await,
+            HistoryAction::Import { file } => self.import_history(&file).await,
+            HistoryAction::Unlock => self.unlock_history().await,
+            HistoryAction::Lock => self.lock_history().await,
+            HistoryAction::Passphrase { remove } => self.change_history_passphrase(remove).await,
+            HistoryAction::Rekey => self.rekey_history().await,
+        }
+    }
+
+    async fn unlock_history(&mut self) -> Result<()> {
+        let passphrase = passphrase::read_passphrase("History passphrase: ")?;
+
+        #[cfg(target_os = "linux")]
+        {
+            let command = ControlCommand::Unlock {
+                passphrase: passphrase.clone(),
+            };
+            if let Some(ControlReply::Unlocked) = control::request(command).await? {
+                println!("✓ History unlocked");
+                return Ok(());
+            }
+        }
+
+        // Without a daemon there is nothing to keep unlocked; just check it
+        let history = self.ensure_history().await?;
+        history.unlock(&passphrase).await?;
+        println!("✓ Passphrase accepted (no daemon running, nothing stays unlocked)");
+        Ok(())
+    }
+
+    async fn lock_history(&mut self) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ControlReply::Locked) = control::request(ControlCommand::Lock).await? {
+                println!("✓ History locked");
+                return Ok(());
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "ClipSync daemon is not running; protected history is locked whenever it starts"
+        ))
+    }
+
+    async fn change_history_passphrase(&mut self, remove: bool) -> Result<()> {
         let history = self.ensure_history().await?;
+        let protected = history.is_protected()?;
+        if remove && !protected {
+            return Err(anyhow::anyhow!("History has no passphrase"));
+        }
+
+        let current = if protected {
+            Some(passphrase::read_passphrase("Current history passphrase: ")?)
+        } else {
+            None
+        };
+        let new = if remove {
+            None
+        } else {
+            Some(passphrase::read_new_passphrase("New history passphrase: ")?)
+        };
+
+        let done = if remove {
+            "✓ History passphrase removed"
+        } else {
+            "✓ History passphrase set"
+        };
+
+        #[cfg(target_os = "linux")]
+        {
+            let command = ControlCommand::SetPassphrase {
+                current: current.clone(),
+                new: new.clone(),
+            };
+            if let Some(ControlReply::PassphraseChanged) = control::request(command).await? {
+                println!("{}", done);
+                return Ok(());
+            }
+        }
+
+        if let Some(current) = &current {
+            if history.is_locked().await {
+                history.unlock(current).await?;
+            }
+        }
+        history
+            .set_passphrase(current.as_deref(), new.as_deref())
+            .await?;
+        println!("{}", done);
+        Ok(())
+    }
+
+    async fn rekey_history(&mut self) -> Result<()> {
+        let history = self.ensure_history().await?;
+        let passphrase = if history.is_protected()? {
+            Some(passphrase::read_passphrase("History passphrase: ")?)
+        } else {
+            None
+        };
+
+        #[cfg(target_os = "linux")]
+        {
+            let command = ControlCommand::Rekey {
+                passphrase: passphrase.clone(),
+            };
+            if let Some(ControlReply::Rekeyed(count)) = control::request(command).await? {
+                println!("✓ Re-encrypted {} history entries under a new key", count);
+                return Ok(());
+            }
+        }
+
+        if let Some(passphrase) = &passphrase {
+            if history.is_locked().await {
+                history.unlock(passphrase).await?;
+            }
+        }
+        let count = history.rekey(passphrase.as_deref()).await?;
+        println!("✓ Re-encrypted {} history entries under a new key", count);
+        Ok(())
+    }
+
+    async fn export_history(&mut self, file: &PathBuf) -> Result<()> {
+        let history = self.unlocked_history().await?;
+        let passphrase = passphrase::read_new_passphrase("Archive passphrase: ")?;
+        let count = history.export(file, &passphrase).await?;
+
+        println!("✓ Exported {} history entries to {}", count, file.display());
+        Ok(())
+    }
+
+    async fn import_history(&mut self, file: &PathBuf) -> Result<()> {
+        let history = self.unlocked_history().await?;
+        let passphrase = passphrase::read_passphrase("Archive passphrase: ")?;
+        let (imported, total) = history.import(file, &passphrase).await?;
+
+        println!(
+            "✓ Imported {} of {} history entries from {} ({} already present)",
+            imported,
+            total,
+            file.display(),
+            total - imported
+        );
+        Ok(())
+    }
+
+    async fn set_entry_pinned(&mut self, entry: &str, pinned: bool) -> Result<()> {
+        let id = match uuid::Uuid::parse_str(entry) {
+            Ok(id) => id,
+            Err(_) => {
+                // Resolve the number shown by `clipsync history`
+                let index: usize = entry
+                    .parse()
+                    .ok()
+                    .filter(|index| *index > 0)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Invalid entry '{}': expected a number from 'clipsync history' or an entry ID",
+                            entry
+                        )
+                    })?;
+                let entries = self.fetch_history(index, None).await?;
+                entries
+                    .get(index - 1)
+                    .map(|e| e.id)
+                    .ok_or_else(|| anyhow::anyhow!("No history entry #{}", index))?
+            }
+        };
+
+        let history = self.ensure_history().await?;
+        if !history.set_pinned(id, pinned).await? {
+            return Err(anyhow::anyhow!("No history entry with ID {}", id));
+        }
+
+        if pinned {
+            println!("✓ Pinned history entry {}", entry);
+        } else {
+            println!("✓ Unpinned history entry {}", entry);
+        }
+
+        Ok(())
+    }
+
+    async fn show_interactive_history(&mut self) -> Result<()> {
+        let history = self.unlocked_history().await?;
         let mut picker = history_picker::HistoryPicker::new(Arc::clone(&history));
         picker.show().await
     }
@@ -566,16 +964,23 @@ impl CliHandler {
         if let Some(sync_engine) = &self.sync_engine {
             sync_engine.force_sync().await?;
             println!("Clipboard sync completed");
-        } else {
-            println!("ClipSync daemon is not running");
+            return Ok(());
         }
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ControlReply::Synced) = control::request(ControlCommand::Sync).await? {
+                println!("Clipboard sync completed");
+                return Ok(());
+            }
+        }
+
+        println!("ClipSync daemon is not running");
         Ok(())
     }
 
     async fn show_peers(&self) -> Result<()> {
-        if let Some(sync_engine) = &self.sync_engine {
-            let peers = sync_engine.get_connected_peers().await;
-
+        if let Some(peers) = self.connected_peers().await? {
             if peers.is_empty() {
                 println!("No connected peers");
                 return Ok(());
@@ -584,6 +989,9 @@ impl CliHandler {
             println!("Connected Peers ({}):", peers.len());
             for peer in peers {
                 println!("  {} - {} ({})", peer.id, peer.hostname, peer.address);
+                if !peer.groups.is_empty() {
+                    println!("    Groups: {}", peer.groups.join(", "));
+                }
             }
         } else {
             println!("ClipSync daemon is not running");
@@ -592,7 +1000,43 @@ impl CliHandler {
         Ok(())
     }
 
-    async fn copy_text(&mut self, text: String) -> Result<()> {
+    async fn copy_text(&mut self, text: String, ttl: Option<String>) -> Result<()> {
+        let ttl = match ttl {
+            Some(ttl) => {
+                let ttl = crate::config::parse_duration(&ttl)
+                    .map_err(|_| anyhow::anyhow!("Invalid --ttl '{}', expected e.g. 30s or 5m", ttl))?;
+                if ttl < std::time::Duration::from_secs(1) {
+                    anyhow::bail!("--ttl must be at least one second");
+                }
+                Some(ttl)
+            }
+            None => None,
+        };
+
+        // Let the daemon own the selection so it outlives this process
+        #[cfg(target_os = "linux")]
+        {
+            let command = ControlCommand::Copy {
+                text: text.clone(),
+                ttl_ms: ttl.map(|ttl| ttl.as_millis() as u64),
+            };
+            if let Some(ControlReply::Copied) = control::request(command).await? {
+                match ttl {
+                    Some(ttl) => println!(
+                        "Text copied to clipboard; it will be cleared from every device in {}s",
+                        ttl.as_secs()
+                    ),
+                    None => println!("Text copied to clipboard"),
+                }
+                return Ok(());
+            }
+        }
+
+        // Only the daemon outlives this process to clear the copy later
+        if ttl.is_some() {
+            anyhow::bail!("--ttl needs a running daemon; start it with 'clipsync start'");
+        }
+
         let clipboard = self.ensure_clipboard().await?;
         clipboard.set_text(&text).await?;
         println!("Text copied to clipboard");
@@ -680,30 +1124,22 @@ impl CliHandler {
         Ok(())
     }
 
-    async fn search_history(&mut self, search_term: &str, limit: usize) -> Result<()> {
-        let history = self.ensure_history().await?;
-        let entries = history.search_entries(search_term, limit).await?;
+    async fn search_history(&mut self, query: &SearchQuery, limit: usize) -> Result<()> {
+        let entries = self.fetch_history(limit, Some(query)).await?;
 
         if entries.is_empty() {
-            println!("No clipboard history entries found matching '{}'", search_term);
+            println!("No clipboard history entries found matching '{}'", query.text);
             return Ok(());
         }
 
-        println!("Clipboard History (showing {} entries matching '{}'):", entries.len(), search_term);
+        println!("Clipboard History (showing {} entries matching '{}'):", entries.len(), query.text);
         for (i, entry) in entries.iter().enumerate() {
             println!(
-                "{}. [{}] {}",
+                "{}. [{}] {}{}",
                 i + 1,
                 entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
-                match &entry.content {
-                    crate::adapters::ClipboardData::Text(text) => {
-                        if text.len() > 50 {
-                            format!("{}...", &text[..50])
-                        } else {
-                            text.clone()
-                        }
-                    }
-                }
+                entry_label(entry),
+                entry.content.preview(50)
             );
         }
 
@@ -1123,6 +1559,45 @@ impl CliHandler {
         Ok(())
     }
 
+    fn handle_safety_command(&self, action: SafetyCommand) -> Result<()> {
+        match action {
+            SafetyCommand::Test { text } => self.test_safety_rules(&text),
+        }
+    }
+
+    fn test_safety_rules(&self, text: &str) -> Result<()> {
+        let filter = SafetyFilter::from_config(&self.config.safety)
+            .map_err(|e| anyhow::anyhow!("Invalid safety rules: {}", e))?;
+
+        match filter.check(text) {
+            Verdict::Clean => {
+                println!("No rule matched; the text would sync normally.");
+            }
+            Verdict::Allowed(rule) => {
+                println!(
+                    "Allowed by {} rule '{}' ({}); the text would sync normally.",
+                    if rule.is_builtin() { "built-in" } else { "configured" },
+                    rule.name(),
+                    rule.kind()
+                );
+            }
+            Verdict::Matched { rule, spans } => {
+                println!(
+                    "Matched {} rule '{}' ({})",
+                    if rule.is_builtin() { "built-in" } else { "configured" },
+                    rule.name(),
+                    rule.kind()
+                );
+                println!("  Action: {}", rule.action().describe());
+                for span in spans {
+                    println!("  Match: {:?} at {}..{}", &text[span.clone()], span.start, span.end);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn handle_auth_action(&mut self, action: AuthAction) -> Result<()> {
         match action {
             AuthAction::Add { public_key, name } => self.add_authorized_key(public_key, name).await,
@@ -1287,3 +1762,106 @@ impl CliHandler {
     }
 
 }
+
+/// Poll the config file and push `[[peers]]` changes into discovery
+async fn watch_manual_peers(
+    path: PathBuf,
+    mut peers: Vec<crate::config::PeerConfig>,
+    discovery: Arc<PeerDiscovery>,
+) {
+    let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    let mut last_modified = modified(&path);
+    let mut interval = tokio::time::interval(CONFIG_RELOAD_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let current = modified(&path);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        let config = match Config::load_from_path(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Ignoring config change in {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        if config.peers == peers {
+            continue;
+        }
+
+        info!("Reloading {} manual peer(s) from config", config.peers.len());
+        if let Err(e) = discovery.update_manual_peers(&config.peers).await {
+            error!("Failed to apply manual peers: {}", e);
+        }
+        peers = config.peers;
+    }
+}
+
+/// Parse a `--since`/`--until` value: a date, an RFC 3339 timestamp, or
+/// an age relative to now such as "2d"
+fn parse_time_bound(value: &str) -> Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&chrono::Utc));
+    }
+
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|time| time.and_local_timezone(Local).earliest())
+            .ok_or_else(|| anyhow::anyhow!("Invalid local date: {}", value))?;
+        return Ok(midnight.with_timezone(&chrono::Utc));
+    }
+
+    let age = crate::config::parse_duration(value).map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid time '{}': expected YYYY-MM-DD, an RFC 3339 timestamp or an age like 2d",
+            value
+        )
+    })?;
+    Ok(chrono::Utc::now() - chrono::Duration::from_std(age)?)
+}
+
+/// One-line progress of a clipboard transfer, for `clipsync status`
+fn transfer_label(transfer: &Transfer) -> String {
+    let progress = &transfer.progress;
+    let direction = match progress.direction {
+        StreamDirection::Outbound => "to",
+        StreamDirection::Inbound => "from",
+    };
+    let percent = if progress.total_bytes > 0 {
+        progress.bytes_transferred * 100 / progress.total_bytes
+    } else {
+        0
+    };
+
+    let mut label = format!(
+        "{} {} - {}% of {} ({}/s)",
+        direction,
+        transfer.peer_id,
+        percent,
+        format_bytes(progress.total_bytes),
+        format_bytes(progress.transfer_rate as u64)
+    );
+    if let Some(eta) = progress.eta_seconds {
+        label.push_str(&format!(" ETA: {}s", eta as u64));
+    }
+    label
+}
+
+/// Prefix marking pinned entries and entries from the PRIMARY selection
+fn entry_label(entry: &ClipboardEntry) -> String {
+    let mut label = String::new();
+    if entry.pinned {
+        label.push_str("(pinned) ");
+    }
+    if entry.selection == ClipboardSelection::Primary {
+        label.push_str("(primary) ");
+    }
+    label
+}
@@ -0,0 +1,83 @@
+//! Passphrase prompts for encrypted history archives
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::{anyhow, Result};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+
+/// Environment variable that supplies the passphrase non-interactively
+pub const PASSPHRASE_ENV: &str = "CLIPSYNC_PASSPHRASE";
+
+/// Read a passphrase from the environment, the terminal, or stdin
+pub fn read_passphrase(prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    let passphrase = if io::stdin().is_terminal() {
+        prompt_hidden(prompt)?
+    } else {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    };
+
+    if passphrase.is_empty() {
+        return Err(anyhow!("Passphrase must not be empty"));
+    }
+
+    Ok(passphrase)
+}
+
+/// Read a new passphrase, asking twice when prompting on a terminal
+pub fn read_new_passphrase(prompt: &str) -> Result<String> {
+    let passphrase = read_passphrase(prompt)?;
+
+    if std::env::var(PASSPHRASE_ENV).is_err() && io::stdin().is_terminal() {
+        let confirmation = prompt_hidden("Confirm passphrase: ")?;
+        if confirmation != passphrase {
+            return Err(anyhow!("Passphrases do not match"));
+        }
+    }
+
+    Ok(passphrase)
+}
+
+fn prompt_hidden(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+
+    enable_raw_mode()?;
+    let result = read_hidden_line();
+    let _ = disable_raw_mode();
+    eprintln!();
+
+    result
+}
+
+fn read_hidden_line() -> Result<String> {
+    let mut input = String::new();
+
+    loop {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event::read()?
+        {
+            match code {
+                KeyCode::Enter => return Ok(input),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Err(anyhow!("Cancelled"));
+                }
+                KeyCode::Esc => return Err(anyhow!("Cancelled")),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+        }
+    }
+}
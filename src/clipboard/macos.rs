@@ -17,6 +17,7 @@ use tokio::time::interval;
 /// NSPasteboard type constants
 const NS_PASTEBOARD_TYPE_STRING: &str = "NSPasteboardTypeString";
 const NS_PASTEBOARD_TYPE_RTF: &str = "NSPasteboardTypeRTF";
+const NS_PASTEBOARD_TYPE_HTML: &str = "NSPasteboardTypeHTML";
 const NS_PASTEBOARD_TYPE_PNG: &str = "NSPasteboardTypePNG";
 const NS_PASTEBOARD_TYPE_TIFF: &str = "NSPasteboardTypeTIFF";
 
@@ -216,6 +217,25 @@ impl ClipboardProvider for MacOSClipboard {
                         }
                     }
                 }
+                "text/html" => {
+                    let data = NSData::dataWithBytes_length_(
+                        nil,
+                        content.data.as_ptr() as *const _,
+                        content.data.len() as u64,
+                    );
+                    let html_type = Self::get_type_string(NS_PASTEBOARD_TYPE_HTML);
+
+                    let success: bool = msg_send![self.pasteboard,
+                        setData: data
+                        forType: html_type];
+
+                    if !success {
+                        let _: () = msg_send![pool, drain];
+                        return Err(ClipboardError::Platform(
+                            "Failed to write HTML to pasteboard".to_string(),
+                        ));
+                    }
+                }
                 "text/rtf" => {
                     let data = NSData::dataWithBytes_length_(
                         nil,
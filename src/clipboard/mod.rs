@@ -23,6 +23,25 @@ pub mod wayland;
 /// Maximum clipboard content size (5MB)
 pub const MAX_CLIPBOARD_SIZE: usize = 5 * 1024 * 1024;
 
+/// Targets password managers offer alongside a secret
+///
+/// KeePassXC and KDE applications add `x-kde-passwordManagerHint`; the
+/// nspasteboard.org markers are used by ports of macOS password managers.
+pub const CONCEALED_TARGETS: &[&str] = &[
+    "x-kde-passwordManagerHint",
+    "application/x-nspasteboard-concealed-type",
+    "org.nspasteboard.ConcealedType",
+];
+
+/// Whether a selection owner offering `targets` marks its content as secret
+pub fn is_concealed(targets: &[String]) -> bool {
+    targets.iter().any(|target| {
+        CONCEALED_TARGETS
+            .iter()
+            .any(|concealed| target.eq_ignore_ascii_case(concealed))
+    })
+}
+
 /// Clipboard content with metadata
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ClipboardContent {
@@ -32,6 +51,10 @@ pub struct ClipboardContent {
     pub data: Vec<u8>,
     /// Timestamp when the content was captured (Unix timestamp)
     pub timestamp: i64,
+    /// Whether the application that owns the clipboard marked the content
+    /// as a secret, so it must never leave this device
+    #[serde(default)]
+    pub concealed: bool,
 }
 
 impl ClipboardContent {
@@ -42,6 +65,7 @@ impl ClipboardContent {
             mime_type: "text/plain".to_string(),
             data: text.into_bytes(),
             timestamp: current_timestamp(),
+            concealed: false,
         }
     }
 
@@ -51,6 +75,7 @@ impl ClipboardContent {
             mime_type: "text/rtf".to_string(),
             data,
             timestamp: current_timestamp(),
+            concealed: false,
         }
     }
 
@@ -60,6 +85,7 @@ impl ClipboardContent {
             mime_type: format!("image/{}", format),
             data,
             timestamp: current_timestamp(),
+            concealed: false,
         }
     }
 
@@ -105,17 +131,69 @@ pub trait ClipboardProvider: Send + Sync {
 
     /// Start watching for clipboard changes
     async fn watch(&self) -> Result<ClipboardWatcher, ClipboardError>;
+
+    /// Get content of a specific selection
+    ///
+    /// Backends without a PRIMARY selection only support `Clipboard`.
+    async fn get_selection(
+        &self,
+        selection: ClipboardSelection,
+    ) -> Result<ClipboardContent, ClipboardError> {
+        match selection {
+            ClipboardSelection::Clipboard => self.get_content().await,
+            ClipboardSelection::Primary => Err(ClipboardError::UnsupportedType(
+                "PRIMARY selection".to_string(),
+            )),
+        }
+    }
+
+    /// Set content of a specific selection
+    ///
+    /// Backends without a PRIMARY selection only support `Clipboard`.
+    async fn set_selection(
+        &self,
+        content: &ClipboardContent,
+        selection: ClipboardSelection,
+    ) -> Result<(), ClipboardError> {
+        match selection {
+            ClipboardSelection::Clipboard => self.set_content(content).await,
+            ClipboardSelection::Primary => Err(ClipboardError::UnsupportedType(
+                "PRIMARY selection".to_string(),
+            )),
+        }
+    }
 }
 
 /// Clipboard selection type (mainly for X11)
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ClipboardSelection {
     /// Primary selection (middle-click on Linux)
     Primary,
     /// Clipboard selection (Ctrl+C/V)
+    #[default]
     Clipboard,
 }
 
+impl ClipboardSelection {
+    /// Name used for history tags and protocol metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClipboardSelection::Primary => "primary",
+            ClipboardSelection::Clipboard => "clipboard",
+        }
+    }
+
+    /// Parse a name produced by [`ClipboardSelection::as_str`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "primary" => Some(ClipboardSelection::Primary),
+            "clipboard" => Some(ClipboardSelection::Clipboard),
+            _ => None,
+        }
+    }
+}
+
 /// Clipboard change event
 #[derive(Debug, Clone)]
 pub struct ClipboardEvent {
@@ -237,6 +315,28 @@ mod tests {
         assert_eq!(content.size(), 3);
     }
 
+    #[test]
+    fn test_concealed_targets() {
+        let targets =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+
+        assert!(is_concealed(&targets(&[
+            "TARGETS",
+            "UTF8_STRING",
+            "x-kde-passwordManagerHint",
+        ])));
+        assert!(is_concealed(&targets(&[
+            "text/plain",
+            "ORG.NSPASTEBOARD.CONCEALEDTYPE"
+        ])));
+        assert!(!is_concealed(&targets(&[
+            "TARGETS",
+            "UTF8_STRING",
+            "text/html"
+        ])));
+        assert!(!is_concealed(&[]));
+    }
+
     #[test]
     fn test_clipboard_content_size() {
         let large_data = vec![0u8; 1024 * 1024]; // 1MB
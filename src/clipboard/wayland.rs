@@ -30,6 +30,8 @@ struct WaylandState {
     seat: Option<WlSeat>,
     current_offer: Option<WlDataOffer>,
     clipboard_content: Arc<Mutex<Option<Vec<u8>>>>,
+    /// MIME types offered for the current selection
+    offered_types: Arc<Mutex<Vec<String>>>,
 }
 
 impl WaylandState {
@@ -41,10 +43,26 @@ impl WaylandState {
             seat: None,
             current_offer: None,
             clipboard_content: Arc::new(Mutex::new(None)),
+            offered_types: Arc::new(Mutex::new(Vec::new())),
         }
     }
 }
 
+/// Build clipboard content from selection data and the types offered for it
+fn offered_content(data: Vec<u8>, offered_types: &[String]) -> ClipboardContent {
+    let mut content = match String::from_utf8(data) {
+        Ok(text) => ClipboardContent::text(text),
+        Err(e) => ClipboardContent {
+            mime_type: "application/octet-stream".to_string(),
+            data: e.into_bytes(),
+            timestamp: super::current_timestamp(),
+            concealed: false,
+        },
+    };
+    content.concealed = super::is_concealed(offered_types);
+    content
+}
+
 /// Wayland clipboard provider
 pub struct WaylandClipboard {
     connection: Connection,
@@ -106,8 +124,8 @@ impl WaylandClipboard {
         Ok(Self { connection, state })
     }
 
-    /// Read clipboard content
-    async fn read_clipboard(&self) -> Result<Option<Vec<u8>>, ClipboardError> {
+    /// Read clipboard content and the types offered for it
+    async fn read_clipboard(&self) -> Result<Option<(Vec<u8>, Vec<String>)>, ClipboardError> {
         // First, dispatch any pending events to ensure we have the latest clipboard state
         let mut event_queue = self.connection.new_event_queue();
         let mut temp_state = WaylandState::new();
@@ -121,6 +139,7 @@ impl WaylandClipboard {
             temp_state.seat = state_guard.seat.clone();
             temp_state.current_offer = state_guard.current_offer.clone();
             temp_state.clipboard_content = state_guard.clipboard_content.clone();
+            temp_state.offered_types = state_guard.offered_types.clone();
         }
 
         // Dispatch pending events
@@ -134,12 +153,13 @@ impl WaylandClipboard {
 
         // Now read the clipboard content
         let state = self.state.lock().unwrap();
-        let content = state.clipboard_content.lock().unwrap();
-        Ok(content.clone())
+        let content = state.clipboard_content.lock().unwrap().clone();
+        let offered_types = state.offered_types.lock().unwrap().clone();
+        Ok(content.map(|data| (data, offered_types)))
     }
 
     /// Write clipboard content
-    async fn write_clipboard(&self, data: Vec<u8>) -> Result<(), ClipboardError> {
+    async fn write_clipboard(&self, mime_type: &str, data: Vec<u8>) -> Result<(), ClipboardError> {
         let state = self.state.lock().unwrap();
 
         if let (Some(manager), Some(device)) = (&state.data_device_manager, &state.data_device) {
@@ -149,8 +169,16 @@ impl WaylandClipboard {
             // Create data source
             let source = manager.create_data_source(&qhandle, ());
 
-            // Offer text/plain mime type
-            source.offer("text/plain".to_string());
+            // Offer the content's mime type, plus the common aliases for text
+            let mut offered = vec![mime_type.to_string()];
+            if mime_type == "text/plain" {
+                offered.push("text/plain;charset=utf-8".to_string());
+                offered.push("UTF8_STRING".to_string());
+            }
+            for offer in &offered {
+                source.offer(offer.clone());
+            }
+            *state.offered_types.lock().unwrap() = offered;
 
             // Set selection
             device.set_selection(Some(&source), 0);
@@ -182,19 +210,9 @@ impl WaylandClipboard {
 #[async_trait]
 impl ClipboardProvider for WaylandClipboard {
     async fn get_content(&self) -> Result<ClipboardContent, ClipboardError> {
-        if let Some(data) = self.read_clipboard().await? {
-            // Try to parse as text
-            if let Ok(text) = String::from_utf8(data.clone()) {
-                Ok(ClipboardContent::text(text))
-            } else {
-                Ok(ClipboardContent {
-                    mime_type: "application/octet-stream".to_string(),
-                    data,
-                    timestamp: super::current_timestamp(),
-                })
-            }
-        } else {
-            Err(ClipboardError::NoContent)
+        match self.read_clipboard().await? {
+            Some((data, offered_types)) => Ok(offered_content(data, &offered_types)),
+            None => Err(ClipboardError::NoContent),
         }
     }
 
@@ -207,12 +225,8 @@ impl ClipboardProvider for WaylandClipboard {
             });
         }
 
-        // For now, only support text content on Wayland
-        if !content.is_text() {
-            return Err(ClipboardError::UnsupportedType(content.mime_type.clone()));
-        }
-
-        self.write_clipboard(content.data.clone()).await
+        self.write_clipboard(&content.mime_type, content.data.clone())
+            .await
     }
 
     async fn clear(&self) -> Result<(), ClipboardError> {
@@ -221,6 +235,7 @@ impl ClipboardProvider for WaylandClipboard {
         if let Some(device) = &state.data_device {
             device.set_selection(None, 0);
             *state.clipboard_content.lock().unwrap() = None;
+            state.offered_types.lock().unwrap().clear();
 
             self.connection.flush().map_err(|e| {
                 ClipboardError::Platform(format!("Failed to flush connection: {}", e))
@@ -255,6 +270,7 @@ impl ClipboardProvider for WaylandClipboard {
                     let state_guard = state.lock().unwrap();
                     temp_state.data_device = state_guard.data_device.clone();
                     temp_state.clipboard_content = state_guard.clipboard_content.clone();
+                    temp_state.offered_types = state_guard.offered_types.clone();
                 }
 
                 // Dispatch any pending events
@@ -270,15 +286,8 @@ impl ClipboardProvider for WaylandClipboard {
                     if let Some(data) = current_content.clone() {
                         last_content = current_content;
 
-                        let content = if let Ok(text) = String::from_utf8(data.clone()) {
-                            ClipboardContent::text(text)
-                        } else {
-                            ClipboardContent {
-                                mime_type: "application/octet-stream".to_string(),
-                                data: data.clone(),
-                                timestamp: super::current_timestamp(),
-                            }
-                        };
+                        let offered_types = temp_state.offered_types.lock().unwrap().clone();
+                        let content = offered_content(data, &offered_types);
 
                         let event = ClipboardEvent {
                             content,
@@ -381,7 +390,9 @@ impl Dispatch<wl_data_device::WlDataDevice, ()> for WaylandState {
     ) {
         match event {
             wl_data_device::Event::DataOffer { id } => {
+                // The types of the new offer follow as offer events
                 state.current_offer = Some(id);
+                state.offered_types.lock().unwrap().clear();
             }
             wl_data_device::Event::Selection { id } => {
                 // Handle selection change
@@ -397,7 +408,7 @@ impl Dispatch<wl_data_device::WlDataDevice, ()> for WaylandState {
 
 impl Dispatch<wl_data_offer::WlDataOffer, ()> for WaylandState {
     fn event(
-        _state: &mut Self,
+        state: &mut Self,
         offer: &WlDataOffer,
         event: wl_data_offer::Event,
         _: &(),
@@ -405,6 +416,8 @@ impl Dispatch<wl_data_offer::WlDataOffer, ()> for WaylandState {
         _: &QueueHandle<Self>,
     ) {
         if let wl_data_offer::Event::Offer { mime_type } = event {
+            state.offered_types.lock().unwrap().push(mime_type.clone());
+
             // We only handle text/plain for now
             if mime_type == "text/plain" {
                 // Accept the offer
@@ -426,17 +439,15 @@ impl Dispatch<wl_data_source::WlDataSource, ()> for WaylandState {
         _: &QueueHandle<Self>,
     ) {
         match event {
-            wl_data_source::Event::Send { mime_type, fd } => {
-                // Send our clipboard data
-                if mime_type == "text/plain" {
-                    if let Some(data) = &*state.clipboard_content.lock().unwrap() {
-                        // Write data to file descriptor
-                        use std::os::unix::io::FromRawFd;
-                        let mut file = unsafe { std::fs::File::from_raw_fd(fd.as_raw_fd()) };
-                        let _ = std::io::Write::write_all(&mut file, data);
-                        // Important: we need to close the fd explicitly
-                        drop(file);
-                    }
+            wl_data_source::Event::Send { mime_type: _, fd } => {
+                // Send our clipboard data; we only offer types matching it
+                if let Some(data) = &*state.clipboard_content.lock().unwrap() {
+                    // Write data to file descriptor
+                    use std::os::unix::io::FromRawFd;
+                    let mut file = unsafe { std::fs::File::from_raw_fd(fd.as_raw_fd()) };
+                    let _ = std::io::Write::write_all(&mut file, data);
+                    // Important: we need to close the fd explicitly
+                    drop(file);
                 }
             }
             _ => {}
@@ -454,4 +465,24 @@ mod tests {
         // This is just a placeholder test
         assert_eq!("Wayland", "Wayland");
     }
+
+    #[test]
+    fn test_offered_content_honors_password_manager_hint() {
+        let offered =
+            |types: &[&str]| -> Vec<String> { types.iter().map(|t| t.to_string()).collect() };
+
+        let secret = offered_content(
+            b"hunter2".to_vec(),
+            &offered(&["text/plain;charset=utf-8", "x-kde-passwordManagerHint"]),
+        );
+        assert_eq!(secret.as_text(), Some("hunter2".to_string()));
+        assert!(secret.concealed);
+
+        let plain = offered_content(b"hello".to_vec(), &offered(&["text/plain", "UTF8_STRING"]));
+        assert!(!plain.concealed);
+
+        let binary = offered_content(vec![0xff, 0xfe], &offered(&["image/png"]));
+        assert_eq!(binary.mime_type, "application/octet-stream");
+        assert!(!binary.concealed);
+    }
 }
@@ -10,6 +10,55 @@ use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::interval;
 use x11_clipboard::Clipboard as X11ClipboardLib;
+use x11rb::protocol::xproto::ConnectionExt;
+
+/// Targets we know how to read, in order of preference
+///
+/// x11-clipboard can only serve a single target per selection, so plain text
+/// is preferred over markup: it is the representation every client can paste.
+const PREFERRED_TARGETS: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/bmp",
+    "UTF8_STRING",
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "text/html",
+    "text/rtf",
+    "application/rtf",
+];
+
+/// Target used when the selection owner does not answer TARGETS
+const FALLBACK_TARGET: &str = "UTF8_STRING";
+
+/// Pick the best target offered by the selection owner
+fn select_target(targets: &[String]) -> Option<&'static str> {
+    PREFERRED_TARGETS
+        .iter()
+        .find(|preferred| targets.iter().any(|t| t.eq_ignore_ascii_case(preferred)))
+        .copied()
+}
+
+/// Map an X11 target name to a MIME type
+fn target_mime_type(target: &str) -> String {
+    match target {
+        "UTF8_STRING" | "STRING" | "TEXT" => "text/plain".to_string(),
+        other => other
+            .split(';')
+            .next()
+            .unwrap_or(other)
+            .to_ascii_lowercase(),
+    }
+}
+
+/// Map a MIME type to the X11 target we serve it under
+fn mime_type_target(mime_type: &str) -> &str {
+    match mime_type {
+        "text/plain" => "UTF8_STRING",
+        other => other,
+    }
+}
 
 /// X11 clipboard provider
 pub struct X11Clipboard {
@@ -27,47 +76,92 @@ impl X11Clipboard {
         })
     }
 
-    /// Read content from a specific selection
+    fn selection_atom(&self, selection: ClipboardSelection) -> x11_clipboard::Atom {
+        match selection {
+            ClipboardSelection::Primary => self.clipboard.setter.atoms.primary,
+            ClipboardSelection::Clipboard => self.clipboard.setter.atoms.clipboard,
+        }
+    }
+
+    /// Ask the selection owner which targets it can convert to
+    fn read_targets(&self, selection: ClipboardSelection) -> Result<Vec<String>, ClipboardError> {
+        let data = self
+            .clipboard
+            .load(
+                self.selection_atom(selection),
+                self.clipboard.getter.atoms.targets,
+                self.clipboard.getter.atoms.property,
+                Duration::from_millis(500),
+            )
+            .map_err(|e| ClipboardError::Platform(format!("Failed to read targets: {}", e)))?;
+
+        let connection = &self.clipboard.getter.connection;
+        let targets = data
+            .chunks_exact(4)
+            .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .filter_map(|atom| connection.get_atom_name(atom).ok()?.reply().ok())
+            .map(|reply| String::from_utf8_lossy(&reply.name).into_owned())
+            .collect();
+
+        Ok(targets)
+    }
+
+    /// Read content from a specific selection in the best available format
     fn read_selection(
         &self,
         selection: ClipboardSelection,
-    ) -> Result<Option<Vec<u8>>, ClipboardError> {
-        let atom = match selection {
-            ClipboardSelection::Primary => self.clipboard.setter.atoms.primary,
-            ClipboardSelection::Clipboard => self.clipboard.setter.atoms.clipboard,
-        };
+    ) -> Result<Option<ClipboardContent>, ClipboardError> {
+        let targets = self.read_targets(selection).unwrap_or_default();
+        let target = select_target(&targets).unwrap_or(FALLBACK_TARGET);
+        let target_atom = self
+            .clipboard
+            .getter
+            .get_atom(target)
+            .map_err(|e| ClipboardError::Platform(format!("Failed to intern atom: {}", e)))?;
 
         // Load clipboard content with a timeout
-        let result = self
+        let data = self
             .clipboard
             .load(
-                atom,
-                self.clipboard.setter.atoms.utf8_string,
-                self.clipboard.setter.atoms.property,
+                self.selection_atom(selection),
+                target_atom,
+                self.clipboard.getter.atoms.property,
                 Duration::from_millis(500),
             )
             .map_err(|e| ClipboardError::Platform(format!("Failed to read clipboard: {}", e)))?;
 
-        if result.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(result))
+        if data.is_empty() {
+            return Ok(None);
+        }
+
+        let mut mime_type = target_mime_type(target);
+        if mime_type == "text/plain" {
+            mime_type = Self::detect_content_type(&data);
         }
+
+        Ok(Some(ClipboardContent {
+            mime_type,
+            data,
+            timestamp: super::current_timestamp(),
+            concealed: super::is_concealed(&targets),
+        }))
     }
 
     /// Write content to a specific selection
     fn write_selection(
         &self,
         selection: ClipboardSelection,
+        mime_type: &str,
         data: &[u8],
     ) -> Result<(), ClipboardError> {
-        let atom = match selection {
-            ClipboardSelection::Primary => self.clipboard.setter.atoms.primary,
-            ClipboardSelection::Clipboard => self.clipboard.setter.atoms.clipboard,
-        };
+        let target = self
+            .clipboard
+            .setter
+            .get_atom(mime_type_target(mime_type))
+            .map_err(|e| ClipboardError::Platform(format!("Failed to intern atom: {}", e)))?;
 
         self.clipboard
-            .store(atom, self.clipboard.setter.atoms.utf8_string, data)
+            .store(self.selection_atom(selection), target, data)
             .map_err(|e| ClipboardError::Platform(format!("Failed to write clipboard: {}", e)))?;
 
         Ok(())
@@ -88,46 +182,27 @@ impl X11Clipboard {
 #[async_trait]
 impl ClipboardProvider for X11Clipboard {
     async fn get_content(&self) -> Result<ClipboardContent, ClipboardError> {
-        // Try clipboard selection first, then primary
-        if let Some(data) = self.read_selection(ClipboardSelection::Clipboard)? {
-            let mime_type = Self::detect_content_type(&data);
-
-            if mime_type == "text/plain" {
-                let text = String::from_utf8(data).map_err(|_| {
-                    ClipboardError::Platform("Invalid UTF-8 in clipboard".to_string())
-                })?;
-                return Ok(ClipboardContent::text(text));
-            } else {
-                return Ok(ClipboardContent {
-                    mime_type,
-                    data,
-                    timestamp: super::current_timestamp(),
-                });
-            }
-        }
+        self.get_selection(ClipboardSelection::Clipboard).await
+    }
 
-        // Try primary selection
-        if let Some(data) = self.read_selection(ClipboardSelection::Primary)? {
-            let mime_type = Self::detect_content_type(&data);
-
-            if mime_type == "text/plain" {
-                let text = String::from_utf8(data).map_err(|_| {
-                    ClipboardError::Platform("Invalid UTF-8 in clipboard".to_string())
-                })?;
-                return Ok(ClipboardContent::text(text));
-            } else {
-                return Ok(ClipboardContent {
-                    mime_type,
-                    data,
-                    timestamp: super::current_timestamp(),
-                });
-            }
-        }
+    async fn set_content(&self, content: &ClipboardContent) -> Result<(), ClipboardError> {
+        self.set_selection(content, ClipboardSelection::Clipboard)
+            .await
+    }
 
-        Err(ClipboardError::NoContent)
+    async fn get_selection(
+        &self,
+        selection: ClipboardSelection,
+    ) -> Result<ClipboardContent, ClipboardError> {
+        self.read_selection(selection)?
+            .ok_or(ClipboardError::NoContent)
     }
 
-    async fn set_content(&self, content: &ClipboardContent) -> Result<(), ClipboardError> {
+    async fn set_selection(
+        &self,
+        content: &ClipboardContent,
+        selection: ClipboardSelection,
+    ) -> Result<(), ClipboardError> {
         // Check size limit
         if content.size() > MAX_CLIPBOARD_SIZE {
             return Err(ClipboardError::TooLarge {
@@ -136,21 +211,13 @@ impl ClipboardProvider for X11Clipboard {
             });
         }
 
-        // For now, only support text content on X11
-        if !content.is_text() {
-            return Err(ClipboardError::UnsupportedType(content.mime_type.clone()));
-        }
-
-        // Write to both clipboard and primary selection
-        self.write_selection(ClipboardSelection::Clipboard, &content.data)?;
-        self.write_selection(ClipboardSelection::Primary, &content.data)?;
-
-        Ok(())
+        // CLIPBOARD and PRIMARY are independent channels
+        self.write_selection(selection, &content.mime_type, &content.data)
     }
 
     async fn clear(&self) -> Result<(), ClipboardError> {
-        self.write_selection(ClipboardSelection::Clipboard, b"")?;
-        self.write_selection(ClipboardSelection::Primary, b"")?;
+        self.write_selection(ClipboardSelection::Clipboard, "text/plain", b"")?;
+        self.write_selection(ClipboardSelection::Primary, "text/plain", b"")?;
         Ok(())
     }
 
@@ -160,11 +227,10 @@ impl ClipboardProvider for X11Clipboard {
 
     async fn watch(&self) -> Result<ClipboardWatcher, ClipboardError> {
         let (tx, rx) = mpsc::channel(100);
-        let clipboard = Arc::clone(&self.clipboard);
 
         let handle = tokio::spawn(async move {
-            let mut last_clipboard_content: Option<Vec<u8>> = None;
-            let mut last_primary_content: Option<Vec<u8>> = None;
+            let mut last_clipboard_content: Option<(String, Vec<u8>)> = None;
+            let mut last_primary_content: Option<(String, Vec<u8>)> = None;
             let mut ticker = interval(Duration::from_millis(200));
 
             // Helper to create a temporary X11Clipboard
@@ -176,66 +242,28 @@ impl ClipboardProvider for X11Clipboard {
             loop {
                 ticker.tick().await;
 
-                // Check clipboard selection
-                if let Ok(Some(data)) = temp_clipboard.read_selection(ClipboardSelection::Clipboard)
-                {
-                    if last_clipboard_content.as_ref() != Some(&data) {
-                        last_clipboard_content = Some(data.clone());
-
-                        let mime_type = X11Clipboard::detect_content_type(&data);
-                        let content = if mime_type == "text/plain" {
-                            if let Ok(text) = String::from_utf8(data) {
-                                ClipboardContent::text(text)
-                            } else {
-                                continue;
-                            }
-                        } else {
-                            ClipboardContent {
-                                mime_type,
-                                data,
-                                timestamp: super::current_timestamp(),
-                            }
-                        };
-
-                        let event = ClipboardEvent {
-                            content,
-                            selection: Some(ClipboardSelection::Clipboard),
-                        };
-
-                        if tx.send(event).await.is_err() {
-                            break;
-                        }
+                for (selection, last_content) in [
+                    (ClipboardSelection::Clipboard, &mut last_clipboard_content),
+                    (ClipboardSelection::Primary, &mut last_primary_content),
+                ] {
+                    let content = match temp_clipboard.read_selection(selection) {
+                        Ok(Some(content)) => content,
+                        _ => continue,
+                    };
+
+                    let current = (content.mime_type.clone(), content.data.clone());
+                    if last_content.as_ref() == Some(&current) {
+                        continue;
                     }
-                }
+                    *last_content = Some(current);
+
+                    let event = ClipboardEvent {
+                        content,
+                        selection: Some(selection),
+                    };
 
-                // Check primary selection
-                if let Ok(Some(data)) = temp_clipboard.read_selection(ClipboardSelection::Primary) {
-                    if last_primary_content.as_ref() != Some(&data) {
-                        last_primary_content = Some(data.clone());
-
-                        let mime_type = X11Clipboard::detect_content_type(&data);
-                        let content = if mime_type == "text/plain" {
-                            if let Ok(text) = String::from_utf8(data) {
-                                ClipboardContent::text(text)
-                            } else {
-                                continue;
-                            }
-                        } else {
-                            ClipboardContent {
-                                mime_type,
-                                data,
-                                timestamp: super::current_timestamp(),
-                            }
-                        };
-
-                        let event = ClipboardEvent {
-                            content,
-                            selection: Some(ClipboardSelection::Primary),
-                        };
-
-                        if tx.send(event).await.is_err() {
-                            break;
-                        }
+                    if tx.send(event).await.is_err() {
+                        return;
                     }
                 }
             }
@@ -253,6 +281,10 @@ unsafe impl Sync for X11Clipboard {}
 mod tests {
     use super::*;
 
+    fn targets(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
     #[test]
     fn test_x11_clipboard_name() {
         if let Ok(clipboard) = X11Clipboard::new() {
@@ -260,6 +292,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_select_target_prefers_images_then_text() {
+        let browser_text = targets(&["TARGETS", "text/html", "UTF8_STRING", "STRING"]);
+        assert_eq!(select_target(&browser_text), Some("UTF8_STRING"));
+
+        let image = targets(&["TARGETS", "text/html", "image/png"]);
+        assert_eq!(select_target(&image), Some("image/png"));
+
+        let rich_only = targets(&["TARGETS", "text/rtf"]);
+        assert_eq!(select_target(&rich_only), Some("text/rtf"));
+
+        assert_eq!(select_target(&targets(&["TARGETS", "SAVE_TARGETS"])), None);
+
+        // KeePassXC marks the password, which is still read as text
+        let password = targets(&[
+            "TARGETS",
+            "x-kde-passwordManagerHint",
+            "UTF8_STRING",
+            "text/plain",
+        ]);
+        assert_eq!(select_target(&password), Some("UTF8_STRING"));
+        assert!(super::super::is_concealed(&password));
+        assert!(!super::super::is_concealed(&browser_text));
+    }
+
+    #[test]
+    fn test_target_mime_mapping() {
+        assert_eq!(target_mime_type("UTF8_STRING"), "text/plain");
+        assert_eq!(target_mime_type("text/plain;charset=utf-8"), "text/plain");
+        assert_eq!(target_mime_type("image/png"), "image/png");
+        assert_eq!(mime_type_target("text/plain"), "UTF8_STRING");
+        assert_eq!(mime_type_target("text/html"), "text/html");
+    }
+
     #[tokio::test]
     async fn test_x11_clipboard_text() {
         // This test will only work on systems with X11
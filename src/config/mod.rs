@@ -4,7 +4,9 @@
 //! for the ClipSync service.
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 /// Configuration errors
@@ -29,6 +31,10 @@ pub enum ConfigError {
     /// Invalid size value
     #[error("Invalid size value: {0}")]
     InvalidSize(String),
+
+    /// Invalid duration value
+    #[error("Invalid duration value: {0}")]
+    InvalidDuration(String),
 }
 
 /// Main configuration structure
@@ -61,9 +67,44 @@ pub struct Config {
     #[serde(default)]
     pub security: SecurityConfig,
 
+    /// Peer sync configuration
+    #[serde(default)]
+    pub sync: SyncConfig,
+
+    /// Sensitive content filter
+    #[serde(default)]
+    pub safety: SafetyConfig,
+
     /// Log level
     #[serde(default = "default_log_level")]
     pub log_level: String,
+
+    /// Manually configured peers, for networks where discovery can't reach
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub peers: Vec<PeerConfig>,
+}
+
+/// Manually configured peer (`[[peers]]` table)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerConfig {
+    /// Hostname or IP address of the peer
+    pub host: String,
+
+    /// Port the peer listens on
+    #[serde(default = "default_peer_port")]
+    pub port: u16,
+
+    /// Node ID of the peer, if known
+    #[serde(default)]
+    pub node_id: Option<uuid::Uuid>,
+
+    /// Expected SSH key fingerprint of the peer
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+
+    /// Sync groups the peer belongs to, as discovery would announce them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
 }
 
 /// Authentication configuration
@@ -109,6 +150,9 @@ pub struct ClipboardConfig {
     pub max_size: usize,
 
     /// Whether to sync middle-click selection on Linux
+    ///
+    /// PRIMARY is synced independently of CLIPBOARD and only to peers
+    /// that enable it too. Ignored on macOS.
     #[serde(default = "default_sync_primary")]
     pub sync_primary: bool,
 
@@ -119,6 +163,35 @@ pub struct ClipboardConfig {
     /// Path to SQLite database for history
     #[serde(default = "default_history_db")]
     pub history_db: PathBuf,
+
+    /// Additional history retention limits
+    #[serde(default)]
+    pub retention: RetentionConfig,
+}
+
+/// History retention limits (`[clipboard.retention]`)
+///
+/// `history_size` caps the number of entries; these limits apply on top.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Maximum total size of stored entries in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+
+    /// Maximum age of entries, e.g. "12h" or "30d"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<String>,
+
+    /// Maximum entries per content type, keyed by MIME type or prefix ("image/*")
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub per_type: BTreeMap<String, usize>,
+}
+
+impl RetentionConfig {
+    /// Parsed `max_age`
+    pub fn max_age(&self) -> Result<Option<Duration>, ConfigError> {
+        self.max_age.as_deref().map(parse_duration).transpose()
+    }
 }
 
 /// Hotkey configuration
@@ -153,6 +226,142 @@ pub struct SecurityConfig {
     pub compression: String,
 }
 
+/// Peer sync configuration (`[sync]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// Fetch history entries missed while a peer was disconnected
+    #[serde(default = "default_catch_up")]
+    pub catch_up: bool,
+
+    /// How far back catch-up looks, e.g. "24h"
+    #[serde(default = "default_catch_up_window")]
+    pub catch_up_window: String,
+
+    /// Maximum entries exchanged with a peer per catch-up
+    #[serde(default = "default_catch_up_max_entries")]
+    pub catch_up_max_entries: usize,
+
+    /// Maximum content bytes sent to a peer per catch-up
+    #[serde(default = "default_catch_up_max_bytes")]
+    pub catch_up_max_bytes: usize,
+
+    /// What is exchanged with particular peers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<PeerPolicyConfig>,
+
+    /// Named groups this device belongs to; empty syncs with every peer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+
+    /// Groups local copies are sent to; empty means all of `groups`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub active_groups: Vec<String>,
+}
+
+/// Sync policy for one peer (`[[sync.policies]]`)
+///
+/// See [`crate::sync::policy`] for how policies are applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerPolicyConfig {
+    /// Node ID of the peer, or its SSH key fingerprint ("SHA256:...")
+    pub peer: String,
+
+    /// Which way content flows: both (default), send_only or receive_only
+    #[serde(default = "default_policy_direction")]
+    pub direction: String,
+
+    /// MIME types or `type/*` patterns exchanged with the peer; empty
+    /// allows every format
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub formats: Vec<String>,
+
+    /// Largest payload in bytes exchanged with the peer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<usize>,
+
+    /// Record entries from the peer in local history
+    #[serde(default = "default_policy_history")]
+    pub history: bool,
+
+    /// Sync groups the peer may belong to; it is in no group not listed
+    /// here, whatever it announces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+impl SyncConfig {
+    /// Parsed `catch_up_window`
+    pub fn catch_up_window(&self) -> Result<Duration, ConfigError> {
+        parse_duration(&self.catch_up_window)
+    }
+}
+
+/// Sensitive content filter (`[safety]`)
+///
+/// See [`crate::clipboard::safety`] for how rules are applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyConfig {
+    /// Check content against the built-in rules after the configured ones
+    #[serde(default = "default_builtin_rules")]
+    pub builtin_rules: bool,
+
+    /// Built-in rules to turn off, by name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_rules: Vec<String>,
+
+    /// Additional rules, checked in order before the built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<SafetyRuleConfig>,
+}
+
+/// Sensitive content rule (`[[safety.rules]]`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SafetyRuleConfig {
+    /// Name shown when the rule matches
+    pub name: String,
+
+    /// What the rule looks for: regex, keyword, entropy, length or luhn
+    pub kind: String,
+
+    /// Let matching content through untouched instead of acting on it
+    #[serde(default)]
+    pub allow: bool,
+
+    /// What happens to matching content: skip_sync (default),
+    /// skip_history, redact or expire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+
+    /// How long content stays on the clipboard with the expire action, e.g. "30s"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+
+    /// Regular expression, for regex rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+
+    /// Words to look for regardless of case, for keyword rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+
+    /// Minimum Shannon entropy in bits per character, for entropy rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+
+    /// Minimum length in characters of a token for entropy rules, or of
+    /// the content for length rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+
+    /// Maximum length in characters, as for `min_length`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+
+    /// How many of lowercase, uppercase, digits and symbols a match must mix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_classes: Option<usize>,
+}
+
 // Default value functions
 fn default_node_id() -> uuid::Uuid {
     uuid::Uuid::new_v4()
@@ -183,6 +392,10 @@ fn default_sync_primary() -> bool {
     true
 }
 
+fn default_peer_port() -> u16 {
+    8484
+}
+
 fn default_history_size() -> usize {
     20
 }
@@ -219,6 +432,34 @@ fn default_compression() -> String {
     "zstd".to_string()
 }
 
+fn default_catch_up() -> bool {
+    true
+}
+
+fn default_catch_up_window() -> String {
+    "24h".to_string()
+}
+
+fn default_catch_up_max_entries() -> usize {
+    50
+}
+
+fn default_catch_up_max_bytes() -> usize {
+    4_194_304 // 4MB
+}
+
+fn default_policy_direction() -> String {
+    "both".to_string()
+}
+
+fn default_policy_history() -> bool {
+    true
+}
+
+fn default_builtin_rules() -> bool {
+    true
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -244,6 +485,7 @@ impl Default for ClipboardConfig {
             sync_primary: default_sync_primary(),
             history_size: default_history_size(),
             history_db: default_history_db(),
+            retention: RetentionConfig::default(),
         }
     }
 }
@@ -268,6 +510,43 @@ impl Default for SecurityConfig {
     }
 }
 
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            catch_up: default_catch_up(),
+            catch_up_window: default_catch_up_window(),
+            catch_up_max_entries: default_catch_up_max_entries(),
+            catch_up_max_bytes: default_catch_up_max_bytes(),
+            policies: Vec::new(),
+            groups: Vec::new(),
+            active_groups: Vec::new(),
+        }
+    }
+}
+
+impl Default for PeerPolicyConfig {
+    fn default() -> Self {
+        Self {
+            peer: String::new(),
+            direction: default_policy_direction(),
+            formats: Vec::new(),
+            max_size: None,
+            history: default_policy_history(),
+            groups: Vec::new(),
+        }
+    }
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            builtin_rules: default_builtin_rules(),
+            disabled_rules: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -278,7 +557,10 @@ impl Default for Config {
             clipboard: ClipboardConfig::default(),
             hotkeys: HotkeyConfig::default(),
             security: SecurityConfig::default(),
+            sync: SyncConfig::default(),
+            safety: SafetyConfig::default(),
             log_level: default_log_level(),
+            peers: Vec::new(),
         }
     }
 }
@@ -398,6 +680,41 @@ impl Config {
             ));
         }
 
+        if self.clipboard.retention.max_bytes == Some(0) {
+            return Err(ConfigError::Validation(
+                "retention.max_bytes must be greater than 0".to_string(),
+            ));
+        }
+        self.clipboard.retention.max_age()?;
+
+        self.sync.catch_up_window()?;
+        if self.sync.catch_up_max_bytes > 52_428_800 {
+            return Err(ConfigError::Validation(
+                "sync.catch_up_max_bytes must not exceed 52428800 bytes (50MB)".to_string(),
+            ));
+        }
+        crate::sync::policy::PeerPolicies::from_config(&self.sync)
+            .map_err(|e| ConfigError::Validation(e.to_string()))?;
+        crate::sync::groups::SyncGroups::from_config(&self.sync)
+            .map_err(|e| ConfigError::Validation(e.to_string()))?;
+
+        crate::clipboard::safety::SafetyFilter::from_config(&self.safety)
+            .map_err(|e| ConfigError::Validation(e.to_string()))?;
+
+        for peer in &self.peers {
+            if peer.host.trim().is_empty() {
+                return Err(ConfigError::Validation(
+                    "peers: host must not be empty".to_string(),
+                ));
+            }
+            if peer.port == 0 {
+                return Err(ConfigError::Validation(format!(
+                    "peers: port for {} must not be 0",
+                    peer.host
+                )));
+            }
+        }
+
         Ok(())
     }
 
@@ -500,6 +817,13 @@ history_size = {}
 # Path to history database
 history_db = "{}"
 
+# Extra history retention limits, applied on top of history_size
+# [clipboard.retention]
+# max_bytes = 52428800     # Total size of stored entries
+# max_age = "30d"          # Units: s, m, h, d, w
+# [clipboard.retention.per_type]
+# "image/*" = 5
+
 # Hotkey configuration
 [hotkeys]
 # Toggle sync on/off
@@ -517,8 +841,68 @@ encryption = "{}"
 # Compression for large payloads
 compression = "{}"
 
+# Peer sync settings
+[sync]
+# Fetch entries copied on a peer while this device was disconnected
+catch_up = {}
+# How far back to look when catching up (units: s, m, h, d, w)
+catch_up_window = "{}"
+# Limits per peer and catch-up
+catch_up_max_entries = {}
+catch_up_max_bytes = {}
+#
+# Per-peer policies, by node ID or SSH key fingerprint. Peers without one
+# get everything and may send everything. Directions: both (default),
+# send_only, receive_only.
+# [[sync.policies]]
+# peer = "SHA256:..."
+# direction = "send_only"
+# formats = ["text/plain", "image/*"]
+# max_size = 1048576
+# history = false          # Don't record this peer's entries locally
+# groups = ["personal"]     # Sync groups the peer may belong to
+#
+# Named sync groups. Copies go only to peers sharing an active group;
+# without groups, every peer gets everything. A peer is only in the groups
+# its policy lists, whatever it announces.
+# groups = ["personal", "pairing"]
+# active_groups = ["personal"]   # Defaults to all of groups
+
+# Sensitive content filter. Built-in rules catch private keys, API tokens,
+# card numbers and password-like strings; `clipsync safety test <text>`
+# shows which rule matches some text.
+[safety]
+builtin_rules = true
+# disabled_rules = ["high-entropy"]
+#
+# Rules are checked in order, before the built-in ones. Kinds: regex,
+# keyword, entropy, length, luhn. Actions: skip_sync (default),
+# skip_history, redact, expire (with a ttl). Allow rules let matching
+# content through.
+# [[safety.rules]]
+# name = "one-time-codes"
+# kind = "regex"
+# pattern = "^\\d{{6}}$"
+# action = "expire"
+# ttl = "30s"
+#
+# [[safety.rules]]
+# name = "build-ids"
+# kind = "regex"
+# pattern = "^build-[0-9]+$"
+# allow = true
+
 # Logging level (trace, debug, info, warn, error)
 log_level = "{}"
+
+# Manually configured peers, for networks where mDNS doesn't reach.
+# Changes are picked up by a running daemon without a restart.
+# [[peers]]
+# host = "192.168.1.50"
+# port = 8484
+# node_id = "..."            # optional
+# fingerprint = "SHA256:..." # optional, expected SSH key fingerprint
+# groups = ["personal"]      # optional, sync groups the peer belongs to
 "#,
             example.lines().next().unwrap_or(""),
             config.auth.ssh_key.display(),
@@ -533,6 +917,10 @@ log_level = "{}"
             config.hotkeys.cycle_next,
             config.security.encryption,
             config.security.compression,
+            config.sync.catch_up,
+            config.sync.catch_up_window,
+            config.sync.catch_up_max_entries,
+            config.sync.catch_up_max_bytes,
             config.log_level
         );
 
@@ -540,6 +928,29 @@ log_level = "{}"
     }
 }
 
+/// Parse a duration such as "90s", "15m", "12h", "30d" or "2w"
+pub fn parse_duration(value: &str) -> Result<Duration, ConfigError> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| ConfigError::InvalidDuration(value.to_string()))?;
+    let multiplier = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        "w" => 604_800,
+        _ => return Err(ConfigError::InvalidDuration(value.to_string())),
+    };
+
+    Ok(Duration::from_secs(number * multiplier))
+}
+
 /// Expand tilde in path
 fn expand_path(path: &Path) -> PathBuf {
     let path_str = path.to_string_lossy();
@@ -601,6 +1012,206 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_load_retention() {
+        let toml_str = r#"
+            [clipboard.retention]
+            max_bytes = 1_048_576
+            max_age = "7d"
+
+            [clipboard.retention.per_type]
+            "image/*" = 5
+        "#;
+
+        let config = Config::from_toml(toml_str).unwrap();
+        let retention = &config.clipboard.retention;
+        assert_eq!(retention.max_bytes, Some(1_048_576));
+        assert_eq!(
+            retention.max_age().unwrap(),
+            Some(Duration::from_secs(7 * 86_400))
+        );
+        assert_eq!(retention.per_type.get("image/*"), Some(&5));
+    }
+
+    #[test]
+    fn test_validation_retention_max_age() {
+        let toml_str = r#"
+            [clipboard.retention]
+            max_age = "soon"
+        "#;
+
+        let result = Config::from_toml(toml_str);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::from_secs(1_209_600));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+    }
+
+    #[test]
+    fn test_load_sync() {
+        let config = Config::from_toml(
+            r#"
+            [sync]
+            catch_up_window = "2h"
+            catch_up_max_entries = 10
+        "#,
+        )
+        .unwrap();
+        assert!(config.sync.catch_up);
+        assert_eq!(
+            config.sync.catch_up_window().unwrap(),
+            Duration::from_secs(7200)
+        );
+        assert_eq!(config.sync.catch_up_max_entries, 10);
+        assert_eq!(config.sync.catch_up_max_bytes, 4_194_304);
+
+        let invalid = Config::from_toml(
+            r#"
+            [sync]
+            catch_up_window = "a while"
+        "#,
+        );
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_load_sync_policies() {
+        let config = Config::from_toml(
+            r#"
+            [[sync.policies]]
+            peer = "SHA256:abc"
+            direction = "send_only"
+            formats = ["text/plain", "image/*"]
+            max_size = 65536
+
+            [[sync.policies]]
+            peer = "6f0c2d39-7a5b-4b8e-9a53-2f9a1d0b6c11"
+            history = false
+        "#,
+        )
+        .unwrap();
+        let policies = &config.sync.policies;
+        assert_eq!(policies.len(), 2);
+        assert_eq!(policies[0].direction, "send_only");
+        assert_eq!(policies[0].formats, vec!["text/plain", "image/*"]);
+        assert_eq!(policies[0].max_size, Some(65536));
+        assert!(policies[0].history);
+        assert_eq!(policies[1].direction, "both");
+        assert!(!policies[1].history);
+
+        for invalid in [
+            "[[sync.policies]]\npeer = \"laptop\"",
+            "[[sync.policies]]\npeer = \"SHA256:abc\"\ndirection = \"upstream\"",
+            "[[sync.policies]]\npeer = \"SHA256:abc\"\n[[sync.policies]]\npeer = \"SHA256:abc\"",
+        ] {
+            assert!(Config::from_toml(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_load_sync_groups() {
+        let config = Config::from_toml(
+            r#"
+            [sync]
+            groups = ["personal", "pairing"]
+            active_groups = ["pairing"]
+
+            [[sync.policies]]
+            peer = "SHA256:abc"
+            groups = ["pairing"]
+
+            [[peers]]
+            host = "laptop.lan"
+            groups = ["personal"]
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.sync.groups, vec!["personal", "pairing"]);
+        assert_eq!(config.sync.active_groups, vec!["pairing"]);
+        assert_eq!(config.sync.policies[0].groups, vec!["pairing"]);
+        assert_eq!(config.peers[0].groups, vec!["personal"]);
+
+        for invalid in [
+            "[sync]\ngroups = [\"my laptop\"]",
+            "[[sync.policies]]\npeer = \"SHA256:abc\"\ngroups = [\"my laptop\"]",
+            "[sync]\ngroups = [\"personal\"]\nactive_groups = [\"pairing\"]",
+        ] {
+            assert!(Config::from_toml(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_load_safety() {
+        let config = Config::from_toml(
+            r#"
+            [safety]
+            disabled_rules = ["high-entropy"]
+
+            [[safety.rules]]
+            name = "otp"
+            kind = "regex"
+            pattern = "^\\d{6}$"
+            action = "expire"
+            ttl = "30s"
+        "#,
+        )
+        .unwrap();
+        assert!(config.safety.builtin_rules);
+        assert_eq!(config.safety.disabled_rules, vec!["high-entropy"]);
+        assert_eq!(config.safety.rules[0].name, "otp");
+        assert_eq!(config.safety.rules[0].pattern.as_deref(), Some("^\\d{6}$"));
+
+        for invalid in [
+            "[safety]\ndisabled_rules = [\"no-such-rule\"]",
+            "[[safety.rules]]\nname = \"broken\"\nkind = \"regex\"\npattern = \"(\"",
+            "[[safety.rules]]\nname = \"otp\"\nkind = \"luhn\"\naction = \"expire\"",
+        ] {
+            assert!(Config::from_toml(invalid).is_err(), "{}", invalid);
+        }
+
+        // The example writes patterns escaped as TOML needs them
+        assert!(Config::generate_example().contains("pattern = \"^\\\\d{6}$\""));
+    }
+
+    #[test]
+    fn test_load_peers() {
+        let toml_str = r#"
+            [[peers]]
+            host = "10.0.0.5"
+
+            [[peers]]
+            host = "desktop.lan"
+            port = 9000
+            node_id = "6f0c2d39-7a5b-4b8e-9a53-2f9a1d0b6c11"
+            fingerprint = "SHA256:abc"
+        "#;
+
+        let config = Config::from_toml(toml_str).unwrap();
+        assert_eq!(config.peers.len(), 2);
+        assert_eq!(config.peers[0].port, 8484);
+        assert_eq!(config.peers[0].node_id, None);
+        assert_eq!(config.peers[1].port, 9000);
+        assert!(config.peers[1].node_id.is_some());
+        assert_eq!(config.peers[1].fingerprint.as_deref(), Some("SHA256:abc"));
+    }
+
+    #[test]
+    fn test_validation_peer_host() {
+        let toml_str = r#"
+            [[peers]]
+            host = ""
+        "#;
+
+        let result = Config::from_toml(toml_str);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_save_and_load() {
         let temp_dir = TempDir::new().unwrap();
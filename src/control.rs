@@ -0,0 +1,652 @@
+//! Local control socket for talking to a running daemon
+//!
+//! The daemon listens on a Unix-domain socket next to its pidfile. Each
+//! connection carries a single newline-delimited JSON request followed by a
+//! single JSON response, both stamped with [`CONTROL_PROTOCOL_VERSION`].
+
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::adapters::{
+    ClipboardData, ClipboardEntry, ClipboardProviderWrapper, HistoryManager, Peer,
+};
+use crate::daemon;
+use crate::history::SearchQuery;
+use crate::sync::TrustAwareSyncEngine;
+use crate::transport::Transfer;
+
+/// Version of the control protocol spoken over the socket
+pub const CONTROL_PROTOCOL_VERSION: u32 = 4;
+
+/// File name of the control socket, placed beside the pidfile
+pub const SOCKET_FILE_NAME: &str = "clipsync.sock";
+
+/// Upper bound for a single request or response line
+const MAX_FRAME_SIZE: u64 = (crate::MAX_PAYLOAD_SIZE as u64) * 2;
+
+/// Commands the CLI can forward to the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Report daemon status
+    Status,
+    /// Force a clipboard sync
+    Sync,
+    /// List connected peers
+    Peers,
+    /// Fetch recent history, optionally filtered by a search query
+    History {
+        limit: usize,
+        search: Option<SearchQuery>,
+    },
+    /// Place text on the daemon's clipboard, optionally clearing it from
+    /// every device after `ttl_ms` milliseconds
+    Copy {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl_ms: Option<u64>,
+    },
+    /// Unlock passphrase-protected history
+    Unlock { passphrase: String },
+    /// Lock history until the next unlock
+    Lock,
+    /// Set, change or remove (`new: None`) the history passphrase
+    SetPassphrase {
+        current: Option<String>,
+        new: Option<String>,
+    },
+    /// Re-encrypt history under a fresh key
+    Rekey { passphrase: Option<String> },
+}
+
+/// Request envelope sent by the CLI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlRequest {
+    pub version: u32,
+    pub command: ControlCommand,
+}
+
+impl ControlRequest {
+    /// Create a request for the current protocol version
+    pub fn new(command: ControlCommand) -> Self {
+        Self {
+            version: CONTROL_PROTOCOL_VERSION,
+            command,
+        }
+    }
+}
+
+/// Status snapshot reported by the daemon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub version: String,
+    pub node_id: Uuid,
+    pub pid: u32,
+    pub connected_peers: usize,
+    #[serde(default)]
+    pub history_locked: bool,
+    /// Large clipboard payloads streaming to or from peers
+    #[serde(default)]
+    pub transfers: Vec<Transfer>,
+}
+
+/// Replies produced by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "reply", content = "data", rename_all = "snake_case")]
+pub enum ControlReply {
+    Status(DaemonStatus),
+    Synced,
+    Peers(Vec<Peer>),
+    History(Vec<ClipboardEntry>),
+    Copied,
+    Unlocked,
+    Locked,
+    PassphraseChanged,
+    /// Number of re-encrypted entries
+    Rekeyed(usize),
+    Error {
+        message: String,
+    },
+}
+
+/// Response envelope sent by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub version: u32,
+    pub reply: ControlReply,
+}
+
+impl ControlResponse {
+    fn new(reply: ControlReply) -> Self {
+        Self {
+            version: CONTROL_PROTOCOL_VERSION,
+            reply,
+        }
+    }
+}
+
+/// Executes control commands inside the daemon
+#[async_trait]
+pub trait ControlHandler: Send + Sync {
+    async fn handle(&self, command: ControlCommand) -> Result<ControlReply>;
+}
+
+/// Control handler backed by the live sync engine
+pub struct DaemonControl {
+    sync_engine: Arc<TrustAwareSyncEngine>,
+    history: Arc<HistoryManager>,
+    clipboard: Arc<ClipboardProviderWrapper>,
+    node_id: Uuid,
+}
+
+impl DaemonControl {
+    pub fn new(
+        sync_engine: Arc<TrustAwareSyncEngine>,
+        history: Arc<HistoryManager>,
+        clipboard: Arc<ClipboardProviderWrapper>,
+        node_id: Uuid,
+    ) -> Self {
+        Self {
+            sync_engine,
+            history,
+            clipboard,
+            node_id,
+        }
+    }
+}
+
+#[async_trait]
+impl ControlHandler for DaemonControl {
+    async fn handle(&self, command: ControlCommand) -> Result<ControlReply> {
+        match command {
+            ControlCommand::Status => Ok(ControlReply::Status(DaemonStatus {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                node_id: self.node_id,
+                pid: std::process::id(),
+                connected_peers: self.sync_engine.get_connected_peers().await.len(),
+                history_locked: self.history.is_locked().await,
+                transfers: self.sync_engine.transfers().await,
+            })),
+            ControlCommand::Sync => {
+                self.sync_engine.force_sync().await?;
+                Ok(ControlReply::Synced)
+            }
+            ControlCommand::Peers => Ok(ControlReply::Peers(
+                self.sync_engine.get_connected_peers().await,
+            )),
+            ControlCommand::History { limit, search } => {
+                let entries = match search {
+                    Some(query) => self.history.search(&query, limit).await?,
+                    None => self.history.get_recent_entries(limit).await?,
+                };
+                Ok(ControlReply::History(entries))
+            }
+            ControlCommand::Copy { text, ttl_ms } => {
+                match ttl_ms {
+                    Some(ttl_ms) => {
+                        self.sync_engine
+                            .copy_with_ttl(
+                                &ClipboardData::Text(text),
+                                std::time::Duration::from_millis(ttl_ms),
+                            )
+                            .await?
+                    }
+                    None => self.clipboard.set_text(&text).await?,
+                }
+                Ok(ControlReply::Copied)
+            }
+            ControlCommand::Unlock { passphrase } => {
+                self.history.unlock(&passphrase).await?;
+                Ok(ControlReply::Unlocked)
+            }
+            ControlCommand::Lock => {
+                self.history.lock().await?;
+                Ok(ControlReply::Locked)
+            }
+            ControlCommand::SetPassphrase { current, new } => {
+                self.history
+                    .set_passphrase(current.as_deref(), new.as_deref())
+                    .await?;
+                Ok(ControlReply::PassphraseChanged)
+            }
+            ControlCommand::Rekey { passphrase } => Ok(ControlReply::Rekeyed(
+                self.history.rekey(passphrase.as_deref()).await?,
+            )),
+        }
+    }
+}
+
+/// Get the path of the control socket
+pub fn get_socket_path() -> Result<PathBuf> {
+    Ok(daemon::get_pidfile_path()?.with_file_name(SOCKET_FILE_NAME))
+}
+
+/// Remove the control socket if it exists
+pub fn remove_socket() -> Result<()> {
+    let socket_path = get_socket_path()?;
+
+    if socket_path.exists() {
+        fs::remove_file(&socket_path)
+            .with_context(|| format!("Failed to remove control socket: {:?}", socket_path))?;
+        info!("Removed control socket: {:?}", socket_path);
+    }
+
+    Ok(())
+}
+
+/// Listening side of the control socket
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Bind the control socket at `path`, replacing any stale socket file
+    pub fn bind(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create control socket directory")?;
+        }
+
+        if path.exists() {
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale control socket: {:?}", path))?;
+        }
+
+        // Bind inside a directory only we can enter, then move the socket
+        // into place, so it never exists with looser permissions
+        let staging = private_dir(path)?;
+        let staged = staging.join(SOCKET_FILE_NAME);
+        let bound = UnixListener::bind(&staged)
+            .with_context(|| format!("Failed to bind control socket: {:?}", path))
+            .and_then(|listener| {
+                // Only the owning user may talk to the daemon
+                fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))?;
+                fs::rename(&staged, path)
+                    .with_context(|| format!("Failed to move control socket to {:?}", path))?;
+                Ok(listener)
+            });
+        let _ = fs::remove_dir_all(&staging);
+        let listener = bound?;
+
+        info!("Control socket listening on {:?}", path);
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Path the server is bound to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accept connections until the listener fails
+    pub async fn serve(self, handler: Arc<dyn ControlHandler>) -> Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let handler = Arc::clone(&handler);
+
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, handler).await {
+                    warn!("Control connection failed: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Empty directory next to `path` that only this user can enter
+fn private_dir(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid control socket path: {:?}", path))?;
+    let dir = path.with_file_name(format!(
+        ".{}.{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    // Left over from a daemon with the same PID that did not clean up
+    if dir.exists() {
+        fs::remove_dir_all(&dir)
+            .with_context(|| format!("Failed to remove stale directory: {:?}", dir))?;
+    }
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("Failed to create control socket directory: {:?}", dir))?;
+
+    Ok(dir)
+}
+
+async fn handle_connection(stream: UnixStream, handler: Arc<dyn ControlHandler>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let line = read_frame(reader).await?;
+
+    let reply = match serde_json::from_str::<ControlRequest>(&line) {
+        Ok(request) if request.version != CONTROL_PROTOCOL_VERSION => ControlReply::Error {
+            message: format!(
+                "Unsupported control protocol version {} (daemon speaks {})",
+                request.version, CONTROL_PROTOCOL_VERSION
+            ),
+        },
+        Ok(request) => {
+            debug!("Control request: {:?}", request.command);
+            handler
+                .handle(request.command)
+                .await
+                .unwrap_or_else(|e| ControlReply::Error {
+                    message: e.to_string(),
+                })
+        }
+        Err(e) => ControlReply::Error {
+            message: format!("Malformed control request: {}", e),
+        },
+    };
+
+    let mut response = serde_json::to_vec(&ControlResponse::new(reply))?;
+    response.push(b'\n');
+    writer.write_all(&response).await?;
+    writer.shutdown().await?;
+
+    Ok(())
+}
+
+async fn read_frame<R: tokio::io::AsyncRead + Unpin>(reader: R) -> Result<String> {
+    let mut reader = BufReader::new(reader.take(MAX_FRAME_SIZE));
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+
+    if line.is_empty() {
+        return Err(anyhow!(
+            "Control connection closed before a message was received"
+        ));
+    }
+
+    Ok(line)
+}
+
+/// Send a command to the daemon listening on `path`
+pub async fn send_request(path: &Path, command: ControlCommand) -> Result<ControlReply> {
+    let stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("Failed to connect to control socket: {:?}", path))?;
+    exchange(stream, command).await
+}
+
+/// Send a command over an open control connection and read the reply
+async fn exchange(stream: UnixStream, command: ControlCommand) -> Result<ControlReply> {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut request = serde_json::to_vec(&ControlRequest::new(command))?;
+    request.push(b'\n');
+    writer.write_all(&request).await?;
+
+    let line = read_frame(reader).await?;
+    let response: ControlResponse = serde_json::from_str(&line)?;
+
+    if response.version != CONTROL_PROTOCOL_VERSION {
+        return Err(anyhow!(
+            "Daemon speaks control protocol version {}, expected {}",
+            response.version,
+            CONTROL_PROTOCOL_VERSION
+        ));
+    }
+
+    match response.reply {
+        ControlReply::Error { message } => Err(anyhow!("Daemon error: {}", message)),
+        reply => Ok(reply),
+    }
+}
+
+/// Send a command to the running daemon
+///
+/// Returns `Ok(None)` when no daemon is listening on the control socket.
+pub async fn request(command: ControlCommand) -> Result<Option<ControlReply>> {
+    let socket_path = get_socket_path()?;
+
+    let stream = match UnixStream::connect(&socket_path).await {
+        Ok(stream) => stream,
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            debug!("No daemon on control socket {:?}: {}", socket_path, e);
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to connect to control socket: {:?}", socket_path))
+        }
+    };
+
+    exchange(stream, command).await.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    struct StubHandler;
+
+    #[async_trait]
+    impl ControlHandler for StubHandler {
+        async fn handle(&self, command: ControlCommand) -> Result<ControlReply> {
+            match command {
+                ControlCommand::Sync => Ok(ControlReply::Synced),
+                ControlCommand::Peers => Ok(ControlReply::Peers(vec![Peer {
+                    id: Uuid::nil(),
+                    hostname: "laptop".to_string(),
+                    address: "127.0.0.1:8484".to_string(),
+                    capabilities: Vec::new(),
+                    groups: Vec::new(),
+                }])),
+                ControlCommand::History { limit, .. } => Ok(ControlReply::History(
+                    (0..limit)
+                        .map(|i| ClipboardEntry {
+                            id: Uuid::new_v4(),
+                            content: ClipboardData::Text(format!("entry {}", i)),
+                            timestamp: Utc::now(),
+                            source: Uuid::nil(),
+                            checksum: String::new(),
+                            selection: Default::default(),
+                            pinned: false,
+                            hlc: Default::default(),
+                            expires_at: None,
+                        })
+                        .collect(),
+                )),
+                ControlCommand::Copy { .. } => Err(anyhow!("clipboard unavailable")),
+                ControlCommand::Unlock { passphrase } if passphrase == "secret" => {
+                    Ok(ControlReply::Unlocked)
+                }
+                ControlCommand::Unlock { .. } => Err(anyhow!("Incorrect history passphrase")),
+                ControlCommand::Lock => Ok(ControlReply::Locked),
+                ControlCommand::SetPassphrase { .. } => Ok(ControlReply::PassphraseChanged),
+                ControlCommand::Rekey { .. } => Ok(ControlReply::Rekeyed(0)),
+                ControlCommand::Status => Ok(ControlReply::Status(DaemonStatus {
+                    version: "test".to_string(),
+                    node_id: Uuid::nil(),
+                    pid: 1,
+                    connected_peers: 0,
+                    history_locked: true,
+                    transfers: Vec::new(),
+                })),
+            }
+        }
+    }
+
+    fn start_server(dir: &TempDir) -> PathBuf {
+        let path = dir.path().join(SOCKET_FILE_NAME);
+        let server = ControlServer::bind(&path).unwrap();
+        tokio::spawn(server.serve(Arc::new(StubHandler)));
+        path
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = ControlRequest::new(ControlCommand::History {
+            limit: 5,
+            search: Some(SearchQuery::new("foo")),
+        });
+
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("\"version\":4"));
+        assert!(json.contains("\"command\":\"history\""));
+
+        let decoded: ControlRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, request);
+
+        // A copy carries its time to live in milliseconds
+        let copy = ControlCommand::Copy {
+            text: "482913".to_string(),
+            ttl_ms: Some(30_000),
+        };
+        let json = serde_json::to_string(&copy).unwrap();
+        assert!(json.contains("\"ttl_ms\":30000"));
+        assert_eq!(serde_json::from_str::<ControlCommand>(&json).unwrap(), copy);
+
+        // One without a time to live leaves the field out
+        let copy = ControlCommand::Copy {
+            text: "hi".to_string(),
+            ttl_ms: None,
+        };
+        let json = serde_json::to_string(&copy).unwrap();
+        assert!(!json.contains("ttl_ms"));
+        assert_eq!(
+            serde_json::from_str::<ControlCommand>(r#"{"command":"copy","text":"hi"}"#).unwrap(),
+            ControlCommand::Copy {
+                text: "hi".to_string(),
+                ttl_ms: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_answers_requests() {
+        let dir = TempDir::new().unwrap();
+        let path = start_server(&dir);
+
+        let reply = send_request(&path, ControlCommand::Sync).await.unwrap();
+        assert!(matches!(reply, ControlReply::Synced));
+
+        match send_request(&path, ControlCommand::Peers).await.unwrap() {
+            ControlReply::Peers(peers) => assert_eq!(peers[0].hostname, "laptop"),
+            other => panic!("unexpected reply: {:?}", other),
+        }
+
+        match send_request(
+            &path,
+            ControlCommand::History {
+                limit: 3,
+                search: None,
+            },
+        )
+        .await
+        .unwrap()
+        {
+            ControlReply::History(entries) => assert_eq!(entries.len(), 3),
+            other => panic!("unexpected reply: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_errors_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let path = start_server(&dir);
+
+        let err = send_request(
+            &path,
+            ControlCommand::Copy {
+                text: "hello".to_string(),
+                ttl_ms: None,
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("clipboard unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_unlock_over_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = start_server(&dir);
+
+        let reply = send_request(
+            &path,
+            ControlCommand::Unlock {
+                passphrase: "secret".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(reply, ControlReply::Unlocked));
+
+        let err = send_request(
+            &path,
+            ControlCommand::Unlock {
+                passphrase: "guess".to_string(),
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Incorrect history passphrase"));
+
+        match send_request(&path, ControlCommand::Status).await.unwrap() {
+            ControlReply::Status(status) => assert!(status.history_locked),
+            other => panic!("unexpected reply: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_version_mismatch_is_rejected() {
+        let dir = TempDir::new().unwrap();
+        let path = start_server(&dir);
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"{\"version\":99,\"command\":{\"command\":\"status\"}}\n")
+            .await
+            .unwrap();
+
+        let line = read_frame(stream).await.unwrap();
+        let response: ControlResponse = serde_json::from_str(&line).unwrap();
+        match response.reply {
+            ControlReply::Error { message } => assert!(message.contains("version 99")),
+            other => panic!("unexpected reply: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bind_replaces_stale_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(SOCKET_FILE_NAME);
+        fs::write(&path, b"stale").unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = ControlServer::bind(&path).unwrap();
+            assert_eq!(server.path(), path.as_path());
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            // Nothing is left of the directory it was bound in
+            let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+            assert_eq!(entries.len(), 1);
+        });
+    }
+}
@@ -1,5 +1,6 @@
 //! Fallback discovery mechanisms for when mDNS is unavailable
 
+use crate::config::PeerConfig;
 use crate::discovery::{
     types::{DiscoveryMethod, PeerMetadata},
     Discovery, DiscoveryEvent, PeerInfo, PeerManager, ServiceInfo,
@@ -29,11 +30,30 @@ pub struct FallbackDiscovery {
 }
 
 /// Manually configured peer
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 struct ManualPeer {
-    id: Option<Uuid>,
+    id: Uuid,
     address: String,
     port: u16,
+    fingerprint: Option<String>,
+    groups: Vec<String>,
+}
+
+impl ManualPeer {
+    fn from_config(peer: &PeerConfig) -> Self {
+        Self {
+            // Without a configured node ID, derive a stable one from the
+            // address so reloads update the same peer entry
+            id: peer.node_id.unwrap_or_else(|| {
+                let digest = md5::compute(format!("{}:{}", peer.host, peer.port));
+                uuid::Builder::from_md5_bytes(digest.0).into_uuid()
+            }),
+            address: peer.host.clone(),
+            port: peer.port,
+            fingerprint: peer.fingerprint.clone(),
+            groups: peer.groups.clone(),
+        }
+    }
 }
 
 impl FallbackDiscovery {
@@ -53,10 +73,29 @@ impl FallbackDiscovery {
     }
 
     /// Parse manual peers from configuration
-    fn parse_manual_peers(_config: &Config) -> Result<Vec<ManualPeer>> {
-        // For now, return empty list as Config implementation details aren't finalized
-        // This would be implemented based on the actual config structure
-        Ok(Vec::new())
+    fn parse_manual_peers(config: &Config) -> Result<Vec<ManualPeer>> {
+        Ok(config.peers.iter().map(ManualPeer::from_config).collect())
+    }
+
+    /// Replace the manual peer list, e.g. after the config file changed
+    ///
+    /// Peers no longer listed are dropped from the peer manager and the
+    /// remaining ones are (re)connected.
+    pub async fn update_manual_peers(&self, peers: &[PeerConfig]) -> Result<()> {
+        let updated: Vec<ManualPeer> = peers.iter().map(ManualPeer::from_config).collect();
+
+        {
+            let mut current = self.manual_peers.write().await;
+            for old in current.iter() {
+                if !updated.iter().any(|p| p.id == old.id) {
+                    tracing::info!("Removing manual peer {}:{}", old.address, old.port);
+                    self.peer_manager.remove_peer(old.id).await?;
+                }
+            }
+            *current = updated;
+        }
+
+        self.connect_manual_peers().await
     }
 
     /// Start broadcast discovery
@@ -283,17 +322,18 @@ impl FallbackDiscovery {
 
         // Create peer info
         let peer_info = PeerInfo {
-            id: manual_peer.id.unwrap_or_else(Uuid::new_v4),
+            id: manual_peer.id,
             name: manual_peer.address.clone(),
             addresses: addrs,
             port: manual_peer.port,
             version: "unknown".to_string(),
             platform: "unknown".to_string(),
             metadata: PeerMetadata {
-                ssh_fingerprint: None,
+                ssh_fingerprint: manual_peer.fingerprint.clone(),
                 ssh_public_key: None,
                 capabilities: vec![],
                 device_name: Some(manual_peer.address.clone()),
+                groups: manual_peer.groups.clone(),
             },
             last_seen: chrono::Utc::now().timestamp(),
         };
@@ -382,4 +422,68 @@ mod tests {
         let discovery = FallbackDiscovery::new(peer_manager, &config);
         assert!(discovery.is_ok());
     }
+
+    fn manual_peer_config(host: &str, port: u16) -> PeerConfig {
+        PeerConfig {
+            host: host.to_string(),
+            port,
+            node_id: None,
+            fingerprint: Some("SHA256:test".to_string()),
+            groups: vec!["personal".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_manual_peers_from_config() {
+        let mut config = Config::default();
+        config.peers.push(manual_peer_config("127.0.0.1", 9000));
+
+        let peer_manager = PeerManager::new();
+        let mut discovery = FallbackDiscovery::new(peer_manager.clone(), &config).unwrap();
+        discovery.connect_manual_peers().await.unwrap();
+
+        let peers = discovery.discover_peers().await.unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].port, 9000);
+        assert_eq!(
+            peers[0].metadata.ssh_fingerprint.as_deref(),
+            Some("SHA256:test")
+        );
+        assert_eq!(peers[0].metadata.groups, vec!["personal"]);
+    }
+
+    #[tokio::test]
+    async fn test_update_manual_peers() {
+        let mut config = Config::default();
+        config.peers.push(manual_peer_config("127.0.0.1", 9000));
+
+        let peer_manager = PeerManager::new();
+        let discovery = FallbackDiscovery::new(peer_manager.clone(), &config).unwrap();
+        discovery.connect_manual_peers().await.unwrap();
+        let first_id = peer_manager.get_all_peers().await.unwrap()[0].id;
+
+        // Same address keeps its ID, the new one is added
+        discovery
+            .update_manual_peers(&[
+                manual_peer_config("127.0.0.1", 9000),
+                manual_peer_config("127.0.0.1", 9001),
+            ])
+            .await
+            .unwrap();
+        let peers = peer_manager
+            .get_peers_by_method(DiscoveryMethod::Manual)
+            .await
+            .unwrap();
+        assert_eq!(peers.len(), 2);
+        assert!(peers.iter().any(|p| p.id == first_id));
+
+        // Dropping a peer from the config removes it
+        discovery
+            .update_manual_peers(&[manual_peer_config("127.0.0.1", 9001)])
+            .await
+            .unwrap();
+        let peers = peer_manager.get_all_peers().await.unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].port, 9001);
+    }
 }
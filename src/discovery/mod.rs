@@ -17,7 +17,9 @@ pub use fallback::FallbackDiscovery;
 pub use mdns::MdnsDiscovery;
 pub use peers::PeerManager;
 pub use trust_integration::TrustAwareDiscovery;
-pub use types::{DiscoveryEvent, DiscoveryMethod, PeerInfo, PeerMetadata, ServiceInfo};
+pub use types::{
+    DiscoveryEvent, DiscoveryMethod, PeerInfo, PeerMetadata, ServiceInfo, CAP_PRIMARY_SELECTION,
+};
 
 /// Trait for service discovery implementations
 #[async_trait]
@@ -65,20 +67,26 @@ impl DiscoveryService {
     pub fn peer_manager(&self) -> &PeerManager {
         &self.peer_manager
     }
+
+    /// Apply a new set of manually configured peers
+    pub async fn update_manual_peers(&self, peers: &[crate::config::PeerConfig]) -> Result<()> {
+        self.fallback.update_manual_peers(peers).await
+    }
 }
 
 #[async_trait]
 impl Discovery for DiscoveryService {
     async fn start(&mut self) -> Result<()> {
+        // Subscribe to events from peer manager first so manual peers
+        // added during startup aren't missed
+        self.event_rx = Some(self.peer_manager.subscribe());
+
         // Start mDNS discovery
         self.mdns.start().await?;
 
         // Start fallback discovery
         self.fallback.start().await?;
 
-        // Subscribe to events from peer manager
-        self.event_rx = Some(self.peer_manager.subscribe());
-
         Ok(())
     }
 
@@ -265,6 +265,7 @@ mod tests {
                 ssh_public_key: None,
                 capabilities: vec![],
                 device_name: None,
+                groups: vec![],
             },
             last_seen: Utc::now().timestamp(),
         }
@@ -93,6 +93,27 @@ mod discovery_tests {
         assert!(txt_map.contains_key("platform"));
     }
 
+    #[test]
+    fn test_groups_in_txt_records() {
+        let groups = vec!["personal".to_string(), "pairing".to_string()];
+        let info = ServiceInfo::default().with_groups(&groups);
+        assert!(info
+            .txt_data
+            .contains(&("groups".to_string(), "personal,pairing".to_string())));
+
+        let peer = PeerInfo::from_mdns("laptop".to_string(), vec![], 8484, &info.txt_data);
+        assert_eq!(peer.metadata.groups, groups);
+
+        // Peers that predate groups announce none
+        let peer = PeerInfo::from_mdns(
+            "old".to_string(),
+            vec![],
+            8484,
+            &ServiceInfo::default().txt_data,
+        );
+        assert!(peer.metadata.groups.is_empty());
+    }
+
     #[tokio::test]
     async fn test_discovery_service_creation() {
         let config = Config::default();
@@ -4,6 +4,11 @@ use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, SocketAddr};
 use uuid::Uuid;
 
+use crate::sync::groups::{self, GROUPS_TXT_KEY};
+
+/// Capability advertised by peers that sync the PRIMARY selection
+pub const CAP_PRIMARY_SELECTION: &str = "primary-selection";
+
 /// Information about a discovered peer
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct PeerInfo {
@@ -36,6 +41,9 @@ pub struct PeerMetadata {
     pub capabilities: Vec<String>,
     /// User-defined device name
     pub device_name: Option<String>,
+    /// Sync groups the peer belongs to
+    #[serde(default)]
+    pub groups: Vec<String>,
 }
 
 /// Service information for announcement
@@ -115,6 +123,13 @@ impl ServiceInfo {
             .push(("caps".to_string(), capabilities.join(",")));
         self
     }
+
+    /// Add sync group membership to TXT data
+    pub fn with_groups(mut self, groups: &[String]) -> Self {
+        self.txt_data
+            .push((GROUPS_TXT_KEY.to_string(), groups.join(",")));
+        self
+    }
 }
 
 impl PeerInfo {
@@ -164,6 +179,12 @@ impl PeerInfo {
             .find(|(k, _)| k == "device")
             .map(|(_, v)| v.clone());
 
+        let groups = txt_data
+            .iter()
+            .find(|(k, _)| k == GROUPS_TXT_KEY)
+            .map(|(_, v)| groups::parse_txt(v))
+            .unwrap_or_default();
+
         Self {
             id,
             name,
@@ -176,6 +197,7 @@ impl PeerInfo {
                 ssh_public_key,
                 capabilities,
                 device_name,
+                groups,
             },
             last_seen: chrono::Utc::now().timestamp(),
         }
@@ -0,0 +1,143 @@
+//! Passphrase-encrypted history archives for backup and transfer
+//!
+//! An archive is a small binary header followed by the AES-256-GCM
+//! encrypted JSON list of entries:
+//!
+//! ```text
+//! magic (8) | version (1) | salt (16) | compressed (1) | nonce (12) | ciphertext
+//! ```
+//!
+//! The key is derived from the passphrase and salt with Argon2id, so an
+//! archive can be opened on any machine without the local history key.
+
+use crate::history::{
+    encryption::{EncryptedData, Encryptor, SALT_SIZE},
+    HistoryEntry,
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"CLIPHIST";
+const ARCHIVE_VERSION: u8 = 1;
+const NONCE_SIZE: usize = 12;
+const HEADER_SIZE: usize = ARCHIVE_MAGIC.len() + 1 + SALT_SIZE + 1 + NONCE_SIZE;
+
+#[derive(Serialize, Deserialize)]
+struct ArchivePayload {
+    /// Unix timestamp of the export
+    exported_at: i64,
+    entries: Vec<HistoryEntry>,
+}
+
+/// Encrypt entries into an archive under the given passphrase
+pub async fn seal(entries: &[HistoryEntry], passphrase: &str) -> Result<Vec<u8>> {
+    let salt = Encryptor::generate_salt();
+    let key = Encryptor::derive_from_password(passphrase, &salt).await?;
+    let encryptor = Encryptor::from_key(key)?;
+
+    let payload = serde_json::to_vec(&ArchivePayload {
+        exported_at: chrono::Utc::now().timestamp(),
+        entries: entries.to_vec(),
+    })?;
+    let encrypted = encryptor.encrypt(&payload)?;
+
+    let mut archive = Vec::with_capacity(HEADER_SIZE + encrypted.ciphertext.len());
+    archive.extend_from_slice(ARCHIVE_MAGIC);
+    archive.push(ARCHIVE_VERSION);
+    archive.extend_from_slice(&salt);
+    archive.push(encrypted.compressed as u8);
+    archive.extend_from_slice(&encrypted.nonce);
+    archive.extend_from_slice(&encrypted.ciphertext);
+
+    Ok(archive)
+}
+
+/// Decrypt an archive created by [`seal`]
+pub async fn open(archive: &[u8], passphrase: &str) -> Result<Vec<HistoryEntry>> {
+    if archive.len() < HEADER_SIZE || &archive[..ARCHIVE_MAGIC.len()] != ARCHIVE_MAGIC {
+        return Err(anyhow!("Not a ClipSync history archive"));
+    }
+
+    let (header, ciphertext) = archive.split_at(HEADER_SIZE);
+    let version = header[ARCHIVE_MAGIC.len()];
+    if version != ARCHIVE_VERSION {
+        return Err(anyhow!("Unsupported history archive version {}", version));
+    }
+
+    let salt_start = ARCHIVE_MAGIC.len() + 1;
+    let salt = &header[salt_start..salt_start + SALT_SIZE];
+    let compressed = header[salt_start + SALT_SIZE] != 0;
+    let nonce = &header[salt_start + SALT_SIZE + 1..];
+
+    let key = Encryptor::derive_from_password(passphrase, salt).await?;
+    let encryptor = Encryptor::from_key(key)?;
+
+    let payload = encryptor
+        .decrypt(&EncryptedData {
+            ciphertext: ciphertext.to_vec(),
+            nonce: nonce.to_vec(),
+            compressed,
+        })
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted history archive"))?;
+
+    let payload: ArchivePayload = serde_json::from_slice(&payload)?;
+    Ok(payload.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::ClipboardSelection;
+    use uuid::Uuid;
+
+    fn entry(text: &str, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            id: Uuid::new_v4(),
+            content: text.as_bytes().to_vec(),
+            content_type: "text/plain".to_string(),
+            content_size: text.len() as u64,
+            timestamp,
+            origin_node: Uuid::new_v4(),
+            checksum: Encryptor::compute_checksum(text.as_bytes()),
+            selection: ClipboardSelection::Primary,
+            pinned: true,
+            expires_at: None,
+            hlc: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_archive_roundtrip() {
+        let entries = vec![entry("first", 1_000), entry("second", 2_000)];
+
+        let archive = seal(&entries, "correct horse").await.unwrap();
+        assert!(!archive
+            .windows(b"text/plain".len())
+            .any(|w| w == b"text/plain"));
+
+        let opened = open(&archive, "correct horse").await.unwrap();
+        assert_eq!(opened.len(), 2);
+        for (original, restored) in entries.iter().zip(&opened) {
+            assert_eq!(original.id, restored.id);
+            assert_eq!(original.content, restored.content);
+            assert_eq!(original.timestamp, restored.timestamp);
+            assert_eq!(original.origin_node, restored.origin_node);
+            assert_eq!(original.checksum, restored.checksum);
+            assert_eq!(original.selection, restored.selection);
+            assert!(restored.pinned);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_archive_rejects_bad_input() {
+        let archive = seal(&[entry("secret", 1_000)], "right").await.unwrap();
+
+        assert!(open(&archive, "wrong").await.is_err());
+        assert!(open(b"not an archive", "right").await.is_err());
+
+        let mut tampered = archive.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0xff;
+        assert!(open(&tampered, "right").await.is_err());
+    }
+}
@@ -1,26 +1,41 @@
 //! SQLite database implementation for clipboard history
 
+use crate::clipboard::ClipboardSelection;
 use crate::history::{
     encryption::{EncryptedData, Encryptor},
-    ClipboardContent, HistoryEntry,
+    retention::{mime_like_pattern, RetentionPolicy},
+    search::{self, SearchQuery},
+    ClipboardContent, EntrySummary, HistoryEntry,
 };
+use crate::sync::HybridTimestamp;
 use anyhow::{anyhow, Result};
-use rusqlite::{params, Connection, OptionalExtension, Row};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Row};
 use std::path::Path;
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
-const SCHEMA_VERSION: u32 = 1;
-const HISTORY_LIMIT: usize = 20;
+const SCHEMA_VERSION: u32 = 7;
+
+/// Columns read by `row_to_entry`, in order
+const ENTRY_COLUMNS: &str = "uuid, content, content_type, content_size, checksum, timestamp, origin_node, iv, compressed, selection, pinned, expires_at, hlc_wall_ms, hlc_logical, hlc_node";
+
+/// `search_indexed` values
+const INDEX_PENDING: i32 = 0;
+const INDEX_DONE: i32 = 1;
+/// Entry is too large to index and is always scanned
+const INDEX_SKIPPED: i32 = 2;
 
 /// SQLite database wrapper for clipboard history storage
 pub struct HistoryDatabase {
     conn: Mutex<Connection>,
+    retention: RetentionPolicy,
 }
 
 impl HistoryDatabase {
-    /// Create new database instance with encryption key
-    pub async fn new(path: &Path, _key: &[u8; 32]) -> Result<Self> {
+    /// Open or create the database
+    ///
+    /// Entries are encrypted individually, so the database itself needs no key.
+    pub async fn new(path: &Path) -> Result<Self> {
         // Create directory if needed
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -38,12 +53,19 @@ impl HistoryDatabase {
 
         let db = Self {
             conn: Mutex::new(conn),
+            retention: RetentionPolicy::default(),
         };
 
         db.initialize().await?;
         Ok(db)
     }
 
+    /// Use the given retention policy for pruning
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+
     async fn initialize(&self) -> Result<()> {
         let conn = self.conn.lock().await;
 
@@ -102,23 +124,30 @@ impl HistoryDatabase {
                 origin_node TEXT NOT NULL,
                 iv BLOB NOT NULL,
                 compressed INTEGER NOT NULL DEFAULT 0,
+                selection TEXT NOT NULL DEFAULT 'clipboard',
+                pinned INTEGER NOT NULL DEFAULT 0,
+                search_indexed INTEGER NOT NULL DEFAULT 0,
+                expires_at INTEGER,
+                hlc_wall_ms INTEGER,
+                hlc_logical INTEGER,
+                hlc_node TEXT,
                 created_at INTEGER DEFAULT (strftime('%s', 'now'))
             );
 
             CREATE INDEX idx_timestamp ON clipboard_history(timestamp DESC);
             CREATE INDEX idx_content_type ON clipboard_history(content_type);
+            CREATE INDEX idx_checksum ON clipboard_history(checksum);
+            CREATE INDEX idx_expires_at ON clipboard_history(expires_at)
+                WHERE expires_at IS NOT NULL;
 
-            -- Trigger to maintain history limit
-            CREATE TRIGGER limit_history_size
-            AFTER INSERT ON clipboard_history
-            BEGIN
-                DELETE FROM clipboard_history
-                WHERE id IN (
-                    SELECT id FROM clipboard_history
-                    ORDER BY timestamp DESC
-                    LIMIT -1 OFFSET 20
-                );
-            END;
+            -- Keyed hashes of the words in each text entry
+            CREATE TABLE IF NOT EXISTS search_tokens (
+                token BLOB NOT NULL,
+                entry_id INTEGER NOT NULL REFERENCES clipboard_history(id) ON DELETE CASCADE,
+                PRIMARY KEY (token, entry_id)
+            ) WITHOUT ROWID;
+
+            CREATE INDEX idx_search_tokens_entry ON search_tokens(entry_id);
             ",
         )?;
 
@@ -130,22 +159,241 @@ impl HistoryDatabase {
         Ok(())
     }
 
-    fn migrate_schema(&self, _conn: &Connection, _from_version: u32) -> Result<()> {
-        // Future migrations would go here
+    fn migrate_schema(&self, conn: &Connection, from_version: u32) -> Result<()> {
+        if from_version < 2 {
+            // Version 2: tag entries with the selection they came from
+            conn.execute_batch(
+                "
+                BEGIN;
+                ALTER TABLE clipboard_history
+                    ADD COLUMN selection TEXT NOT NULL DEFAULT 'clipboard';
+                INSERT INTO schema_version (version) VALUES (2);
+                COMMIT;
+                ",
+            )?;
+        }
+
+        if from_version < 3 {
+            // Version 3: retention is applied by `prune` from the configured
+            // policy instead of a fixed-size trigger
+            conn.execute_batch(
+                "
+                BEGIN;
+                DROP TRIGGER IF EXISTS limit_history_size;
+                INSERT INTO schema_version (version) VALUES (3);
+                COMMIT;
+                ",
+            )?;
+        }
+
+        if from_version < 4 {
+            // Version 4: pinned entries are exempt from retention
+            conn.execute_batch(
+                "
+                BEGIN;
+                ALTER TABLE clipboard_history
+                    ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+                INSERT INTO schema_version (version) VALUES (4);
+                COMMIT;
+                ",
+            )?;
+        }
+
+        if from_version < 5 {
+            // Version 5: keyed-hash search index and checksum lookups.
+            // Existing entries are indexed by `reindex` once a key is available.
+            conn.execute_batch(
+                "
+                BEGIN;
+                ALTER TABLE clipboard_history
+                    ADD COLUMN search_indexed INTEGER NOT NULL DEFAULT 0;
+                CREATE INDEX idx_checksum ON clipboard_history(checksum);
+                CREATE TABLE search_tokens (
+                    token BLOB NOT NULL,
+                    entry_id INTEGER NOT NULL REFERENCES clipboard_history(id) ON DELETE CASCADE,
+                    PRIMARY KEY (token, entry_id)
+                ) WITHOUT ROWID;
+                CREATE INDEX idx_search_tokens_entry ON search_tokens(entry_id);
+                INSERT INTO schema_version (version) VALUES (5);
+                COMMIT;
+                ",
+            )?;
+        }
+
+        if from_version < 6 {
+            // Version 6: entries with a time to live keep their deadline, so
+            // they are deleted even if the daemon restarts before it passes
+            conn.execute_batch(
+                "
+                BEGIN;
+                ALTER TABLE clipboard_history ADD COLUMN expires_at INTEGER;
+                CREATE INDEX idx_expires_at ON clipboard_history(expires_at)
+                    WHERE expires_at IS NOT NULL;
+                INSERT INTO schema_version (version) VALUES (6);
+                COMMIT;
+                ",
+            )?;
+        }
+
+        if from_version < 7 {
+            // Version 7: entries keep their hybrid logical clock stamp, so
+            // history caught up from a peer is ordered like live sync.
+            // Older entries fall back to their wall-clock timestamp.
+            conn.execute_batch(
+                "
+                BEGIN;
+                ALTER TABLE clipboard_history ADD COLUMN hlc_wall_ms INTEGER;
+                ALTER TABLE clipboard_history ADD COLUMN hlc_logical INTEGER;
+                ALTER TABLE clipboard_history ADD COLUMN hlc_node TEXT;
+                INSERT INTO schema_version (version) VALUES (7);
+                COMMIT;
+                ",
+            )?;
+        }
+
         Ok(())
     }
 
+    /// Delete expired entries and entries that fall outside the retention
+    /// policy
+    ///
+    /// Pinned entries are only deleted once expired, and don't count towards
+    /// the limits. Returns the number of deleted entries.
+    pub async fn prune(&self) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        self.prune_locked(&conn)
+    }
+
+    fn prune_locked(&self, conn: &Connection) -> Result<usize> {
+        let policy = &self.retention;
+        let mut deleted = conn.execute(
+            "DELETE FROM clipboard_history WHERE expires_at <= ?",
+            params![chrono::Utc::now().timestamp()],
+        )?;
+
+        if let Some(max_age) = policy.max_age {
+            let cutoff = chrono::Utc::now().timestamp() - max_age.as_secs() as i64;
+            deleted += conn.execute(
+                "DELETE FROM clipboard_history WHERE timestamp < ? AND pinned = 0",
+                params![cutoff],
+            )?;
+        }
+
+        for limit in &policy.per_type {
+            deleted += conn.execute(
+                "DELETE FROM clipboard_history
+                 WHERE id IN (
+                     SELECT id FROM clipboard_history
+                     WHERE content_type LIKE ? ESCAPE '\\' AND pinned = 0
+                     ORDER BY timestamp DESC, id DESC
+                     LIMIT -1 OFFSET ?
+                 )",
+                params![limit.like_pattern(), limit.max_entries as i64],
+            )?;
+        }
+
+        deleted += conn.execute(
+            "DELETE FROM clipboard_history
+             WHERE id IN (
+                 SELECT id FROM clipboard_history
+                 WHERE pinned = 0
+                 ORDER BY timestamp DESC, id DESC
+                 LIMIT -1 OFFSET ?
+             )",
+            params![policy.max_entries as i64],
+        )?;
+
+        if let Some(max_bytes) = policy.max_bytes {
+            // Keep the newest entries whose combined size fits the budget
+            deleted += conn.execute(
+                "DELETE FROM clipboard_history
+                 WHERE id IN (
+                     SELECT id FROM (
+                         SELECT id, SUM(content_size) OVER (
+                             ORDER BY timestamp DESC, id DESC
+                         ) AS running_size
+                         FROM clipboard_history
+                         WHERE pinned = 0
+                     )
+                     WHERE running_size > ?
+                 )",
+                params![max_bytes as i64],
+            )?;
+        }
+
+        Ok(deleted)
+    }
+
     /// Insert new clipboard content into history
     pub async fn insert(&self, content: &ClipboardContent, encryptor: &Encryptor) -> Result<()> {
-        let checksum = Encryptor::compute_checksum(&content.content);
-        let encrypted = encryptor.encrypt(&content.content)?;
+        let conn = self.conn.lock().await;
+        let tx = conn.unchecked_transaction()?;
 
+        Self::insert_entry(&tx, content, false, encryptor)?;
+
+        self.prune_locked(&tx)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Import entries from another history, keeping their IDs and timestamps
+    ///
+    /// Entries whose content checksum or ID is already present are skipped.
+    /// Returns the number of imported entries.
+    pub async fn import(&self, entries: &[HistoryEntry], encryptor: &Encryptor) -> Result<usize> {
         let conn = self.conn.lock().await;
+        let tx = conn.unchecked_transaction()?;
+        let mut imported = 0;
+
+        for entry in entries {
+            let checksum = Encryptor::compute_checksum(&entry.content);
+            if checksum != entry.checksum {
+                return Err(anyhow!("Checksum mismatch for entry {}", entry.id));
+            }
+
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM clipboard_history WHERE checksum = ? OR uuid = ?)",
+                params![&checksum, entry.id.to_string()],
+                |row| row.get(0),
+            )?;
+            if exists {
+                continue;
+            }
+
+            let content = ClipboardContent {
+                id: entry.id,
+                content: entry.content.clone(),
+                content_type: entry.content_type.clone(),
+                timestamp: entry.timestamp,
+                origin_node: entry.origin_node,
+                selection: entry.selection,
+                expires_at: entry.expires_at,
+                hlc: entry.hlc,
+            };
+            Self::insert_entry(&tx, &content, entry.pinned, encryptor)?;
+            imported += 1;
+        }
+
+        self.prune_locked(&tx)?;
+        tx.commit()?;
+
+        Ok(imported)
+    }
+
+    fn insert_entry(
+        conn: &Connection,
+        content: &ClipboardContent,
+        pinned: bool,
+        encryptor: &Encryptor,
+    ) -> Result<()> {
+        let checksum = Encryptor::compute_checksum(&content.content);
+        let encrypted = encryptor.encrypt(&content.content)?;
 
         conn.execute(
             "INSERT INTO clipboard_history
-             (uuid, content, content_type, content_size, checksum, timestamp, origin_node, iv, compressed)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+             (uuid, content, content_type, content_size, checksum, timestamp, origin_node, iv, compressed, selection, pinned, expires_at, hlc_wall_ms, hlc_logical, hlc_node)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 content.id.to_string(),
                 &encrypted.ciphertext,
@@ -156,12 +404,145 @@ impl HistoryDatabase {
                 content.origin_node.to_string(),
                 &encrypted.nonce,
                 encrypted.compressed as i32,
+                content.selection.as_str(),
+                pinned as i32,
+                content.expires_at,
+                content.hlc.map(|hlc| hlc.wall_ms as i64),
+                content.hlc.map(|hlc| hlc.logical),
+                content.hlc.map(|hlc| hlc.node.to_string()),
             ],
         )?;
 
+        if content.content_type.starts_with("text/") {
+            let id = conn.last_insert_rowid();
+            Self::index_entry(conn, id, &content.content, encryptor)?;
+        }
+
         Ok(())
     }
 
+    /// Store search tokens for a text entry
+    fn index_entry(
+        conn: &Connection,
+        id: i64,
+        plaintext: &[u8],
+        encryptor: &Encryptor,
+    ) -> Result<()> {
+        let keys = std::str::from_utf8(plaintext)
+            .ok()
+            .and_then(search::index_keys);
+
+        let state = match keys {
+            Some(keys) => {
+                let mut stmt = conn.prepare_cached(
+                    "INSERT OR IGNORE INTO search_tokens (token, entry_id) VALUES (?, ?)",
+                )?;
+                for key in keys {
+                    stmt.execute(params![encryptor.index_token(&key), id])?;
+                }
+                INDEX_DONE
+            }
+            None => INDEX_SKIPPED,
+        };
+
+        conn.execute(
+            "UPDATE clipboard_history SET search_indexed = ? WHERE id = ?",
+            params![state, id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Index text entries written before the search index existed
+    ///
+    /// Returns the number of entries processed.
+    pub async fn reindex(&self, encryptor: &Encryptor) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        let tx = conn.unchecked_transaction()?;
+
+        let pending = {
+            let mut stmt = tx.prepare(
+                "SELECT id, content, iv, compressed FROM clipboard_history
+                 WHERE search_indexed = ? AND content_type LIKE 'text/%'",
+            )?;
+            let rows = stmt.query_map(params![INDEX_PENDING], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    EncryptedData {
+                        ciphertext: row.get(1)?,
+                        nonce: row.get(2)?,
+                        compressed: row.get::<_, i32>(3)? != 0,
+                    },
+                ))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        for (id, encrypted) in &pending {
+            let plaintext = encryptor.decrypt(encrypted)?;
+            Self::index_entry(&tx, *id, &plaintext, encryptor)?;
+        }
+
+        tx.commit()?;
+        Ok(pending.len())
+    }
+
+    /// Re-encrypt every entry under a new key and rebuild the search index
+    ///
+    /// Runs in a single transaction, so on failure all entries keep the old
+    /// key. Returns the number of re-encrypted entries.
+    pub async fn rekey(&self, old: &Encryptor, new: &Encryptor) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        let tx = conn.unchecked_transaction()?;
+
+        let rows = {
+            let mut stmt = tx.prepare(
+                "SELECT id, content, iv, compressed, content_type FROM clipboard_history",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    EncryptedData {
+                        ciphertext: row.get(1)?,
+                        nonce: row.get(2)?,
+                        compressed: row.get::<_, i32>(3)? != 0,
+                    },
+                    row.get::<_, String>(4)?,
+                ))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        // Index tokens are keyed too, so the index is rebuilt from scratch
+        tx.execute("DELETE FROM search_tokens", [])?;
+        tx.execute(
+            "UPDATE clipboard_history SET search_indexed = ?",
+            params![INDEX_PENDING],
+        )?;
+
+        for (id, encrypted, content_type) in &rows {
+            let plaintext = old.decrypt(encrypted)?;
+            let reencrypted = new.encrypt(&plaintext)?;
+
+            tx.execute(
+                "UPDATE clipboard_history SET content = ?, iv = ?, compressed = ? WHERE id = ?",
+                params![
+                    &reencrypted.ciphertext,
+                    &reencrypted.nonce,
+                    reencrypted.compressed as i32,
+                    id
+                ],
+            )?;
+
+            if content_type.starts_with("text/") {
+                Self::index_entry(&tx, *id, &plaintext, new)?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(rows.len())
+    }
+
     /// Get recent entries from history
     pub async fn get_recent(
         &self,
@@ -170,15 +551,12 @@ impl HistoryDatabase {
     ) -> Result<Vec<HistoryEntry>> {
         let conn = self.conn.lock().await;
 
-        // Ensure we don't request more than the limit
-        let count = count.min(HISTORY_LIMIT);
-
-        let mut stmt = conn.prepare(
-            "SELECT uuid, content, content_type, content_size, checksum, timestamp, origin_node, iv, compressed
-             FROM clipboard_history
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM clipboard_history
              ORDER BY timestamp DESC
              LIMIT ?",
-        )?;
+            ENTRY_COLUMNS
+        ))?;
 
         let entries = stmt
             .query_map(params![count], |row| Ok(self.row_to_entry(row, encryptor)))?
@@ -187,56 +565,260 @@ impl HistoryDatabase {
         entries.into_iter().collect()
     }
 
+    /// Get every entry, newest first
+    pub async fn get_all(&self, encryptor: &Encryptor) -> Result<Vec<HistoryEntry>> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM clipboard_history ORDER BY timestamp DESC, id DESC",
+            ENTRY_COLUMNS
+        ))?;
+
+        let entries = stmt
+            .query_map([], |row| Ok(self.row_to_entry(row, encryptor)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        entries.into_iter().collect()
+    }
+
     /// Get entry by index position
     pub async fn get_by_index(&self, index: u8, encryptor: &Encryptor) -> Result<HistoryEntry> {
         let conn = self.conn.lock().await;
 
+        let entry = conn.query_row(
+            &format!(
+                "SELECT {} FROM clipboard_history
+                     ORDER BY timestamp DESC
+                     LIMIT 1 OFFSET ?",
+                ENTRY_COLUMNS
+            ),
+            params![index as i64],
+            |row| Ok(self.row_to_entry(row, encryptor)),
+        )?;
+
+        entry
+    }
+
+    /// Search entries, newest first
+    ///
+    /// The token index and metadata filters select candidates in SQL;
+    /// candidates are decrypted and checked against the full query.
+    pub async fn search(
+        &self,
+        query: &SearchQuery,
+        limit: usize,
+        encryptor: &Encryptor,
+    ) -> Result<Vec<HistoryEntry>> {
+        let terms = query.terms()?;
+
+        let mut sql = format!(
+            "SELECT {} FROM clipboard_history WHERE 1 = 1",
+            ENTRY_COLUMNS
+        );
+        let mut values: Vec<Value> = Vec::new();
+
+        if query.has_text() {
+            sql.push_str(" AND content_type LIKE 'text/%'");
+        }
+        if let Some(origin) = query.origin {
+            sql.push_str(" AND origin_node = ?");
+            values.push(Value::Text(origin.to_string()));
+        }
+        if let Some(content_type) = &query.content_type {
+            sql.push_str(" AND content_type LIKE ? ESCAPE '\\'");
+            values.push(Value::Text(mime_like_pattern(content_type)));
+        }
+        if let Some(since) = query.since {
+            sql.push_str(" AND timestamp >= ?");
+            values.push(Value::Integer(since.timestamp()));
+        }
+        if let Some(until) = query.until {
+            sql.push_str(" AND timestamp <= ?");
+            values.push(Value::Integer(until.timestamp()));
+        }
+        for key in terms.iter().flat_map(|term| term.index_keys()) {
+            sql.push_str(
+                " AND (search_indexed != 1
+                       OR id IN (SELECT entry_id FROM search_tokens WHERE token = ?))",
+            );
+            values.push(Value::Blob(encryptor.index_token(&key)));
+        }
+        sql.push_str(" ORDER BY timestamp DESC, id DESC");
+
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(&sql)?;
+        let candidates = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok(self.row_to_entry(row, encryptor))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut results = Vec::new();
+        for entry in candidates {
+            let entry = entry?;
+            if !terms.is_empty() {
+                match std::str::from_utf8(&entry.content) {
+                    Ok(text) if search::matches_all(&terms, text) => {}
+                    _ => continue,
+                }
+            }
+
+            results.push(entry);
+            if results.len() >= limit {
+                break;
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Find the newest entry with the given SHA-256 content checksum
+    pub async fn get_by_checksum(
+        &self,
+        checksum: &str,
+        encryptor: &Encryptor,
+    ) -> Result<Option<HistoryEntry>> {
+        let conn = self.conn.lock().await;
+
         let entry = conn
             .query_row(
-                "SELECT uuid, content, content_type, content_size, checksum, timestamp, origin_node, iv, compressed
-                 FROM clipboard_history
-                 ORDER BY timestamp DESC
-                 LIMIT 1 OFFSET ?",
-                params![index as i64],
+                &format!(
+                    "SELECT {} FROM clipboard_history
+                     WHERE checksum = ?
+                     ORDER BY timestamp DESC
+                     LIMIT 1",
+                    ENTRY_COLUMNS
+                ),
+                params![checksum],
                 |row| Ok(self.row_to_entry(row, encryptor)),
-            )?;
+            )
+            .optional()?;
 
-        entry
+        entry.transpose()
     }
 
-    /// Search for entries containing query text
-    pub async fn search(&self, query: &str, encryptor: &Encryptor) -> Result<Vec<HistoryEntry>> {
+    /// Summaries of entries created at or after `since`, newest first
+    pub async fn summaries_since(&self, since: i64, limit: usize) -> Result<Vec<EntrySummary>> {
         let conn = self.conn.lock().await;
 
-        // For security, we can't search encrypted content directly
-        // Instead, we'll decrypt and search in memory
         let mut stmt = conn.prepare(
-            "SELECT uuid, content, content_type, content_size, checksum, timestamp, origin_node, iv, compressed
-             FROM clipboard_history
-             WHERE content_type LIKE 'text/%'
-             ORDER BY timestamp DESC",
+            "SELECT uuid, checksum, timestamp FROM clipboard_history
+             WHERE timestamp >= ? AND (expires_at IS NULL OR expires_at > ?)
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?",
         )?;
+        let now = chrono::Utc::now().timestamp();
+        let rows = stmt
+            .query_map(params![since, now, limit as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let entries: Vec<Result<HistoryEntry>> = stmt
-            .query_map([], |row| Ok(self.row_to_entry(row, encryptor)))?
+        rows.into_iter()
+            .map(|(uuid, checksum, timestamp)| {
+                Ok(EntrySummary {
+                    id: Uuid::parse_str(&uuid)?,
+                    checksum,
+                    timestamp,
+                })
+            })
+            .collect()
+    }
+
+    /// IDs and deadlines of entries with a time to live
+    pub async fn expiring(&self) -> Result<Vec<(Uuid, i64)>> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn.prepare(
+            "SELECT uuid, expires_at FROM clipboard_history
+             WHERE expires_at IS NOT NULL
+             ORDER BY expires_at",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        // Filter entries that match the query
-        let mut results = Vec::new();
-        for entry_result in entries {
-            match entry_result {
-                Ok(entry) => {
-                    if let Ok(text) = String::from_utf8(entry.content.clone()) {
-                        if text.to_lowercase().contains(&query.to_lowercase()) {
-                            results.push(entry);
-                        }
-                    }
-                }
-                Err(_) => {}
-            }
+        rows.into_iter()
+            .map(|(uuid, expires_at)| Ok((Uuid::parse_str(&uuid)?, expires_at)))
+            .collect()
+    }
+
+    /// Get the entries with the given IDs, newest first
+    pub async fn get_by_ids(
+        &self,
+        ids: &[Uuid],
+        encryptor: &Encryptor,
+    ) -> Result<Vec<HistoryEntry>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
         }
 
-        Ok(results)
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let sql = format!(
+            "SELECT {} FROM clipboard_history
+             WHERE uuid IN ({})
+             ORDER BY timestamp DESC, id DESC",
+            ENTRY_COLUMNS, placeholders
+        );
+
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(&sql)?;
+        let entries = stmt
+            .query_map(params_from_iter(ids.iter().map(Uuid::to_string)), |row| {
+                Ok(self.row_to_entry(row, encryptor))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        entries.into_iter().collect()
+    }
+
+    /// Set or clear the pinned flag on an entry
+    ///
+    /// Returns `false` if there is no entry with the given ID.
+    pub async fn set_pinned(&self, id: Uuid, pinned: bool) -> Result<bool> {
+        let conn = self.conn.lock().await;
+
+        let updated = conn.execute(
+            "UPDATE clipboard_history SET pinned = ? WHERE uuid = ?",
+            params![pinned as i32, id.to_string()],
+        )?;
+
+        // An unpinned entry falls under the retention policy again
+        if updated > 0 && !pinned {
+            self.prune_locked(&conn)?;
+        }
+
+        Ok(updated > 0)
+    }
+
+    /// Set when an entry expires, as a Unix timestamp
+    ///
+    /// Returns `false` if there is no entry with the given ID.
+    pub async fn set_expires_at(&self, id: Uuid, expires_at: i64) -> Result<bool> {
+        let conn = self.conn.lock().await;
+        let updated = conn.execute(
+            "UPDATE clipboard_history SET expires_at = ? WHERE uuid = ?",
+            params![expires_at, id.to_string()],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Delete an entry, pinned or not
+    ///
+    /// Returns `false` if there is no entry with the given ID.
+    pub async fn delete(&self, id: Uuid) -> Result<bool> {
+        let conn = self.conn.lock().await;
+        let deleted = conn.execute(
+            "DELETE FROM clipboard_history WHERE uuid = ?",
+            params![id.to_string()],
+        )?;
+        Ok(deleted > 0)
     }
 
     /// Clear all entries from history
@@ -256,6 +838,12 @@ impl HistoryDatabase {
         let origin_node: String = row.get(6)?;
         let iv: Vec<u8> = row.get(7)?;
         let compressed: i32 = row.get(8)?;
+        let selection: String = row.get(9)?;
+        let pinned: i32 = row.get(10)?;
+        let expires_at: Option<i64> = row.get(11)?;
+        let hlc_wall_ms: Option<i64> = row.get(12)?;
+        let hlc_logical: Option<u32> = row.get(13)?;
+        let hlc_node: Option<String> = row.get(14)?;
 
         let encrypted = EncryptedData {
             ciphertext,
@@ -279,6 +867,17 @@ impl HistoryDatabase {
             timestamp,
             origin_node: Uuid::parse_str(&origin_node)?,
             checksum,
+            selection: ClipboardSelection::from_name(&selection).unwrap_or_default(),
+            pinned: pinned != 0,
+            expires_at,
+            hlc: match (hlc_wall_ms, hlc_logical, hlc_node) {
+                (Some(wall_ms), Some(logical), Some(node)) => Some(HybridTimestamp {
+                    wall_ms: wall_ms.max(0) as u64,
+                    logical,
+                    node: Uuid::parse_str(&node)?,
+                }),
+                _ => None,
+            },
         })
     }
 }
@@ -286,6 +885,7 @@ impl HistoryDatabase {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::history::retention::TypeLimit;
     use chrono::Utc;
     use tempfile::TempDir;
 
@@ -294,13 +894,13 @@ mod tests {
         let db_path = temp_dir.path().join("test.db");
 
         // Create a test encryptor with a fixed key
-        use aes_gcm::aead::{OsRng, rand_core::RngCore};
+        use aes_gcm::aead::{rand_core::RngCore, OsRng};
         let mut key = [0u8; 32];
         OsRng.fill_bytes(&mut key);
 
         let encryptor = Encryptor::new_for_tests(key)?;
 
-        let db = HistoryDatabase::new(&db_path, encryptor.get_key()).await?;
+        let db = HistoryDatabase::new(&db_path).await?;
         Ok((db, encryptor, temp_dir))
     }
 
@@ -314,6 +914,9 @@ mod tests {
             content_type: "text/plain".to_string(),
             timestamp: Utc::now().timestamp(),
             origin_node: Uuid::new_v4(),
+            selection: ClipboardSelection::Clipboard,
+            expires_at: None,
+            hlc: None,
         };
 
         db.insert(&content, &encryptor).await.unwrap();
@@ -335,6 +938,9 @@ mod tests {
                 content_type: "text/plain".to_string(),
                 timestamp: Utc::now().timestamp() + i,
                 origin_node: Uuid::new_v4(),
+                selection: ClipboardSelection::Clipboard,
+                expires_at: None,
+                hlc: None,
             };
             db.insert(&content, &encryptor).await.unwrap();
         }
@@ -350,6 +956,199 @@ mod tests {
         );
     }
 
+    fn test_content(text: &str, mime: &str, timestamp: i64) -> ClipboardContent {
+        ClipboardContent {
+            id: Uuid::new_v4(),
+            content: text.as_bytes().to_vec(),
+            content_type: mime.to_string(),
+            timestamp,
+            origin_node: Uuid::new_v4(),
+            selection: ClipboardSelection::Clipboard,
+            expires_at: None,
+            hlc: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retention_max_entries() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+        let db = db.with_retention(RetentionPolicy {
+            max_entries: 50,
+            ..RetentionPolicy::default()
+        });
+
+        let now = Utc::now().timestamp();
+        for i in 0..60 {
+            let content = test_content(&format!("Item {}", i), "text/plain", now + i);
+            db.insert(&content, &encryptor).await.unwrap();
+        }
+
+        let entries = db.get_recent(100, &encryptor).await.unwrap();
+        assert_eq!(entries.len(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_retention_per_type() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+        let db = db.with_retention(RetentionPolicy {
+            per_type: vec![TypeLimit {
+                pattern: "image/*".to_string(),
+                max_entries: 2,
+            }],
+            ..RetentionPolicy::default()
+        });
+
+        let now = Utc::now().timestamp();
+        for i in 0..4 {
+            db.insert(&test_content("pixels", "image/png", now + i), &encryptor)
+                .await
+                .unwrap();
+            db.insert(&test_content("words", "text/plain", now + i), &encryptor)
+                .await
+                .unwrap();
+        }
+
+        let entries = db.get_recent(20, &encryptor).await.unwrap();
+        let images = entries
+            .iter()
+            .filter(|e| e.content_type.starts_with("image/"))
+            .count();
+        assert_eq!(images, 2);
+        assert_eq!(entries.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_retention_max_bytes() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+        let db = db.with_retention(RetentionPolicy {
+            max_bytes: Some(25),
+            ..RetentionPolicy::default()
+        });
+
+        let now = Utc::now().timestamp();
+        for i in 0..5 {
+            // 10 bytes each
+            let content = test_content(&format!("entry-{:04}", i), "text/plain", now + i);
+            db.insert(&content, &encryptor).await.unwrap();
+        }
+
+        let entries = db.get_recent(20, &encryptor).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].content, b"entry-0004");
+    }
+
+    #[tokio::test]
+    async fn test_retention_max_age() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+        let db = db.with_retention(RetentionPolicy {
+            max_age: Some(std::time::Duration::from_secs(3_600)),
+            ..RetentionPolicy::default()
+        });
+
+        let now = Utc::now().timestamp();
+        db.insert(&test_content("old", "text/plain", now - 7_200), &encryptor)
+            .await
+            .unwrap();
+        db.insert(&test_content("new", "text/plain", now), &encryptor)
+            .await
+            .unwrap();
+
+        let entries = db.get_recent(20, &encryptor).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content, b"new");
+    }
+
+    #[tokio::test]
+    async fn test_pinned_survives_pruning() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+        let db = db.with_retention(RetentionPolicy {
+            max_entries: 3,
+            ..RetentionPolicy::default()
+        });
+
+        let now = Utc::now().timestamp();
+        let keeper = test_content("ssh deploy@prod", "text/plain", now - 100);
+        db.insert(&keeper, &encryptor).await.unwrap();
+        assert!(db.set_pinned(keeper.id, true).await.unwrap());
+
+        for i in 0..10 {
+            let content = test_content(&format!("Item {}", i), "text/plain", now + i);
+            db.insert(&content, &encryptor).await.unwrap();
+        }
+
+        // Three unpinned entries plus the pinned one
+        let entries = db.get_recent(20, &encryptor).await.unwrap();
+        assert_eq!(entries.len(), 4);
+        let pinned = entries.iter().find(|e| e.id == keeper.id).unwrap();
+        assert!(pinned.pinned);
+
+        // Unpinning makes it subject to retention again
+        assert!(db.set_pinned(keeper.id, false).await.unwrap());
+        let entries = db.get_recent(20, &encryptor).await.unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|e| e.id != keeper.id));
+
+        assert!(!db.set_pinned(Uuid::new_v4(), true).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_deleted() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+
+        let now = Utc::now().timestamp();
+        let code = ClipboardContent {
+            expires_at: Some(now + 60),
+            ..test_content("482913", "text/plain", now)
+        };
+        let notes = test_content("meeting notes", "text/plain", now + 1);
+        db.insert(&code, &encryptor).await.unwrap();
+        db.insert(&notes, &encryptor).await.unwrap();
+        assert!(db.set_pinned(code.id, true).await.unwrap());
+        assert_eq!(db.expiring().await.unwrap(), vec![(code.id, now + 60)]);
+
+        let entries = db.get_by_ids(&[code.id], &encryptor).await.unwrap();
+        assert_eq!(entries[0].expires_at, Some(now + 60));
+
+        // The deadline passes while nothing is running
+        db.conn
+            .lock()
+            .await
+            .execute(
+                "UPDATE clipboard_history SET expires_at = ? WHERE uuid = ?",
+                params![now - 1, code.id.to_string()],
+            )
+            .unwrap();
+        let summaries = db.summaries_since(now - 10, 10).await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].id, notes.id);
+
+        // Pinned or not, it is gone on the next prune
+        assert_eq!(db.prune().await.unwrap(), 1);
+        let entries = db.get_recent(10, &encryptor).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, notes.id);
+        assert!(db.expiring().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_entry() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+
+        let now = Utc::now().timestamp();
+        let code = test_content("482913", "text/plain", now);
+        let other = test_content("meeting notes", "text/plain", now + 1);
+        db.insert(&code, &encryptor).await.unwrap();
+        db.insert(&other, &encryptor).await.unwrap();
+        assert!(db.set_pinned(code.id, true).await.unwrap());
+
+        assert!(db.delete(code.id).await.unwrap());
+        assert!(!db.delete(code.id).await.unwrap());
+
+        let entries = db.get_recent(10, &encryptor).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, other.id);
+    }
+
     #[tokio::test]
     async fn test_search() {
         let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
@@ -367,11 +1166,348 @@ mod tests {
                 content_type: mime.to_string(),
                 timestamp: Utc::now().timestamp(),
                 origin_node: Uuid::new_v4(),
+                selection: ClipboardSelection::Clipboard,
+                expires_at: None,
+                hlc: None,
             };
             db.insert(&content, &encryptor).await.unwrap();
         }
 
-        let results = db.search("world", &encryptor).await.unwrap();
+        let results = db
+            .search(&SearchQuery::new("world"), usize::MAX, &encryptor)
+            .await
+            .unwrap();
         assert_eq!(results.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_search_filters() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+        let laptop = Uuid::new_v4();
+
+        let now = Utc::now().timestamp();
+        let mut old = test_content("deploy staging", "text/plain", now - 86_400);
+        old.origin_node = laptop;
+        db.insert(&old, &encryptor).await.unwrap();
+        db.insert(
+            &test_content("deploy production", "text/plain", now),
+            &encryptor,
+        )
+        .await
+        .unwrap();
+        db.insert(&test_content("<b>deploy</b>", "text/html", now), &encryptor)
+            .await
+            .unwrap();
+        db.insert(&test_content("deploy", "image/png", now), &encryptor)
+            .await
+            .unwrap();
+
+        let search = |query: SearchQuery| {
+            let db = &db;
+            let encryptor = &encryptor;
+            async move { db.search(&query, usize::MAX, encryptor).await.unwrap() }
+        };
+
+        // Text queries never match binary entries
+        assert_eq!(search(SearchQuery::new("deploy")).await.len(), 3);
+        assert_eq!(search(SearchQuery::new("prod*")).await.len(), 1);
+        assert_eq!(search(SearchQuery::new("/stag(ing)?$/")).await.len(), 1);
+
+        let by_origin = SearchQuery {
+            origin: Some(laptop),
+            ..SearchQuery::new("deploy")
+        };
+        assert_eq!(search(by_origin).await[0].id, old.id);
+
+        let by_type = SearchQuery {
+            content_type: Some("text/html".to_string()),
+            ..SearchQuery::new("deploy")
+        };
+        assert_eq!(search(by_type).await.len(), 1);
+
+        let recent = SearchQuery {
+            since: Some(Utc::now() - chrono::Duration::hours(1)),
+            ..SearchQuery::default()
+        };
+        assert_eq!(search(recent).await.len(), 3);
+
+        let limited = db
+            .search(&SearchQuery::new("deploy"), 1, &encryptor)
+            .await
+            .unwrap();
+        assert_eq!(limited.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_index_has_no_plaintext() {
+        let (db, encryptor, temp_dir) = setup_test_db().await.unwrap();
+
+        let content = test_content("hunter2 zanzibar", "text/plain", Utc::now().timestamp());
+        db.insert(&content, &encryptor).await.unwrap();
+
+        {
+            let conn = db.conn.lock().await;
+            let tokens: i64 = conn
+                .query_row("SELECT COUNT(*) FROM search_tokens", [], |row| row.get(0))
+                .unwrap();
+            assert!(tokens > 0);
+        }
+
+        for file in std::fs::read_dir(temp_dir.path()).unwrap() {
+            let bytes = std::fs::read(file.unwrap().path()).unwrap();
+            assert!(!bytes.windows(8).any(|w| w == b"zanzibar"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reindex_unindexed_entries() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+
+        let content = test_content("legacy entry", "text/plain", Utc::now().timestamp());
+        db.insert(&content, &encryptor).await.unwrap();
+
+        // Simulate a row written before the index existed
+        {
+            let conn = db.conn.lock().await;
+            conn.execute_batch(
+                "DELETE FROM search_tokens; UPDATE clipboard_history SET search_indexed = 0;",
+            )
+            .unwrap();
+        }
+
+        // Unindexed rows are still found by scanning
+        let query = SearchQuery::new("legacy");
+        assert_eq!(db.search(&query, 10, &encryptor).await.unwrap().len(), 1);
+
+        assert_eq!(db.reindex(&encryptor).await.unwrap(), 1);
+        assert_eq!(db.reindex(&encryptor).await.unwrap(), 0);
+        assert_eq!(db.search(&query, 10, &encryptor).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_by_checksum() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+
+        let content = test_content("find me", "text/plain", Utc::now().timestamp());
+        db.insert(&content, &encryptor).await.unwrap();
+
+        let checksum = Encryptor::compute_checksum(b"find me");
+        let found = db.get_by_checksum(&checksum, &encryptor).await.unwrap();
+        assert_eq!(found.unwrap().id, content.id);
+
+        let missing = db.get_by_checksum("nope", &encryptor).await.unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_summaries_and_lookup_by_id() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+
+        let now = Utc::now().timestamp();
+        let old = test_content("old", "text/plain", now - 7200);
+        let recent = test_content("recent", "text/plain", now - 60);
+        let newest = test_content("newest", "text/plain", now);
+        for content in [&old, &recent, &newest] {
+            db.insert(content, &encryptor).await.unwrap();
+        }
+
+        let summaries = db.summaries_since(now - 3600, 10).await.unwrap();
+        let ids: Vec<Uuid> = summaries.iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![newest.id, recent.id]);
+        assert_eq!(
+            summaries[1].checksum,
+            Encryptor::compute_checksum(b"recent")
+        );
+        assert_eq!(db.summaries_since(0, 1).await.unwrap().len(), 1);
+
+        let entries = db
+            .get_by_ids(&[old.id, Uuid::new_v4(), newest.id], &encryptor)
+            .await
+            .unwrap();
+        let contents: Vec<&[u8]> = entries.iter().map(|e| e.content.as_slice()).collect();
+        assert_eq!(contents, vec![&b"newest"[..], &b"old"[..]]);
+        assert!(db.get_by_ids(&[], &encryptor).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_import_dedupes_by_checksum() {
+        let (source, encryptor, _source_dir) = setup_test_db().await.unwrap();
+        let (target, target_encryptor, _target_dir) = setup_test_db().await.unwrap();
+
+        let now = Utc::now().timestamp();
+        source
+            .insert(&test_content("shared", "text/plain", now - 100), &encryptor)
+            .await
+            .unwrap();
+        source
+            .insert(
+                &test_content("only in source", "text/plain", now - 50),
+                &encryptor,
+            )
+            .await
+            .unwrap();
+        let pinned = test_content("pinned", "text/plain", now - 10);
+        source.insert(&pinned, &encryptor).await.unwrap();
+        source.set_pinned(pinned.id, true).await.unwrap();
+        target
+            .insert(
+                &test_content("shared", "text/plain", now),
+                &target_encryptor,
+            )
+            .await
+            .unwrap();
+
+        let exported = source.get_all(&encryptor).await.unwrap();
+        assert_eq!(
+            target.import(&exported, &target_encryptor).await.unwrap(),
+            2
+        );
+        // Importing the same entries again is a no-op
+        assert_eq!(
+            target.import(&exported, &target_encryptor).await.unwrap(),
+            0
+        );
+
+        let entries = target.get_all(&target_encryptor).await.unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let restored = entries.iter().find(|e| e.id == pinned.id).unwrap();
+        assert_eq!(restored.timestamp, now - 10);
+        assert_eq!(restored.origin_node, pinned.origin_node);
+        assert!(restored.pinned);
+
+        // Imported text is searchable
+        let found = target
+            .search(&SearchQuery::new("source"), 10, &target_encryptor)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_selection_roundtrip() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+
+        let content = ClipboardContent {
+            id: Uuid::new_v4(),
+            content: b"middle click".to_vec(),
+            content_type: "text/plain".to_string(),
+            timestamp: Utc::now().timestamp(),
+            origin_node: Uuid::new_v4(),
+            selection: ClipboardSelection::Primary,
+            expires_at: None,
+            hlc: None,
+        };
+        db.insert(&content, &encryptor).await.unwrap();
+
+        let entries = db.get_recent(1, &encryptor).await.unwrap();
+        assert_eq!(entries[0].selection, ClipboardSelection::Primary);
+    }
+
+    #[tokio::test]
+    async fn test_hlc_roundtrip() {
+        let (db, encryptor, _temp_dir) = setup_test_db().await.unwrap();
+
+        let now = Utc::now();
+        let hlc = HybridTimestamp {
+            wall_ms: now.timestamp_millis() as u64,
+            logical: 3,
+            node: Uuid::new_v4(),
+        };
+        let content = ClipboardContent {
+            hlc: Some(hlc),
+            ..test_content("ordered", "text/plain", now.timestamp())
+        };
+        db.insert(&content, &encryptor).await.unwrap();
+        db.insert(
+            &test_content("unordered", "text/plain", now.timestamp()),
+            &encryptor,
+        )
+        .await
+        .unwrap();
+
+        let entries = db.get_recent(2, &encryptor).await.unwrap();
+        let ordered = entries.iter().find(|e| e.id == content.id).unwrap();
+        assert_eq!(ordered.hlc, Some(hlc));
+        assert!(entries.iter().any(|e| e.hlc.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_from_v1() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("v1.db");
+
+        // Lay down a version 1 schema without the selection column
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(
+                "
+                CREATE TABLE schema_version (
+                    version INTEGER PRIMARY KEY,
+                    applied_at INTEGER DEFAULT (strftime('%s', 'now'))
+                );
+                CREATE TABLE clipboard_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    uuid TEXT NOT NULL UNIQUE,
+                    content BLOB NOT NULL,
+                    content_type TEXT NOT NULL,
+                    content_size INTEGER NOT NULL,
+                    checksum TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    origin_node TEXT NOT NULL,
+                    iv BLOB NOT NULL,
+                    compressed INTEGER NOT NULL DEFAULT 0,
+                    created_at INTEGER DEFAULT (strftime('%s', 'now'))
+                );
+                CREATE TRIGGER limit_history_size
+                AFTER INSERT ON clipboard_history
+                BEGIN
+                    DELETE FROM clipboard_history
+                    WHERE id IN (
+                        SELECT id FROM clipboard_history
+                        ORDER BY timestamp DESC
+                        LIMIT -1 OFFSET 20
+                    );
+                END;
+                INSERT INTO schema_version (version) VALUES (1);
+                ",
+            )
+            .unwrap();
+        }
+
+        use aes_gcm::aead::{rand_core::RngCore, OsRng};
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        let encryptor = Encryptor::new_for_tests(key).unwrap();
+
+        let db = HistoryDatabase::new(&db_path).await.unwrap();
+        {
+            let conn = db.conn.lock().await;
+            assert_eq!(db.get_schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+            let trigger_exists: bool = conn
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type='trigger' AND name='limit_history_size')",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert!(!trigger_exists);
+        }
+
+        let content = ClipboardContent {
+            id: Uuid::new_v4(),
+            content: b"after migration".to_vec(),
+            content_type: "text/plain".to_string(),
+            timestamp: Utc::now().timestamp(),
+            origin_node: Uuid::new_v4(),
+            selection: ClipboardSelection::Primary,
+            expires_at: None,
+            hlc: None,
+        };
+        db.insert(&content, &encryptor).await.unwrap();
+
+        let entries = db.get_recent(1, &encryptor).await.unwrap();
+        assert_eq!(entries[0].selection, ClipboardSelection::Primary);
+    }
 }
@@ -1,28 +1,52 @@
 //! AES-256-GCM encryption for clipboard history
 
+use crate::history::keystore::KeyStore;
 use aes_gcm::aead::rand_core::RngCore;
 use aes_gcm::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
     Aes256Gcm, Nonce,
 };
 use anyhow::{anyhow, Result};
-use argon2::{Argon2, PasswordHasher};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::{
-    fs,
-    path::{Path, PathBuf},
-};
 use zeroize::{Zeroize, Zeroizing};
 use zstd::stream::{decode_all, encode_all};
 
 const COMPRESSION_THRESHOLD: usize = 100 * 1024; // 100KB
 const COMPRESSION_LEVEL: i32 = 3;
-const KEY_FILE_NAME: &str = "history.key";
+const INDEX_KEY_LABEL: &[u8] = b"clipsync history search index v1";
+const INDEX_TOKEN_SIZE: usize = 16;
+/// Salt length for password-derived keys
+pub const SALT_SIZE: usize = 16;
 
 /// AES-256-GCM encryptor with secure key management
 pub struct Encryptor {
     cipher: Aes256Gcm,
     key: Zeroizing<[u8; 32]>,
+    /// HMAC key for search index tokens, derived from the master key
+    index_key: ring::hmac::Key,
+}
+
+/// Argon2id cost parameters for password-derived keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub m_cost: u32,
+    /// Number of iterations
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: 15000,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
 }
 
 /// Encrypted data container with metadata
@@ -38,13 +62,27 @@ pub struct EncryptedData {
 
 impl Encryptor {
     /// Create a new encryptor with automatic key management
+    ///
+    /// Fails with [`HistoryLocked`](crate::history::keystore::HistoryLocked)
+    /// if the key is passphrase-protected.
     pub async fn new() -> Result<Self> {
-        let key = Self::load_or_create_key().await?;
+        let key = KeyStore::default_location()?.load_or_create()?;
+        Self::from_key(key)
+    }
+
+    /// Create an encryptor from a raw key, e.g. one derived from a password
+    pub fn from_key(key: [u8; 32]) -> Result<Self> {
         let cipher = Aes256Gcm::new_from_slice(&key)?;
 
+        // Separate key so index tokens can't be used to attack the cipher key
+        let master = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &key);
+        let derived = ring::hmac::sign(&master, INDEX_KEY_LABEL);
+        let index_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, derived.as_ref());
+
         Ok(Self {
             cipher,
             key: Zeroizing::new(key),
+            index_key,
         })
     }
 
@@ -101,154 +139,59 @@ impl Encryptor {
         format!("{:x}", hasher.finalize())
     }
 
+    /// Keyed hash of a search index key, safe to store on disk
+    pub fn index_token(&self, key: &str) -> Vec<u8> {
+        let tag = ring::hmac::sign(&self.index_key, key.as_bytes());
+        tag.as_ref()[..INDEX_TOKEN_SIZE].to_vec()
+    }
+
     #[cfg(test)]
     pub fn new_for_tests(key: [u8; 32]) -> Result<Self> {
-        let cipher = Aes256Gcm::new_from_slice(&key)?;
-        Ok(Self {
-            cipher,
-            key: Zeroizing::new(key),
-        })
+        Self::from_key(key)
     }
 
-    async fn load_or_create_key() -> Result<[u8; 32]> {
-        // Use file-based key storage for cross-platform compatibility
-        let key_path = Self::get_key_file_path()?;
-
-        if key_path.exists() {
-            // Try to load existing key
-            match Self::load_from_file(&key_path).await {
-                Ok(key) => return Ok(key),
-                Err(e) => {
-                    tracing::warn!("Failed to load existing key: {}", e);
-                    // Continue to check for migration or generate new key
-                }
-            }
-        }
-
-        // Generate new key
+    /// Generate a random master key
+    pub fn generate_key() -> [u8; 32] {
         let mut key = [0u8; 32];
         OsRng.fill_bytes(&mut key);
-
-        // Save to file with proper permissions
-        Self::save_to_file(&key_path, &key).await?;
-        Ok(key)
+        key
     }
 
-    async fn load_from_file(path: &Path) -> Result<[u8; 32]> {
-        // Check file permissions on Unix systems
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let metadata = fs::metadata(path)?;
-            let mode = metadata.permissions().mode();
-            if mode & 0o077 != 0 {
-                return Err(anyhow!("Key file has insecure permissions: {:o}", mode));
-            }
-        }
-
-        let data = fs::read(path)?;
-        if data.len() != 32 {
-            return Err(anyhow!(
-                "Invalid key file: expected 32 bytes, got {}",
-                data.len()
-            ));
-        }
-
-        let mut key = [0u8; 32];
-        key.copy_from_slice(&data);
-        Ok(key)
-    }
-
-    async fn save_to_file(path: &Path, key: &[u8; 32]) -> Result<()> {
-        // Create directory if needed
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-
-            // Set directory permissions on Unix
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                if let Ok(metadata) = fs::metadata(parent) {
-                    let mut perms = metadata.permissions();
-                    perms.set_mode(0o700); // rwx for owner only
-                                           // Ignore permission errors in tests/temp directories
-                    let _ = fs::set_permissions(parent, perms);
-                }
-            }
-        }
-
-        // Write key file
-        fs::write(path, key)?;
-
-        // Set restrictive permissions on Unix
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            if let Ok(metadata) = fs::metadata(path) {
-                let mut perms = metadata.permissions();
-                perms.set_mode(0o600); // Read/write for owner only
-                                       // Ignore permission errors in tests/temp directories
-                let _ = fs::set_permissions(path, perms);
-            }
-        }
-
-        // On Windows, file permissions are handled by default ACLs
-        #[cfg(windows)]
-        {
-            // Windows file permissions are more complex and typically handled
-            // by the default ACLs. The file will be created with permissions
-            // inherited from the parent directory.
-        }
-
-        tracing::info!("Encryption key saved to {:?}", path);
-        Ok(())
+    /// Generate a random salt for [`Self::derive_from_password`]
+    pub fn generate_salt() -> [u8; SALT_SIZE] {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        salt
     }
 
-    fn get_key_file_path() -> Result<PathBuf> {
-        // Use platform-specific config directory
-        let config_dir = if cfg!(target_os = "linux") {
-            // On Linux, prefer XDG_CONFIG_HOME or ~/.config
-            std::env::var("XDG_CONFIG_HOME")
-                .map(PathBuf::from)
-                .or_else(|_| {
-                    dirs::home_dir()
-                        .map(|home| home.join(".config"))
-                        .ok_or_else(|| anyhow!("Could not determine home directory"))
-                })?
-        } else {
-            // On macOS and other platforms, use the standard config directory
-            dirs::config_dir().ok_or_else(|| anyhow!("Could not determine config directory"))?
-        };
-
-        Ok(config_dir.join("clipsync").join(KEY_FILE_NAME))
+    /// Derive encryption key from password and salt using Argon2id
+    ///
+    /// The same password and salt always produce the same key, so the salt
+    /// must be stored alongside whatever the key encrypts.
+    pub async fn derive_from_password(password: &str, salt: &[u8]) -> Result<[u8; 32]> {
+        Self::derive_with_params(password, salt, &KdfParams::default()).await
     }
 
-    /// Derive encryption key from password using Argon2id
-    pub async fn derive_from_password(password: &str) -> Result<[u8; 32]> {
-        use argon2::password_hash::SaltString;
+    /// Derive encryption key from password and salt with explicit Argon2 parameters
+    pub async fn derive_with_params(
+        password: &str,
+        salt: &[u8],
+        params: &KdfParams,
+    ) -> Result<[u8; 32]> {
         use argon2::{Algorithm, Params, Version};
 
-        let salt = SaltString::generate(&mut OsRng);
         let argon2 = Argon2::new(
             Algorithm::Argon2id,
             Version::V0x13,
-            Params::new(15000, 2, 1, Some(32))
+            Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
                 .map_err(|e| anyhow!("Invalid argon2 params: {}", e))?,
         );
 
-        let hash = argon2
-            .hash_password(password.as_bytes(), &salt)
+        let mut key = [0u8; 32];
+        argon2
+            .hash_password_into(password.as_bytes(), salt, &mut key)
             .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
 
-        let hash_bytes = hash.hash.ok_or_else(|| anyhow!("No hash output"))?;
-        let bytes = hash_bytes.as_bytes();
-
-        if bytes.len() != 32 {
-            return Err(anyhow!("Invalid derived key length"));
-        }
-
-        let mut key = [0u8; 32];
-        key.copy_from_slice(bytes);
         Ok(key)
     }
 }
@@ -271,11 +214,7 @@ mod tests {
         std::fs::write(&key_path, &key)?;
 
         // Create encryptor with the key
-        let cipher = Aes256Gcm::new_from_slice(&key)?;
-        let encryptor = Encryptor {
-            cipher,
-            key: Zeroizing::new(key),
-        };
+        let encryptor = Encryptor::from_key(key)?;
 
         Ok((encryptor, temp_dir))
     }
@@ -316,13 +255,37 @@ mod tests {
         assert_ne!(checksum1, checksum3);
     }
 
+    #[test]
+    fn test_index_token() {
+        let encryptor = Encryptor::new_for_tests([7u8; 32]).unwrap();
+        let other = Encryptor::new_for_tests([8u8; 32]).unwrap();
+
+        let token = encryptor.index_token("t:hello");
+        assert_eq!(token.len(), INDEX_TOKEN_SIZE);
+        assert_eq!(token, encryptor.index_token("t:hello"));
+        assert_ne!(token, encryptor.index_token("t:world"));
+        // Tokens depend on the key, so they don't reveal common words
+        assert_ne!(token, other.index_token("t:hello"));
+    }
+
     #[tokio::test]
     async fn test_key_derivation() {
         let password = "test_password";
-        let key1 = Encryptor::derive_from_password(password).await.unwrap();
-        let key2 = Encryptor::derive_from_password(password).await.unwrap();
+        let salt = Encryptor::generate_salt();
+        let key1 = Encryptor::derive_from_password(password, &salt)
+            .await
+            .unwrap();
+        let key2 = Encryptor::derive_from_password(password, &salt)
+            .await
+            .unwrap();
+
+        // The same salt reproduces the key
+        assert_eq!(key1, key2);
 
         // Different salts should produce different keys
-        assert_ne!(key1, key2);
+        let key3 = Encryptor::derive_from_password(password, &Encryptor::generate_salt())
+            .await
+            .unwrap();
+        assert_ne!(key1, key3);
     }
 }
@@ -0,0 +1,364 @@
+//! Storage for the history master key
+//!
+//! The master key lives in `history.key`, either as 32 raw bytes or wrapped
+//! under a passphrase. A wrapped key file is JSON carrying the Argon2
+//! parameters and salt of the wrapping key, so the master key can always be
+//! recovered from the passphrase alone. While the key is wrapped and no
+//! passphrase has been given, history is locked.
+
+use crate::history::encryption::{EncryptedData, Encryptor, KdfParams};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+const KEY_FILE_NAME: &str = "history.key";
+const KEY_SIZE: usize = 32;
+const WRAPPED_KEY_VERSION: u32 = 1;
+
+/// Returned when history is needed but its key is still wrapped
+#[derive(Debug, thiserror::Error)]
+#[error("History is locked; run 'clipsync history unlock' first")]
+pub struct HistoryLocked;
+
+/// Master key encrypted under a passphrase-derived key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WrappedKey {
+    version: u32,
+    kdf: KdfParams,
+    /// Base64 Argon2 salt
+    salt: String,
+    /// Base64 AES-GCM nonce
+    nonce: String,
+    /// Base64 encrypted master key
+    key: String,
+}
+
+impl WrappedKey {
+    async fn wrap(key: &[u8; KEY_SIZE], passphrase: &str) -> Result<Self> {
+        let kdf = KdfParams::default();
+        let salt = Encryptor::generate_salt();
+        let wrapping_key = Encryptor::derive_with_params(passphrase, &salt, &kdf).await?;
+        let encrypted = Encryptor::from_key(wrapping_key)?.encrypt(key)?;
+
+        Ok(Self {
+            version: WRAPPED_KEY_VERSION,
+            kdf,
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(&encrypted.nonce),
+            key: BASE64.encode(&encrypted.ciphertext),
+        })
+    }
+
+    async fn unwrap(&self, passphrase: &str) -> Result<[u8; KEY_SIZE]> {
+        if self.version != WRAPPED_KEY_VERSION {
+            return Err(anyhow!("Unsupported key file version {}", self.version));
+        }
+
+        let salt = BASE64.decode(&self.salt)?;
+        let wrapping_key = Encryptor::derive_with_params(passphrase, &salt, &self.kdf).await?;
+        let plaintext = Zeroizing::new(
+            Encryptor::from_key(wrapping_key)?
+                .decrypt(&EncryptedData {
+                    ciphertext: BASE64.decode(&self.key)?,
+                    nonce: BASE64.decode(&self.nonce)?,
+                    compressed: false,
+                })
+                .map_err(|_| anyhow!("Incorrect history passphrase"))?,
+        );
+
+        plaintext
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("Invalid wrapped key length"))
+    }
+}
+
+/// Key file contents
+enum StoredKey {
+    Plain(Zeroizing<[u8; KEY_SIZE]>),
+    Wrapped(WrappedKey),
+}
+
+/// Location of the history master key
+#[derive(Debug, Clone)]
+pub struct KeyStore {
+    path: PathBuf,
+}
+
+impl KeyStore {
+    /// Key store backed by the given key file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Key store at the platform config location
+    pub fn default_location() -> Result<Self> {
+        // Use platform-specific config directory
+        let config_dir = if cfg!(target_os = "linux") {
+            // On Linux, prefer XDG_CONFIG_HOME or ~/.config
+            std::env::var("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|_| {
+                    dirs::home_dir()
+                        .map(|home| home.join(".config"))
+                        .ok_or_else(|| anyhow!("Could not determine home directory"))
+                })?
+        } else {
+            // On macOS and other platforms, use the standard config directory
+            dirs::config_dir().ok_or_else(|| anyhow!("Could not determine config directory"))?
+        };
+
+        Ok(Self::new(config_dir.join("clipsync").join(KEY_FILE_NAME)))
+    }
+
+    /// Path of the key file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the key is wrapped under a passphrase
+    pub fn is_protected(&self) -> Result<bool> {
+        Ok(matches!(self.read()?, Some(StoredKey::Wrapped(_))))
+    }
+
+    /// Load the key, creating one on first use
+    ///
+    /// A key is only generated when no key file exists; an unreadable or
+    /// corrupt one is an error, since replacing it would lose all history.
+    /// Fails with [`HistoryLocked`] if the key is passphrase-protected.
+    pub fn load_or_create(&self) -> Result<[u8; KEY_SIZE]> {
+        let staged = self.staged_path();
+        if staged.exists() {
+            tracing::warn!(
+                "Found {:?} from an interrupted rekey; if history fails to decrypt, \
+                 move it over {:?}",
+                staged,
+                self.path
+            );
+        }
+
+        match self.read() {
+            Ok(Some(StoredKey::Plain(key))) => return Ok(*key),
+            Ok(Some(StoredKey::Wrapped(_))) => return Err(HistoryLocked.into()),
+            Ok(None) => {}
+            Err(e) => return Err(e.context(format!("Failed to load history key {:?}", self.path))),
+        }
+
+        // Generate new key
+        let key = Encryptor::generate_key();
+
+        // Save to file with proper permissions
+        self.save(&key)?;
+        Ok(key)
+    }
+
+    /// Recover the key with its passphrase
+    ///
+    /// An unprotected key is returned as is.
+    pub async fn unlock(&self, passphrase: &str) -> Result<[u8; KEY_SIZE]> {
+        match self.read()? {
+            Some(StoredKey::Plain(key)) => Ok(*key),
+            Some(StoredKey::Wrapped(wrapped)) => wrapped.unwrap(passphrase).await,
+            None => Err(anyhow!("No history key at {:?}", self.path)),
+        }
+    }
+
+    /// Store the key unprotected
+    pub fn save(&self, key: &[u8; KEY_SIZE]) -> Result<()> {
+        write_private(&self.path, key)?;
+        tracing::info!("Encryption key saved to {:?}", self.path);
+        Ok(())
+    }
+
+    /// Store the key wrapped under a passphrase
+    pub async fn save_protected(&self, key: &[u8; KEY_SIZE], passphrase: &str) -> Result<()> {
+        let wrapped = WrappedKey::wrap(key, passphrase).await?;
+        write_private(&self.path, &serde_json::to_vec_pretty(&wrapped)?)?;
+        tracing::info!(
+            "Passphrase-protected encryption key saved to {:?}",
+            self.path
+        );
+        Ok(())
+    }
+
+    /// Write a replacement key beside the current one
+    ///
+    /// The staged key only takes effect on [`Self::commit_staged`], so a
+    /// failed rekey leaves the current key in place.
+    pub async fn stage(&self, key: &[u8; KEY_SIZE], passphrase: Option<&str>) -> Result<()> {
+        let contents = match passphrase {
+            Some(passphrase) => {
+                serde_json::to_vec_pretty(&WrappedKey::wrap(key, passphrase).await?)?
+            }
+            None => key.to_vec(),
+        };
+        write_private(&self.staged_path(), &contents)
+    }
+
+    /// Replace the current key with the staged one
+    pub fn commit_staged(&self) -> Result<()> {
+        fs::rename(self.staged_path(), &self.path)?;
+        tracing::info!("Encryption key rotated in {:?}", self.path);
+        Ok(())
+    }
+
+    /// Remove a staged key after a failed rekey
+    pub fn discard_staged(&self) {
+        let _ = fs::remove_file(self.staged_path());
+    }
+
+    fn staged_path(&self) -> PathBuf {
+        self.path.with_extension("key.next")
+    }
+
+    /// Key file contents, or `None` only if there is no key file
+    fn read(&self) -> Result<Option<StoredKey>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => Zeroizing::new(data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Check file permissions on Unix systems
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(&self.path)?;
+            let mode = metadata.permissions().mode();
+            if mode & 0o077 != 0 {
+                return Err(anyhow!("Key file has insecure permissions: {:o}", mode));
+            }
+        }
+
+        if data.len() == KEY_SIZE {
+            let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+            key.copy_from_slice(&data);
+            return Ok(Some(StoredKey::Plain(key)));
+        }
+
+        serde_json::from_slice(&data)
+            .map(|wrapped| Some(StoredKey::Wrapped(wrapped)))
+            .map_err(|_| {
+                anyhow!(
+                    "Invalid key file: expected 32 bytes or a wrapped key, got {} bytes",
+                    data.len()
+                )
+            })
+    }
+}
+
+/// Write a file readable only by the owner, replacing it atomically
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    // Create directory if needed
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+
+        // Set directory permissions on Unix
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Ok(metadata) = fs::metadata(parent) {
+                let mut perms = metadata.permissions();
+                perms.set_mode(0o700); // rwx for owner only
+                                       // Ignore permission errors in tests/temp directories
+                let _ = fs::set_permissions(parent, perms);
+            }
+        }
+    }
+
+    let tmp_path = path.with_extension(format!("tmp{}", OsRng.next_u32()));
+    fs::write(&tmp_path, contents)?;
+
+    // Set restrictive permissions on Unix
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    // On Windows, file permissions are handled by default ACLs inherited
+    // from the parent directory
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_plain_key_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let store = KeyStore::new(dir.path().join(KEY_FILE_NAME));
+
+        let key = store.load_or_create().unwrap();
+        assert_eq!(store.load_or_create().unwrap(), key);
+        assert!(!store.is_protected().unwrap());
+        assert_eq!(store.unlock("ignored").await.unwrap(), key);
+    }
+
+    #[tokio::test]
+    async fn test_protected_key() {
+        let dir = TempDir::new().unwrap();
+        let store = KeyStore::new(dir.path().join(KEY_FILE_NAME));
+        let key = store.load_or_create().unwrap();
+
+        store.save_protected(&key, "hunter2").await.unwrap();
+        assert!(store.is_protected().unwrap());
+
+        let err = store.load_or_create().unwrap_err();
+        assert!(err.is::<HistoryLocked>());
+        assert!(store.unlock("wrong").await.is_err());
+        assert_eq!(store.unlock("hunter2").await.unwrap(), key);
+
+        // The key file holds the KDF parameters, not the key
+        let contents = fs::read_to_string(store.path()).unwrap();
+        assert!(contents.contains("m_cost"));
+        assert!(!contents.contains(&BASE64.encode(key)));
+    }
+
+    #[tokio::test]
+    async fn test_staged_key() {
+        let dir = TempDir::new().unwrap();
+        let store = KeyStore::new(dir.path().join(KEY_FILE_NAME));
+        let old = store.load_or_create().unwrap();
+        let new = Encryptor::generate_key();
+
+        store.stage(&new, None).await.unwrap();
+        store.discard_staged();
+        assert_eq!(store.load_or_create().unwrap(), old);
+
+        store.stage(&new, Some("pass")).await.unwrap();
+        store.commit_staged().unwrap();
+        assert_eq!(store.unlock("pass").await.unwrap(), new);
+    }
+
+    #[test]
+    fn test_unreadable_key_is_not_replaced() {
+        let dir = TempDir::new().unwrap();
+        let store = KeyStore::new(dir.path().join(KEY_FILE_NAME));
+        store.load_or_create().unwrap();
+
+        // A half-written key file is an error, and stays as it was
+        write_private(store.path(), b"{\"version\": 1, \"kdf\"").unwrap();
+        assert!(store.load_or_create().is_err());
+        assert!(store.is_protected().is_err());
+        assert_eq!(fs::read(store.path()).unwrap(), b"{\"version\": 1, \"kdf\"");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(store.path(), fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(store.load_or_create().is_err());
+            assert_eq!(fs::read(store.path()).unwrap(), b"{\"version\": 1, \"kdf\"");
+        }
+    }
+}
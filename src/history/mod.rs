@@ -1,13 +1,25 @@
 //! Clipboard history management and persistence
 
+pub mod archive;
 pub mod database;
 pub mod encryption;
+pub mod keystore;
+pub mod retention;
+pub mod search;
 
-use anyhow::Result;
+use crate::clipboard::ClipboardSelection;
+use crate::sync::HybridTimestamp;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::path::Path;
+use tokio::sync::{Mutex, RwLock};
 use uuid::Uuid;
 
+pub use keystore::{HistoryLocked, KeyStore};
+pub use retention::RetentionPolicy;
+pub use search::SearchQuery;
+
 /// Content to be stored in clipboard history
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardContent {
@@ -21,6 +33,22 @@ pub struct ClipboardContent {
     pub timestamp: i64,
     /// UUID of the node that created this content
     pub origin_node: Uuid,
+    /// Selection the content was captured from
+    #[serde(default)]
+    pub selection: ClipboardSelection,
+    /// Unix timestamp after which the entry is deleted
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Position in the cross-node order of entries
+    #[serde(default)]
+    pub hlc: Option<HybridTimestamp>,
+}
+
+impl ClipboardContent {
+    /// Whether the entry's time to live has run out
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
 }
 
 /// Entry retrieved from clipboard history
@@ -40,41 +68,343 @@ pub struct HistoryEntry {
     pub origin_node: Uuid,
     /// SHA-256 checksum of content
     pub checksum: String,
+    /// Selection the content was captured from
+    #[serde(default)]
+    pub selection: ClipboardSelection,
+    /// Whether the entry is exempt from retention pruning
+    #[serde(default)]
+    pub pinned: bool,
+    /// Unix timestamp after which the entry is deleted, pinned or not
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Position in the cross-node order of entries; missing for entries
+    /// recorded before history kept it
+    #[serde(default)]
+    pub hlc: Option<HybridTimestamp>,
+}
+
+/// Identity of a stored entry, readable without the history key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntrySummary {
+    /// Unique identifier
+    pub id: Uuid,
+    /// SHA-256 checksum of content
+    pub checksum: String,
+    /// Unix timestamp
+    pub timestamp: i64,
 }
 
 /// Main interface for clipboard history management
+///
+/// When the master key is passphrase-protected, history starts locked: new
+/// entries are held in memory and reads fail with [`HistoryLocked`] until
+/// [`Self::unlock`] is called.
 pub struct ClipboardHistory {
     db: database::HistoryDatabase,
-    encryptor: encryption::Encryptor,
+    keys: KeyStore,
+    encryptor: RwLock<Option<encryption::Encryptor>>,
+    /// Entries added while locked, written on unlock
+    pending: Mutex<VecDeque<ClipboardContent>>,
+    pending_limit: usize,
 }
 
 impl ClipboardHistory {
     /// Create a new clipboard history instance
     pub async fn new(db_path: &Path) -> Result<Self> {
-        let encryptor = encryption::Encryptor::new().await?;
-        let db = database::HistoryDatabase::new(db_path, encryptor.get_key()).await?;
+        Self::with_retention(db_path, RetentionPolicy::default()).await
+    }
+
+    /// Create a clipboard history instance pruned by the given policy
+    pub async fn with_retention(db_path: &Path, retention: RetentionPolicy) -> Result<Self> {
+        Self::with_key_store(db_path, KeyStore::default_location()?, retention).await
+    }
+
+    /// Create a clipboard history instance using the given key file
+    pub async fn with_key_store(
+        db_path: &Path,
+        keys: KeyStore,
+        retention: RetentionPolicy,
+    ) -> Result<Self> {
+        let pending_limit = retention.max_entries;
+        let db = database::HistoryDatabase::new(db_path)
+            .await?
+            .with_retention(retention);
+
+        // Apply a policy that may have tightened since the last run
+        db.prune().await?;
+
+        let encryptor = match keys.load_or_create() {
+            Ok(key) => Some(encryption::Encryptor::from_key(key)?),
+            Err(e) if e.is::<HistoryLocked>() => {
+                tracing::info!("History key is passphrase-protected; history is locked");
+                None
+            }
+            Err(e) => return Err(e),
+        };
+
+        if let Some(encryptor) = &encryptor {
+            Self::reindex(&db, encryptor).await?;
+        }
+
+        Ok(Self {
+            db,
+            keys,
+            encryptor: RwLock::new(encryptor),
+            pending: Mutex::new(VecDeque::new()),
+            pending_limit,
+        })
+    }
+
+    async fn reindex(
+        db: &database::HistoryDatabase,
+        encryptor: &encryption::Encryptor,
+    ) -> Result<()> {
+        // Entries from before the search index need their tokens
+        let indexed = db.reindex(encryptor).await?;
+        if indexed > 0 {
+            tracing::info!("Indexed {} existing history entries for search", indexed);
+        }
+        Ok(())
+    }
+
+    /// Whether history is waiting for its passphrase
+    pub async fn is_locked(&self) -> bool {
+        self.encryptor.read().await.is_none()
+    }
+
+    /// Whether the master key is passphrase-protected
+    pub fn is_protected(&self) -> Result<bool> {
+        self.keys.is_protected()
+    }
+
+    /// Unlock history with its passphrase and store entries held while locked
+    pub async fn unlock(&self, passphrase: &str) -> Result<()> {
+        let key = self.keys.unlock(passphrase).await?;
+        let encryptor = encryption::Encryptor::from_key(key)?;
+
+        let mut guard = self.encryptor.write().await;
+        let now = chrono::Utc::now().timestamp();
+        let pending: Vec<_> = self
+            .pending
+            .lock()
+            .await
+            .drain(..)
+            .filter(|content| !content.is_expired(now))
+            .collect();
+        for content in &pending {
+            self.db.insert(content, &encryptor).await?;
+        }
+        if !pending.is_empty() {
+            tracing::info!(
+                "Stored {} entries captured while history was locked",
+                pending.len()
+            );
+        }
+
+        Self::reindex(&self.db, &encryptor).await?;
+        *guard = Some(encryptor);
+
+        tracing::info!("History unlocked");
+        Ok(())
+    }
+
+    /// Forget the key until the next [`Self::unlock`]
+    pub async fn lock(&self) -> Result<()> {
+        if !self.keys.is_protected()? {
+            return Err(anyhow!(
+                "History has no passphrase; set one with 'clipsync history passphrase'"
+            ));
+        }
+
+        *self.encryptor.write().await = None;
+        tracing::info!("History locked");
+        Ok(())
+    }
+
+    /// Protect the master key with a new passphrase, or remove protection
+    ///
+    /// If the key is already protected, `current` must be its passphrase.
+    pub async fn set_passphrase(&self, current: Option<&str>, new: Option<&str>) -> Result<()> {
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        self.verify_passphrase(current).await?;
+
+        match new {
+            Some(passphrase) => {
+                self.keys
+                    .save_protected(encryptor.get_key(), passphrase)
+                    .await
+            }
+            None => self.keys.save(encryptor.get_key()),
+        }
+    }
+
+    /// Re-encrypt all entries under a fresh master key
+    ///
+    /// A protected key stays protected by the same passphrase, which must be
+    /// given. Returns the number of re-encrypted entries.
+    pub async fn rekey(&self, passphrase: Option<&str>) -> Result<usize> {
+        let mut guard = self.encryptor.write().await;
+        let old = guard.as_ref().ok_or(HistoryLocked)?;
+        let protected = self.verify_passphrase(passphrase).await?;
+
+        let key = encryption::Encryptor::generate_key();
+        let new = encryption::Encryptor::from_key(key)?;
+
+        // Stage the key first so it is on disk before any row uses it
+        self.keys
+            .stage(&key, passphrase.filter(|_| protected))
+            .await?;
+
+        match self.db.rekey(old, &new).await {
+            Ok(count) => {
+                self.keys.commit_staged()?;
+                *guard = Some(new);
+                Ok(count)
+            }
+            Err(e) => {
+                self.keys.discard_staged();
+                Err(e)
+            }
+        }
+    }
 
-        Ok(Self { db, encryptor })
+    /// Check the passphrase of a protected key; returns whether it is protected
+    async fn verify_passphrase(&self, passphrase: Option<&str>) -> Result<bool> {
+        if !self.keys.is_protected()? {
+            return Ok(false);
+        }
+
+        let passphrase =
+            passphrase.ok_or_else(|| anyhow!("The current history passphrase is required"))?;
+        self.keys.unlock(passphrase).await?;
+        Ok(true)
     }
 
     /// Add new content to history
+    ///
+    /// While locked, content is held in memory until the next unlock.
     pub async fn add(&self, content: &ClipboardContent) -> Result<()> {
-        self.db.insert(content, &self.encryptor).await
+        let guard = self.encryptor.read().await;
+        match guard.as_ref() {
+            Some(encryptor) => self.db.insert(content, encryptor).await,
+            None => {
+                let mut pending = self.pending.lock().await;
+                let now = chrono::Utc::now().timestamp();
+                pending.retain(|content| !content.is_expired(now));
+                if pending.len() >= self.pending_limit {
+                    pending.pop_front();
+                }
+                pending.push_back(content.clone());
+                Ok(())
+            }
+        }
     }
 
     /// Get the most recent entries from history
     pub async fn get_recent(&self, count: usize) -> Result<Vec<HistoryEntry>> {
-        self.db.get_recent(count, &self.encryptor).await
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        self.db.get_recent(count, encryptor).await
     }
 
     /// Get entry by index (0 = most recent)
     pub async fn get_by_index(&self, index: u8) -> Result<HistoryEntry> {
-        self.db.get_by_index(index, &self.encryptor).await
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        self.db.get_by_index(index, encryptor).await
+    }
+
+    /// Search entries matching the query, newest first
+    pub async fn search(&self, query: &SearchQuery, limit: usize) -> Result<Vec<HistoryEntry>> {
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        self.db.search(query, limit, encryptor).await
+    }
+
+    /// Find the newest entry with the given SHA-256 content checksum
+    pub async fn get_by_checksum(&self, checksum: &str) -> Result<Option<HistoryEntry>> {
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        self.db.get_by_checksum(checksum, encryptor).await
+    }
+
+    /// Summaries of entries created at or after `since`, newest first
+    pub async fn summaries_since(&self, since: i64, limit: usize) -> Result<Vec<EntrySummary>> {
+        self.db.summaries_since(since, limit).await
+    }
+
+    /// IDs and deadlines of entries with a time to live
+    pub async fn expiring(&self) -> Result<Vec<(Uuid, i64)>> {
+        self.db.expiring().await
+    }
+
+    /// Get the entries with the given IDs, newest first
+    ///
+    /// Unknown IDs are skipped.
+    pub async fn get_by_ids(&self, ids: &[Uuid]) -> Result<Vec<HistoryEntry>> {
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        self.db.get_by_ids(ids, encryptor).await
     }
 
-    /// Search text entries for matching content
-    pub async fn search(&self, query: &str) -> Result<Vec<HistoryEntry>> {
-        self.db.search(query, &self.encryptor).await
+    /// Add entries received from another node, keeping their IDs and
+    /// timestamps
+    ///
+    /// Entries already in history (by ID or checksum) are skipped. Returns
+    /// the number of added entries.
+    pub async fn merge(&self, entries: &[HistoryEntry]) -> Result<usize> {
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        self.db.import(entries, encryptor).await
+    }
+
+    /// Pin or unpin an entry; returns `false` if it doesn't exist
+    pub async fn set_pinned(&self, id: Uuid, pinned: bool) -> Result<bool> {
+        self.db.set_pinned(id, pinned).await
+    }
+
+    /// Set when an entry expires; returns `false` if it doesn't exist
+    pub async fn set_expires_at(&self, id: Uuid, expires_at: i64) -> Result<bool> {
+        self.db.set_expires_at(id, expires_at).await
+    }
+
+    /// Write every entry to a passphrase-encrypted archive
+    ///
+    /// Returns the number of exported entries.
+    pub async fn export(&self, path: &Path, passphrase: &str) -> Result<usize> {
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        let entries = self.db.get_all(encryptor).await?;
+        let data = archive::seal(&entries, passphrase).await?;
+        std::fs::write(path, data)?;
+        Ok(entries.len())
+    }
+
+    /// Import entries from an archive created by [`Self::export`]
+    ///
+    /// Entries already in history (by checksum) are skipped, and imported
+    /// entries keep their original timestamps. Returns the imported and
+    /// total entry counts.
+    pub async fn import(&self, path: &Path, passphrase: &str) -> Result<(usize, usize)> {
+        let guard = self.encryptor.read().await;
+        let encryptor = guard.as_ref().ok_or(HistoryLocked)?;
+        let data = std::fs::read(path)?;
+        let entries = archive::open(&data, passphrase).await?;
+        let imported = self.db.import(&entries, encryptor).await?;
+        Ok((imported, entries.len()))
+    }
+
+    /// Delete an entry, including one held while locked; returns `false`
+    /// if it doesn't exist
+    pub async fn delete(&self, id: Uuid) -> Result<bool> {
+        let mut pending = self.pending.lock().await;
+        let held = pending.len();
+        pending.retain(|content| content.id != id);
+        let was_pending = pending.len() < held;
+        drop(pending);
+
+        Ok(self.db.delete(id).await? || was_pending)
     }
 
     /// Clear all history entries
@@ -82,3 +412,124 @@ impl ClipboardHistory {
         self.db.clear().await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn open(dir: &TempDir) -> ClipboardHistory {
+        ClipboardHistory::with_key_store(
+            &dir.path().join("history.db"),
+            KeyStore::new(dir.path().join("history.key")),
+            RetentionPolicy::default(),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn content(text: &str) -> ClipboardContent {
+        ClipboardContent {
+            id: Uuid::new_v4(),
+            content: text.as_bytes().to_vec(),
+            content_type: "text/plain".to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            origin_node: Uuid::new_v4(),
+            selection: ClipboardSelection::Clipboard,
+            expires_at: None,
+            hlc: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expiring_entries_held_while_locked() {
+        let dir = TempDir::new().unwrap();
+        let history = open(&dir).await;
+        history.set_passphrase(None, Some("hunter2")).await.unwrap();
+        drop(history);
+
+        let history = open(&dir).await;
+        let now = chrono::Utc::now().timestamp();
+        let expired = ClipboardContent {
+            expires_at: Some(now),
+            ..content("482913")
+        };
+        let deleted = ClipboardContent {
+            expires_at: Some(now + 60),
+            ..content("correct horse")
+        };
+        history.add(&expired).await.unwrap();
+        history.add(&deleted).await.unwrap();
+        history.add(&content("kept")).await.unwrap();
+
+        // The expiry timer removes an entry that was never written
+        assert!(history.delete(deleted.id).await.unwrap());
+        assert!(!history.delete(deleted.id).await.unwrap());
+
+        history.unlock("hunter2").await.unwrap();
+        let entries = history.get_recent(10).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content, b"kept");
+    }
+
+    #[tokio::test]
+    async fn test_locked_history() {
+        let dir = TempDir::new().unwrap();
+        let history = open(&dir).await;
+        history.add(&content("before")).await.unwrap();
+        history.set_passphrase(None, Some("hunter2")).await.unwrap();
+        drop(history);
+
+        // A protected key leaves history locked on open
+        let history = open(&dir).await;
+        assert!(history.is_locked().await);
+        let err = history.get_recent(10).await.unwrap_err();
+        assert!(err.is::<HistoryLocked>());
+
+        // Entries added while locked are kept until unlock
+        history.add(&content("while locked")).await.unwrap();
+        assert!(history.unlock("wrong").await.is_err());
+        history.unlock("hunter2").await.unwrap();
+
+        let entries = history.get_recent(10).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            history
+                .search(&SearchQuery::new("locked"), 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        history.lock().await.unwrap();
+        assert!(history.is_locked().await);
+    }
+
+    #[tokio::test]
+    async fn test_rekey() {
+        let dir = TempDir::new().unwrap();
+        let history = open(&dir).await;
+        history.add(&content("rotate me")).await.unwrap();
+        history.set_passphrase(None, Some("pass")).await.unwrap();
+
+        let old_key = std::fs::read(dir.path().join("history.key")).unwrap();
+        assert!(history.rekey(None).await.is_err());
+        assert_eq!(history.rekey(Some("pass")).await.unwrap(), 1);
+        assert_ne!(
+            std::fs::read(dir.path().join("history.key")).unwrap(),
+            old_key
+        );
+
+        // Reopening with the rotated key reads the re-encrypted entries
+        drop(history);
+        let history = open(&dir).await;
+        history.unlock("pass").await.unwrap();
+        let found = history
+            .search(&SearchQuery::new("rotate"), 10)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].content, b"rotate me");
+    }
+}
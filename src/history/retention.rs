@@ -0,0 +1,118 @@
+//! Retention policy for pruning clipboard history
+
+use crate::config::ClipboardConfig;
+use std::time::Duration;
+
+/// Number of entries kept when no policy is configured
+pub const DEFAULT_MAX_ENTRIES: usize = 20;
+
+/// Limits applied to the history table after every insert
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    /// Maximum number of entries overall
+    pub max_entries: usize,
+    /// Maximum total content size in bytes
+    pub max_bytes: Option<u64>,
+    /// Maximum age of an entry
+    pub max_age: Option<Duration>,
+    /// Per content type entry limits
+    pub per_type: Vec<TypeLimit>,
+}
+
+/// Entry limit for one content type or family of content types
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeLimit {
+    /// MIME type (`image/png`) or wildcard prefix (`image/*`)
+    pub pattern: String,
+    /// Maximum number of entries matching the pattern
+    pub max_entries: usize,
+}
+
+impl TypeLimit {
+    /// Translate the pattern into a SQL `LIKE` pattern (escape char `\`)
+    pub fn like_pattern(&self) -> String {
+        mime_like_pattern(&self.pattern)
+    }
+}
+
+/// Translate a MIME type or `type/*` pattern into a SQL `LIKE` pattern
+/// using `\` as the escape character
+pub fn mime_like_pattern(pattern: &str) -> String {
+    let (base, wildcard) = match pattern.strip_suffix('*') {
+        Some(prefix) => (prefix, true),
+        None => (pattern, false),
+    };
+
+    let mut like = base
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    if wildcard {
+        like.push('%');
+    }
+    like
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: None,
+            max_age: None,
+            per_type: Vec::new(),
+        }
+    }
+}
+
+impl From<&ClipboardConfig> for RetentionPolicy {
+    fn from(config: &ClipboardConfig) -> Self {
+        let retention = &config.retention;
+
+        Self {
+            max_entries: config.history_size,
+            max_bytes: retention.max_bytes,
+            // Invalid values are rejected when the config is loaded
+            max_age: retention.max_age().ok().flatten(),
+            per_type: retention
+                .per_type
+                .iter()
+                .map(|(pattern, max_entries)| TypeLimit {
+                    pattern: pattern.clone(),
+                    max_entries: *max_entries,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_like_pattern() {
+        let limit = |pattern: &str| TypeLimit {
+            pattern: pattern.to_string(),
+            max_entries: 1,
+        };
+
+        assert_eq!(limit("image/*").like_pattern(), "image/%");
+        assert_eq!(limit("text/plain").like_pattern(), "text/plain");
+        assert_eq!(limit("x_custom/*").like_pattern(), "x\\_custom/%");
+    }
+
+    #[test]
+    fn test_policy_from_config() {
+        let mut config = ClipboardConfig::default();
+        config.history_size = 50;
+        config.retention.max_bytes = Some(1024);
+        config.retention.max_age = Some("2d".to_string());
+        config.retention.per_type.insert("image/*".to_string(), 3);
+
+        let policy = RetentionPolicy::from(&config);
+        assert_eq!(policy.max_entries, 50);
+        assert_eq!(policy.max_bytes, Some(1024));
+        assert_eq!(policy.max_age, Some(Duration::from_secs(2 * 86_400)));
+        assert_eq!(policy.per_type.len(), 1);
+    }
+}
@@ -0,0 +1,283 @@
+//! Search queries over encrypted clipboard history
+//!
+//! Text entries are indexed as keyed hashes of their tokens and token
+//! prefixes (see [`Encryptor::index_token`]), so the index narrows down
+//! candidates without putting plaintext on disk. Candidates are then
+//! decrypted and checked against the full query.
+//!
+//! [`Encryptor::index_token`]: crate::history::encryption::Encryptor::index_token
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Longest token prefix with its own index key
+const MAX_PREFIX_CHARS: usize = 12;
+/// Tokens are truncated to this many characters before indexing
+const MAX_TOKEN_CHARS: usize = 64;
+/// Entries producing more index keys than this are not indexed and are
+/// always scanned instead
+const MAX_INDEX_KEYS: usize = 20_000;
+
+/// History search request: query text plus metadata filters
+///
+/// The query text is a list of terms that must all match:
+/// - `word` matches a whole word (case-insensitive)
+/// - `pre*` matches words starting with `pre`
+/// - `"exact phrase"` matches consecutive words
+/// - `/regex/` matches the raw text against a regular expression
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchQuery {
+    /// Query text
+    #[serde(default)]
+    pub text: String,
+    /// Only entries created by this node
+    #[serde(default)]
+    pub origin: Option<Uuid>,
+    /// Only entries of this MIME type, or a prefix such as `image/*`
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Only entries created at or after this time
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries created at or before this time
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// A single parsed query term
+#[derive(Debug, Clone)]
+pub enum QueryTerm {
+    /// Whole word
+    Word(String),
+    /// Word prefix
+    Prefix(String),
+    /// Consecutive words
+    Phrase(Vec<String>),
+    /// Regular expression over the raw text
+    Regex(Regex),
+}
+
+impl SearchQuery {
+    /// Query matching the given text without further filters
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the query has text terms (and so only matches text entries)
+    pub fn has_text(&self) -> bool {
+        !self.text.trim().is_empty()
+    }
+
+    /// Parse the query text into terms
+    pub fn terms(&self) -> Result<Vec<QueryTerm>> {
+        let mut terms = Vec::new();
+        let mut chars = self.text.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+
+            match c {
+                '"' => {
+                    chars.next();
+                    let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                    let words = tokenize(&phrase);
+                    match words.len() {
+                        0 => {}
+                        1 => terms.push(QueryTerm::Word(words[0].clone())),
+                        _ => terms.push(QueryTerm::Phrase(words)),
+                    }
+                }
+                '/' => {
+                    chars.next();
+                    let mut pattern = String::new();
+                    let mut closed = false;
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' if chars.peek() == Some(&'/') => {
+                                pattern.push('/');
+                                chars.next();
+                            }
+                            '/' => {
+                                closed = true;
+                                break;
+                            }
+                            _ => pattern.push(c),
+                        }
+                    }
+                    if !closed {
+                        return Err(anyhow!("Unterminated regex in search query"));
+                    }
+                    let regex = Regex::new(&pattern)
+                        .map_err(|e| anyhow!("Invalid regex /{}/: {}", pattern, e))?;
+                    terms.push(QueryTerm::Regex(regex));
+                }
+                _ => {
+                    let mut chunk = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        chunk.push(c);
+                        chars.next();
+                    }
+
+                    let is_prefix = chunk.ends_with('*');
+                    let mut words = tokenize(chunk.trim_end_matches('*'));
+                    let last = words.pop();
+                    terms.extend(words.into_iter().map(QueryTerm::Word));
+                    if let Some(last) = last {
+                        terms.push(if is_prefix {
+                            QueryTerm::Prefix(last)
+                        } else {
+                            QueryTerm::Word(last)
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(terms)
+    }
+}
+
+impl QueryTerm {
+    /// Index keys an entry must have to possibly match this term
+    pub fn index_keys(&self) -> Vec<String> {
+        match self {
+            QueryTerm::Word(word) => vec![word_key(word)],
+            QueryTerm::Prefix(prefix) => vec![prefix_key(prefix)],
+            QueryTerm::Phrase(words) => words.iter().map(|w| word_key(w)).collect(),
+            QueryTerm::Regex(_) => Vec::new(),
+        }
+    }
+
+    fn matches(&self, text: &str, tokens: &[String]) -> bool {
+        match self {
+            QueryTerm::Word(word) => tokens.iter().any(|t| t == word),
+            QueryTerm::Prefix(prefix) => tokens.iter().any(|t| t.starts_with(prefix.as_str())),
+            QueryTerm::Phrase(words) => tokens.windows(words.len()).any(|w| w == &words[..]),
+            QueryTerm::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// Check decrypted text against every term
+pub fn matches_all(terms: &[QueryTerm], text: &str) -> bool {
+    let tokens = tokenize(text);
+    terms.iter().all(|term| term.matches(text, &tokens))
+}
+
+/// Split text into lowercase words
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Index keys for a text entry, or `None` if it has too many to index
+pub fn index_keys(text: &str) -> Option<HashSet<String>> {
+    let mut keys = HashSet::new();
+
+    for token in tokenize(text) {
+        keys.insert(word_key(&token));
+        for (count, _) in token.chars().enumerate().take(MAX_PREFIX_CHARS) {
+            keys.insert(prefix_key(
+                &token.chars().take(count + 1).collect::<String>(),
+            ));
+        }
+
+        if keys.len() > MAX_INDEX_KEYS {
+            return None;
+        }
+    }
+
+    Some(keys)
+}
+
+fn word_key(word: &str) -> String {
+    format!(
+        "t:{}",
+        word.chars().take(MAX_TOKEN_CHARS).collect::<String>()
+    )
+}
+
+fn prefix_key(prefix: &str) -> String {
+    format!(
+        "p:{}",
+        prefix.chars().take(MAX_PREFIX_CHARS).collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str, text: &str) -> bool {
+        let terms = SearchQuery::new(query).terms().unwrap();
+        matches_all(&terms, text)
+    }
+
+    #[test]
+    fn test_parse_terms() {
+        let terms = SearchQuery::new(r#"deploy prod* "rm -rf" /\d{3}\/x/"#)
+            .terms()
+            .unwrap();
+
+        assert!(matches!(&terms[0], QueryTerm::Word(w) if w == "deploy"));
+        assert!(matches!(&terms[1], QueryTerm::Prefix(p) if p == "prod"));
+        assert!(matches!(&terms[2], QueryTerm::Phrase(p) if p == &["rm", "rf"]));
+        assert!(matches!(&terms[3], QueryTerm::Regex(r) if r.as_str() == r"\d{3}/x"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(SearchQuery::new("/unterminated").terms().is_err());
+        assert!(SearchQuery::new("/(/").terms().is_err());
+    }
+
+    #[test]
+    fn test_matching() {
+        let text = "ssh deploy@prod-01.example.com -p 2222";
+
+        assert!(search("deploy", text));
+        assert!(search("DEPLOY", text));
+        assert!(!search("dep", text));
+        assert!(search("dep*", text));
+        assert!(search("\"example com\"", text));
+        assert!(!search("\"com example\"", text));
+        assert!(search(r"/-p \d+/", text));
+        assert!(search("ssh prod*", text));
+        assert!(!search("ssh staging", text));
+    }
+
+    #[test]
+    fn test_query_keys_are_indexed() {
+        let text = "Deploy the production build";
+        let keys = index_keys(text).unwrap();
+
+        for query in ["deploy", "prod*", "\"production build\"", "productio*"] {
+            for term in SearchQuery::new(query).terms().unwrap() {
+                for key in term.index_keys() {
+                    assert!(keys.contains(&key), "{} missing {}", query, key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_keys_limit() {
+        let text: String = (0..12_000).map(|i| format!("word{} ", i)).collect();
+        assert!(index_keys(&text).is_none());
+    }
+}
@@ -23,6 +23,8 @@ pub mod cli;
 pub mod clipboard;
 pub mod config;
 #[cfg(target_os = "linux")]
+pub mod control;
+#[cfg(target_os = "linux")]
 pub mod daemon;
 pub mod discovery;
 pub mod history;
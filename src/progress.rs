@@ -367,7 +367,7 @@ impl TransferProgress {
 }
 
 /// Format bytes in human-readable format
-fn format_bytes(bytes: u64) -> String {
+pub fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
     let mut size = bytes as f64;
     let mut unit_index = 0;
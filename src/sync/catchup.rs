@@ -0,0 +1,240 @@
+//! History catch-up between peers
+//!
+//! A peer that was disconnected misses everything copied elsewhere in the
+//! meantime. When the connection comes back, the two sides compare recent
+//! history by range digest: entries are grouped into fixed time ranges, and
+//! each range is summarised by a hash over its content checksums. Only the
+//! ranges whose digests differ are listed entry by entry, and entries are
+//! matched by checksum, so content both sides already hold is never sent.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::{ConfigError, SyncConfig};
+use crate::history::EntrySummary;
+use crate::transport::protocol::{DigestBucket, HistoryDigest, HistoryInventory};
+
+/// Width of a digest range
+pub const DEFAULT_BUCKET_SECS: u32 = 3600;
+
+/// How much history a catch-up may move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatchUpLimits {
+    /// How far back to compare history
+    pub window: Duration,
+    /// Most entries requested from or sent to a peer
+    pub max_entries: usize,
+    /// Most content bytes sent to a peer
+    pub max_bytes: usize,
+}
+
+impl CatchUpLimits {
+    /// Limits from the `[sync]` settings
+    pub fn from_config(config: &SyncConfig) -> Result<Self, ConfigError> {
+        Ok(Self {
+            window: config.catch_up_window()?,
+            max_entries: config.catch_up_max_entries,
+            max_bytes: config.catch_up_max_bytes,
+        })
+    }
+}
+
+/// What each side of a catch-up lacks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// Entries to request from the peer, newest first
+    pub missing: Vec<Uuid>,
+    /// Local entries the peer lacks, newest first
+    pub unknown_to_peer: Vec<Uuid>,
+}
+
+/// Digest of the entries created at or after `since`
+pub fn digest(entries: &[EntrySummary], since: i64, bucket_secs: u32) -> HistoryDigest {
+    let buckets = buckets(entries, since, bucket_secs)
+        .into_iter()
+        .map(|(start, checksums)| DigestBucket {
+            start,
+            count: checksums.len() as u32,
+            hash: bucket_hash(&checksums),
+        })
+        .collect();
+
+    HistoryDigest {
+        since,
+        bucket_secs,
+        buckets,
+    }
+}
+
+/// Local entries in every range where `remote` differs
+///
+/// Returns `None` when both histories hold the same content.
+pub fn inventory(local: &[EntrySummary], remote: &HistoryDigest) -> Option<HistoryInventory> {
+    let bucket_secs = remote.bucket_secs.max(1);
+    let ours: BTreeMap<i64, String> = digest(local, remote.since, bucket_secs)
+        .buckets
+        .into_iter()
+        .map(|bucket| (bucket.start, bucket.hash))
+        .collect();
+    let theirs: BTreeMap<i64, &str> = remote
+        .buckets
+        .iter()
+        .map(|bucket| (bucket.start, bucket.hash.as_str()))
+        .collect();
+
+    let ranges: BTreeSet<i64> = ours
+        .keys()
+        .chain(theirs.keys())
+        .filter(|start| ours.get(start).map(String::as_str) != theirs.get(start).copied())
+        .copied()
+        .collect();
+    if ranges.is_empty() {
+        return None;
+    }
+
+    let entries = local
+        .iter()
+        .filter(|entry| {
+            entry.timestamp >= remote.since
+                && ranges.contains(&bucket_start(entry.timestamp, bucket_secs))
+        })
+        .cloned()
+        .collect();
+
+    Some(HistoryInventory {
+        ranges: ranges.into_iter().collect(),
+        bucket_secs,
+        entries,
+    })
+}
+
+/// Compare local history with the peer's inventory
+pub fn reconcile(local: &[EntrySummary], inventory: &HistoryInventory) -> Reconciliation {
+    let bucket_secs = inventory.bucket_secs.max(1);
+    let ranges: HashSet<i64> = inventory.ranges.iter().copied().collect();
+    let ours: HashSet<&str> = local.iter().map(|e| e.checksum.as_str()).collect();
+    let theirs: HashSet<&str> = inventory
+        .entries
+        .iter()
+        .map(|e| e.checksum.as_str())
+        .collect();
+
+    let missing = newest_unique(
+        inventory
+            .entries
+            .iter()
+            .filter(|entry| !ours.contains(entry.checksum.as_str())),
+    );
+    let unknown_to_peer = newest_unique(local.iter().filter(|entry| {
+        ranges.contains(&bucket_start(entry.timestamp, bucket_secs))
+            && !theirs.contains(entry.checksum.as_str())
+    }));
+
+    Reconciliation {
+        missing,
+        unknown_to_peer,
+    }
+}
+
+/// IDs newest first, one per content
+fn newest_unique<'a>(entries: impl Iterator<Item = &'a EntrySummary>) -> Vec<Uuid> {
+    let mut entries: Vec<&EntrySummary> = entries.collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp));
+
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .filter(|entry| seen.insert(entry.checksum.as_str()))
+        .map(|entry| entry.id)
+        .collect()
+}
+
+fn buckets(
+    entries: &[EntrySummary],
+    since: i64,
+    bucket_secs: u32,
+) -> BTreeMap<i64, BTreeSet<&str>> {
+    let mut buckets: BTreeMap<i64, BTreeSet<&str>> = BTreeMap::new();
+    for entry in entries.iter().filter(|entry| entry.timestamp >= since) {
+        buckets
+            .entry(bucket_start(entry.timestamp, bucket_secs))
+            .or_default()
+            .insert(&entry.checksum);
+    }
+    buckets
+}
+
+/// Ranges are aligned to the epoch so both sides agree on their bounds
+fn bucket_start(timestamp: i64, bucket_secs: u32) -> i64 {
+    let width = i64::from(bucket_secs.max(1));
+    timestamp.div_euclid(width) * width
+}
+
+fn bucket_hash(checksums: &BTreeSet<&str>) -> String {
+    let mut hasher = Sha256::new();
+    for checksum in checksums {
+        hasher.update(checksum.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = DEFAULT_BUCKET_SECS as i64;
+
+    fn summary(checksum: &str, timestamp: i64) -> EntrySummary {
+        EntrySummary {
+            id: Uuid::new_v4(),
+            checksum: checksum.to_string(),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_digest_ignores_ids_and_order() {
+        let a = vec![summary("x", 10 * HOUR), summary("y", 10 * HOUR + 5)];
+        let b = vec![
+            summary("y", 10 * HOUR + 5),
+            summary("x", 10 * HOUR),
+            summary("x", 10 * HOUR + 60),
+        ];
+
+        let digest_a = digest(&a, 0, DEFAULT_BUCKET_SECS);
+        assert_eq!(digest_a.buckets, digest(&b, 0, DEFAULT_BUCKET_SECS).buckets);
+        assert_eq!(digest_a.buckets.len(), 1);
+        assert_eq!(digest_a.buckets[0].count, 2);
+        assert!(inventory(&b, &digest_a).is_none());
+
+        // Entries before the window are left out
+        assert!(digest(&a, 11 * HOUR, DEFAULT_BUCKET_SECS)
+            .buckets
+            .is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_lists_only_differing_ranges() {
+        let shared = summary("shared", 10 * HOUR);
+        let laptop = vec![shared.clone(), summary("laptop", 12 * HOUR)];
+        let desktop = vec![
+            shared,
+            summary("older", 11 * HOUR),
+            summary("newer", 11 * HOUR + 30),
+        ];
+
+        // The laptop reconnects and sends its digest
+        let request = digest(&laptop, 0, DEFAULT_BUCKET_SECS);
+        let inventory = inventory(&desktop, &request).unwrap();
+        assert_eq!(inventory.ranges, vec![11 * HOUR, 12 * HOUR]);
+        assert_eq!(inventory.entries.len(), 2);
+
+        let result = reconcile(&laptop, &inventory);
+        assert_eq!(result.missing, vec![desktop[2].id, desktop[1].id]);
+        assert_eq!(result.unknown_to_peer, vec![laptop[1].id]);
+    }
+}
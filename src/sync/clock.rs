@@ -0,0 +1,156 @@
+//! Hybrid logical clock for ordering clipboard entries across nodes
+//!
+//! A [`HybridTimestamp`] pairs wall-clock milliseconds with a logical
+//! counter. A node's clock never runs backwards and always moves past every
+//! timestamp it has received, so an entry copied after another one was seen
+//! orders after it even when the two machines' clocks disagree.
+//!
+//! Timestamps are totally ordered by wall time, then logical counter, then
+//! node ID. Every node sorts the same set of entries the same way, so
+//! concurrent copies resolve to the same winner everywhere.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A point on the hybrid logical clock of one node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HybridTimestamp {
+    /// Milliseconds since the Unix epoch, never behind the node's wall clock
+    pub wall_ms: u64,
+    /// Counter ordering events within the same millisecond
+    pub logical: u32,
+    /// Node that produced the timestamp, the final tie-breaker
+    pub node: Uuid,
+}
+
+impl HybridTimestamp {
+    /// Timestamp for an event known only by its wall-clock time, such as a
+    /// history entry or a message from a peer without a clock
+    pub fn from_wall(wall_ms: u64, node: Uuid) -> Self {
+        Self {
+            wall_ms,
+            logical: 0,
+            node,
+        }
+    }
+}
+
+impl Ord for HybridTimestamp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.wall_ms, self.logical, self.node).cmp(&(other.wall_ms, other.logical, other.node))
+    }
+}
+
+impl PartialOrd for HybridTimestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for HybridTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}@{}", self.wall_ms, self.logical, self.node)
+    }
+}
+
+/// Hybrid logical clock of the local node
+#[derive(Debug)]
+pub struct HybridClock {
+    node: Uuid,
+    last: Mutex<HybridTimestamp>,
+}
+
+impl HybridClock {
+    /// Clock for the given node
+    pub fn new(node: Uuid) -> Self {
+        Self {
+            node,
+            last: Mutex::new(HybridTimestamp::from_wall(0, node)),
+        }
+    }
+
+    /// Timestamp a local event
+    pub fn now(&self) -> HybridTimestamp {
+        self.tick(wall_clock_ms(), None)
+    }
+
+    /// Move past a timestamp received from a peer
+    pub fn observe(&self, remote: &HybridTimestamp) -> HybridTimestamp {
+        self.tick(wall_clock_ms(), Some(remote))
+    }
+
+    fn tick(&self, wall_ms: u64, remote: Option<&HybridTimestamp>) -> HybridTimestamp {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+
+        let remote_ms = remote.map_or(0, |r| r.wall_ms);
+        let next_ms = wall_ms.max(last.wall_ms).max(remote_ms);
+
+        let logical = if next_ms == last.wall_ms && next_ms == remote_ms {
+            last.logical.max(remote.map_or(0, |r| r.logical)) + 1
+        } else if next_ms == last.wall_ms {
+            last.logical + 1
+        } else if next_ms == remote_ms {
+            remote.map_or(0, |r| r.logical) + 1
+        } else {
+            0
+        };
+
+        *last = HybridTimestamp {
+            wall_ms: next_ms,
+            logical,
+            node: self.node,
+        };
+        *last
+    }
+}
+
+fn wall_clock_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_is_monotonic() {
+        let clock = HybridClock::new(Uuid::new_v4());
+
+        let first = clock.tick(1_000, None);
+        // Wall clock stepped back, e.g. after an NTP correction
+        let second = clock.tick(900, None);
+        let third = clock.tick(1_000, None);
+
+        assert!(first < second && second < third);
+        assert_eq!(second.wall_ms, 1_000);
+        assert_eq!(third.logical, 2);
+    }
+
+    #[test]
+    fn test_clock_moves_past_skewed_peer() {
+        let fast = HybridClock::new(Uuid::new_v4());
+        let slow = HybridClock::new(Uuid::new_v4());
+
+        // The fast laptop is a minute ahead
+        let copied_on_fast = fast.tick(61_000, None);
+        slow.tick(1_000, Some(&copied_on_fast));
+
+        // A later copy on the slow laptop still orders after it
+        let copied_on_slow = slow.tick(2_000, None);
+        assert!(copied_on_slow > copied_on_fast);
+    }
+
+    #[test]
+    fn test_total_order_breaks_ties_by_node() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let at_a = HybridTimestamp::from_wall(5, a);
+        let at_b = HybridTimestamp::from_wall(5, b);
+
+        assert_ne!(at_a.cmp(&at_b), Ordering::Equal);
+        assert_eq!(at_a < at_b, a < b);
+    }
+}
@@ -0,0 +1,226 @@
+//! Clipboard content adapted to what a peer can render
+//!
+//! Peers list the formats they can render during capability negotiation.
+//! Content in any other format is converted when there is a sensible
+//! fallback, such as HTML to plain text, and otherwise not sent.
+
+use std::borrow::Cow;
+
+use crate::adapters::ClipboardData;
+use crate::transport::protocol::ClipboardFormat;
+use crate::transport::NegotiatedCapabilities;
+
+/// Elements whose content is never shown
+const HIDDEN_ELEMENTS: [&str; 3] = ["script", "style", "head"];
+
+/// Elements that start or end a line of text
+const BLOCK_ELEMENTS: [&str; 16] = [
+    "br",
+    "p",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+    "blockquote",
+    "ul",
+    "ol",
+    "table",
+];
+
+/// Content as the peer should receive it, or `None` if it cannot render it
+pub fn for_peer<'a>(
+    content: &'a ClipboardData,
+    capabilities: &NegotiatedCapabilities,
+) -> Option<Cow<'a, ClipboardData>> {
+    if capabilities.accepts(&content.format()) {
+        return Some(Cow::Borrowed(content));
+    }
+
+    match content {
+        ClipboardData::Html(html) if capabilities.accepts(&ClipboardFormat::Text) => {
+            Some(Cow::Owned(ClipboardData::Text(html_to_text(html))))
+        }
+        _ => None,
+    }
+}
+
+/// Plain text rendering of an HTML fragment
+///
+/// Tags are dropped, block elements become line breaks, runs of whitespace
+/// collapse to a single space and common entities are decoded.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut hidden: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if hidden.is_none() {
+            push_text(&mut text, &rest[..start]);
+        }
+
+        let end = match rest[start..].find('>') {
+            Some(end) => end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match &hidden {
+            Some(element) => {
+                if closing && name == *element {
+                    hidden = None;
+                }
+            }
+            None if !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) => {
+                hidden = Some(name);
+            }
+            None if BLOCK_ELEMENTS.contains(&name.as_str()) => {
+                let trimmed = text.trim_end_matches(' ').len();
+                text.truncate(trimmed);
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+            }
+            None => {}
+        }
+    }
+
+    if hidden.is_none() {
+        push_text(&mut text, rest);
+    }
+
+    text.trim().to_string()
+}
+
+/// Append HTML text content, collapsing whitespace and decoding entities
+fn push_text(text: &mut String, content: &str) {
+    for c in decode_entities(content).chars() {
+        if c.is_whitespace() {
+            if !text.is_empty() && !text.ends_with(' ') && !text.ends_with('\n') {
+                text.push(' ');
+            }
+        } else {
+            text.push(c);
+        }
+    }
+}
+
+fn decode_entities(content: &str) -> Cow<'_, str> {
+    if !content.contains('&') {
+        return Cow::Borrowed(content);
+    }
+
+    let mut decoded = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let value = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::protocol::CompressionMethod;
+
+    fn peer(formats: Vec<ClipboardFormat>) -> NegotiatedCapabilities {
+        NegotiatedCapabilities {
+            formats,
+            compression: CompressionMethod::None,
+            max_message_size: crate::MAX_PAYLOAD_SIZE,
+            streaming: true,
+        }
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<html><head><title>Ignored</title></head><body>
+            <h1>Release&nbsp;notes</h1>
+            <p>Fixes   <b>crash</b> &amp; <a href="x">hang</a>.</p>
+            <script>alert("no")</script>
+            <ul><li>one</li><li>two &#x2713;</li></ul>
+            line<br>break &lt;3 &bogus;
+        </body></html>"#;
+
+        assert_eq!(
+            html_to_text(html),
+            "Release notes\nFixes crash & hang.\none\ntwo \u{2713}\nline\nbreak <3 &bogus;"
+        );
+        assert_eq!(html_to_text("a < b"), "a < b");
+    }
+
+    #[test]
+    fn test_content_for_peer() {
+        let html = ClipboardData::Html("<p>Hello <i>world</i></p>".to_string());
+        let image = ClipboardData::Image {
+            mime_type: "image/png".to_string(),
+            data: vec![0x89, b'P', b'N', b'G'],
+        };
+
+        // Peers that render the format get it unchanged
+        let desktop = peer(crate::transport::capabilities::default_formats());
+        assert!(matches!(for_peer(&html, &desktop), Some(Cow::Borrowed(_))));
+        assert!(matches!(for_peer(&image, &desktop), Some(Cow::Borrowed(_))));
+
+        // Text-only peers get HTML as plain text and no images
+        let terminal = peer(vec![ClipboardFormat::Text]);
+        assert_eq!(
+            for_peer(&html, &terminal).map(Cow::into_owned),
+            Some(ClipboardData::Text("Hello world".to_string()))
+        );
+        assert!(for_peer(&image, &terminal).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+//! Named sync groups
+//!
+//! A device can belong to groups such as `personal` or `pairing`, listed
+//! under `[sync] groups` and announced to peers in the `groups` TXT record.
+//! Announcements are not authenticated, so a peer only counts as a member
+//! of the groups its `[[sync.policies]]` entry lists as well (see
+//! [`crate::sync::PeerPolicy::member_groups`]). That entry is found by the
+//! key the peer authenticated with, never by the node ID it claims.
+//!
+//! A local copy goes only to peers sharing one of the device's active
+//! groups, and is tagged with that group; a tagged entry is only applied
+//! by devices in its group, and only from a peer allowed in it. Devices
+//! without groups sync with everyone, as before groups existed, and ignore
+//! tags.
+
+use thiserror::Error;
+
+use crate::config::SyncConfig;
+
+/// TXT record key listing the groups a device belongs to
+pub const GROUPS_TXT_KEY: &str = "groups";
+
+/// Invalid group configuration
+#[derive(Debug, Error)]
+pub enum GroupError {
+    /// Group names are letters, digits, `-` and `_`
+    #[error("invalid sync group name '{0}' (use letters, digits, '-' and '_')")]
+    InvalidName(String),
+
+    /// An active group the device does not belong to
+    #[error("active sync group '{0}' is not listed in sync.groups")]
+    NotMember(String),
+}
+
+/// Where a local copy goes for one peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route<'a> {
+    /// Groups are not in use here; sent untagged
+    Ungrouped,
+    /// Sent tagged with this active group, which the peer belongs to
+    Group(&'a str),
+    /// The peer is in none of the active groups
+    Excluded,
+}
+
+/// Groups this device belongs to and sends to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncGroups {
+    groups: Vec<String>,
+    active: Vec<String>,
+}
+
+impl SyncGroups {
+    /// Groups from the `[sync]` config section
+    ///
+    /// Without `active_groups`, local copies go to every group.
+    pub fn from_config(config: &SyncConfig) -> Result<Self, GroupError> {
+        for name in config.groups.iter().chain(&config.active_groups) {
+            if !is_valid_name(name) {
+                return Err(GroupError::InvalidName(name.clone()));
+            }
+        }
+        if let Some(name) = config
+            .active_groups
+            .iter()
+            .find(|name| !config.groups.contains(name))
+        {
+            return Err(GroupError::NotMember(name.clone()));
+        }
+
+        let active = if config.active_groups.is_empty() {
+            config.groups.clone()
+        } else {
+            config.active_groups.clone()
+        };
+        Ok(Self {
+            groups: config.groups.clone(),
+            active,
+        })
+    }
+
+    /// Whether this device uses groups at all
+    pub fn is_enabled(&self) -> bool {
+        !self.groups.is_empty()
+    }
+
+    /// Groups this device belongs to
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    /// Groups local copies are sent to
+    pub fn active(&self) -> &[String] {
+        &self.active
+    }
+
+    /// Where a local copy goes for a peer in `peer_groups`
+    pub fn route(&self, peer_groups: &[String]) -> Route<'_> {
+        if !self.is_enabled() {
+            return Route::Ungrouped;
+        }
+        match self.active.iter().find(|group| peer_groups.contains(group)) {
+            Some(group) => Route::Group(group),
+            None => Route::Excluded,
+        }
+    }
+
+    /// Whether an entry shared in group `tag`, by a peer allowed in
+    /// `sender_groups`, is applied here
+    pub fn accepts(&self, tag: Option<&str>, sender_groups: &[String]) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        tag.is_some_and(|tag| {
+            self.groups.iter().any(|group| group == tag)
+                && sender_groups.iter().any(|group| group == tag)
+        })
+    }
+
+    /// Whether history is exchanged with a peer in `peer_groups`
+    ///
+    /// History does not record which group an entry was shared in, so it is
+    /// only sent to and taken from peers in every one of this device's
+    /// groups.
+    pub fn shares_history_with(&self, peer_groups: &[String]) -> bool {
+        self.groups.iter().all(|group| peer_groups.contains(group))
+    }
+}
+
+/// Groups listed in a `groups` TXT record value
+pub fn parse_txt(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| is_valid_name(name))
+        .map(String::from)
+        .collect()
+}
+
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups(groups: &[&str], active: &[&str]) -> Result<SyncGroups, GroupError> {
+        SyncGroups::from_config(&SyncConfig {
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            active_groups: active.iter().map(|g| g.to_string()).collect(),
+            ..SyncConfig::default()
+        })
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_route_to_active_groups() {
+        let workstation = groups(&["personal", "pairing"], &["pairing"]).unwrap();
+        assert_eq!(workstation.active(), ["pairing"]);
+        assert_eq!(
+            workstation.route(&names(&["pairing", "team"])),
+            Route::Group("pairing")
+        );
+        assert_eq!(workstation.route(&names(&["personal"])), Route::Excluded);
+        assert_eq!(workstation.route(&[]), Route::Excluded);
+
+        // Every group is active by default
+        let laptop = groups(&["personal", "pairing"], &[]).unwrap();
+        assert_eq!(
+            laptop.route(&names(&["pairing", "personal"])),
+            Route::Group("personal")
+        );
+
+        assert_eq!(
+            SyncGroups::default().route(&names(&["pairing"])),
+            Route::Ungrouped
+        );
+    }
+
+    #[test]
+    fn test_accepts_tags_of_own_groups() {
+        let laptop = groups(&["personal"], &[]).unwrap();
+        let sender = names(&["personal", "pairing"]);
+        assert!(laptop.accepts(Some("personal"), &sender));
+        assert!(!laptop.accepts(Some("pairing"), &sender));
+        assert!(!laptop.accepts(None, &sender));
+
+        // Nor from a sender not allowed in the group
+        assert!(!laptop.accepts(Some("personal"), &names(&["pairing"])));
+
+        // Devices without groups take everything
+        assert!(SyncGroups::default().accepts(Some("pairing"), &[]));
+        assert!(SyncGroups::default().accepts(None, &[]));
+    }
+
+    #[test]
+    fn test_history_only_with_peers_in_every_group() {
+        let workstation = groups(&["personal", "pairing"], &["personal"]).unwrap();
+        assert!(workstation.shares_history_with(&names(&["pairing", "personal"])));
+        assert!(!workstation.shares_history_with(&names(&["pairing"])));
+        assert!(SyncGroups::default().shares_history_with(&names(&["team"])));
+    }
+
+    #[test]
+    fn test_invalid_groups() {
+        assert!(matches!(
+            groups(&["my group"], &[]),
+            Err(GroupError::InvalidName(_))
+        ));
+        assert!(matches!(
+            groups(&["personal"], &["pairing"]),
+            Err(GroupError::NotMember(_))
+        ));
+        assert_eq!(
+            parse_txt("personal, pairing,,bad name"),
+            ["personal", "pairing"]
+        );
+    }
+}
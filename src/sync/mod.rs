@@ -1,32 +1,127 @@
+pub mod catchup;
+pub mod clock;
+pub mod formats;
+pub mod groups;
+pub mod origin;
+pub mod policy;
 pub mod trust_sync;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tokio::time::{interval, sleep};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+pub use catchup::CatchUpLimits;
+pub use clock::{HybridClock, HybridTimestamp};
+pub use groups::SyncGroups;
+pub use origin::EchoGuard;
+pub use policy::{PeerFingerprints, PeerPolicies, PeerPolicy, SyncDirection};
 pub use trust_sync::{setup_trust_sync, TrustAwareSyncEngine};
 
 use crate::adapters::{
     ClipboardData, ClipboardEntry, ClipboardProviderWrapper, HistoryManager, Peer, PeerDiscovery,
 };
+use crate::clipboard::safety::{self, SafetyAction, SafetyFilter, Verdict};
+use crate::clipboard::{ClipboardContent, ClipboardSelection};
 use crate::config::Config;
-use crate::transport::protocol::ClipboardFormat;
+use crate::discovery::CAP_PRIMARY_SELECTION;
+use crate::transport::protocol::{
+    ClipboardFormat, HistoryPayload, HistoryRecord, SELECTION_METADATA_KEY,
+};
 use crate::transport::{
-    ClipboardData as TransportClipboardData, Message, MessagePayload, MessageType, TransportManager,
+    ClipboardData as TransportClipboardData, Message, MessagePayload, MessageType, Transfer,
+    TransportEvent, TransportManager,
 };
+use groups::Route;
+
+/// Transport metadata key carrying the entry's stable content ID
+const CONTENT_ID_METADATA_KEY: &str = "content_id";
+
+/// Transport metadata key naming the node the entry was copied on
+const ORIGIN_METADATA_KEY: &str = "origin";
+
+/// Transport metadata key naming the entry the sender's selection held
+/// before this one
+const REPLACES_METADATA_KEY: &str = "replaces";
+
+/// Transport metadata key set to [`SKIP_HISTORY`] for entries no node
+/// should record
+const HISTORY_METADATA_KEY: &str = "history";
+
+const SKIP_HISTORY: &str = "skip";
+
+/// Transport metadata key carrying an entry's time to live in milliseconds
+const TTL_METADATA_KEY: &str = "ttl_ms";
+
+/// Most history entries compared during catch-up
+const CATCH_UP_SCAN_LIMIT: usize = 10_000;
 
 #[derive(Debug, Clone)]
 pub struct SyncEvent {
     pub timestamp: DateTime<Utc>,
     pub source_peer: Uuid,
     pub entry: ClipboardEntry,
+    pub kind: SyncEventKind,
+    /// Entry the selection held on the originating node before `entry`
+    pub replaces: Option<Uuid>,
+    /// Whether nodes record the entry in history
+    pub keep_history: bool,
+    /// How long after arriving every node clears and forgets the entry
+    pub ttl: Option<Duration>,
+    /// Sync group a remote entry was shared in
+    pub group: Option<String>,
+}
+
+/// What a [`SyncEvent`] reports
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncEventKind {
+    /// Copied on this node, to be sent to peers
+    Local,
+    /// Received from a peer, to be applied
+    Remote,
+    /// A remote entry raced a different one in the same selection; `entry`
+    /// is the remote entry
+    Conflict(ConflictResolution),
+}
+
+/// Outcome of two entries made concurrently on different nodes
+///
+/// An entry conflicts with the one the local selection holds unless it was
+/// made on top of it. The entry with the greater [`HybridTimestamp`] wins,
+/// so every node keeps the same one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictResolution {
+    /// Entry kept in the selection
+    pub winner: Uuid,
+    /// Entry that lost, still recorded in history
+    pub loser: Uuid,
+    /// Whether the entry already held locally won
+    pub local_won: bool,
+}
+
+/// Entry a selection currently holds
+#[derive(Debug, Clone, Copy)]
+struct SelectionHead {
+    id: Uuid,
+    hlc: HybridTimestamp,
+}
+
+/// Local content as it may leave this device
+struct Outgoing {
+    /// Checksum of the content as the selection holds it here
+    checksum: String,
+    /// Content for peers, redacted if a safety rule asks for it
+    content: ClipboardData,
+    keep_history: bool,
+    /// Time to live a safety rule gives the content
+    ttl: Option<Duration>,
 }
 
 pub struct SyncEngine {
@@ -37,7 +132,16 @@ pub struct SyncEngine {
     transport: Arc<TransportManager>,
     peers: Arc<RwLock<HashMap<Uuid, Peer>>>,
     event_sender: broadcast::Sender<SyncEvent>,
-    last_local_update: Arc<RwLock<SystemTime>>,
+    clock: Arc<HybridClock>,
+    heads: Arc<Mutex<HashMap<ClipboardSelection, SelectionHead>>>,
+    echoes: Arc<Mutex<EchoGuard>>,
+    safety: Arc<SafetyFilter>,
+    policies: Arc<PeerPolicies>,
+    groups: Arc<SyncGroups>,
+    /// Keys node IDs were trusted with, for policies that name a node ID
+    fingerprints: Option<Arc<dyn PeerFingerprints>>,
+    /// Time to live for the next local copy of the content with this checksum
+    pending_ttl: Arc<Mutex<Option<(String, Duration)>>>,
     sync_interval: Duration,
 }
 
@@ -50,6 +154,18 @@ impl SyncEngine {
         transport: Arc<TransportManager>,
     ) -> Self {
         let (event_sender, _) = broadcast::channel(100);
+        let safety = SafetyFilter::from_config(&config.safety).unwrap_or_else(|e| {
+            warn!("Using the built-in safety rules only: {}", e);
+            SafetyFilter::builtin()
+        });
+        let groups = SyncGroups::from_config(&config.sync).unwrap_or_else(|e| {
+            warn!("Syncing without groups: {}", e);
+            SyncGroups::default()
+        });
+        let policies = PeerPolicies::from_config(&config.sync).unwrap_or_else(|e| {
+            warn!("Ignoring sync policies: {}", e);
+            PeerPolicies::default()
+        });
 
         Self {
             config: Arc::clone(&config),
@@ -59,24 +175,67 @@ impl SyncEngine {
             transport,
             peers: Arc::new(RwLock::new(HashMap::new())),
             event_sender,
-            last_local_update: Arc::new(RwLock::new(UNIX_EPOCH)),
+            clock: Arc::new(HybridClock::new(config.node_id())),
+            heads: Arc::new(Mutex::new(HashMap::new())),
+            echoes: Arc::new(Mutex::new(EchoGuard::default())),
+            safety: Arc::new(safety),
+            policies: Arc::new(policies),
+            groups: Arc::new(groups),
+            fingerprints: None,
+            pending_ttl: Arc::new(Mutex::new(None)),
             sync_interval: Duration::from_millis(config.sync_interval_ms()),
         }
     }
 
+    /// Apply policies that name a node ID only to the key `fingerprints`
+    /// says the node was trusted with
+    pub fn with_fingerprints(mut self, fingerprints: Arc<dyn PeerFingerprints>) -> Self {
+        self.fingerprints = Some(fingerprints);
+        self
+    }
+
+    /// What may be exchanged with a peer, by the key its connection
+    /// authenticated with
+    pub async fn policy_for(&self, peer_id: Uuid) -> PeerPolicy {
+        let fingerprint = self.transport.peer_fingerprint(peer_id).await;
+        self.policies
+            .resolve(
+                peer_id,
+                fingerprint.as_deref(),
+                self.fingerprints.as_deref(),
+            )
+            .await
+    }
+
+    /// Sync groups a peer announced and its policy allows it in
+    async fn peer_groups(&self, peer_id: Uuid, policy: &PeerPolicy) -> Vec<String> {
+        self.peers
+            .read()
+            .await
+            .get(&peer_id)
+            .map(|peer| policy.member_groups(&peer.groups))
+            .unwrap_or_default()
+    }
+
     pub async fn start(&self) -> Result<()> {
         info!("Starting sync engine");
 
+        if let Err(e) = self.schedule_history_expiry().await {
+            warn!("Failed to schedule expiry of history entries: {}", e);
+        }
+
         let discovery_task = self.start_discovery();
         let clipboard_monitor_task = self.start_clipboard_monitor();
         let sync_task = self.start_sync_loop();
         let transport_handler_task = self.start_transport_handler();
+        let catch_up_task = self.start_catch_up_handler();
 
         tokio::try_join!(
             discovery_task,
             clipboard_monitor_task,
             sync_task,
-            transport_handler_task
+            transport_handler_task,
+            catch_up_task
         )?;
 
         Ok(())
@@ -131,70 +290,374 @@ impl SyncEngine {
     }
 
     async fn start_clipboard_monitor(&self) -> Result<()> {
-        let clipboard = Arc::clone(&self.clipboard);
-        let history = Arc::clone(&self.history);
-        let event_sender = self.event_sender.clone();
-        let last_update = Arc::clone(&self.last_local_update);
-        let config = Arc::clone(&self.config);
+        let mut last_content_hash = None;
+        let mut last_primary_hash = None;
+        let sync_primary = self.config.primary_sync_enabled();
+
+        // Prefer change notifications from the provider and only poll when
+        // the backend cannot deliver them
+        match self.clipboard.watch().await {
+            Ok(mut watcher) => {
+                info!(
+                    "Watching {} clipboard for change events",
+                    self.clipboard.name()
+                );
+
+                while let Some(event) = watcher.receiver.recv().await {
+                    let selection = event.selection.unwrap_or_default();
+                    let last_hash = match selection {
+                        ClipboardSelection::Clipboard => &mut last_content_hash,
+                        ClipboardSelection::Primary if sync_primary => &mut last_primary_hash,
+                        ClipboardSelection::Primary => continue,
+                    };
+
+                    self.handle_local_change(event.content, selection, last_hash)
+                        .await;
+                }
 
+                warn!("Clipboard watcher stopped, falling back to polling");
+            }
+            Err(e) => {
+                warn!(
+                    "Clipboard watch unavailable ({}), falling back to polling",
+                    e
+                );
+            }
+        }
+
+        self.poll_clipboard(&mut last_content_hash, &mut last_primary_hash)
+            .await
+    }
+
+    async fn poll_clipboard(
+        &self,
+        last_content_hash: &mut Option<String>,
+        last_primary_hash: &mut Option<String>,
+    ) -> Result<()> {
         // Use a reasonable interval of 1 second instead of 200ms
         // This prevents excessive CPU usage and potential interference with password managers
         let mut interval = interval(Duration::from_secs(1));
-        let mut last_content_hash = None;
 
         loop {
             interval.tick().await;
 
-            match clipboard.get_text().await {
+            match self
+                .clipboard
+                .read_selection(ClipboardSelection::Clipboard)
+                .await
+            {
                 Ok(content) => {
-                    // Safety check: Skip potentially sensitive content
-                    if crate::clipboard::safety::is_potentially_sensitive(&content) {
-                        debug!("Skipping potentially sensitive clipboard content");
-                        continue;
-                    }
-                    
-                    // Safety check: Skip if in sensitive context
-                    if crate::clipboard::safety::is_sensitive_context() {
-                        debug!("Skipping clipboard sync in sensitive context");
-                        continue;
+                    self.handle_local_change(
+                        content,
+                        ClipboardSelection::Clipboard,
+                        last_content_hash,
+                    )
+                    .await
+                }
+                Err(e) => {
+                    warn!("Failed to read clipboard: {}", e);
+                }
+            }
+
+            if self.config.primary_sync_enabled() {
+                // An empty PRIMARY selection is the common case, so stay quiet
+                if let Ok(content) = self
+                    .clipboard
+                    .read_selection(ClipboardSelection::Primary)
+                    .await
+                {
+                    self.handle_local_change(
+                        content,
+                        ClipboardSelection::Primary,
+                        last_primary_hash,
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+
+    async fn handle_local_change(
+        &self,
+        content: ClipboardContent,
+        selection: ClipboardSelection,
+        last_content_hash: &mut Option<String>,
+    ) {
+        let Some(outgoing) = self.outgoing(content, selection) else {
+            return;
+        };
+        let content_hash = outgoing.checksum;
+
+        if Some(&content_hash) == last_content_hash.as_ref() {
+            return;
+        }
+
+        // Our own write of a remote entry, already in history
+        if self.echoes.lock().await.take_echo(&content_hash) {
+            debug!(
+                "Ignoring echo of remotely synced {} content",
+                selection.as_str()
+            );
+            *last_content_hash = Some(content_hash);
+            return;
+        }
+
+        debug!(
+            "{} content changed locally ({})",
+            selection.as_str(),
+            outgoing.content.mime_type()
+        );
+
+        let keep_history = outgoing.keep_history;
+        let ttl = self.take_pending_ttl(&content_hash).await.or(outgoing.ttl);
+
+        let entry = ClipboardEntry {
+            id: Uuid::new_v4(),
+            checksum: outgoing.content.checksum(),
+            content: outgoing.content,
+            timestamp: Utc::now(),
+            source: self.config.node_id(),
+            selection,
+            pinned: false,
+            hlc: self.clock.now(),
+            expires_at: ttl.map(deadline),
+        };
+        self.echoes.lock().await.mark_seen(entry.id);
+        let entry_id = entry.id;
+
+        if keep_history {
+            if let Err(e) = self.history.add_entry(&entry).await {
+                error!("Failed to save clipboard entry: {}", e);
+            }
+        }
+
+        let event = SyncEvent {
+            keep_history,
+            ttl,
+            ..self.local_event(entry).await
+        };
+        if let Err(e) = self.event_sender.send(event) {
+            warn!("Failed to broadcast sync event: {}", e);
+        }
+
+        if let Some(ttl) = ttl {
+            self.expire_after(selection, Some(entry_id), content_hash.clone(), ttl);
+        }
+
+        *last_content_hash = Some(content_hash);
+    }
+
+    /// What of a selection's content may be sent to peers, if anything
+    ///
+    /// Applies the concealed flag, the safety rules, the size limit and the
+    /// sensitive context check, for every way local content leaves this
+    /// device.
+    fn outgoing(
+        &self,
+        content: ClipboardContent,
+        selection: ClipboardSelection,
+    ) -> Option<Outgoing> {
+        // Secrets marked by a password manager never leave this device
+        if content.concealed {
+            debug!("Skipping concealed {} content", selection.as_str());
+            return None;
+        }
+        let content = ClipboardData::from(content);
+        if content.size() == 0 {
+            return None;
+        }
+
+        // Text matching a safety rule may stay here, or be changed first
+        let mut matched = None;
+        if let Some(text) = content.as_text() {
+            match self.safety.check(text) {
+                Verdict::Matched { rule, spans } => {
+                    debug!(
+                        "{} content matches safety rule '{}' ({})",
+                        selection.as_str(),
+                        rule.name(),
+                        rule.action()
+                    );
+                    if !rule.action().syncs() {
+                        return None;
                     }
-                    
-                    let content_hash = format!("{:x}", md5::compute(&content));
-
-                    if Some(&content_hash) != last_content_hash.as_ref() {
-                        debug!("Clipboard content changed locally");
-
-                        let entry = ClipboardEntry {
-                            id: Uuid::new_v4(),
-                            content: ClipboardData::Text(content),
-                            timestamp: Utc::now(),
-                            source: config.node_id(),
-                            checksum: content_hash.clone(),
-                        };
-
-                        if let Err(e) = history.add_entry(&entry).await {
-                            error!("Failed to save clipboard entry: {}", e);
-                        }
+                    matched = Some((rule.action(), spans));
+                }
+                Verdict::Allowed(rule) => {
+                    debug!("Content allowed by safety rule '{}'", rule.name());
+                }
+                Verdict::Clean => {}
+            }
+        }
 
-                        let sync_event = SyncEvent {
-                            timestamp: entry.timestamp,
-                            source_peer: config.node_id(),
-                            entry,
-                        };
+        if content.size() > self.config.clipboard.max_size {
+            debug!(
+                "Skipping {} clipboard content of {} bytes (max {})",
+                content.mime_type(),
+                content.size(),
+                self.config.clipboard.max_size
+            );
+            return None;
+        }
 
-                        if let Err(e) = event_sender.send(sync_event) {
-                            warn!("Failed to broadcast sync event: {}", e);
-                        }
+        // Safety check: Skip if in sensitive context
+        if crate::clipboard::safety::is_sensitive_context() {
+            debug!("Skipping clipboard sync in sensitive context");
+            return None;
+        }
+
+        let checksum = content.checksum();
+
+        // The clipboard here keeps the original; everyone else gets the
+        // redacted copy
+        let content = match (&matched, content) {
+            (Some((SafetyAction::Redact, spans)), ClipboardData::Text(text)) => {
+                ClipboardData::Text(safety::redact(&text, spans))
+            }
+            (Some((SafetyAction::Redact, spans)), ClipboardData::Html(html)) => {
+                ClipboardData::Html(safety::redact(&html, spans))
+            }
+            (_, content) => content,
+        };
+        let keep_history = matched
+            .as_ref()
+            .map_or(true, |(action, _)| action.records_history());
+        let ttl = match matched {
+            Some((SafetyAction::Expire(ttl), _)) => Some(ttl),
+            _ => None,
+        };
+
+        Some(Outgoing {
+            checksum,
+            content,
+            keep_history,
+            ttl,
+        })
+    }
+
+    /// Put content on the clipboard so that, once synced, every node clears
+    /// and forgets it after `ttl`
+    ///
+    /// If the clipboard already holds the content, the monitor sees no
+    /// change, so the content and its history entry expire here instead.
+    pub async fn copy_with_ttl(&self, content: &ClipboardData, ttl: Duration) -> Result<()> {
+        let checksum = content.checksum();
+        let unchanged = self
+            .clipboard
+            .get_content()
+            .await
+            .is_ok_and(|current| current.checksum() == checksum);
+        if unchanged {
+            *self.pending_ttl.lock().await = None;
+            return self.expire_unchanged(content, checksum, ttl).await;
+        }
+
+        *self.pending_ttl.lock().await = Some((checksum, ttl));
+        let copied = self.clipboard.set_content(content).await;
+        if copied.is_err() {
+            *self.pending_ttl.lock().await = None;
+        }
+        copied
+    }
+
+    /// Give the content the clipboard already holds a time to live
+    async fn expire_unchanged(
+        &self,
+        content: &ClipboardData,
+        checksum: String,
+        ttl: Duration,
+    ) -> Result<()> {
+        debug!("Clipboard already holds the content, expiring it in place");
+        let entry = match self.history.get_by_content(content).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("No history entry to expire: {}", e);
+                None
+            }
+        };
+
+        let id = match entry {
+            Some(entry) => {
+                self.history.set_expiry(entry.id, deadline(ttl)).await?;
+                Some(entry.id)
+            }
+            None => None,
+        };
+        self.expire_after(ClipboardSelection::Clipboard, id, checksum, ttl);
+
+        Ok(())
+    }
+
+    async fn take_pending_ttl(&self, checksum: &str) -> Option<Duration> {
+        let mut pending = self.pending_ttl.lock().await;
+        match pending.as_ref() {
+            Some((pending_checksum, _)) if pending_checksum == checksum => {
+                pending.take().map(|(_, ttl)| ttl)
+            }
+            _ => None,
+        }
+    }
+
+    /// After `ttl`, clear a selection if it still holds the content with
+    /// `checksum`, and remove entry `id`, if any, from history
+    fn expire_after(
+        &self,
+        selection: ClipboardSelection,
+        id: Option<Uuid>,
+        checksum: String,
+        ttl: Duration,
+    ) {
+        let clipboard = Arc::clone(&self.clipboard);
+        let history = Arc::clone(&self.history);
+        tokio::spawn(async move {
+            sleep(ttl).await;
 
-                        *last_update.write().await = SystemTime::now();
-                        last_content_hash = Some(content_hash);
+            // Anything copied since stays
+            let current = clipboard.get_selection(selection).await;
+            if current.map_or(false, |content| content.checksum() == checksum) {
+                debug!("Clearing expired {} content", selection.as_str());
+                let cleared = match selection {
+                    ClipboardSelection::Clipboard => clipboard.clear().await,
+                    _ => {
+                        clipboard
+                            .set_selection(&ClipboardData::Text(String::new()), selection)
+                            .await
                     }
+                };
+                if let Err(e) = cleared {
+                    warn!(
+                        "Failed to clear expired {} content: {}",
+                        selection.as_str(),
+                        e
+                    );
                 }
-                Err(e) => {
-                    warn!("Failed to read clipboard: {}", e);
+            }
+
+            if let Some(id) = id {
+                if let Err(e) = history.delete_entry(id).await {
+                    warn!("Failed to remove expired entry {} from history: {}", id, e);
                 }
             }
+        });
+    }
+
+    /// Schedule the removal of history entries still to expire
+    ///
+    /// Expiry timers don't survive a restart, but deadlines are kept in
+    /// history, which drops entries past theirs when it is opened.
+    async fn schedule_history_expiry(&self) -> Result<()> {
+        for (id, expires_at) in self.history.expiring().await? {
+            let history = Arc::clone(&self.history);
+            let ttl = (expires_at - Utc::now()).to_std().unwrap_or_default();
+            tokio::spawn(async move {
+                sleep(ttl).await;
+                if let Err(e) = history.delete_entry(id).await {
+                    warn!("Failed to remove expired entry {} from history: {}", id, e);
+                }
+            });
         }
+
+        Ok(())
     }
 
     async fn start_sync_loop(&self) -> Result<()> {
@@ -204,13 +667,11 @@ impl SyncEngine {
 
         loop {
             match event_receiver.recv().await {
-                Ok(sync_event) => {
-                    if sync_event.source_peer == self.config.node_id() {
-                        self.broadcast_to_peers(&sync_event).await;
-                    } else {
-                        self.handle_remote_sync_event(&sync_event).await;
-                    }
-                }
+                Ok(sync_event) => match sync_event.kind {
+                    SyncEventKind::Local => self.broadcast_to_peers(&sync_event).await,
+                    SyncEventKind::Remote => self.handle_remote_sync_event(&sync_event).await,
+                    SyncEventKind::Conflict(_) => {}
+                },
                 Err(broadcast::error::RecvError::Lagged(count)) => {
                     warn!("Sync loop lagged by {} events", count);
                 }
@@ -227,23 +688,117 @@ impl SyncEngine {
     async fn broadcast_to_peers(&self, event: &SyncEvent) {
         let peers = self.peers.read().await;
 
-        let clipboard_data = match &event.entry.content {
-            ClipboardData::Text(text) => TransportClipboardData {
-                format: ClipboardFormat::Text,
-                data: text.as_bytes().to_vec(),
-                compression: None,
-                checksum: event.entry.checksum.clone(),
-                metadata: std::collections::HashMap::new(),
-            },
-        };
+        let selection = event.entry.selection;
 
-        let message = Message::new(
-            MessageType::ClipboardData,
-            MessagePayload::Clipboard(clipboard_data),
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert(
+            SELECTION_METADATA_KEY.to_string(),
+            selection.as_str().to_string(),
         );
+        metadata.insert(
+            CONTENT_ID_METADATA_KEY.to_string(),
+            event.entry.id.to_string(),
+        );
+        metadata.insert(
+            ORIGIN_METADATA_KEY.to_string(),
+            event.entry.source.to_string(),
+        );
+        if let Some(replaces) = event.replaces {
+            metadata.insert(REPLACES_METADATA_KEY.to_string(), replaces.to_string());
+        }
+        if !event.keep_history {
+            metadata.insert(HISTORY_METADATA_KEY.to_string(), SKIP_HISTORY.to_string());
+        }
+        if let Some(ttl) = event.ttl {
+            metadata.insert(TTL_METADATA_KEY.to_string(), ttl.as_millis().to_string());
+        }
+
+        let build_message = |content: &ClipboardData, checksum: String| {
+            let clipboard_data = TransportClipboardData {
+                format: content.format(),
+                data: content.as_bytes().to_vec(),
+                compression: None,
+                checksum,
+                metadata: metadata.clone(),
+            };
+
+            Message::new(
+                MessageType::ClipboardData,
+                MessagePayload::Clipboard(clipboard_data),
+            )
+            .with_hlc(event.entry.hlc)
+        };
+        let message = build_message(&event.entry.content, event.entry.checksum.clone());
 
         for peer in peers.values() {
-            if let Err(e) = self.transport.send_to_peer(peer.id, &message).await {
+            // PRIMARY only goes to peers that opted in to it
+            if selection == ClipboardSelection::Primary
+                && !peer.has_capability(CAP_PRIMARY_SELECTION)
+            {
+                continue;
+            }
+
+            let policy = self.policy_for(peer.id).await;
+            if !policy.sends() {
+                debug!("Policy for peer {} is receive only", peer.id);
+                continue;
+            }
+
+            // Copies only go to peers in an active group
+            let group = match self.groups.route(&policy.member_groups(&peer.groups)) {
+                Route::Ungrouped => None,
+                Route::Group(group) => Some(group),
+                Route::Excluded => {
+                    debug!("Peer {} is in none of the active sync groups", peer.id);
+                    continue;
+                }
+            };
+
+            // Peers only get formats they said they can render
+            let content = match self.transport.peer_capabilities(peer.id).await {
+                Some(capabilities) => {
+                    match formats::for_peer(&event.entry.content, &capabilities) {
+                        Some(content) => content,
+                        None => {
+                            debug!(
+                                "Peer {} cannot render {}, not sending entry {}",
+                                peer.id,
+                                event.entry.content.format().mime_type(),
+                                event.entry.id
+                            );
+                            continue;
+                        }
+                    }
+                }
+                None => Cow::Borrowed(&event.entry.content),
+            };
+
+            if !policy.allows(&content) {
+                debug!(
+                    "Policy for peer {} does not allow {} of {} bytes, not sending entry {}",
+                    peer.id,
+                    content.mime_type(),
+                    content.size(),
+                    event.entry.id
+                );
+                continue;
+            }
+
+            let converted = match content {
+                Cow::Borrowed(_) => None,
+                Cow::Owned(content) => {
+                    let checksum = content.checksum();
+                    Some(build_message(&content, checksum))
+                }
+            };
+            let mut message = match converted {
+                Some(converted) => Cow::Owned(converted),
+                None => Cow::Borrowed(&message),
+            };
+            if let Some(group) = group {
+                message.to_mut().group = Some(group.to_string());
+            }
+            if let Err(e) = self.transport.send_clipboard(peer.id, &message).await {
                 warn!("Failed to send sync event to peer {}: {}", peer.id, e);
             }
         }
@@ -252,46 +807,162 @@ impl SyncEngine {
     async fn handle_remote_sync_event(&self, event: &SyncEvent) {
         debug!("Handling remote sync event from peer {}", event.source_peer);
 
-        if let Err(e) = self.resolve_conflict(event).await {
-            error!("Failed to resolve sync conflict: {}", e);
+        let selection = event.entry.selection;
+        if selection == ClipboardSelection::Primary && !self.config.primary_sync_enabled() {
+            debug!("Ignoring PRIMARY selection update, sync_primary is disabled");
             return;
         }
 
-        match &event.entry.content {
-            ClipboardData::Text(text) => {
-                if let Err(e) = self.clipboard.set_text(text).await {
-                    error!("Failed to update local clipboard: {}", e);
-                }
+        let policy = self.policy_for(event.source_peer).await;
+        if !policy.receives() || !policy.allows(&event.entry.content) {
+            debug!(
+                "Policy for peer {} does not allow entry {} ({} of {} bytes)",
+                event.source_peer,
+                event.entry.id,
+                event.entry.content.mime_type(),
+                event.entry.content.size()
+            );
+            return;
+        }
+
+        if !self.groups.accepts(event.group.as_deref(), &policy.groups) {
+            debug!(
+                "Ignoring entry {} shared in {} by peer {}, not a sync group of ours it may use",
+                event.entry.id,
+                event.group.as_deref().unwrap_or("no group"),
+                event.source_peer
+            );
+            return;
+        }
+
+        // Entries that started here, or that arrived by another path already
+        if event.entry.source == self.config.node_id()
+            || !self.echoes.lock().await.mark_seen(event.entry.id)
+        {
+            debug!("Ignoring already applied entry {}", event.entry.id);
+            return;
+        }
+
+        self.clock.observe(&event.entry.hlc);
+        let conflict = self.resolve_conflict(event).await;
+        let apply = conflict.as_ref().map_or(true, |c| !c.local_won);
+
+        if apply {
+            self.heads.lock().await.insert(
+                selection,
+                SelectionHead {
+                    id: event.entry.id,
+                    hlc: event.entry.hlc,
+                },
+            );
+
+            // The monitor will see this write as a change; don't announce it again
+            self.echoes
+                .lock()
+                .await
+                .expect_echo(event.entry.content.checksum());
+
+            if let Err(e) = self
+                .clipboard
+                .set_selection(&event.entry.content, selection)
+                .await
+            {
+                error!(
+                    "Failed to update local {} selection with {}: {}",
+                    selection.as_str(),
+                    event.entry.content.mime_type(),
+                    e
+                );
             }
         }
 
-        if let Err(e) = self.history.add_entry(&event.entry).await {
+        // Losing entries are still part of history
+        if !event.keep_history || !policy.history {
+            debug!("Not recording entry {} in history", event.entry.id);
+        } else if let Err(e) = self.history.add_entry(&event.entry).await {
             error!("Failed to save remote clipboard entry: {}", e);
         }
+
+        if let Some(ttl) = event.ttl {
+            self.expire_after(
+                selection,
+                Some(event.entry.id),
+                event.entry.content.checksum(),
+                ttl,
+            );
+        }
+
+        if let Some(resolution) = conflict {
+            info!(
+                "Resolved {} conflict between {} and {}: kept {}",
+                selection.as_str(),
+                resolution.winner,
+                resolution.loser,
+                if resolution.local_won {
+                    "local entry"
+                } else {
+                    "remote entry"
+                }
+            );
+
+            let _ = self.event_sender.send(SyncEvent {
+                kind: SyncEventKind::Conflict(resolution),
+                ..event.clone()
+            });
+        }
     }
 
-    async fn resolve_conflict(&self, remote_event: &SyncEvent) -> Result<()> {
-        let last_local = *self.last_local_update.read().await;
-        let remote_timestamp = remote_event.timestamp.timestamp() as u64;
-        let local_timestamp = last_local.duration_since(UNIX_EPOCH)?.as_secs();
+    /// Compare a remote entry with what its selection holds locally
+    ///
+    /// Returns `None` when the remote entry simply follows the local one.
+    async fn resolve_conflict(&self, remote_event: &SyncEvent) -> Option<ConflictResolution> {
+        let heads = self.heads.lock().await;
+        let head = heads.get(&remote_event.entry.selection)?;
+        let remote = &remote_event.entry;
 
-        if remote_timestamp <= local_timestamp {
-            debug!("Remote event is older than local, ignoring");
-            return Ok(());
+        if head.id == remote.id || remote_event.replaces == Some(head.id) {
+            return None;
         }
 
-        if let Some(existing) = self
-            .history
-            .get_by_checksum(&remote_event.entry.checksum)
-            .await?
-        {
-            if existing.timestamp >= remote_event.entry.timestamp {
-                debug!("Already have newer version of this content");
-                return Ok(());
+        let local_won = head.hlc > remote.hlc;
+        Some(if local_won {
+            ConflictResolution {
+                winner: head.id,
+                loser: remote.id,
+                local_won,
             }
-        }
+        } else {
+            ConflictResolution {
+                winner: remote.id,
+                loser: head.id,
+                local_won,
+            }
+        })
+    }
 
-        Ok(())
+    /// Sync event for an entry made here, which becomes its selection's head
+    async fn local_event(&self, entry: ClipboardEntry) -> SyncEvent {
+        let head = SelectionHead {
+            id: entry.id,
+            hlc: entry.hlc,
+        };
+        let replaces = self
+            .heads
+            .lock()
+            .await
+            .insert(entry.selection, head)
+            .map(|previous| previous.id);
+
+        SyncEvent {
+            timestamp: entry.timestamp,
+            source_peer: self.config.node_id(),
+            entry,
+            kind: SyncEventKind::Local,
+            replaces,
+            keep_history: true,
+            ttl: None,
+            group: None,
+        }
     }
 
     async fn start_transport_handler(&self) -> Result<()> {
@@ -302,54 +973,42 @@ impl SyncEngine {
 
         loop {
             match message_receiver.recv().await {
-                Ok(message) => {
-                    match message.payload {
-                        MessagePayload::Clipboard(clipboard_data) => {
-                            let content = match clipboard_data.format {
-                                ClipboardFormat::Text => {
-                                    match String::from_utf8(clipboard_data.data) {
-                                        Ok(text) => ClipboardData::Text(text),
-                                        Err(e) => {
-                                            warn!("Failed to decode text clipboard data: {}", e);
-                                            continue;
-                                        }
-                                    }
-                                }
-                                _ => {
-                                    debug!(
-                                        "Unsupported clipboard format: {:?}",
-                                        clipboard_data.format
-                                    );
-                                    continue;
-                                }
-                            };
-
-                            // Extract the source peer ID from the message
-                            let source_peer_id = message.source_peer_id.unwrap_or_else(|| {
-                                warn!("Message missing source_peer_id, using random UUID");
-                                Uuid::new_v4()
-                            });
-
-                            let entry = ClipboardEntry {
-                                id: Uuid::new_v4(),
-                                content,
-                                timestamp: message.timestamp,
-                                source: source_peer_id,
-                                checksum: clipboard_data.checksum,
-                            };
-
-                            let sync_event = SyncEvent {
-                                timestamp: message.timestamp,
-                                source_peer: entry.source,
-                                entry,
-                            };
-
-                            if let Err(e) = event_sender.send(sync_event) {
-                                warn!("Failed to broadcast received sync event: {}", e);
-                            }
+                Ok(Message {
+                    payload: MessagePayload::History(payload),
+                    source_peer_id: Some(peer_id),
+                    ..
+                }) => {
+                    if let Err(e) = self.handle_history_message(peer_id, payload).await {
+                        warn!("History catch-up with peer {} failed: {}", peer_id, e);
+                    }
+                }
+                Ok(
+                    message @ Message {
+                        payload: MessagePayload::Stream(_),
+                        source_peer_id: Some(_),
+                        ..
+                    },
+                ) => {
+                    if let Some(sync_event) = self.receive_stream_message(message).await {
+                        if let Err(e) = event_sender.send(sync_event) {
+                            warn!("Failed to broadcast received sync event: {}", e);
                         }
-                        _ => {
-                            debug!("Received non-sync message: {:?}", message.message_type);
+                    }
+                }
+                Ok(Message {
+                    payload: MessagePayload::Unknown(payload),
+                    version,
+                    ..
+                }) => {
+                    debug!(
+                        "Ignoring {} message from protocol v{}",
+                        payload.kind, version
+                    );
+                }
+                Ok(message) => {
+                    if let Some(sync_event) = Self::sync_event_from_message(message) {
+                        if let Err(e) = event_sender.send(sync_event) {
+                            warn!("Failed to broadcast received sync event: {}", e);
                         }
                     }
                 }
@@ -361,36 +1020,1727 @@ impl SyncEngine {
         }
     }
 
-    pub fn subscribe(&self) -> broadcast::Receiver<SyncEvent> {
-        self.event_sender.subscribe()
+    /// Feed a stream message to the peer's stream, returning the sync
+    /// event for a completed clipboard stream
+    async fn receive_stream_message(&self, message: Message) -> Option<SyncEvent> {
+        let peer_id = message.source_peer_id?;
+        match self.transport.handle_stream_message(peer_id, message).await {
+            Ok(Some(mut clipboard)) => {
+                clipboard.source_peer_id = Some(peer_id);
+                Self::sync_event_from_message(clipboard)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Stream from peer {} failed: {}", peer_id, e);
+                None
+            }
+        }
     }
 
-    pub async fn get_connected_peers(&self) -> Vec<Peer> {
-        self.peers.read().await.values().cloned().collect()
+    /// Catch up on history whenever a lost connection comes back
+    async fn start_catch_up_handler(&self) -> Result<()> {
+        let mut events = self.transport.subscribe_events();
+
+        loop {
+            match events.recv().await {
+                Ok(TransportEvent::ConnectionRestored(peer_id)) => {
+                    if let Err(e) = self.catch_up(peer_id).await {
+                        warn!("Failed to start catch-up with peer {}: {}", peer_id, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Catch-up handler lagged by {} transport events", count);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
     }
 
-    pub async fn force_sync(&self) -> Result<()> {
-        info!("Forcing clipboard sync");
+    /// Start comparing recent history with a peer
+    ///
+    /// Sends a digest of local history; the peer's answer drives the rest
+    /// of the exchange through [`Self::handle_history_message`].
+    pub async fn catch_up(&self, peer_id: Uuid) -> Result<()> {
+        if !self.config.sync.catch_up {
+            return Ok(());
+        }
 
-        if let Ok(content) = self.clipboard.get_text().await {
-            let checksum = format!("{:x}", md5::compute(&content));
-            let entry = ClipboardEntry {
-                id: Uuid::new_v4(),
-                content: ClipboardData::Text(content),
-                timestamp: Utc::now(),
-                source: self.config.node_id(),
-                checksum,
-            };
+        let limits = CatchUpLimits::from_config(&self.config.sync)?;
+        let since = catch_up_since(&limits);
+        let local = self
+            .history
+            .summaries_since(since, CATCH_UP_SCAN_LIMIT)
+            .await?;
 
-            let sync_event = SyncEvent {
-                timestamp: entry.timestamp,
-                source_peer: self.config.node_id(),
-                entry,
-            };
+        debug!("Starting history catch-up with peer {}", peer_id);
+        let digest = catchup::digest(&local, since, catchup::DEFAULT_BUCKET_SECS);
+        self.send_history(
+            peer_id,
+            MessageType::HistoryDigest,
+            HistoryPayload::Digest(digest),
+        )
+        .await
+    }
+
+    /// Handle one step of a history catch-up with a peer
+    async fn handle_history_message(&self, peer_id: Uuid, payload: HistoryPayload) -> Result<()> {
+        if !self.config.sync.catch_up {
+            debug!("Ignoring history catch-up from peer {}, disabled", peer_id);
+            return Ok(());
+        }
+        let limits = CatchUpLimits::from_config(&self.config.sync)?;
+
+        match payload {
+            HistoryPayload::Digest(digest) => {
+                let local = self
+                    .history
+                    .summaries_since(digest.since, CATCH_UP_SCAN_LIMIT)
+                    .await?;
+                match catchup::inventory(&local, &digest) {
+                    Some(inventory) => {
+                        self.send_history(
+                            peer_id,
+                            MessageType::HistoryInventory,
+                            HistoryPayload::Inventory(inventory),
+                        )
+                        .await?
+                    }
+                    None => debug!("History already in sync with peer {}", peer_id),
+                }
+            }
+            HistoryPayload::Inventory(inventory) => {
+                let local = self
+                    .history
+                    .summaries_since(catch_up_since(&limits), CATCH_UP_SCAN_LIMIT)
+                    .await?;
+                let mut reconciliation = catchup::reconcile(&local, &inventory);
 
-            self.event_sender.send(sync_event)?;
+                reconciliation.missing.truncate(limits.max_entries);
+                if !reconciliation.missing.is_empty() {
+                    self.send_history(
+                        peer_id,
+                        MessageType::HistoryRequest,
+                        HistoryPayload::Request(reconciliation.missing),
+                    )
+                    .await?;
+                }
+                self.send_history_entries(peer_id, &reconciliation.unknown_to_peer, &limits)
+                    .await?;
+            }
+            HistoryPayload::Request(ids) => {
+                self.send_history_entries(peer_id, &ids, &limits).await?;
+            }
+            HistoryPayload::Entries(records) => {
+                self.receive_history_entries(peer_id, records, &limits)
+                    .await?;
+            }
         }
 
         Ok(())
     }
+
+    /// Send history entries, newest first, within the catch-up limits
+    async fn send_history_entries(
+        &self,
+        peer_id: Uuid,
+        ids: &[Uuid],
+        limits: &CatchUpLimits,
+    ) -> Result<()> {
+        let ids = &ids[..ids.len().min(limits.max_entries)];
+        let policy = self.policy_for(peer_id).await;
+        if ids.is_empty() || !policy.sends() {
+            return Ok(());
+        }
+        if !self
+            .groups
+            .shares_history_with(&self.peer_groups(peer_id, &policy).await)
+        {
+            debug!(
+                "Not sending history to peer {}, not in all our sync groups",
+                peer_id
+            );
+            return Ok(());
+        }
+
+        let mut budget = limits.max_bytes;
+        let mut records = Vec::new();
+        let now = Utc::now();
+        for entry in self.history.get_by_ids(ids).await? {
+            if !policy.allows(&entry.content) {
+                continue;
+            }
+            if entry.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                debug!("Not sending expired entry {}", entry.id);
+                continue;
+            }
+            let size = entry.content.size();
+            if size > budget {
+                debug!("Catch-up byte limit reached, not sending {}", entry.id);
+                continue;
+            }
+            budget -= size;
+
+            records.push(HistoryRecord {
+                id: entry.id,
+                origin: entry.source,
+                timestamp: entry.timestamp.timestamp(),
+                selection: entry.selection.as_str().to_string(),
+                data: TransportClipboardData {
+                    format: entry.content.format(),
+                    data: entry.content.as_bytes().to_vec(),
+                    compression: None,
+                    checksum: entry.checksum,
+                    metadata: HashMap::new(),
+                },
+                expires_at: entry.expires_at.map(|expires_at| expires_at.timestamp()),
+                hlc: Some(entry.hlc),
+            });
+        }
+
+        if records.is_empty() {
+            return Ok(());
+        }
+        debug!(
+            "Sending {} history entries to peer {}",
+            records.len(),
+            peer_id
+        );
+        self.send_history(
+            peer_id,
+            MessageType::HistoryEntries,
+            HistoryPayload::Entries(records),
+        )
+        .await
+    }
+
+    /// Store entries a peer sent during catch-up
+    ///
+    /// The newest entry of each selection is also applied if it is newer
+    /// than what the selection holds.
+    async fn receive_history_entries(
+        &self,
+        peer_id: Uuid,
+        mut records: Vec<HistoryRecord>,
+        limits: &CatchUpLimits,
+    ) -> Result<()> {
+        let policy = self.policy_for(peer_id).await;
+        if !policy.receives() {
+            debug!("Ignoring history entries from send only peer {}", peer_id);
+            return Ok(());
+        }
+        if !self
+            .groups
+            .shares_history_with(&self.peer_groups(peer_id, &policy).await)
+        {
+            debug!(
+                "Ignoring history entries from peer {}, not in all our sync groups",
+                peer_id
+            );
+            return Ok(());
+        }
+        records.truncate(limits.max_entries);
+
+        let now = Utc::now();
+        let mut entries = Vec::with_capacity(records.len());
+        for record in records {
+            let expires_at = record
+                .expires_at
+                .map(|expires_at| DateTime::from_timestamp(expires_at, 0).unwrap_or(now));
+            if expires_at.is_some_and(|expires_at| expires_at <= now) {
+                debug!("Ignoring expired history entry {}", record.id);
+                continue;
+            }
+            let selection = ClipboardSelection::from_name(&record.selection).unwrap_or_default();
+            if selection == ClipboardSelection::Primary && !self.config.primary_sync_enabled() {
+                debug!(
+                    "Ignoring PRIMARY history entry {}, sync_primary is disabled",
+                    record.id
+                );
+                continue;
+            }
+            let content = ClipboardData::from_format(&record.data.format, record.data.data);
+            if !policy.allows(&content) {
+                continue;
+            }
+            entries.push(ClipboardEntry {
+                id: record.id,
+                content,
+                timestamp: DateTime::from_timestamp(record.timestamp, 0).unwrap_or_else(Utc::now),
+                source: record.origin,
+                checksum: record.data.checksum,
+                selection,
+                pinned: false,
+                // Older peers send whole seconds only
+                hlc: record.hlc.unwrap_or_else(|| {
+                    HybridTimestamp::from_wall(record.timestamp.max(0) as u64 * 1000, record.origin)
+                }),
+                expires_at,
+            });
+        }
+
+        // The newest entries are still applied below
+        if policy.history {
+            let added = self.history.merge(&entries).await?;
+            info!(
+                "Caught up on {} history entries from peer {}",
+                added, peer_id
+            );
+        }
+
+        let mut newest: HashMap<ClipboardSelection, &ClipboardEntry> = HashMap::new();
+        {
+            let mut echoes = self.echoes.lock().await;
+            for entry in &entries {
+                echoes.mark_seen(entry.id);
+                self.clock.observe(&entry.hlc);
+
+                let current = newest.entry(entry.selection).or_insert(entry);
+                if entry.hlc > current.hlc {
+                    *current = entry;
+                }
+            }
+        }
+
+        for (selection, entry) in newest {
+            if selection == ClipboardSelection::Primary && !self.config.primary_sync_enabled() {
+                continue;
+            }
+
+            {
+                let mut heads = self.heads.lock().await;
+                if heads
+                    .get(&selection)
+                    .is_some_and(|head| head.hlc >= entry.hlc)
+                {
+                    continue;
+                }
+                heads.insert(
+                    selection,
+                    SelectionHead {
+                        id: entry.id,
+                        hlc: entry.hlc,
+                    },
+                );
+            }
+
+            self.echoes
+                .lock()
+                .await
+                .expect_echo(entry.content.checksum());
+            if let Err(e) = self
+                .clipboard
+                .set_selection(&entry.content, selection)
+                .await
+            {
+                error!(
+                    "Failed to apply caught-up entry to {} selection: {}",
+                    selection.as_str(),
+                    e
+                );
+            }
+        }
+
+        // Entries copied with a time to live expire here too
+        for entry in &entries {
+            if let Some(expires_at) = entry.expires_at {
+                self.expire_after(
+                    entry.selection,
+                    Some(entry.id),
+                    entry.content.checksum(),
+                    (expires_at - Utc::now()).to_std().unwrap_or_default(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send_history(
+        &self,
+        peer_id: Uuid,
+        message_type: MessageType,
+        payload: HistoryPayload,
+    ) -> Result<()> {
+        let message = Message::new(message_type, MessagePayload::History(payload));
+        self.transport.send_to_peer(peer_id, &message).await?;
+        Ok(())
+    }
+
+    /// Turn a clipboard message from a peer into a sync event
+    fn sync_event_from_message(message: Message) -> Option<SyncEvent> {
+        let clipboard_data = match message.payload {
+            MessagePayload::Clipboard(clipboard_data) => clipboard_data,
+            _ => {
+                debug!("Received non-sync message: {:?}", message.message_type);
+                return None;
+            }
+        };
+
+        let content = ClipboardData::from_format(&clipboard_data.format, clipboard_data.data);
+        if clipboard_data.format == ClipboardFormat::Text
+            && !matches!(content, ClipboardData::Text(_))
+        {
+            warn!("Failed to decode text clipboard data");
+            return None;
+        }
+
+        // Extract the source peer ID from the message
+        let source_peer_id = message.source_peer_id.unwrap_or_else(|| {
+            warn!("Message missing source_peer_id, using random UUID");
+            Uuid::new_v4()
+        });
+
+        // Payloads from older peers carry no selection, content ID or origin
+        let metadata = &clipboard_data.metadata;
+        let selection = metadata
+            .get(SELECTION_METADATA_KEY)
+            .and_then(|name| ClipboardSelection::from_name(name))
+            .unwrap_or_default();
+        let id = metadata
+            .get(CONTENT_ID_METADATA_KEY)
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .unwrap_or_else(Uuid::new_v4);
+        let origin = metadata
+            .get(ORIGIN_METADATA_KEY)
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .unwrap_or(source_peer_id);
+        let replaces = metadata
+            .get(REPLACES_METADATA_KEY)
+            .and_then(|id| Uuid::parse_str(id).ok());
+        let keep_history =
+            metadata.get(HISTORY_METADATA_KEY).map(String::as_str) != Some(SKIP_HISTORY);
+        let ttl = metadata
+            .get(TTL_METADATA_KEY)
+            .and_then(|ms| ms.parse().ok())
+            .map(Duration::from_millis);
+        let hlc = message.hlc.unwrap_or_else(|| {
+            HybridTimestamp::from_wall(message.timestamp.timestamp_millis().max(0) as u64, origin)
+        });
+
+        let entry = ClipboardEntry {
+            id,
+            content,
+            timestamp: message.timestamp,
+            source: origin,
+            checksum: clipboard_data.checksum,
+            selection,
+            pinned: false,
+            hlc,
+            expires_at: ttl.map(deadline),
+        };
+
+        Some(SyncEvent {
+            timestamp: message.timestamp,
+            source_peer: source_peer_id,
+            entry,
+            kind: SyncEventKind::Remote,
+            replaces,
+            keep_history,
+            ttl,
+            group: message.group,
+        })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SyncEvent> {
+        self.event_sender.subscribe()
+    }
+
+    pub async fn get_connected_peers(&self) -> Vec<Peer> {
+        self.peers.read().await.values().cloned().collect()
+    }
+
+    /// Large clipboard payloads currently streaming to or from peers
+    pub async fn transfers(&self) -> Vec<Transfer> {
+        self.transport.transfers().await
+    }
+
+    pub async fn force_sync(&self) -> Result<()> {
+        info!("Forcing clipboard sync");
+
+        if let Ok(content) = self
+            .clipboard
+            .read_selection(ClipboardSelection::Clipboard)
+            .await
+        {
+            let Some(outgoing) = self.outgoing(content, ClipboardSelection::Clipboard) else {
+                debug!("Clipboard content stays on this device");
+                return Ok(());
+            };
+            let entry = ClipboardEntry {
+                id: Uuid::new_v4(),
+                checksum: outgoing.content.checksum(),
+                content: outgoing.content,
+                timestamp: Utc::now(),
+                source: self.config.node_id(),
+                selection: ClipboardSelection::Clipboard,
+                pinned: false,
+                hlc: self.clock.now(),
+                expires_at: outgoing.ttl.map(deadline),
+            };
+
+            let event = SyncEvent {
+                keep_history: outgoing.keep_history,
+                ttl: outgoing.ttl,
+                ..self.local_event(entry).await
+            };
+            self.event_sender.send(event)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// When an entry with time to live `ttl` expires, counted from now
+fn deadline(ttl: Duration) -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX)
+}
+
+/// Start of the catch-up window, as a Unix timestamp
+fn catch_up_since(limits: &CatchUpLimits) -> i64 {
+    Utc::now().timestamp() - limits.window.as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::{ClipboardError, ClipboardProvider, ClipboardWatcher};
+    use crate::config::{PeerPolicyConfig, SafetyRuleConfig};
+    use crate::history::{ClipboardHistory, KeyStore, RetentionPolicy};
+    use crate::transport::protocol::CompressionMethod;
+    use crate::transport::stream::MAX_IN_FLIGHT_CHUNKS;
+    use crate::transport::unit_tests::MockConnection;
+    use crate::transport::{NegotiatedCapabilities, QueueStats, StreamDirection, TransportConfig};
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    /// In-memory clipboard standing in for the system one
+    #[derive(Default)]
+    struct MemoryClipboard {
+        content: std::sync::Mutex<Option<ClipboardContent>>,
+        /// Targets the owning application offers besides the content
+        targets: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ClipboardProvider for MemoryClipboard {
+        async fn get_content(&self) -> std::result::Result<ClipboardContent, ClipboardError> {
+            let mut content = self
+                .content
+                .lock()
+                .unwrap()
+                .clone()
+                .ok_or_else(|| ClipboardError::UnsupportedType("empty".to_string()))?;
+            content.concealed = crate::clipboard::is_concealed(&self.targets.lock().unwrap());
+            Ok(content)
+        }
+
+        async fn set_content(
+            &self,
+            content: &ClipboardContent,
+        ) -> std::result::Result<(), ClipboardError> {
+            *self.content.lock().unwrap() = Some(content.clone());
+            self.targets.lock().unwrap().clear();
+            Ok(())
+        }
+
+        async fn clear(&self) -> std::result::Result<(), ClipboardError> {
+            *self.content.lock().unwrap() = None;
+            self.targets.lock().unwrap().clear();
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "memory"
+        }
+
+        async fn watch(&self) -> std::result::Result<ClipboardWatcher, ClipboardError> {
+            Err(ClipboardError::UnsupportedType("watch".to_string()))
+        }
+    }
+
+    /// Every node trusted with the key named after it, see [`key_of`]
+    struct TrustedNodes;
+
+    #[async_trait::async_trait]
+    impl PeerFingerprints for TrustedNodes {
+        async fn fingerprint_of(&self, peer_id: Uuid) -> Option<String> {
+            Some(key_of(peer_id))
+        }
+    }
+
+    /// Fingerprint of the key node `id` authenticates with
+    fn key_of(id: Uuid) -> String {
+        format!("SHA256:{}", id)
+    }
+
+    /// One engine plus the test's view of its clipboard and connections
+    struct Node {
+        engine: SyncEngine,
+        clipboard: Arc<ClipboardProviderWrapper>,
+        /// Extra targets offered with the clipboard content
+        targets: Arc<std::sync::Mutex<Vec<String>>>,
+        events: broadcast::Receiver<SyncEvent>,
+        /// Messages sent to each peer, by peer node ID
+        outbound: Vec<(Uuid, mpsc::UnboundedReceiver<Message>)>,
+        last_hash: Option<String>,
+        /// Fingerprint of the key peers see this node authenticate with
+        key: String,
+        sent: usize,
+        /// Entries announced to peers
+        announced: Vec<ClipboardEntry>,
+        conflicts: Vec<ConflictResolution>,
+        _dir: TempDir,
+    }
+
+    impl Node {
+        fn id(&self) -> Uuid {
+            self.engine.config.node_id()
+        }
+
+        async fn copy(&self, text: &str) {
+            self.clipboard
+                .set_text(text)
+                .await
+                .expect("memory clipboard");
+        }
+
+        /// Copy text from an application that also offers `targets`
+        async fn copy_offering(&self, text: &str, targets: &[&str]) {
+            self.copy(text).await;
+            *self.targets.lock().unwrap() = targets.iter().map(|t| t.to_string()).collect();
+        }
+
+        async fn clipboard_text(&self) -> Option<String> {
+            self.clipboard.get_text().await.ok()
+        }
+
+        async fn history(&self) -> Vec<ClipboardEntry> {
+            self.engine.history.get_recent_entries(100).await.unwrap()
+        }
+
+        /// One monitor poll, announcing any local change to peers
+        async fn poll(&mut self) {
+            if let Ok(content) = self
+                .clipboard
+                .read_selection(ClipboardSelection::Clipboard)
+                .await
+            {
+                let mut last_hash = self.last_hash.take();
+                self.engine
+                    .handle_local_change(content, ClipboardSelection::Clipboard, &mut last_hash)
+                    .await;
+                self.last_hash = last_hash;
+            }
+
+            while let Ok(event) = self.events.try_recv() {
+                match event.kind {
+                    SyncEventKind::Local => {
+                        self.engine.broadcast_to_peers(&event).await;
+                        self.announced.push(event.entry);
+                    }
+                    SyncEventKind::Conflict(resolution) => self.conflicts.push(resolution),
+                    SyncEventKind::Remote => {}
+                }
+            }
+        }
+    }
+
+    async fn node() -> Node {
+        node_with(Config::default()).await
+    }
+
+    async fn node_with(config: Config) -> Node {
+        node_with_transport(config, TransportConfig::default()).await
+    }
+
+    async fn node_with_transport(config: Config, transport: TransportConfig) -> Node {
+        let dir = TempDir::new().unwrap();
+        let config = Arc::new(config);
+
+        let history = ClipboardHistory::with_key_store(
+            &dir.path().join("history.db"),
+            KeyStore::new(dir.path().join("history.key")),
+            RetentionPolicy::default(),
+        )
+        .await
+        .unwrap();
+        let memory = MemoryClipboard::default();
+        let targets = Arc::clone(&memory.targets);
+        let clipboard = Arc::new(ClipboardProviderWrapper::new(Box::new(memory)));
+
+        let engine = SyncEngine::new(
+            Arc::clone(&config),
+            Arc::clone(&clipboard),
+            Arc::new(HistoryManager::from_history(history)),
+            Arc::new(PeerDiscovery::new(Arc::clone(&config)).await.unwrap()),
+            Arc::new(TransportManager::new(transport)),
+        )
+        .with_fingerprints(Arc::new(TrustedNodes));
+        let events = engine.subscribe();
+
+        Node {
+            engine,
+            clipboard,
+            targets,
+            events,
+            outbound: Vec::new(),
+            last_hash: None,
+            key: key_of(config.node_id),
+            sent: 0,
+            announced: Vec::new(),
+            conflicts: Vec::new(),
+            _dir: dir,
+        }
+    }
+
+    /// Connect `from` to `to` over a mock connection
+    async fn link(nodes: &mut [Node], from: usize, to: usize) {
+        link_with(nodes, from, to, None).await;
+    }
+
+    /// Connect `from` to `to`, with `to` having negotiated `capabilities`
+    async fn link_with(
+        nodes: &mut [Node],
+        from: usize,
+        to: usize,
+        capabilities: Option<NegotiatedCapabilities>,
+    ) {
+        let (mut connection, _inbound, outbound) = MockConnection::new();
+        connection.capabilities = capabilities;
+        let peer_id = nodes[to].id();
+        connection.fingerprint = Some(nodes[to].key.clone());
+
+        nodes[from]
+            .engine
+            .transport
+            .register_peer_connection(peer_id, Box::new(connection))
+            .await
+            .unwrap();
+        nodes[from].engine.peers.write().await.insert(
+            peer_id,
+            Peer {
+                id: peer_id,
+                hostname: format!("node{}", to),
+                address: String::new(),
+                capabilities: Vec::new(),
+                groups: Vec::new(),
+            },
+        );
+        nodes[from].outbound.push((peer_id, outbound));
+    }
+
+    /// Have every other node see `node` announce `groups`
+    async fn announce_groups(nodes: &[Node], node: usize, groups: &[&str]) {
+        let id = nodes[node].id();
+        for other in nodes {
+            if let Some(peer) = other.engine.peers.write().await.get_mut(&id) {
+                peer.groups = groups.iter().map(|g| g.to_string()).collect();
+            }
+        }
+    }
+
+    /// Config for a node in `groups`, sending to `active`
+    fn grouped(groups: &[&str], active: &[&str]) -> Config {
+        let mut config = Config::default();
+        config.sync.groups = groups.iter().map(|g| g.to_string()).collect();
+        config.sync.active_groups = active.iter().map(|g| g.to_string()).collect();
+        config
+    }
+
+    /// Let the node with `config` count `peer` as a member of `groups`
+    fn allow_groups(config: &mut Config, peer: &Config, groups: &[&str]) {
+        config.sync.policies.push(PeerPolicyConfig {
+            peer: peer.node_id.to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            ..PeerPolicyConfig::default()
+        });
+    }
+
+    /// Nodes with the given configs, all connected to each other
+    async fn mesh_of(configs: Vec<Config>) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        for config in configs {
+            nodes.push(node_with(config).await);
+        }
+        let size = nodes.len();
+        for from in 0..size {
+            for to in 0..size {
+                if from != to {
+                    link(&mut nodes, from, to).await;
+                }
+            }
+        }
+        nodes
+    }
+
+    async fn mesh(size: usize) -> Vec<Node> {
+        mesh_with(size, TransportConfig::default()).await
+    }
+
+    async fn mesh_with(size: usize, transport: TransportConfig) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        for _ in 0..size {
+            nodes.push(node_with_transport(Config::default(), transport.clone()).await);
+        }
+        for from in 0..size {
+            for to in 0..size {
+                if from != to {
+                    link(&mut nodes, from, to).await;
+                }
+            }
+        }
+        nodes
+    }
+
+    /// Hand every queued message to its receiver, returning how many moved
+    async fn deliver(nodes: &mut [Node]) -> usize {
+        let mut queued = Vec::new();
+        for node in nodes.iter_mut() {
+            let sender = node.id();
+            for (peer_id, outbound) in node.outbound.iter_mut() {
+                while let Ok(mut message) = outbound.try_recv() {
+                    message.source_peer_id = Some(sender);
+                    queued.push((*peer_id, message));
+                }
+            }
+        }
+
+        let delivered = queued.len();
+        for (peer_id, message) in queued {
+            let node = nodes.iter_mut().find(|n| n.id() == peer_id).unwrap();
+            node.sent += 1;
+            let event = match message.payload {
+                MessagePayload::History(payload) => {
+                    let sender = message.source_peer_id.unwrap();
+                    node.engine
+                        .handle_history_message(sender, payload)
+                        .await
+                        .unwrap();
+                    None
+                }
+                MessagePayload::Stream(_) => node.engine.receive_stream_message(message).await,
+                _ => SyncEngine::sync_event_from_message(message),
+            };
+            if let Some(event) = event {
+                node.engine.handle_remote_sync_event(&event).await;
+            }
+        }
+        delivered
+    }
+
+    /// Poll and deliver until nothing moves, returning the messages sent
+    async fn settle(nodes: &mut [Node]) -> usize {
+        let mut total = 0;
+        for _ in 0..10 {
+            for node in nodes.iter_mut() {
+                node.poll().await;
+            }
+            let delivered = deliver(nodes).await;
+            if delivered == 0 {
+                return total;
+            }
+            total += delivered;
+        }
+        panic!(
+            "sync did not settle after 10 rounds, {} messages sent",
+            total
+        );
+    }
+
+    #[tokio::test]
+    async fn test_three_node_mesh_does_not_echo() {
+        let mut nodes = mesh(3).await;
+
+        nodes[0].copy("shared between three machines").await;
+        assert_eq!(settle(&mut nodes).await, 2);
+
+        let origin = nodes[0].id();
+        let id = nodes[0].history().await[0].id;
+        for node in &nodes {
+            assert_eq!(
+                node.clipboard_text().await.as_deref(),
+                Some("shared between three machines")
+            );
+
+            // One entry everywhere, with the same ID and origin
+            let history = node.history().await;
+            assert_eq!(history.len(), 1);
+            assert_eq!(history[0].id, id);
+            assert_eq!(history[0].source, origin);
+        }
+
+        // Another round of polling finds nothing new to announce
+        assert_eq!(settle(&mut nodes).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_three_node_successive_copies() {
+        let mut nodes = mesh(3).await;
+
+        nodes[0].copy("first copy").await;
+        assert_eq!(settle(&mut nodes).await, 2);
+        nodes[2].copy("second copy").await;
+        assert_eq!(settle(&mut nodes).await, 2);
+
+        // Copying content seen earlier is a fresh local change again
+        nodes[1].copy("first copy").await;
+        assert_eq!(settle(&mut nodes).await, 2);
+
+        for node in &nodes {
+            assert_eq!(node.clipboard_text().await.as_deref(), Some("first copy"));
+            assert_eq!(node.history().await.len(), 3);
+        }
+        assert_eq!(nodes[0].sent, 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_copies_converge() {
+        let mut nodes = mesh(3).await;
+
+        // Both copies are announced before either node hears of the other
+        nodes[0].copy("copied on zero").await;
+        nodes[1].copy("copied on one").await;
+        nodes[0].poll().await;
+        nodes[1].poll().await;
+        assert_eq!(settle(&mut nodes).await, 4);
+
+        let mut entries = vec![nodes[0].announced[0].clone(), nodes[1].announced[0].clone()];
+        entries.sort_by_key(|entry| entry.hlc);
+        let (loser, winner) = (&entries[0], &entries[1]);
+
+        for node in &mut nodes {
+            node.poll().await;
+            assert_eq!(
+                node.clipboard_text().await,
+                winner.content.as_text().map(str::to_string),
+                "node {} kept the wrong entry",
+                node.id()
+            );
+            // Both copies stay in history
+            assert_eq!(node.history().await.len(), 2);
+        }
+
+        // The nodes that made a copy each saw the other's arrive
+        for node in &nodes[..2] {
+            assert_eq!(node.conflicts.len(), 1);
+            let resolution = &node.conflicts[0];
+            assert_eq!((resolution.winner, resolution.loser), (winner.id, loser.id));
+            assert_eq!(resolution.local_won, node.id() == winner.source);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_later_copy_wins_over_skewed_clock() {
+        let mut nodes = mesh(2).await;
+
+        // A message from a peer whose clock runs an hour ahead
+        nodes[0].copy("from the future").await;
+        nodes[0].poll().await;
+        let mut message = nodes[0].outbound[0].1.try_recv().unwrap();
+        let hlc = message.hlc.as_mut().unwrap();
+        hlc.wall_ms += 3_600_000;
+        message.source_peer_id = Some(nodes[0].id());
+        let event = SyncEngine::sync_event_from_message(message).unwrap();
+        nodes[1].engine.handle_remote_sync_event(&event).await;
+
+        // Copying after it arrived orders after it, so nothing conflicts
+        nodes[1].copy("copied later").await;
+        nodes[1].poll().await;
+        let mut message = nodes[1].outbound[0].1.try_recv().unwrap();
+        assert!(message.hlc.unwrap() > event.entry.hlc);
+        message.source_peer_id = Some(nodes[1].id());
+        let reply = SyncEngine::sync_event_from_message(message).unwrap();
+        assert_eq!(reply.replaces, Some(event.entry.id));
+
+        nodes[0].engine.handle_remote_sync_event(&reply).await;
+        nodes[0].poll().await;
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("copied later")
+        );
+        assert!(nodes[0].conflicts.is_empty());
+    }
+
+    /// Drop whatever a node queued for its peers, as if it was offline
+    fn disconnect(node: &mut Node) {
+        for (_, outbound) in node.outbound.iter_mut() {
+            while outbound.try_recv().is_ok() {}
+        }
+    }
+
+    /// Close a node's connection to a peer, so sending to it fails
+    fn go_offline(node: &mut Node, peer_id: Uuid) {
+        node.outbound.retain(|(id, _)| *id != peer_id);
+    }
+
+    async fn history_texts(node: &Node) -> Vec<String> {
+        let mut texts: Vec<String> = node
+            .history()
+            .await
+            .iter()
+            .filter_map(|entry| entry.content.as_text().map(str::to_string))
+            .collect();
+        texts.sort();
+        texts
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_after_reconnect() {
+        let mut nodes = mesh(2).await;
+
+        nodes[0].copy("shared").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+
+        // Both sides copy while the connection is down
+        for text in ["first while away", "second while away"] {
+            nodes[0].copy(text).await;
+            nodes[0].poll().await;
+        }
+        disconnect(&mut nodes[0]);
+        nodes[1].copy("copied on the laptop").await;
+        nodes[1].poll().await;
+        disconnect(&mut nodes[1]);
+
+        // Digest, inventory, request, entries in both directions
+        let desktop = nodes[0].id();
+        nodes[1].engine.catch_up(desktop).await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 5);
+
+        let expected = vec![
+            "copied on the laptop",
+            "first while away",
+            "second while away",
+            "shared",
+        ];
+        assert_eq!(history_texts(&nodes[0]).await, expected);
+        assert_eq!(history_texts(&nodes[1]).await, expected);
+
+        // Caught-up entries keep their IDs, and a second catch-up finds
+        // nothing to do
+        let ids = |history: Vec<ClipboardEntry>| {
+            let mut ids: Vec<Uuid> = history.iter().map(|e| e.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(nodes[0].history().await), ids(nodes[1].history().await));
+        nodes[1].engine.catch_up(desktop).await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_applies_newest_by_clock() {
+        let mut nodes = mesh(2).await;
+
+        // Both copy within the same second while the connection is down,
+        // the desktop last
+        nodes[1].copy("on the laptop").await;
+        nodes[1].poll().await;
+        disconnect(&mut nodes[1]);
+        sleep(Duration::from_millis(5)).await;
+        nodes[0].copy("on the desktop").await;
+        nodes[0].poll().await;
+        disconnect(&mut nodes[0]);
+
+        let desktop = nodes[0].id();
+        nodes[1].engine.catch_up(desktop).await.unwrap();
+        settle(&mut nodes).await;
+
+        // Ordered by clock stamp, not the whole-second timestamp, which
+        // caught-up entries keep
+        let stamp = |history: Vec<ClipboardEntry>| {
+            history
+                .into_iter()
+                .find(|entry| entry.content.as_text() == Some("on the desktop"))
+                .map(|entry| entry.hlc)
+        };
+        assert_eq!(
+            stamp(nodes[1].history().await),
+            stamp(nodes[0].history().await)
+        );
+        for node in &nodes {
+            assert_eq!(
+                node.clipboard_text().await.as_deref(),
+                Some("on the desktop")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_skips_primary_when_disabled() {
+        let mut desktop = Config::default();
+        desktop.clipboard.sync_primary = true;
+        let mut laptop = Config::default();
+        laptop.clipboard.sync_primary = false;
+        let mut nodes = mesh_of(vec![desktop, laptop]).await;
+
+        // Copied and selected while the laptop is away
+        nodes[0].copy("copied").await;
+        nodes[0].poll().await;
+        nodes[0]
+            .engine
+            .handle_local_change(
+                ClipboardContent::text("selected"),
+                ClipboardSelection::Primary,
+                &mut None,
+            )
+            .await;
+        while nodes[0].events.try_recv().is_ok() {}
+        disconnect(&mut nodes[0]);
+
+        let desktop = nodes[0].id();
+        nodes[1].engine.catch_up(desktop).await.unwrap();
+        settle(&mut nodes).await;
+        assert_eq!(history_texts(&nodes[0]).await, vec!["copied", "selected"]);
+        assert_eq!(history_texts(&nodes[1]).await, vec!["copied"]);
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_limits() {
+        let mut limited = Config::default();
+        limited.sync.catch_up_max_entries = 2;
+        let mut nodes = vec![node().await, node_with(limited).await];
+        link(&mut nodes, 0, 1).await;
+        link(&mut nodes, 1, 0).await;
+
+        for text in ["one", "two", "three"] {
+            nodes[0].copy(text).await;
+            nodes[0].poll().await;
+        }
+        disconnect(&mut nodes[0]);
+
+        let desktop = nodes[0].id();
+        nodes[1].engine.catch_up(desktop).await.unwrap();
+        settle(&mut nodes).await;
+
+        // Only the newest entries are fetched, and the newest is applied
+        assert_eq!(history_texts(&nodes[1]).await, vec!["three", "two"]);
+        assert_eq!(nodes[1].clipboard_text().await.as_deref(), Some("three"));
+
+        // Nothing moves when catch-up is off
+        let mut off = Config::default();
+        off.sync.catch_up = false;
+        let mut nodes = vec![nodes.remove(0), node_with(off).await];
+        link(&mut nodes, 1, 0).await;
+        nodes[1].engine.catch_up(desktop).await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_offline_peer_gets_latest_copy_on_reconnect() {
+        let mut nodes = mesh(2).await;
+        let laptop = nodes[1].id();
+        go_offline(&mut nodes[0], laptop);
+
+        for text in ["first while away", "second while away", "third while away"] {
+            nodes[0].copy(text).await;
+            nodes[0].poll().await;
+        }
+        assert_eq!(deliver(&mut nodes).await, 0);
+        assert_eq!(
+            nodes[0].engine.transport.queue_stats(laptop).await,
+            QueueStats {
+                depth: 1,
+                dropped: 0,
+                superseded: 2,
+            }
+        );
+
+        // Only the latest copy is sent once the peer is back
+        link(&mut nodes, 0, 1).await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("third while away")
+        );
+        assert_eq!(nodes[0].engine.transport.queue_stats(laptop).await.depth, 0);
+    }
+
+    #[tokio::test]
+    async fn test_safety_rules_shape_what_peers_see() {
+        let rule = |name: &str, keyword: &str, action: &str| SafetyRuleConfig {
+            name: name.to_string(),
+            kind: "keyword".to_string(),
+            keywords: vec![keyword.to_string()],
+            action: Some(action.to_string()),
+            ..SafetyRuleConfig::default()
+        };
+        let mut config = Config::default();
+        config.safety.rules = vec![
+            rule("deploy-token", "token:", "redact"),
+            rule("scratch", "scratch:", "skip_history"),
+            rule("vault", "vault:", "skip_sync"),
+        ];
+        let mut nodes = vec![node_with(config).await, node().await];
+        link(&mut nodes, 0, 1).await;
+        link(&mut nodes, 1, 0).await;
+
+        // Peers get the redacted text, while the local clipboard keeps it
+        nodes[0].copy("deploy token: 7f3a9c").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("deploy [redacted]")
+        );
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("deploy token: 7f3a9c")
+        );
+
+        // Synced, but recorded by neither side
+        nodes[0].copy("scratch: 555-0100").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("scratch: 555-0100")
+        );
+
+        // Not synced at all
+        nodes[0].copy("vault: hunter2").await;
+        assert_eq!(settle(&mut nodes).await, 0);
+
+        let expected = vec!["deploy [redacted]"];
+        assert_eq!(history_texts(&nodes[0]).await, expected);
+        assert_eq!(history_texts(&nodes[1]).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_forced_sync_follows_safety_rules() {
+        let rule = |name: &str, keyword: &str, action: &str| SafetyRuleConfig {
+            name: name.to_string(),
+            kind: "keyword".to_string(),
+            keywords: vec![keyword.to_string()],
+            action: Some(action.to_string()),
+            ..SafetyRuleConfig::default()
+        };
+        let mut config = Config::default();
+        config.safety.rules = vec![
+            rule("deploy-token", "token:", "redact"),
+            rule("vault", "vault:", "skip_sync"),
+        ];
+        let mut nodes = vec![node_with(config).await, node().await];
+        link(&mut nodes, 0, 1).await;
+        link(&mut nodes, 1, 0).await;
+
+        nodes[0].copy("vault: hunter2").await;
+        assert_eq!(settle(&mut nodes).await, 0);
+        nodes[0].engine.force_sync().await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 0);
+        assert_eq!(nodes[1].clipboard_text().await, None);
+
+        // Peers get the redacted text, however often it is sent
+        nodes[0].copy("deploy token: 7f3a9c").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        nodes[0].engine.force_sync().await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 1);
+        let forced = nodes[0].announced.last().unwrap();
+        assert_eq!(forced.content.as_text(), Some("deploy [redacted]"));
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("deploy [redacted]")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_peer_policies_limit_what_is_exchanged() {
+        let (desktop, kiosk) = (Config::default(), Config::default());
+        let mut laptop = Config::default();
+        laptop.sync.policies = vec![
+            PeerPolicyConfig {
+                peer: desktop.node_id.to_string(),
+                formats: vec!["text/plain".to_string()],
+                max_size: Some(16),
+                history: false,
+                ..PeerPolicyConfig::default()
+            },
+            PeerPolicyConfig {
+                peer: kiosk.node_id.to_string(),
+                direction: "send_only".to_string(),
+                ..PeerPolicyConfig::default()
+            },
+        ];
+        let mut nodes = vec![
+            node_with(laptop).await,
+            node_with(desktop).await,
+            node_with(kiosk).await,
+        ];
+        for from in 0..3 {
+            for to in 0..3 {
+                if from != to {
+                    link(&mut nodes, from, to).await;
+                }
+            }
+        }
+
+        // Sent to both peers
+        nodes[0].copy("short note").await;
+        assert_eq!(settle(&mut nodes).await, 2);
+        assert_eq!(
+            nodes[2].clipboard_text().await.as_deref(),
+            Some("short note")
+        );
+
+        // The laptop only sends to the kiosk
+        nodes[2].copy("from the kiosk").await;
+        settle(&mut nodes).await;
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("short note")
+        );
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("from the kiosk")
+        );
+
+        // Too large for the desktop's policy
+        nodes[1].copy("a note longer than sixteen bytes").await;
+        settle(&mut nodes).await;
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("short note")
+        );
+
+        // Applied, but kept out of the laptop's history
+        nodes[1].copy("small").await;
+        settle(&mut nodes).await;
+        assert_eq!(nodes[0].clipboard_text().await.as_deref(), Some("small"));
+        assert_eq!(history_texts(&nodes[0]).await, vec!["short note"]);
+        assert_eq!(history_texts(&nodes[1]).await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_copies_stay_within_sync_groups() {
+        let mut workstation = grouped(&["personal", "pairing"], &["pairing"]);
+        let mut laptop = grouped(&["personal"], &[]);
+        let mut colleague = grouped(&["pairing"], &[]);
+        allow_groups(&mut workstation, &laptop, &["personal"]);
+        allow_groups(&mut workstation, &colleague, &["pairing"]);
+        allow_groups(&mut laptop, &workstation, &["personal"]);
+        // Wrongly, but the colleague still ignores personal copies
+        allow_groups(&mut laptop, &colleague, &["personal"]);
+        allow_groups(&mut colleague, &workstation, &["pairing"]);
+        allow_groups(&mut colleague, &laptop, &["personal"]);
+
+        let mut nodes = mesh_of(vec![workstation, laptop, colleague]).await;
+        announce_groups(&nodes, 0, &["personal", "pairing"]).await;
+        announce_groups(&nodes, 1, &["personal"]).await;
+        announce_groups(&nodes, 2, &["pairing"]).await;
+
+        // The laptop has a stale view of the colleague's groups, but the
+        // colleague ignores an entry tagged for a group it is not in
+        let colleague = nodes[2].id();
+        nodes[1]
+            .engine
+            .peers
+            .write()
+            .await
+            .get_mut(&colleague)
+            .unwrap()
+            .groups = vec!["personal".to_string()];
+        nodes[1].copy("note to self").await;
+        assert_eq!(settle(&mut nodes).await, 2);
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("note to self")
+        );
+        assert_eq!(nodes[2].clipboard_text().await, None);
+
+        // Only pairing is active on the workstation
+        nodes[0].copy("pairing snippet").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[2].clipboard_text().await.as_deref(),
+            Some("pairing snippet")
+        );
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("note to self")
+        );
+
+        nodes[2].copy("review comments").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("review comments")
+        );
+
+        assert_eq!(history_texts(&nodes[1]).await, vec!["note to self"]);
+        assert_eq!(
+            history_texts(&nodes[2]).await,
+            vec!["pairing snippet", "review comments"]
+        );
+        assert_eq!(history_texts(&nodes[0]).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_peer_cannot_claim_a_sync_group() {
+        let mut workstation = grouped(&["personal", "pairing"], &["personal"]);
+        let mut laptop = grouped(&["personal"], &[]);
+        // The work laptop claims to be one of our own machines
+        let mut work = grouped(&["personal", "pairing"], &["personal"]);
+        allow_groups(&mut workstation, &laptop, &["personal"]);
+        allow_groups(&mut workstation, &work, &["pairing"]);
+        allow_groups(&mut laptop, &workstation, &["personal"]);
+        allow_groups(&mut work, &workstation, &["personal", "pairing"]);
+
+        let mut nodes = mesh_of(vec![workstation, laptop, work]).await;
+        announce_groups(&nodes, 0, &["personal", "pairing"]).await;
+        announce_groups(&nodes, 1, &["personal"]).await;
+        announce_groups(&nodes, 2, &["personal", "pairing"]).await;
+
+        // Personal copies only go to the laptop
+        nodes[0].copy("note to self").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("note to self")
+        );
+        assert_eq!(nodes[2].clipboard_text().await, None);
+
+        // Its entries tagged personal are not applied
+        nodes[2].copy("injected").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("note to self")
+        );
+        assert_eq!(history_texts(&nodes[0]).await, vec!["note to self"]);
+
+        // Nor does it get or give history
+        let workstation = nodes[0].id();
+        nodes[2].engine.catch_up(workstation).await.unwrap();
+        settle(&mut nodes).await;
+        assert_eq!(history_texts(&nodes[0]).await, vec!["note to self"]);
+        assert_eq!(history_texts(&nodes[2]).await, vec!["injected"]);
+    }
+
+    #[tokio::test]
+    async fn test_claimed_node_id_does_not_join_its_groups() {
+        let mut workstation = grouped(&["personal", "pairing"], &["personal"]);
+        let laptop = grouped(&["personal"], &[]);
+        allow_groups(&mut workstation, &laptop, &["personal"]);
+        // The work laptop claims the personal laptop's node ID, but
+        // authenticates with its own key
+        let mut work = grouped(&["personal"], &[]);
+        work.node_id = laptop.node_id;
+        allow_groups(&mut work, &workstation, &["personal"]);
+
+        let mut nodes = vec![node_with(workstation).await, node_with(work).await];
+        nodes[1].key = "SHA256:work".to_string();
+        link(&mut nodes, 0, 1).await;
+        link(&mut nodes, 1, 0).await;
+        announce_groups(&nodes, 0, &["personal", "pairing"]).await;
+        announce_groups(&nodes, 1, &["personal"]).await;
+
+        // Personal copies don't reach it
+        nodes[0].copy("note to self").await;
+        assert_eq!(settle(&mut nodes).await, 0);
+        assert_eq!(nodes[1].clipboard_text().await, None);
+
+        // Nor are its copies tagged personal applied
+        nodes[1].copy("injected").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[0].clipboard_text().await.as_deref(),
+            Some("note to self")
+        );
+        assert_eq!(history_texts(&nodes[0]).await, vec!["note to self"]);
+    }
+
+    #[tokio::test]
+    async fn test_expiring_copy_is_cleared_everywhere() {
+        let mut config = Config::default();
+        config.safety.rules = vec![SafetyRuleConfig {
+            name: "otp".to_string(),
+            kind: "regex".to_string(),
+            pattern: Some(r"^\d{6}$".to_string()),
+            action: Some("expire".to_string()),
+            ttl: Some("1s".to_string()),
+            ..SafetyRuleConfig::default()
+        }];
+        let mut nodes = vec![node_with(config).await, node().await];
+        link(&mut nodes, 0, 1).await;
+        link(&mut nodes, 1, 0).await;
+
+        nodes[0].copy("482913").await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(nodes[1].clipboard_text().await.as_deref(), Some("482913"));
+        assert_eq!(history_texts(&nodes[1]).await, vec!["482913"]);
+
+        sleep(Duration::from_millis(1500)).await;
+        for node in &nodes {
+            assert_eq!(node.clipboard_text().await, None);
+            assert!(node.history().await.is_empty());
+        }
+        assert_eq!(settle(&mut nodes).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_copy_with_ttl_keeps_newer_copies() {
+        let mut nodes = mesh(2).await;
+
+        nodes[0]
+            .engine
+            .copy_with_ttl(
+                &ClipboardData::Text("temporary".to_string()),
+                Duration::from_millis(300),
+            )
+            .await
+            .unwrap();
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("temporary")
+        );
+        assert!(nodes[0].engine.pending_ttl.lock().await.is_none());
+
+        // Copied over on the peer before the entry expires
+        nodes[1].copy("kept").await;
+        sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(nodes[0].clipboard_text().await, None);
+        assert_eq!(nodes[1].clipboard_text().await.as_deref(), Some("kept"));
+        assert!(history_texts(&nodes[0]).await.is_empty());
+        assert!(history_texts(&nodes[1]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_copy_with_ttl_of_content_already_copied() {
+        let mut nodes = mesh(2).await;
+        let otp = ClipboardData::Text("482913".to_string());
+
+        nodes[0]
+            .engine
+            .copy_with_ttl(&otp, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(settle(&mut nodes).await, 1);
+
+        // The clipboard doesn't change, so the shorter time to live applies
+        // to what it holds
+        nodes[0]
+            .engine
+            .copy_with_ttl(&otp, Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert_eq!(settle(&mut nodes).await, 0);
+        assert!(nodes[0].engine.pending_ttl.lock().await.is_none());
+
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(nodes[0].clipboard_text().await, None);
+        assert!(nodes[0].history().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expiring_copy_expires_after_catch_up() {
+        let mut nodes = mesh(2).await;
+
+        // Copied while the laptop is away
+        nodes[0]
+            .engine
+            .copy_with_ttl(
+                &ClipboardData::Text("482913".to_string()),
+                Duration::from_secs(2),
+            )
+            .await
+            .unwrap();
+        nodes[0].poll().await;
+        disconnect(&mut nodes[0]);
+
+        let desktop = nodes[0].id();
+        nodes[1].engine.catch_up(desktop).await.unwrap();
+        settle(&mut nodes).await;
+        assert_eq!(nodes[1].clipboard_text().await.as_deref(), Some("482913"));
+        let caught_up = nodes[1].history().await;
+        assert_eq!(caught_up.len(), 1);
+        assert!(caught_up[0].expires_at.is_some());
+
+        sleep(Duration::from_millis(2500)).await;
+        for node in &nodes {
+            assert_eq!(node.clipboard_text().await, None);
+            assert!(node.history().await.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concealed_copies_stay_local() {
+        let mut nodes = mesh(2).await;
+
+        nodes[0]
+            .copy_offering(
+                "correct horse",
+                &["UTF8_STRING", "x-kde-passwordManagerHint"],
+            )
+            .await;
+        assert_eq!(settle(&mut nodes).await, 0);
+        assert!(nodes[0].history().await.is_empty());
+        assert_eq!(nodes[1].clipboard_text().await, None);
+
+        // The next ordinary copy syncs as usual
+        nodes[0]
+            .copy_offering("battery staple", &["UTF8_STRING", "text/plain"])
+            .await;
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(history_texts(&nodes[0]).await, vec!["battery staple"]);
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some("battery staple")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forced_sync_keeps_concealed_copies_local() {
+        let mut nodes = mesh(2).await;
+
+        nodes[0]
+            .copy_offering(
+                "correct horse",
+                &["UTF8_STRING", "x-kde-passwordManagerHint"],
+            )
+            .await;
+        assert_eq!(settle(&mut nodes).await, 0);
+        nodes[0].engine.force_sync().await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 0);
+        assert!(nodes[0].announced.is_empty());
+        assert_eq!(nodes[1].clipboard_text().await, None);
+    }
+
+    /// Large text that compresses poorly without looking like a secret
+    fn large_text(len: usize) -> String {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                match state % 16 {
+                    15 => ' ',
+                    n => char::from(b'a' + n as u8),
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_large_copy_streams_and_resumes() {
+        const CHUNK_SIZE: usize = 8 * 1024;
+        let transport = TransportConfig {
+            stream_chunk_size: CHUNK_SIZE,
+            stream_threshold: 64 * 1024,
+            ..TransportConfig::default()
+        };
+        let mut nodes = mesh_with(2, transport).await;
+        let screenshot = large_text(512 * 1024);
+        nodes[0].copy(&screenshot).await;
+        nodes[0].poll().await;
+
+        // The first window of chunks arrives and is acknowledged
+        assert_eq!(deliver(&mut nodes).await, 1 + MAX_IN_FLIGHT_CHUNKS);
+        assert_eq!(deliver(&mut nodes).await, MAX_IN_FLIGHT_CHUNKS);
+        let transfers = nodes[0].engine.transfers().await;
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].peer_id, nodes[1].id());
+        assert_eq!(transfers[0].progress.direction, StreamDirection::Outbound);
+        assert_eq!(
+            transfers[0].progress.bytes_transferred,
+            (MAX_IN_FLIGHT_CHUNKS * CHUNK_SIZE) as u64
+        );
+        assert!(transfers[0].progress.total_chunks > 2 * MAX_IN_FLIGHT_CHUNKS as u64);
+
+        // The connection drops with the next window in flight and comes
+        // back; the stream carries on rather than starting over
+        disconnect(&mut nodes[0]);
+        link(&mut nodes, 0, 1).await;
+        let progress = &nodes[0].engine.transfers().await[0].progress;
+        assert_eq!(progress.current_chunk, 2 * MAX_IN_FLIGHT_CHUNKS as u64);
+
+        settle(&mut nodes).await;
+        assert_eq!(
+            nodes[1].clipboard_text().await.as_deref(),
+            Some(screenshot.as_str())
+        );
+        assert_eq!(
+            nodes[1].history().await[0].id,
+            nodes[0].history().await[0].id
+        );
+        assert!(nodes[0].engine.transfers().await.is_empty());
+        assert!(nodes[1].engine.transfers().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_peers_only_receive_formats_they_render() {
+        let mut nodes = Vec::new();
+        for _ in 0..3 {
+            nodes.push(node().await);
+        }
+        link(&mut nodes, 0, 1).await;
+        let terminal = NegotiatedCapabilities {
+            formats: vec![ClipboardFormat::Text],
+            compression: CompressionMethod::None,
+            max_message_size: crate::MAX_PAYLOAD_SIZE,
+            streaming: true,
+        };
+        link_with(&mut nodes, 0, 2, Some(terminal)).await;
+
+        let html = ClipboardData::Html("<p>Build <b>passed</b></p>".to_string());
+        nodes[0].clipboard.set_content(&html).await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 2);
+
+        // The desktop gets the HTML, the text-only peer a plain rendering
+        assert_eq!(nodes[1].history().await[0].content, html);
+        let converted = &nodes[2].history().await[0];
+        assert_eq!(
+            converted.content,
+            ClipboardData::Text("Build passed".to_string())
+        );
+        assert_eq!(converted.id, nodes[0].history().await[0].id);
+
+        // Images have no text fallback, so they are not sent at all
+        let image = ClipboardData::Image {
+            mime_type: "image/png".to_string(),
+            data: vec![0x89, b'P', b'N', b'G', 1, 2, 3],
+        };
+        nodes[0].clipboard.set_content(&image).await.unwrap();
+        assert_eq!(settle(&mut nodes).await, 1);
+        assert_eq!(nodes[1].clipboard.get_content().await.unwrap(), image);
+        assert_eq!(nodes[2].history().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_relayed_entries_apply_once() {
+        let mut nodes = mesh(3).await;
+        nodes[0].copy("relayed").await;
+        nodes[0].poll().await;
+
+        // The same entry arriving again, e.g. relayed by a third node, or
+        // coming back to where it started, is ignored
+        let (peer_id, outbound) = &mut nodes[0].outbound[0];
+        let peer_id = *peer_id;
+        let mut message = outbound.try_recv().unwrap();
+        message.source_peer_id = Some(nodes[0].id());
+        let event = SyncEngine::sync_event_from_message(message).unwrap();
+
+        let receiver = nodes.iter().position(|n| n.id() == peer_id).unwrap();
+        nodes[receiver]
+            .engine
+            .handle_remote_sync_event(&event)
+            .await;
+        nodes[receiver]
+            .clipboard
+            .set_text("changed since")
+            .await
+            .unwrap();
+        nodes[receiver]
+            .engine
+            .handle_remote_sync_event(&event)
+            .await;
+        assert_eq!(
+            nodes[receiver].clipboard_text().await.as_deref(),
+            Some("changed since")
+        );
+
+        nodes[0].copy("moved on").await;
+        nodes[0].engine.handle_remote_sync_event(&event).await;
+        assert_eq!(nodes[0].clipboard_text().await.as_deref(), Some("moved on"));
+    }
 }
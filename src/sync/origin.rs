@@ -0,0 +1,124 @@
+//! Origin tracking and echo suppression for synced clipboard entries
+//!
+//! Every entry keeps the ID given to it by the node where it was copied,
+//! and that node's ID as its origin, wherever it travels. Applying a remote
+//! entry writes to the local clipboard, which the monitor then reports as a
+//! local change; [`EchoGuard`] recognises that change so it is not announced
+//! again as new content.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+/// Content IDs remembered by default
+pub const DEFAULT_SEEN_CAPACITY: usize = 1024;
+
+/// How long a remote write waits for the monitor to report it
+pub const DEFAULT_ECHO_WINDOW: Duration = Duration::from_secs(30);
+
+/// Remembers content IDs that were applied or announced, and clipboard
+/// writes whose echo is still expected
+#[derive(Debug)]
+pub struct EchoGuard {
+    seen: HashSet<Uuid>,
+    seen_order: VecDeque<Uuid>,
+    capacity: usize,
+    pending: HashMap<String, Instant>,
+    echo_window: Duration,
+}
+
+impl Default for EchoGuard {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEEN_CAPACITY, DEFAULT_ECHO_WINDOW)
+    }
+}
+
+impl EchoGuard {
+    /// Guard remembering up to `capacity` content IDs
+    pub fn new(capacity: usize, echo_window: Duration) -> Self {
+        Self {
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+            capacity: capacity.max(1),
+            pending: HashMap::new(),
+            echo_window,
+        }
+    }
+
+    /// Record a content ID, returning `false` if it was already known
+    pub fn mark_seen(&mut self, id: Uuid) -> bool {
+        if !self.seen.insert(id) {
+            return false;
+        }
+
+        self.seen_order.push_back(id);
+        while self.seen_order.len() > self.capacity {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// Whether a content ID was applied or announced recently
+    pub fn is_seen(&self, id: &Uuid) -> bool {
+        self.seen.contains(id)
+    }
+
+    /// Note that content with this checksum is about to be written locally
+    pub fn expect_echo(&mut self, checksum: impl Into<String>) {
+        let now = Instant::now();
+        self.pending
+            .retain(|_, at| now.duration_since(*at) < self.echo_window);
+        self.pending.insert(checksum.into(), now);
+    }
+
+    /// Consume the expectation for a local change, returning `true` if the
+    /// change is the echo of a remote write
+    pub fn take_echo(&mut self, checksum: &str) -> bool {
+        match self.pending.remove(checksum) {
+            Some(at) => at.elapsed() < self.echo_window,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_ids_are_bounded() {
+        let mut guard = EchoGuard::new(2, DEFAULT_ECHO_WINDOW);
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        assert!(guard.mark_seen(ids[0]));
+        assert!(!guard.mark_seen(ids[0]));
+        assert!(guard.mark_seen(ids[1]));
+        assert!(guard.mark_seen(ids[2]));
+
+        // The oldest ID made room for the newest
+        assert!(!guard.is_seen(&ids[0]));
+        assert!(guard.is_seen(&ids[1]));
+        assert!(guard.is_seen(&ids[2]));
+    }
+
+    #[test]
+    fn test_echo_is_consumed_once() {
+        let mut guard = EchoGuard::default();
+        guard.expect_echo("abc");
+
+        assert!(!guard.take_echo("other"));
+        assert!(guard.take_echo("abc"));
+        // Copying the same content again later is a real change
+        assert!(!guard.take_echo("abc"));
+    }
+
+    #[test]
+    fn test_echo_expires() {
+        let mut guard = EchoGuard::new(8, Duration::ZERO);
+        guard.expect_echo("abc");
+        assert!(!guard.take_echo("abc"));
+    }
+}
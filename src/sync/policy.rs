@@ -0,0 +1,495 @@
+//! Per-peer sync policies
+//!
+//! A policy from `[[sync.policies]]` narrows what is exchanged with one
+//! peer, named by node ID or by SSH key fingerprint: which way content
+//! flows, which formats and sizes it may have, whether entries from the
+//! peer are recorded in local history, and which sync groups it may belong
+//! to. Peers without a policy exchange everything but are in no group.
+//!
+//! Policies are resolved from the key a peer's connection authenticated
+//! with. Node IDs are only claimed, so a policy naming one applies only to
+//! the key the trust manager bound that ID to (see [`PeerFingerprints`]).
+
+use std::fmt;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use thiserror::Error;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::adapters::ClipboardData;
+use crate::auth::TrustManager;
+use crate::config::{PeerPolicyConfig, SyncConfig};
+use crate::sync::groups;
+use crate::transport::capabilities::mime_matches;
+
+/// Prefix of an SSH key fingerprint naming a peer
+const FINGERPRINT_PREFIX: &str = "SHA256:";
+
+/// Which way content flows between this node and a peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncDirection {
+    /// Content is sent to and accepted from the peer
+    #[default]
+    Both,
+    /// Content is sent to the peer, and anything it sends is ignored
+    SendOnly,
+    /// Content from the peer is accepted, and nothing is sent to it
+    ReceiveOnly,
+}
+
+/// Invalid policy configuration
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    /// A policy has an invalid setting
+    #[error("sync policy for '{peer}': {reason}")]
+    InvalidPolicy { peer: String, reason: String },
+
+    /// Two policies name the same peer
+    #[error("more than one sync policy for '{0}'")]
+    Duplicate(String),
+}
+
+/// How a peer is named in a policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerKey {
+    /// Node ID of the peer, as trusted with its key
+    Node(Uuid),
+    /// Fingerprint of the peer's SSH key, such as `SHA256:...`
+    Fingerprint(String),
+}
+
+/// What may be exchanged with one peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerPolicy {
+    /// Which way content flows
+    pub direction: SyncDirection,
+    /// MIME types or `type/*` patterns allowed; empty allows every format
+    pub formats: Vec<String>,
+    /// Largest payload in bytes, on top of `clipboard.max_size`
+    pub max_size: Option<usize>,
+    /// Whether entries from the peer are recorded in local history
+    pub history: bool,
+    /// Sync groups the peer may belong to
+    pub groups: Vec<String>,
+}
+
+/// Policies from the config, by peer
+#[derive(Debug, Clone, Default)]
+pub struct PeerPolicies {
+    policies: Vec<(PeerKey, PeerPolicy)>,
+}
+
+/// Looks up the fingerprint of the SSH key a node ID was trusted with
+#[async_trait]
+pub trait PeerFingerprints: Send + Sync {
+    async fn fingerprint_of(&self, peer_id: Uuid) -> Option<String>;
+}
+
+impl FromStr for SyncDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "both" => Ok(SyncDirection::Both),
+            "send_only" => Ok(SyncDirection::SendOnly),
+            "receive_only" => Ok(SyncDirection::ReceiveOnly),
+            other => Err(format!(
+                "unknown direction '{}' (expected both, send_only or receive_only)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SyncDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SyncDirection::Both => "both",
+            SyncDirection::SendOnly => "send_only",
+            SyncDirection::ReceiveOnly => "receive_only",
+        })
+    }
+}
+
+impl FromStr for PeerKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(hash) = s.strip_prefix(FINGERPRINT_PREFIX) {
+            if hash.is_empty() {
+                return Err("fingerprint has no hash after SHA256:".to_string());
+            }
+            return Ok(PeerKey::Fingerprint(s.to_string()));
+        }
+
+        Uuid::parse_str(s)
+            .map(PeerKey::Node)
+            .map_err(|_| "peer must be a node ID or a SHA256: key fingerprint".to_string())
+    }
+}
+
+impl fmt::Display for PeerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerKey::Node(id) => write!(f, "{}", id),
+            PeerKey::Fingerprint(fingerprint) => f.write_str(fingerprint),
+        }
+    }
+}
+
+impl PeerPolicy {
+    /// Policy for peers the config says nothing about
+    pub fn allow_all() -> Self {
+        Self {
+            direction: SyncDirection::Both,
+            formats: Vec::new(),
+            max_size: None,
+            history: true,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Build a policy from its `[[sync.policies]]` table
+    pub fn from_config(config: &PeerPolicyConfig) -> Result<Self, PolicyError> {
+        let invalid = |reason: String| PolicyError::InvalidPolicy {
+            peer: config.peer.clone(),
+            reason,
+        };
+
+        let direction = config.direction.parse().map_err(invalid)?;
+        let mut formats = Vec::with_capacity(config.formats.len());
+        for format in &config.formats {
+            let format = format.trim().to_ascii_lowercase();
+            if !format.contains('/') {
+                return Err(invalid(format!(
+                    "format '{}' is not a MIME type such as text/plain or image/*",
+                    format
+                )));
+            }
+            formats.push(format);
+        }
+        if config.max_size == Some(0) {
+            return Err(invalid("max_size must be greater than 0".to_string()));
+        }
+        if let Some(name) = config
+            .groups
+            .iter()
+            .find(|name| !groups::is_valid_name(name))
+        {
+            return Err(invalid(format!(
+                "invalid sync group name '{}' (use letters, digits, '-' and '_')",
+                name
+            )));
+        }
+
+        Ok(Self {
+            direction,
+            formats,
+            max_size: config.max_size,
+            history: config.history,
+            groups: config.groups.clone(),
+        })
+    }
+
+    /// Whether content is sent to the peer
+    pub fn sends(&self) -> bool {
+        self.direction != SyncDirection::ReceiveOnly
+    }
+
+    /// Whether content from the peer is accepted
+    pub fn receives(&self) -> bool {
+        self.direction != SyncDirection::SendOnly
+    }
+
+    /// Groups from those a peer announced that it may belong to
+    pub fn member_groups(&self, announced: &[String]) -> Vec<String> {
+        announced
+            .iter()
+            .filter(|group| self.groups.contains(group))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether content of this format and size may be exchanged
+    pub fn allows(&self, content: &ClipboardData) -> bool {
+        if self.max_size.is_some_and(|max| content.size() > max) {
+            return false;
+        }
+
+        let mime_type = content.format().mime_type().to_ascii_lowercase();
+        self.formats.is_empty()
+            || self
+                .formats
+                .iter()
+                .any(|pattern| mime_matches(pattern, &mime_type))
+    }
+}
+
+impl Default for PeerPolicy {
+    fn default() -> Self {
+        Self::allow_all()
+    }
+}
+
+impl PeerPolicies {
+    /// Policies from the `[sync]` config section
+    pub fn from_config(config: &SyncConfig) -> Result<Self, PolicyError> {
+        let mut policies: Vec<(PeerKey, PeerPolicy)> = Vec::new();
+        for policy in &config.policies {
+            let key: PeerKey =
+                policy
+                    .peer
+                    .parse()
+                    .map_err(|reason| PolicyError::InvalidPolicy {
+                        peer: policy.peer.clone(),
+                        reason,
+                    })?;
+            if policies.iter().any(|(existing, _)| *existing == key) {
+                return Err(PolicyError::Duplicate(key.to_string()));
+            }
+            policies.push((key, PeerPolicy::from_config(policy)?));
+        }
+
+        Ok(Self { policies })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Configured policy for a peer
+    pub fn get(&self, key: &PeerKey) -> Option<&PeerPolicy> {
+        self.policies
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, policy)| policy)
+    }
+
+    /// Policy for peer `peer_id`, whose connection authenticated with the
+    /// key `fingerprint`
+    ///
+    /// A policy naming the fingerprint comes first. One naming the node ID
+    /// applies only if `fingerprints` binds that ID to the same key, so a
+    /// peer cannot pick up another's policy by claiming its node ID.
+    pub async fn resolve(
+        &self,
+        peer_id: Uuid,
+        fingerprint: Option<&str>,
+        fingerprints: Option<&dyn PeerFingerprints>,
+    ) -> PeerPolicy {
+        let Some(fingerprint) = fingerprint else {
+            return PeerPolicy::allow_all();
+        };
+        if let Some(policy) = self.get(&PeerKey::Fingerprint(fingerprint.to_string())) {
+            return policy.clone();
+        }
+
+        if let (Some(policy), Some(fingerprints)) =
+            (self.get(&PeerKey::Node(peer_id)), fingerprints)
+        {
+            match fingerprints.fingerprint_of(peer_id).await {
+                Some(trusted) if trusted == fingerprint => return policy.clone(),
+                _ => debug!(
+                    "Ignoring the sync policy for {}: the peer authenticated with {}, \
+                     which that node ID is not trusted with",
+                    peer_id, fingerprint
+                ),
+            }
+        }
+
+        PeerPolicy::allow_all()
+    }
+}
+
+#[async_trait]
+impl PeerFingerprints for TrustManager {
+    async fn fingerprint_of(&self, peer_id: Uuid) -> Option<String> {
+        TrustManager::fingerprint_of(self, peer_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(peer: &str) -> PeerPolicyConfig {
+        PeerPolicyConfig {
+            peer: peer.to_string(),
+            ..PeerPolicyConfig::default()
+        }
+    }
+
+    /// Node IDs and the keys they were trusted with
+    struct Fingerprints(Vec<(Uuid, &'static str)>);
+
+    #[async_trait]
+    impl PeerFingerprints for Fingerprints {
+        async fn fingerprint_of(&self, peer_id: Uuid) -> Option<String> {
+            self.0
+                .iter()
+                .find(|(id, _)| *id == peer_id)
+                .map(|(_, fingerprint)| fingerprint.to_string())
+        }
+    }
+
+    #[test]
+    fn test_policy_allows() {
+        let policy = PeerPolicy::from_config(&PeerPolicyConfig {
+            direction: "send_only".to_string(),
+            formats: vec!["text/plain".to_string(), "Image/*".to_string()],
+            max_size: Some(8),
+            ..config("SHA256:laptop")
+        })
+        .unwrap();
+
+        assert!(policy.sends());
+        assert!(!policy.receives());
+        assert!(policy.allows(&ClipboardData::Text("short".to_string())));
+        assert!(!policy.allows(&ClipboardData::Text("far too long".to_string())));
+        assert!(!policy.allows(&ClipboardData::Html("<b>hi</b>".to_string())));
+        assert!(policy.allows(&ClipboardData::Image {
+            mime_type: "image/png".to_string(),
+            data: vec![0; 4],
+        }));
+
+        let everything = PeerPolicy::allow_all();
+        assert!(everything.sends() && everything.receives() && everything.history);
+        assert!(everything.allows(&ClipboardData::Rtf(vec![0; 1 << 20])));
+    }
+
+    #[test]
+    fn test_member_groups_are_bound_by_policy() {
+        let policy = PeerPolicy::from_config(&PeerPolicyConfig {
+            groups: vec!["pairing".to_string()],
+            ..config("SHA256:laptop")
+        })
+        .unwrap();
+        let announced = vec!["personal".to_string(), "pairing".to_string()];
+
+        assert_eq!(policy.member_groups(&announced), ["pairing"]);
+        assert!(policy.member_groups(&[]).is_empty());
+        assert!(PeerPolicy::allow_all().member_groups(&announced).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        let node = Uuid::new_v4().to_string();
+        for invalid in [
+            vec![config("laptop")],
+            vec![config("SHA256:")],
+            vec![PeerPolicyConfig {
+                direction: "sideways".to_string(),
+                ..config(&node)
+            }],
+            vec![PeerPolicyConfig {
+                formats: vec!["text".to_string()],
+                ..config(&node)
+            }],
+            vec![PeerPolicyConfig {
+                max_size: Some(0),
+                ..config(&node)
+            }],
+            vec![PeerPolicyConfig {
+                groups: vec!["my group".to_string()],
+                ..config(&node)
+            }],
+            vec![config(&node), config(&node)],
+        ] {
+            let sync = SyncConfig {
+                policies: invalid.clone(),
+                ..SyncConfig::default()
+            };
+            assert!(PeerPolicies::from_config(&sync).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_by_fingerprint_then_node() {
+        let laptop = Uuid::new_v4();
+        let desktop = Uuid::new_v4();
+        let sync = SyncConfig {
+            policies: vec![
+                PeerPolicyConfig {
+                    direction: "receive_only".to_string(),
+                    ..config("SHA256:laptop")
+                },
+                PeerPolicyConfig {
+                    history: false,
+                    ..config(&laptop.to_string())
+                },
+                PeerPolicyConfig {
+                    history: false,
+                    ..config(&desktop.to_string())
+                },
+            ],
+            ..SyncConfig::default()
+        };
+        let policies = PeerPolicies::from_config(&sync).unwrap();
+        let fingerprints =
+            Fingerprints(vec![(laptop, "SHA256:laptop"), (desktop, "SHA256:desktop")]);
+
+        // The fingerprint policy wins over the node ID one
+        let policy = policies
+            .resolve(laptop, Some("SHA256:laptop"), Some(&fingerprints))
+            .await;
+        assert_eq!(policy.direction, SyncDirection::ReceiveOnly);
+        assert!(policy.history);
+
+        // A node ID policy applies to the key the node was trusted with
+        let policy = policies
+            .resolve(desktop, Some("SHA256:desktop"), Some(&fingerprints))
+            .await;
+        assert!(!policy.history);
+
+        // Nothing matches without an authenticated key or trust bindings
+        assert_eq!(
+            policies.resolve(desktop, None, Some(&fingerprints)).await,
+            PeerPolicy::allow_all()
+        );
+        assert_eq!(
+            policies
+                .resolve(desktop, Some("SHA256:desktop"), None)
+                .await,
+            PeerPolicy::allow_all()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_node_id_gets_no_policy() {
+        let desktop = Uuid::new_v4();
+        let sync = SyncConfig {
+            policies: vec![
+                PeerPolicyConfig {
+                    groups: vec!["personal".to_string()],
+                    ..config(&desktop.to_string())
+                },
+                PeerPolicyConfig {
+                    direction: "send_only".to_string(),
+                    ..config("SHA256:kiosk")
+                },
+            ],
+            ..SyncConfig::default()
+        };
+        let policies = PeerPolicies::from_config(&sync).unwrap();
+        let fingerprints = Fingerprints(vec![(desktop, "SHA256:desktop")]);
+
+        // Another paired key claiming the desktop's node ID
+        let policy = policies
+            .resolve(desktop, Some("SHA256:phone"), Some(&fingerprints))
+            .await;
+        assert_eq!(policy, PeerPolicy::allow_all());
+        assert!(policy.groups.is_empty());
+
+        // The kiosk keeps its own policy, whichever node ID it claims
+        for claimed in [desktop, Uuid::new_v4()] {
+            let policy = policies
+                .resolve(claimed, Some("SHA256:kiosk"), Some(&fingerprints))
+                .await;
+            assert_eq!(policy.direction, SyncDirection::SendOnly);
+            assert!(policy.groups.is_empty());
+        }
+    }
+}
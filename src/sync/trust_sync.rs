@@ -4,7 +4,7 @@ use crate::adapters::{ClipboardProviderWrapper, HistoryManager, PeerDiscovery};
 use crate::auth::{SshAuthenticator, TrustManager};
 use crate::config::Config;
 use crate::discovery::TrustAwareDiscovery;
-use crate::sync::SyncEngine;
+use crate::sync::{PeerFingerprints, PeerPolicy, SyncEngine};
 use crate::transport::TransportManager;
 use anyhow::Result;
 use std::path::PathBuf;
@@ -57,10 +57,12 @@ impl TrustAwareSyncEngine {
             Arc::clone(&ssh_auth),
         ));
 
-        // Create base sync engine
-        let sync_engine = Arc::new(SyncEngine::new(
-            config, clipboard, history, discovery, transport,
-        ));
+        // Create base sync engine, with policies naming a node ID bound to
+        // the key that node was trusted with
+        let sync_engine = Arc::new(
+            SyncEngine::new(config, clipboard, history, discovery, transport)
+                .with_fingerprints(Arc::clone(&trust_manager) as Arc<dyn PeerFingerprints>),
+        );
 
         Ok(Self {
             sync_engine,
@@ -143,6 +145,25 @@ impl TrustAwareSyncEngine {
         self.sync_engine.get_connected_peers().await
     }
 
+    /// Get large clipboard payloads currently streaming
+    pub async fn transfers(&self) -> Vec<crate::transport::Transfer> {
+        self.sync_engine.transfers().await
+    }
+
+    /// Put content on the clipboard to be cleared everywhere after `ttl`
+    pub async fn copy_with_ttl(
+        &self,
+        content: &crate::adapters::ClipboardData,
+        ttl: std::time::Duration,
+    ) -> Result<()> {
+        self.sync_engine.copy_with_ttl(content, ttl).await
+    }
+
+    /// What may be exchanged with a peer under the configured policies
+    pub async fn policy_for(&self, peer_id: uuid::Uuid) -> PeerPolicy {
+        self.sync_engine.policy_for(peer_id).await
+    }
+
     /// Force sync
     pub async fn force_sync(&self) -> Result<()> {
         self.sync_engine.force_sync().await
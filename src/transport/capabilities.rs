@@ -0,0 +1,165 @@
+//! Capability negotiation between authenticated peers
+//!
+//! Right after authentication both sides send a [`CapabilitiesPayload`]
+//! over the encrypted session. Each side then combines the two into
+//! [`NegotiatedCapabilities`]: the formats the peer can render, the best
+//! compression method both support, the smaller message size limit, and
+//! whether both can stream.
+//!
+//! Formats are matched by MIME type, and an advertised format may use a
+//! wildcard such as `image/*` or `*/*`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::transport::protocol::{CapabilitiesPayload, ClipboardFormat, CompressionMethod};
+
+/// Compression methods this node can use, most preferred first
+pub const SUPPORTED_COMPRESSION: [CompressionMethod; 2] =
+    [CompressionMethod::Zstd, CompressionMethod::None];
+
+/// Formats a node advertises unless configured otherwise: everything
+pub fn default_formats() -> Vec<ClipboardFormat> {
+    vec![
+        ClipboardFormat::Text,
+        ClipboardFormat::Html,
+        ClipboardFormat::Rtf,
+        ClipboardFormat::Image {
+            mime_type: "image/*".to_string(),
+        },
+        ClipboardFormat::Files,
+        ClipboardFormat::Binary {
+            mime_type: "*/*".to_string(),
+        },
+    ]
+}
+
+/// Capabilities this node offers its peers
+pub fn local_capabilities(
+    formats: Vec<ClipboardFormat>,
+    max_message_size: usize,
+    enable_compression: bool,
+    keepalive_interval: u64,
+) -> CapabilitiesPayload {
+    let compression = if enable_compression {
+        SUPPORTED_COMPRESSION.to_vec()
+    } else {
+        vec![CompressionMethod::None]
+    };
+
+    CapabilitiesPayload {
+        formats,
+        compression,
+        max_message_size,
+        streaming_support: true,
+        keepalive_interval,
+        extensions: Default::default(),
+    }
+}
+
+/// What both sides of a connection agreed on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NegotiatedCapabilities {
+    /// Formats the peer can render
+    pub formats: Vec<ClipboardFormat>,
+    /// Best compression method both sides support
+    pub compression: CompressionMethod,
+    /// Largest message either side accepts
+    pub max_message_size: usize,
+    /// Whether both sides can stream large payloads
+    pub streaming: bool,
+}
+
+impl NegotiatedCapabilities {
+    /// Combine our capabilities with the peer's
+    pub fn negotiate(local: &CapabilitiesPayload, remote: &CapabilitiesPayload) -> Self {
+        let compression = local
+            .compression
+            .iter()
+            .find(|method| remote.compression.contains(method))
+            .cloned()
+            .unwrap_or(CompressionMethod::None);
+
+        Self {
+            formats: remote.formats.clone(),
+            compression,
+            max_message_size: local.max_message_size.min(remote.max_message_size),
+            streaming: local.streaming_support && remote.streaming_support,
+        }
+    }
+
+    /// Whether the peer can render content of this format
+    pub fn accepts(&self, format: &ClipboardFormat) -> bool {
+        let mime_type = format.mime_type().to_ascii_lowercase();
+        self.formats
+            .iter()
+            .any(|offered| mime_matches(&offered.mime_type().to_ascii_lowercase(), &mime_type))
+    }
+}
+
+/// Match a MIME type against a pattern such as `image/*` or `*/*`
+pub(crate) fn mime_matches(pattern: &str, mime_type: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some("*/") => true,
+        Some(prefix) if prefix.ends_with('/') => mime_type.starts_with(prefix),
+        _ => pattern == mime_type,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(mime_type: &str) -> ClipboardFormat {
+        ClipboardFormat::Image {
+            mime_type: mime_type.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_negotiate_picks_shared_settings() {
+        let local = local_capabilities(default_formats(), 5 * 1024 * 1024, true, 30);
+        let mut remote = local_capabilities(vec![ClipboardFormat::Text], 1024 * 1024, true, 30);
+        remote.compression = vec![CompressionMethod::Gzip, CompressionMethod::Zstd];
+        remote.streaming_support = false;
+
+        let negotiated = NegotiatedCapabilities::negotiate(&local, &remote);
+        assert_eq!(negotiated.compression, CompressionMethod::Zstd);
+        assert_eq!(negotiated.max_message_size, 1024 * 1024);
+        assert!(!negotiated.streaming);
+        assert_eq!(negotiated.formats, vec![ClipboardFormat::Text]);
+
+        // Nothing shared but no compression at all
+        remote.compression = vec![CompressionMethod::Gzip];
+        let negotiated = NegotiatedCapabilities::negotiate(&local, &remote);
+        assert_eq!(negotiated.compression, CompressionMethod::None);
+
+        let local = local_capabilities(default_formats(), 1024, false, 30);
+        let negotiated = NegotiatedCapabilities::negotiate(&local, &local);
+        assert_eq!(negotiated.compression, CompressionMethod::None);
+    }
+
+    #[test]
+    fn test_accepts_matches_wildcards() {
+        let negotiated = |formats| NegotiatedCapabilities {
+            formats,
+            compression: CompressionMethod::None,
+            max_message_size: 1024,
+            streaming: true,
+        };
+
+        let text_only = negotiated(vec![ClipboardFormat::Text]);
+        assert!(text_only.accepts(&ClipboardFormat::Text));
+        assert!(!text_only.accepts(&ClipboardFormat::Html));
+        assert!(!text_only.accepts(&image("image/png")));
+
+        let images = negotiated(vec![image("image/*")]);
+        assert!(images.accepts(&image("image/png")));
+        assert!(images.accepts(&image("IMAGE/JPEG")));
+        assert!(!images.accepts(&ClipboardFormat::Text));
+
+        let everything = negotiated(default_formats());
+        assert!(everything.accepts(&ClipboardFormat::Binary {
+            mime_type: "application/x-custom".to_string()
+        }));
+    }
+}
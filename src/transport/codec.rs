@@ -0,0 +1,267 @@
+//! Wire encodings for messages on an encrypted session
+//!
+//! Peers agree on an encoding during the handshake: compact CBOR, or JSON
+//! for debugging. CBOR is self-describing like JSON, so message types and
+//! fields from newer protocol versions still decode, but clipboard data
+//! travels as raw bytes rather than as an array of numbers. Each encoded
+//! message is framed as
+//!
+//! ```text
+//! encoding (1) | length (4, big endian) | body
+//! ```
+//!
+//! so a receiver can decode a frame whichever encoding it was written in.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::transport::protocol::Message;
+
+/// Handshake parameter listing the encodings a peer accepts
+pub const ENCODINGS_PARAMETER: &str = "encodings";
+
+/// Handshake parameter carrying the encoding the server chose
+pub const ENCODING_PARAMETER: &str = "encoding";
+
+/// Size of the encoding tag and length prefix
+const HEADER_SIZE: usize = 5;
+
+/// Message encoding on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireEncoding {
+    /// Length-prefixed CBOR
+    Cbor,
+
+    /// Length-prefixed JSON, readable when debugging
+    Json,
+}
+
+/// Encoding errors
+#[derive(Debug, Error)]
+pub enum CodecError {
+    /// Frame is shorter than its header or its declared length
+    #[error("truncated message frame")]
+    Truncated,
+
+    /// Frame names an encoding this version does not know
+    #[error("unknown message encoding {0:#04x}")]
+    UnknownEncoding(u8),
+
+    /// Message is too large for the length prefix
+    #[error("message of {0} bytes is too large to frame")]
+    TooLarge(usize),
+
+    /// JSON body could not be encoded or decoded
+    #[error("invalid JSON message: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// CBOR body could not be encoded or decoded
+    #[error("invalid CBOR message: {0}")]
+    Cbor(String),
+}
+
+impl WireEncoding {
+    /// Encodings this node accepts, most preferred first
+    pub const ALL: [WireEncoding; 2] = [WireEncoding::Cbor, WireEncoding::Json];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WireEncoding::Cbor => "cbor",
+            WireEncoding::Json => "json",
+        }
+    }
+
+    /// First of our encodings the peer also accepts
+    ///
+    /// Every peer can decode JSON, so that is the fallback.
+    pub fn negotiate(ours: &[WireEncoding], theirs: &[WireEncoding]) -> WireEncoding {
+        ours.iter()
+            .find(|encoding| theirs.contains(encoding))
+            .copied()
+            .unwrap_or(WireEncoding::Json)
+    }
+
+    /// Parse a handshake encoding list, skipping encodings we do not know
+    pub fn parse_list(list: &str) -> Vec<WireEncoding> {
+        list.split(',')
+            .filter_map(|encoding| encoding.parse().ok())
+            .collect()
+    }
+
+    /// Format an encoding list for the handshake
+    pub fn format_list(encodings: &[WireEncoding]) -> String {
+        encodings
+            .iter()
+            .map(WireEncoding::as_str)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            WireEncoding::Json => 1,
+            WireEncoding::Cbor => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self, CodecError> {
+        match tag {
+            1 => Ok(WireEncoding::Json),
+            2 => Ok(WireEncoding::Cbor),
+            other => Err(CodecError::UnknownEncoding(other)),
+        }
+    }
+}
+
+impl FromStr for WireEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cbor" => Ok(WireEncoding::Cbor),
+            "json" => Ok(WireEncoding::Json),
+            other => Err(format!("unknown message encoding '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for WireEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Encode and frame a message
+pub fn encode(message: &Message, encoding: WireEncoding) -> Result<Vec<u8>, CodecError> {
+    let mut frame = vec![0u8; HEADER_SIZE];
+    match encoding {
+        WireEncoding::Json => serde_json::to_writer(&mut frame, message)?,
+        WireEncoding::Cbor => ciborium::into_writer(message, &mut frame)
+            .map_err(|e| CodecError::Cbor(e.to_string()))?,
+    }
+
+    let length = frame.len() - HEADER_SIZE;
+    let length = u32::try_from(length).map_err(|_| CodecError::TooLarge(length))?;
+    frame[0] = encoding.tag();
+    frame[1..HEADER_SIZE].copy_from_slice(&length.to_be_bytes());
+    Ok(frame)
+}
+
+/// Decode a framed message in either encoding
+pub fn decode(frame: &[u8]) -> Result<Message, CodecError> {
+    if frame.len() < HEADER_SIZE {
+        return Err(CodecError::Truncated);
+    }
+
+    let encoding = WireEncoding::from_tag(frame[0])?;
+    let mut length = [0u8; 4];
+    length.copy_from_slice(&frame[1..HEADER_SIZE]);
+    let body = frame[HEADER_SIZE..]
+        .get(..u32::from_be_bytes(length) as usize)
+        .ok_or(CodecError::Truncated)?;
+
+    match encoding {
+        WireEncoding::Json => Ok(serde_json::from_slice(body)?),
+        WireEncoding::Cbor => {
+            ciborium::from_reader(body).map_err(|e| CodecError::Cbor(e.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::protocol::{
+        ClipboardData, ClipboardFormat, MessagePayload, MessageType, StreamOperation, StreamPayload,
+    };
+    use uuid::Uuid;
+
+    fn image_message() -> Message {
+        let data: Vec<u8> = (0..64 * 1024u32).map(|i| (i * 7919 % 251) as u8).collect();
+        Message::new(
+            MessageType::ClipboardData,
+            MessagePayload::Clipboard(ClipboardData {
+                format: ClipboardFormat::Image {
+                    mime_type: "image/png".to_string(),
+                },
+                data,
+                compression: None,
+                checksum: "abc".to_string(),
+                metadata: [("origin".to_string(), Uuid::new_v4().to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_round_trip_in_both_encodings() {
+        let chunk = Message::new(
+            MessageType::StreamChunk,
+            MessagePayload::Stream(StreamPayload {
+                operation: StreamOperation::Chunk,
+                stream_id: Uuid::new_v4(),
+                metadata: None,
+                data: Some(vec![0, 1, 2, 255]),
+                chunk_sequence: Some(3),
+                completion: None,
+            }),
+        )
+        .with_sequence(9);
+
+        for message in [image_message(), chunk] {
+            for encoding in WireEncoding::ALL {
+                let frame = encode(&message, encoding).unwrap();
+                assert_eq!(frame[0], encoding.tag());
+                assert_eq!(decode(&frame).unwrap(), message, "{}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cbor_carries_bytes_compactly() {
+        let message = image_message();
+        let json = encode(&message, WireEncoding::Json).unwrap();
+        let cbor = encode(&message, WireEncoding::Cbor).unwrap();
+
+        assert!(cbor.len() < 64 * 1024 + 1024, "{} bytes", cbor.len());
+        assert!(json.len() > 2 * cbor.len());
+    }
+
+    #[test]
+    fn test_rejects_bad_frames() {
+        let frame = encode(&image_message(), WireEncoding::Cbor).unwrap();
+        assert!(matches!(
+            decode(&frame[..frame.len() - 1]),
+            Err(CodecError::Truncated)
+        ));
+        assert!(matches!(decode(&frame[..3]), Err(CodecError::Truncated)));
+
+        let mut unknown = frame.clone();
+        unknown[0] = 9;
+        assert!(matches!(
+            decode(&unknown),
+            Err(CodecError::UnknownEncoding(9))
+        ));
+    }
+
+    #[test]
+    fn test_negotiate_encoding() {
+        use WireEncoding::*;
+
+        assert_eq!(WireEncoding::negotiate(&[Cbor, Json], &[Json, Cbor]), Cbor);
+        assert_eq!(WireEncoding::negotiate(&[Cbor, Json], &[Json]), Json);
+        assert_eq!(WireEncoding::negotiate(&[Json], &[Cbor, Json]), Json);
+        assert_eq!(WireEncoding::negotiate(&[Cbor], &[]), Json);
+
+        assert_eq!(
+            WireEncoding::parse_list("cbor,msgpack,json"),
+            vec![Cbor, Json]
+        );
+        assert_eq!(WireEncoding::format_list(&WireEncoding::ALL), "cbor,json");
+    }
+}
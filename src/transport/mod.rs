@@ -8,39 +8,58 @@ use std::net::SocketAddr;
 use thiserror::Error;
 use uuid::Uuid;
 
+pub mod capabilities;
+pub mod codec;
 pub mod protocol;
+pub mod queue;
 pub mod reconnect;
+pub mod session;
 pub mod stream;
+pub mod version;
 pub mod websocket;
 
 #[cfg(test)]
-mod unit_tests;
+pub(crate) mod unit_tests;
 
 // Re-export types from other modules for convenience
 pub use crate::auth::{AuthToken, Authenticator};
 pub use crate::discovery::PeerInfo;
+pub use capabilities::NegotiatedCapabilities;
+pub use codec::WireEncoding;
 pub use protocol::{ClipboardData, ConnectionId, Message, MessagePayload, MessageType};
-pub use reconnect::{ReconnectionConfig, ReconnectionManager};
-pub use stream::{ProgressUpdate, StreamChunk, StreamingTransport};
+pub use queue::{OutboundQueue, QueueStats};
+pub use reconnect::{ConnectionStats, ReconnectionConfig, ReconnectionManager};
+pub use stream::{ProgressUpdate, StreamChunk, StreamConfig, StreamDirection, StreamingTransport};
+pub use version::{ProtocolVersion, VersionRange, MIN_PROTOCOL_VERSION};
 pub use websocket::{WebSocketConnection, WebSocketListener, WebSocketTransport};
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use tracing::{debug, warn};
 
 pub struct TransportManager {
-    connections: Arc<RwLock<HashMap<Uuid, Box<dyn Connection>>>>,
+    /// Connections by peer; stream state survives reconnects
+    connections: Arc<RwLock<HashMap<Uuid, StreamingTransport>>>,
+    /// Messages waiting for each peer to come back; locked after
+    /// `connections`
+    queues: Arc<Mutex<HashMap<Uuid, OutboundQueue>>>,
     message_sender: broadcast::Sender<Message>,
+    event_sender: broadcast::Sender<TransportEvent>,
     config: TransportConfig,
 }
 
 impl TransportManager {
     pub fn new(config: TransportConfig) -> Self {
         let (message_sender, _) = broadcast::channel(1000);
+        let (event_sender, _) = broadcast::channel(100);
 
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
+            queues: Arc::new(Mutex::new(HashMap::new())),
             message_sender,
+            event_sender,
             config,
         }
     }
@@ -50,35 +69,244 @@ impl TransportManager {
         todo!("Implement actual WebSocket connection")
     }
 
+    /// Send a message to a peer
+    ///
+    /// While the peer is offline the message waits in its queue, and is
+    /// sent once the peer reconnects.
     pub async fn send_to_peer(&self, peer_id: Uuid, message: &Message) -> Result<()> {
-        let connections = self.connections.read().await;
-        if let Some(connection) = connections.get(&peer_id) {
-            // This would send the message through the connection
-            todo!("Implement message sending")
+        self.send_or_queue(peer_id, message).await
+    }
+
+    /// Send a clipboard message to a peer, streaming payloads larger than
+    /// [`TransportConfig::stream_threshold`] or the peer's message size limit
+    ///
+    /// Like [`Self::send_to_peer`], the message is queued while the peer is
+    /// offline.
+    pub async fn send_clipboard(&self, peer_id: Uuid, message: &Message) -> Result<()> {
+        self.send_or_queue(peer_id, message).await
+    }
+
+    /// Queue statistics for a peer
+    pub async fn queue_stats(&self, peer_id: Uuid) -> QueueStats {
+        let queues = self.queues.lock().await;
+        queues
+            .get(&peer_id)
+            .map(OutboundQueue::stats)
+            .unwrap_or_default()
+    }
+
+    /// Statistics of a peer's connection, including its queue
+    pub async fn connection_stats(&self, reconnection: &ReconnectionManager) -> ConnectionStats {
+        let mut stats = reconnection.get_stats();
+        stats.queue = self.queue_stats(stats.peer_id).await;
+        stats
+    }
+
+    /// Send a message after whatever is already queued for the peer, or
+    /// queue it if the peer is offline
+    async fn send_or_queue(&self, peer_id: Uuid, message: &Message) -> Result<()> {
+        let mut connections = self.connections.write().await;
+        let connection = connections
+            .get_mut(&peer_id)
+            .ok_or(TransportError::PeerNotFound {
+                peer_id,
+                peer_name: None,
+            })?;
+
+        let mut queues = self.queues.lock().await;
+        let queue = queues
+            .entry(peer_id)
+            .or_insert_with(|| OutboundQueue::new(self.config.peer_queue_size));
+
+        if connection.is_connected() && self.flush(peer_id, connection, queue).await {
+            match self.deliver(connection, message).await {
+                Err(e) if e.is_connection_lost() => {
+                    debug!("Peer {} went offline, queueing message: {}", peer_id, e);
+                }
+                result => return result,
+            }
+        }
+
+        queue.push(message.clone());
+        Ok(())
+    }
+
+    /// Send queued messages in order, returning whether the queue emptied
+    ///
+    /// Messages the peer rejects for reasons other than a lost connection
+    /// are dropped rather than retried.
+    async fn flush(
+        &self,
+        peer_id: Uuid,
+        connection: &mut StreamingTransport,
+        queue: &mut OutboundQueue,
+    ) -> bool {
+        while let Some(message) = queue.front() {
+            match self.deliver(connection, message).await {
+                Ok(()) => {}
+                Err(e) if e.is_connection_lost() => return false,
+                Err(e) => warn!("Dropping queued message for peer {}: {}", peer_id, e),
+            }
+            queue.pop_front();
+        }
+        true
+    }
+
+    /// Send a message on a connection, streaming large clipboard payloads
+    async fn deliver(&self, connection: &mut StreamingTransport, message: &Message) -> Result<()> {
+        let size = match &message.payload {
+            MessagePayload::Clipboard(data) => data.data.len(),
+            _ => return connection.send(message.clone()).await,
+        };
+
+        let capabilities = connection.capabilities();
+        let max_message_size = capabilities.map_or(usize::MAX, |c| c.max_message_size);
+        let can_stream = capabilities.map_or(true, |c| c.streaming);
+        let large = size > self.config.stream_threshold.min(max_message_size);
+
+        if large && !can_stream && size > max_message_size {
+            return Err(TransportError::Streaming {
+                message: format!(
+                    "{} bytes exceeds the {} byte limit of a device that cannot stream",
+                    size, max_message_size
+                ),
+            });
+        }
+
+        if large && can_stream {
+            // Completion is tracked through progress updates
+            connection.send_clipboard_message(message.clone()).await?;
+            Ok(())
         } else {
-            Err(TransportError::PeerNotFound {
+            connection.send(message.clone()).await
+        }
+    }
+
+    /// Handle a stream message from a peer
+    ///
+    /// Returns the streamed clipboard message once the stream completes.
+    pub async fn handle_stream_message(
+        &self,
+        peer_id: Uuid,
+        message: Message,
+    ) -> Result<Option<Message>> {
+        let mut connections = self.connections.write().await;
+        let connection = connections
+            .get_mut(&peer_id)
+            .ok_or(TransportError::PeerNotFound {
                 peer_id,
-                peer_name: None, // Could be enhanced to look up peer name
+                peer_name: None,
+            })?;
+        connection.handle_stream_message(message).await
+    }
+
+    /// Capabilities negotiated with a peer
+    pub async fn peer_capabilities(&self, peer_id: Uuid) -> Option<NegotiatedCapabilities> {
+        let connections = self.connections.read().await;
+        connections
+            .get(&peer_id)
+            .and_then(|connection| connection.capabilities().cloned())
+    }
+
+    /// Fingerprint of the key a peer's connection authenticated with
+    pub async fn peer_fingerprint(&self, peer_id: Uuid) -> Option<String> {
+        let connections = self.connections.read().await;
+        connections
+            .get(&peer_id)
+            .and_then(|connection| connection.authenticated_fingerprint())
+            .map(String::from)
+    }
+
+    /// Progress of every unfinished stream
+    pub async fn transfers(&self) -> Vec<Transfer> {
+        let connections = self.connections.read().await;
+        connections
+            .iter()
+            .flat_map(|(peer_id, connection)| {
+                connection.progress().into_iter().map(|progress| Transfer {
+                    peer_id: *peer_id,
+                    progress,
+                })
             })
-        }
+            .collect()
     }
 
     pub async fn subscribe(&self) -> Result<broadcast::Receiver<Message>> {
         Ok(self.message_sender.subscribe())
     }
 
+    /// Subscribe to connection events
+    pub fn subscribe_events(&self) -> broadcast::Receiver<TransportEvent> {
+        self.event_sender.subscribe()
+    }
+
+    /// Publish events from a [`ReconnectionManager`] to event subscribers
+    pub fn forward_events(&self, mut events: mpsc::UnboundedReceiver<TransportEvent>) {
+        let event_sender = self.event_sender.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let _ = event_sender.send(event);
+            }
+        });
+    }
+
     /// Register an authenticated peer connection
+    ///
+    /// A new connection to a known peer replaces the old one and resumes
+    /// any streams that were cut off.
     pub async fn register_peer_connection(
         &self,
         peer_id: Uuid,
         connection: Box<dyn Connection>,
     ) -> Result<()> {
         let mut connections = self.connections.write().await;
-        connections.insert(peer_id, connection);
+        if let Some(existing) = connections.get_mut(&peer_id) {
+            existing.replace_connection(connection).await?;
+
+            // Catch the peer up on what it missed while offline
+            if let Some(queue) = self.queues.lock().await.get_mut(&peer_id) {
+                if !queue.is_empty() {
+                    debug!(
+                        "Sending {} queued messages to peer {}",
+                        queue.len(),
+                        peer_id
+                    );
+                    self.flush(peer_id, existing, queue).await;
+                }
+            }
+            return Ok(());
+        }
+
+        let stream_config = StreamConfig {
+            chunk_size: self.config.stream_chunk_size,
+            enable_compression: self.config.enable_compression,
+            ..StreamConfig::default()
+        };
+        let (streaming, mut progress) = StreamingTransport::new(connection, stream_config);
+
+        let event_sender = self.event_sender.clone();
+        tokio::spawn(async move {
+            while let Some(update) = progress.recv().await {
+                let _ = event_sender.send(TransportEvent::StreamingProgress(peer_id, update));
+            }
+        });
+
+        connections.insert(peer_id, streaming);
+        self.queues
+            .lock()
+            .await
+            .insert(peer_id, OutboundQueue::new(self.config.peer_queue_size));
         Ok(())
     }
 }
 
+/// Progress of a stream to or from a peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub peer_id: Uuid,
+    pub progress: ProgressUpdate,
+}
+
 /// Transport layer errors with user-friendly messages
 #[derive(Debug, Error)]
 pub enum TransportError {
@@ -130,7 +358,7 @@ pub enum TransportError {
     Timeout,
 
     /// Protocol version mismatch
-    #[error("CS011: Incompatible ClipSync versions. This device is running v{expected}, but the remote device is running v{actual}. Please update both devices to the same version.")]
+    #[error("CS011: Incompatible ClipSync versions. This device speaks protocol v{expected}, but the remote device speaks v{actual}. Please update the older device.")]
     VersionMismatch { expected: String, actual: String },
 
     /// Configuration error
@@ -148,6 +376,26 @@ pub enum TransportError {
     /// Service unavailable
     #[error("CS015: ClipSync service is not running. Start the service with 'clipsync start'.")]
     ServiceUnavailable,
+
+    /// Encrypted session error
+    #[error("CS016: Secure channel error: {0}. The connection may have been tampered with; reconnect to start a new session.")]
+    Session(#[from] session::SessionError),
+}
+
+impl TransportError {
+    /// Whether the error means the peer can no longer be reached over the
+    /// connection, rather than that it refused the message
+    pub fn is_connection_lost(&self) -> bool {
+        matches!(
+            self,
+            TransportError::WebSocket { .. }
+                | TransportError::Connection { .. }
+                | TransportError::Io(_)
+                | TransportError::ConnectionClosed
+                | TransportError::Timeout
+                | TransportError::NetworkUnavailable
+        )
+    }
 }
 
 /// Result type for transport operations
@@ -202,6 +450,16 @@ pub trait Connection: Send + Sync {
     /// Check if connection is active
     fn is_connected(&self) -> bool;
 
+    /// Capabilities agreed with the peer, once negotiated
+    fn capabilities(&self) -> Option<&NegotiatedCapabilities> {
+        None
+    }
+
+    /// Fingerprint of the key the peer proved it holds, once authenticated
+    fn authenticated_fingerprint(&self) -> Option<&str> {
+        None
+    }
+
     /// Close the connection
     async fn close(&mut self) -> Result<()>;
 }
@@ -245,6 +503,9 @@ pub struct ConnectionInfo {
 
     /// Protocol version
     pub protocol_version: String,
+
+    /// Message encoding
+    pub encoding: WireEncoding,
 }
 
 /// Connection state enumeration
@@ -298,6 +559,9 @@ pub enum TransportEvent {
 
     /// Reconnection attempt
     ReconnectionAttempt(ConnectionId, u32),
+
+    /// Connection to a peer came back after being lost
+    ConnectionRestored(ConnectionId),
 }
 
 /// Configuration for transport layer
@@ -318,9 +582,15 @@ pub struct TransportConfig {
     /// Streaming chunk size for large payloads
     pub stream_chunk_size: usize,
 
+    /// Clipboard payloads larger than this are streamed (default: 1MB)
+    pub stream_threshold: usize,
+
     /// Maximum concurrent connections
     pub max_connections: usize,
 
+    /// Messages kept for a peer while it is offline (default: 32)
+    pub peer_queue_size: usize,
+
     /// Reconnection configuration
     pub reconnection: ReconnectionConfig,
 }
@@ -333,7 +603,9 @@ impl Default for TransportConfig {
             keepalive_interval: std::time::Duration::from_secs(30),
             enable_compression: true,
             stream_chunk_size: 64 * 1024, // 64KB chunks
+            stream_threshold: stream::DEFAULT_STREAM_THRESHOLD,
             max_connections: 10,
+            peer_queue_size: queue::DEFAULT_PEER_QUEUE_SIZE,
             reconnection: ReconnectionConfig::default(),
         }
     }
@@ -350,7 +622,9 @@ mod tests {
         assert_eq!(config.connect_timeout, std::time::Duration::from_secs(30));
         assert!(config.enable_compression);
         assert_eq!(config.stream_chunk_size, 64 * 1024);
+        assert_eq!(config.stream_threshold, 1024 * 1024);
         assert_eq!(config.max_connections, 10);
+        assert_eq!(config.peer_queue_size, 32);
     }
 
     #[test]
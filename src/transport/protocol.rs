@@ -4,12 +4,34 @@
 //! communication between ClipSync peers over WebSocket connections.
 
 use crate::auth::PeerId;
+use crate::history::EntrySummary;
+use crate::sync::clock::HybridTimestamp;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt;
 use uuid::Uuid;
 
 /// Protocol version for compatibility checking
-pub const PROTOCOL_VERSION: &str = "1.0.0";
+///
+/// This is the newest version this node speaks; see
+/// [`crate::transport::version`] for how peers agree on one.
+pub const PROTOCOL_VERSION: &str = "2.0.0";
+
+/// Authentication method of the challenge-response exchange
+pub const AUTH_METHOD_CHALLENGE_RESPONSE: &str = "ssh_challenge_response";
+
+/// Size of authentication nonces in bytes
+pub const AUTH_NONCE_SIZE: usize = 32;
+
+/// Clipboard metadata key naming the selection a payload belongs to
+pub const SELECTION_METADATA_KEY: &str = "selection";
+
+/// Domain separator for authentication signatures
+const AUTH_SIGNATURE_LABEL: &[u8] = b"clipsync auth v1";
+
+/// Domain separator for binding session key shares into the transcript
+const KEY_EXCHANGE_LABEL: &[u8] = b"clipsync key exchange v1";
 
 /// Connection identifier type
 pub type ConnectionId = Uuid;
@@ -38,6 +60,14 @@ pub struct Message {
     /// Source peer ID (added for tracking message origin)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_peer_id: Option<Uuid>,
+
+    /// Hybrid logical clock stamp of the event the message carries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hlc: Option<HybridTimestamp>,
+
+    /// Sync group the clipboard entry was shared in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
 }
 
 /// Message type enumeration
@@ -87,6 +117,22 @@ pub enum MessageType {
 
     /// Status update
     Status,
+
+    /// Summary of recent history, starting catch-up
+    HistoryDigest,
+
+    /// Entries in the history ranges that differ
+    HistoryInventory,
+
+    /// Request for missing history entries
+    HistoryRequest,
+
+    /// History entries for a peer that missed them
+    HistoryEntries,
+
+    /// Message type added by a newer protocol version
+    #[serde(other)]
+    Unknown,
 }
 
 /// Message payload variants
@@ -119,6 +165,25 @@ pub enum MessagePayload {
 
     /// Status payload
     Status(StatusPayload),
+
+    /// History reconciliation payload
+    History(HistoryPayload),
+
+    /// Payload added by a newer protocol version
+    #[serde(untagged)]
+    Unknown(UnknownPayload),
+}
+
+/// Payload of a type this version does not know, kept as received
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnknownPayload {
+    /// Payload type name
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    /// Payload data
+    #[serde(default, deserialize_with = "any_value")]
+    pub data: serde_json::Value,
 }
 
 /// Handshake message payload
@@ -166,6 +231,59 @@ pub enum AuthResult {
     Continue,
 }
 
+/// Data of one challenge-response step, carried in [`AuthPayload::data`]
+///
+/// 1. Client → server: `public_key`, a fresh `nonce` and its `key_share`.
+/// 2. Server → client ([`AuthResult::Continue`]): `public_key`, a fresh
+///    `nonce`, its `key_share` and a `signature` over the client's nonce.
+/// 3. Client → server: a `signature` over the server's nonce.
+/// 4. Server → client: [`AuthResult::Success`] or [`AuthResult::Failed`].
+///
+/// Signatures cover [`AuthContext::signing_input`], which binds them to both
+/// node IDs, the handshake and both key shares. The key shares then yield
+/// the session keys (see [`crate::transport::session`]).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AuthExchange {
+    /// Sender's public key in OpenSSH wire format (base64)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+
+    /// Nonce the other side must sign (base64)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+
+    /// Signature over the other side's nonce (base64)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+
+    /// Ephemeral X25519 public key for the session (base64)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_share: Option<String>,
+}
+
+/// Side of the connection producing an authentication signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthRole {
+    /// Connecting peer
+    Client,
+
+    /// Accepting peer
+    Server,
+}
+
+/// Handshake state that authentication signatures are bound to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthContext {
+    /// Node ID announced by the client
+    pub client_id: Uuid,
+
+    /// Node ID announced by the server
+    pub server_id: Uuid,
+
+    /// SHA-256 over both handshake payloads and, once bound, both key shares
+    pub transcript: [u8; 32],
+}
+
 /// Clipboard data payload
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ClipboardData {
@@ -173,6 +291,7 @@ pub struct ClipboardData {
     pub format: ClipboardFormat,
 
     /// Clipboard content data
+    #[serde(with = "bytes")]
     pub data: Vec<u8>,
 
     /// Data compression method
@@ -236,6 +355,7 @@ pub struct StreamPayload {
     pub metadata: Option<StreamMetadata>,
 
     /// Chunk data for stream chunks
+    #[serde(with = "bytes::optional")]
     pub data: Option<Vec<u8>>,
 
     /// Chunk sequence number
@@ -284,6 +404,14 @@ pub struct StreamMetadata {
 
     /// Stream checksum
     pub checksum: String,
+
+    /// Checksum of the clipboard payload before compression
+    #[serde(default)]
+    pub content_checksum: String,
+
+    /// Metadata of the clipboard payload being streamed
+    #[serde(default)]
+    pub attributes: std::collections::HashMap<String, String>,
 }
 
 /// Stream completion information
@@ -438,6 +566,93 @@ pub enum ConnectionStatus {
     Closing,
 }
 
+/// History reconciliation payload
+///
+/// Catch-up runs in one round trip: the reconnecting peer sends a
+/// [`HistoryDigest`], the other side answers with the entries in every
+/// range whose digest differs, and the reconnecting peer then requests what
+/// it lacks and sends what the other side lacks.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum HistoryPayload {
+    /// Digest of the sender's recent history
+    Digest(HistoryDigest),
+
+    /// Entries in the ranges where the digests differ
+    Inventory(HistoryInventory),
+
+    /// IDs of entries to send back
+    Request(Vec<Uuid>),
+
+    /// Entries the receiver lacks
+    Entries(Vec<HistoryRecord>),
+}
+
+/// Range digest of recent history
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryDigest {
+    /// Unix timestamp the digest starts at
+    pub since: i64,
+
+    /// Width of each range in seconds
+    pub bucket_secs: u32,
+
+    /// Non-empty ranges, oldest first
+    pub buckets: Vec<DigestBucket>,
+}
+
+/// Digest of the entries in one time range
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DigestBucket {
+    /// Unix timestamp the range starts at
+    pub start: i64,
+
+    /// Number of distinct contents in the range
+    pub count: u32,
+
+    /// Hex SHA-256 over the sorted content checksums
+    pub hash: String,
+}
+
+/// Entries in the ranges whose digests differ
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryInventory {
+    /// Start of each differing range
+    pub ranges: Vec<i64>,
+
+    /// Width of each range in seconds
+    pub bucket_secs: u32,
+
+    /// The sender's entries in those ranges
+    pub entries: Vec<EntrySummary>,
+}
+
+/// A history entry sent during catch-up
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryRecord {
+    /// Entry ID, kept on every node
+    pub id: Uuid,
+
+    /// Node the entry was copied on
+    pub origin: Uuid,
+
+    /// Unix timestamp the entry was created
+    pub timestamp: i64,
+
+    /// Selection the entry was copied from
+    pub selection: String,
+
+    /// Entry content; its checksum is the history (SHA-256) checksum
+    pub data: ClipboardData,
+
+    /// Unix timestamp after which every node deletes the entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+
+    /// Hybrid logical clock stamp of the entry; missing from older peers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hlc: Option<HybridTimestamp>,
+}
+
 impl Message {
     /// Create a new message with the current timestamp
     pub fn new(message_type: MessageType, payload: MessagePayload) -> Self {
@@ -449,6 +664,8 @@ impl Message {
             correlation_id: None,
             version: PROTOCOL_VERSION.to_string(),
             source_peer_id: None,
+            hlc: None,
+            group: None,
         }
     }
 
@@ -469,6 +686,18 @@ impl Message {
         self
     }
 
+    /// Set the hybrid logical clock stamp
+    pub fn with_hlc(mut self, hlc: HybridTimestamp) -> Self {
+        self.hlc = Some(hlc);
+        self
+    }
+
+    /// Tag the message with the sync group it is shared in
+    pub fn with_group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
     /// Check if this is a response to another message
     pub fn is_response_to(&self, other: &Message) -> bool {
         self.correlation_id.is_some()
@@ -487,6 +716,87 @@ impl Message {
     }
 }
 
+impl AuthExchange {
+    /// Encode for [`AuthPayload::data`]
+    pub fn encode(&self) -> String {
+        BASE64.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    /// Decode from [`AuthPayload::data`]
+    pub fn decode(data: &str) -> Option<Self> {
+        let json = BASE64.decode(data).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+impl AuthContext {
+    /// Bind authentication to the handshake sent by the client and the
+    /// response sent by the server
+    pub fn new(client: &HandshakePayload, server: &HandshakePayload) -> Self {
+        let mut hasher = Sha256::new();
+        hash_handshake(&mut hasher, client);
+        hash_handshake(&mut hasher, server);
+
+        Self {
+            client_id: client.peer_id,
+            server_id: server.peer_id,
+            transcript: hasher.finalize().into(),
+        }
+    }
+
+    /// Mix both session key shares into the transcript
+    pub fn bind_key_exchange(&mut self, client_share: &[u8], server_share: &[u8]) {
+        let mut hasher = Sha256::new();
+        hasher.update(KEY_EXCHANGE_LABEL);
+        hasher.update(self.transcript);
+        for share in [client_share, server_share] {
+            hasher.update((share.len() as u64).to_be_bytes());
+            hasher.update(share);
+        }
+        self.transcript = hasher.finalize().into();
+    }
+
+    /// Bytes `signer` signs to answer the other side's `nonce`
+    pub fn signing_input(&self, signer: AuthRole, nonce: &[u8]) -> Vec<u8> {
+        let role: &[u8] = match signer {
+            AuthRole::Client => b"client",
+            AuthRole::Server => b"server",
+        };
+
+        let mut input = Vec::with_capacity(AUTH_SIGNATURE_LABEL.len() + 128 + nonce.len());
+        input.extend_from_slice(AUTH_SIGNATURE_LABEL);
+        input.push(0);
+        input.extend_from_slice(role);
+        input.push(0);
+        input.extend_from_slice(self.client_id.as_bytes());
+        input.extend_from_slice(self.server_id.as_bytes());
+        input.extend_from_slice(&self.transcript);
+        input.extend_from_slice(nonce);
+        input
+    }
+}
+
+/// Hash a handshake payload independently of map ordering
+fn hash_handshake(hasher: &mut Sha256, payload: &HandshakePayload) {
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    };
+
+    field(payload.version.as_bytes());
+    field(payload.peer_id.as_bytes());
+    for capability in &payload.capabilities {
+        field(capability.as_bytes());
+    }
+
+    let mut parameters: Vec<_> = payload.parameters.iter().collect();
+    parameters.sort();
+    for (key, value) in parameters {
+        field(key.as_bytes());
+        field(value.as_bytes());
+    }
+}
+
 impl fmt::Display for MessageType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -505,6 +815,11 @@ impl fmt::Display for MessageType {
             MessageType::Error => write!(f, "ERROR"),
             MessageType::Capabilities => write!(f, "CAPABILITIES"),
             MessageType::Status => write!(f, "STATUS"),
+            MessageType::HistoryDigest => write!(f, "HISTORY_DIGEST"),
+            MessageType::HistoryInventory => write!(f, "HISTORY_INVENTORY"),
+            MessageType::HistoryRequest => write!(f, "HISTORY_REQUEST"),
+            MessageType::HistoryEntries => write!(f, "HISTORY_ENTRIES"),
+            MessageType::Unknown => write!(f, "UNKNOWN"),
         }
     }
 }
@@ -538,6 +853,94 @@ impl ClipboardFormat {
     }
 }
 
+/// Byte fields as byte strings in binary encodings
+///
+/// JSON has no byte strings and keeps writing an array of numbers.
+mod bytes {
+    use serde::de::{Deserializer, SeqAccess, Visitor};
+    use serde::{Deserialize, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(data)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("bytes")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(64 * 1024));
+            while let Some(byte) = seq.next_element()? {
+                data.push(byte);
+            }
+            Ok(data)
+        }
+    }
+
+    struct ByteBuf(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for ByteBuf {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize(deserializer).map(ByteBuf)
+        }
+    }
+
+    pub mod optional {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            data: &Option<Vec<u8>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match data {
+                Some(data) => serializer.serialize_some(&Bytes(data)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Vec<u8>>, D::Error> {
+            Ok(Option::<ByteBuf>::deserialize(deserializer)?.map(|bytes| bytes.0))
+        }
+    }
+
+    struct Bytes<'a>(&'a [u8]);
+
+    impl serde::Serialize for Bytes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+/// Any value, including byte strings from binary encodings
+fn any_value<'de, D>(deserializer: D) -> Result<serde_json::Value, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = ciborium::Value::deserialize(deserializer)?;
+    serde_json::to_value(value).map_err(serde::de::Error::custom)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -589,6 +992,67 @@ mod tests {
         assert_eq!(MessageType::StreamStart.to_string(), "STREAM_START");
     }
 
+    fn handshake(peer_id: Uuid) -> HandshakePayload {
+        HandshakePayload {
+            version: PROTOCOL_VERSION.to_string(),
+            peer_id,
+            capabilities: vec!["clipboard_sync".to_string()],
+            parameters: [
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn test_auth_context_binding() {
+        let client = handshake(Uuid::new_v4());
+        let server = handshake(Uuid::new_v4());
+        let context = AuthContext::new(&client, &server);
+        let nonce = [7u8; AUTH_NONCE_SIZE];
+
+        // Both sides derive the same context from the same handshake
+        assert_eq!(context, AuthContext::new(&client, &server));
+
+        // Roles, nonces and handshakes all change what gets signed
+        let input = context.signing_input(AuthRole::Client, &nonce);
+        assert_ne!(input, context.signing_input(AuthRole::Server, &nonce));
+        assert_ne!(
+            input,
+            context.signing_input(AuthRole::Client, &[8u8; AUTH_NONCE_SIZE])
+        );
+
+        let mut tampered = server.clone();
+        tampered.capabilities.clear();
+        assert_ne!(
+            input,
+            AuthContext::new(&client, &tampered).signing_input(AuthRole::Client, &nonce)
+        );
+
+        // So do the session key shares
+        let mut bound = context.clone();
+        bound.bind_key_exchange(b"client share", b"server share");
+        assert_ne!(input, bound.signing_input(AuthRole::Client, &nonce));
+        let mut swapped = context.clone();
+        swapped.bind_key_exchange(b"server share", b"client share");
+        assert_ne!(bound, swapped);
+    }
+
+    #[test]
+    fn test_auth_exchange_encoding() {
+        let exchange = AuthExchange {
+            public_key: Some("key".to_string()),
+            nonce: Some("nonce".to_string()),
+            signature: None,
+            key_share: Some("share".to_string()),
+        };
+
+        assert_eq!(AuthExchange::decode(&exchange.encode()), Some(exchange));
+        assert_eq!(AuthExchange::decode("not base64!"), None);
+    }
+
     #[test]
     fn test_message_serialization() {
         let data = ClipboardData {
@@ -607,5 +1071,15 @@ mod tests {
         let deserialized: Message = serde_json::from_str(&serialized).unwrap();
 
         assert_eq!(msg, deserialized);
+
+        // Untagged messages encode as before groups existed
+        assert!(!serialized.contains("\"group\""));
+        let tagged = msg.with_group("pairing");
+        let serialized = serde_json::to_string(&tagged).unwrap();
+        assert!(serialized.contains("\"group\":\"pairing\""));
+        assert_eq!(
+            serde_json::from_str::<Message>(&serialized).unwrap(),
+            tagged
+        );
     }
 }
@@ -0,0 +1,176 @@
+//! Messages waiting for an offline peer
+//!
+//! Messages for a peer that has dropped off are queued rather than lost,
+//! and sent in order once it reconnects. A clipboard message supersedes any
+//! queued clipboard message for the same selection, since the peer would
+//! only keep the newest. The queue is bounded; when it is full the oldest
+//! message is dropped to make room.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::transport::protocol::{Message, MessagePayload, SELECTION_METADATA_KEY};
+
+/// Messages kept for each offline peer by default
+pub const DEFAULT_PEER_QUEUE_SIZE: usize = 32;
+
+/// Selection clipboard messages belong to when they do not name one
+const DEFAULT_SELECTION: &str = "clipboard";
+
+/// Counters for one peer's queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStats {
+    /// Messages waiting to be sent
+    pub depth: usize,
+
+    /// Messages dropped because the queue was full
+    pub dropped: u64,
+
+    /// Clipboard messages replaced by a newer one before they were sent
+    pub superseded: u64,
+}
+
+/// Bounded queue of messages for one peer
+#[derive(Debug)]
+pub struct OutboundQueue {
+    messages: VecDeque<Message>,
+    capacity: usize,
+    dropped: u64,
+    superseded: u64,
+}
+
+impl OutboundQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+            superseded: 0,
+        }
+    }
+
+    /// Queue a message behind the others
+    pub fn push(&mut self, message: Message) {
+        if let Some(selection) = selection(&message) {
+            let before = self.messages.len();
+            self.messages
+                .retain(|queued| self::selection(queued) != Some(selection));
+            self.superseded += (before - self.messages.len()) as u64;
+        }
+
+        while self.messages.len() >= self.capacity {
+            self.messages.pop_front();
+            self.dropped += 1;
+        }
+        self.messages.push_back(message);
+    }
+
+    /// Oldest queued message
+    pub fn front(&self) -> Option<&Message> {
+        self.messages.front()
+    }
+
+    /// Remove the oldest queued message
+    pub fn pop_front(&mut self) -> Option<Message> {
+        self.messages.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            depth: self.messages.len(),
+            dropped: self.dropped,
+            superseded: self.superseded,
+        }
+    }
+}
+
+/// Selection a clipboard message would replace on the peer
+fn selection(message: &Message) -> Option<&str> {
+    match &message.payload {
+        MessagePayload::Clipboard(data) => Some(
+            data.metadata
+                .get(SELECTION_METADATA_KEY)
+                .map_or(DEFAULT_SELECTION, String::as_str),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::protocol::{ClipboardData, ClipboardFormat, HistoryPayload, MessageType};
+
+    fn copy(text: &str, selection: &str) -> Message {
+        Message::new(
+            MessageType::ClipboardData,
+            MessagePayload::Clipboard(ClipboardData {
+                format: ClipboardFormat::Text,
+                data: text.as_bytes().to_vec(),
+                compression: None,
+                checksum: String::new(),
+                metadata: [(SELECTION_METADATA_KEY.to_string(), selection.to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+        )
+    }
+
+    fn text(message: &Message) -> &str {
+        match &message.payload {
+            MessagePayload::Clipboard(data) => std::str::from_utf8(&data.data).unwrap(),
+            other => panic!("unexpected payload {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_newer_copies_supersede_queued_ones() {
+        let mut queue = OutboundQueue::new(8);
+        queue.push(copy("one", "clipboard"));
+        queue.push(copy("selected", "primary"));
+        queue.push(copy("two", "clipboard"));
+        queue.push(copy("three", "clipboard"));
+
+        assert_eq!(
+            queue.stats(),
+            QueueStats {
+                depth: 2,
+                dropped: 0,
+                superseded: 2,
+            }
+        );
+        assert_eq!(text(&queue.pop_front().unwrap()), "selected");
+        assert_eq!(text(&queue.pop_front().unwrap()), "three");
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_full_queue_drops_oldest() {
+        let mut queue = OutboundQueue::new(2);
+        for _ in 0..3 {
+            queue.push(Message::new(
+                MessageType::HistoryRequest,
+                MessagePayload::History(HistoryPayload::Request(Vec::new())),
+            ));
+        }
+        queue.push(copy("latest", "clipboard"));
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.stats().dropped, 2);
+        assert_eq!(queue.stats().superseded, 0);
+        assert_eq!(
+            queue.front().unwrap().message_type,
+            MessageType::HistoryRequest
+        );
+        assert_eq!(text(queue.messages.back().unwrap()), "latest");
+    }
+}
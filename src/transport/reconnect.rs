@@ -4,7 +4,7 @@
 //! backoff, connection health monitoring, and graceful degradation.
 
 use crate::auth::Authenticator;
-use crate::transport::{Connection, PeerInfo, Result, TransportError, TransportEvent};
+use crate::transport::{Connection, PeerInfo, QueueStats, Result, TransportError, TransportEvent};
 use std::time::{Duration, Instant};
 use tokio::{sync::mpsc, time::sleep};
 use tracing::{debug, error, info, warn};
@@ -92,6 +92,9 @@ pub struct ReconnectionManager {
     /// Connection health status
     health_status: HealthStatus,
 
+    /// Whether a connection was ever established
+    connected_before: bool,
+
     /// Health check statistics
     health_stats: HealthStats,
 
@@ -138,6 +141,7 @@ impl ReconnectionManager {
             attempt_count: 0,
             last_attempt: None,
             health_status: HealthStatus::Unknown,
+            connected_before: false,
             health_stats: HealthStats::default(),
             event_tx,
             shutdown_rx: None,
@@ -267,6 +271,14 @@ impl ReconnectionManager {
                     ));
                 }
 
+                // Anything sent while the peer was away needs catching up
+                if self.connected_before {
+                    let _ = self
+                        .event_tx
+                        .send(TransportEvent::ConnectionRestored(self.peer_info.id));
+                }
+                self.connected_before = true;
+
                 Ok(())
             }
             Ok(Err(e)) => {
@@ -424,6 +436,7 @@ impl ReconnectionManager {
             avg_response_time: self.health_stats.avg_response_time,
             uptime,
             last_check: self.health_stats.last_check,
+            queue: QueueStats::default(),
         }
     }
 }
@@ -454,6 +467,10 @@ pub struct ConnectionStats {
 
     /// Last health check time
     pub last_check: Option<Instant>,
+
+    /// Messages waiting for the peer, filled in by
+    /// [`crate::transport::TransportManager::connection_stats`]
+    pub queue: QueueStats,
 }
 
 impl ConnectionStats {
@@ -506,6 +523,7 @@ mod tests {
             avg_response_time: Duration::from_millis(100),
             uptime: Duration::from_secs(120),
             last_check: Some(Instant::now()),
+            queue: QueueStats::default(),
         };
 
         assert!(stats.success_rate() > 0.9); // Now ~0.909
@@ -532,6 +550,7 @@ mod tests {
             attempt_count: 3,
             last_attempt: None,
             health_status: HealthStatus::Unknown,
+            connected_before: false,
             health_stats: HealthStats::default(),
             event_tx: mpsc::unbounded_channel().0,
             shutdown_rx: None,
@@ -573,6 +592,15 @@ mod tests {
             ))
         }
 
+        async fn sign(
+            &self,
+            _message: &[u8],
+        ) -> std::result::Result<Vec<u8>, crate::auth::AuthError> {
+            Err(crate::auth::AuthError::AuthenticationFailed(
+                "dummy".to_string(),
+            ))
+        }
+
         async fn is_authorized(
             &self,
             _key: &crate::auth::PublicKey,
@@ -0,0 +1,318 @@
+//! Encrypted session layer for authenticated connections
+//!
+//! During authentication both peers send an ephemeral X25519 key share
+//! that is covered by their Ed25519 signatures. The shared secret is
+//! expanded with HKDF-SHA256, salted with the handshake transcript, into one
+//! AES-256-GCM key per direction. Once authentication succeeds every
+//! message travels as a sealed binary frame:
+//!
+//! ```text
+//! sequence (8, big endian) | ciphertext + tag
+//! ```
+//!
+//! The plaintext is the message in the encoding agreed during the
+//! handshake (see [`crate::transport::codec`]).
+//!
+//! The sequence is the message's [`Message::sequence`]. It forms the AEAD
+//! nonce, is authenticated as associated data and must match the sequence
+//! inside the decrypted message. Receivers only accept strictly increasing
+//! sequences, so replayed, reordered or reflected frames are rejected.
+
+use crate::transport::codec::{self, CodecError, WireEncoding};
+use crate::transport::protocol::{AuthContext, AuthRole, Message};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::SystemRandom,
+};
+use thiserror::Error;
+
+/// Size of the cleartext sequence header of a sealed frame
+const SEQUENCE_SIZE: usize = 8;
+
+/// Domain separator for session key derivation
+const SESSION_KEY_LABEL: &[u8] = b"clipsync session v1";
+
+/// Secure channel errors
+#[derive(Debug, Error)]
+pub enum SessionError {
+    /// Key share generation or agreement failed
+    #[error("key exchange failed")]
+    KeyExchange,
+
+    /// Frame is too short to hold a sequence and tag
+    #[error("truncated frame")]
+    Truncated,
+
+    /// Message could not be encrypted
+    #[error("failed to encrypt frame")]
+    Encrypt,
+
+    /// Frame did not decrypt under the session key
+    #[error("frame failed authentication")]
+    Decrypt,
+
+    /// Frame sequence was not newer than the last accepted one
+    #[error("replayed frame {sequence} (last accepted {last})")]
+    Replay { sequence: u64, last: u64 },
+
+    /// Sequence inside the message differs from the frame header
+    #[error("frame sequence {header} does not match message sequence {message}")]
+    SequenceMismatch { header: u64, message: u64 },
+
+    /// Outgoing message would reuse a nonce
+    #[error("message sequence {sequence} not above last sent {last}")]
+    StaleSequence { sequence: u64, last: u64 },
+
+    /// Peer sent plaintext after the session was established
+    #[error("unencrypted message on an encrypted session")]
+    Unsealed,
+
+    /// Peer sent a sealed frame before the session was established
+    #[error("encrypted frame before the session was established")]
+    NotEstablished,
+
+    /// Message could not be encoded or decoded
+    #[error("invalid message: {0}")]
+    Codec(#[from] CodecError),
+}
+
+/// Ephemeral X25519 key share for one connection
+pub struct KeyShare {
+    private_key: EphemeralPrivateKey,
+    public_key: Vec<u8>,
+}
+
+impl KeyShare {
+    /// Generate a fresh key share
+    pub fn generate() -> Result<Self, SessionError> {
+        let private_key = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new())
+            .map_err(|_| SessionError::KeyExchange)?;
+        let public_key = private_key
+            .compute_public_key()
+            .map_err(|_| SessionError::KeyExchange)?
+            .as_ref()
+            .to_vec();
+
+        Ok(Self {
+            private_key,
+            public_key,
+        })
+    }
+
+    /// Public half to send to the peer
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Combine with the peer's share into session keys for `role`
+    ///
+    /// `context` must already be bound to both shares with
+    /// [`AuthContext::bind_key_exchange`].
+    pub fn agree(
+        self,
+        role: AuthRole,
+        peer_public_key: &[u8],
+        context: &AuthContext,
+    ) -> Result<SessionCipher, SessionError> {
+        let peer = UnparsedPublicKey::new(&X25519, peer_public_key);
+        let (client_key, server_key) =
+            agreement::agree_ephemeral(self.private_key, &peer, |secret| {
+                let prk = Salt::new(HKDF_SHA256, &context.transcript).extract(secret);
+                let expand = |direction: &[u8]| {
+                    let info = [SESSION_KEY_LABEL, direction];
+                    prk.expand(&info, &AES_256_GCM)
+                        .map(|okm| LessSafeKey::new(UnboundKey::from(okm)))
+                        .map_err(|_| SessionError::KeyExchange)
+                };
+                Ok::<_, SessionError>((expand(b"client")?, expand(b"server")?))
+            })
+            .map_err(|_| SessionError::KeyExchange)??;
+
+        let (sealer, opener) = match role {
+            AuthRole::Client => (client_key, server_key),
+            AuthRole::Server => (server_key, client_key),
+        };
+
+        Ok(SessionCipher {
+            sealer,
+            opener,
+            encoding: WireEncoding::Json,
+            last_sent: 0,
+            last_received: 0,
+        })
+    }
+}
+
+/// Per-connection keys and replay state
+pub struct SessionCipher {
+    sealer: LessSafeKey,
+    opener: LessSafeKey,
+    encoding: WireEncoding,
+    last_sent: u64,
+    last_received: u64,
+}
+
+impl SessionCipher {
+    /// Encode outgoing messages with `encoding`
+    ///
+    /// Incoming frames are decoded in whichever encoding they name.
+    pub fn with_encoding(mut self, encoding: WireEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Encrypt a message into a frame
+    ///
+    /// Messages must be sealed in increasing [`Message::sequence`] order.
+    pub fn seal(&mut self, message: &Message) -> Result<Vec<u8>, SessionError> {
+        if message.sequence <= self.last_sent {
+            return Err(SessionError::StaleSequence {
+                sequence: message.sequence,
+                last: self.last_sent,
+            });
+        }
+
+        let header = message.sequence.to_be_bytes();
+        let mut body = codec::encode(message, self.encoding)?;
+        self.sealer
+            .seal_in_place_append_tag(nonce(message.sequence), Aad::from(header), &mut body)
+            .map_err(|_| SessionError::Encrypt)?;
+        self.last_sent = message.sequence;
+
+        let mut frame = Vec::with_capacity(SEQUENCE_SIZE + body.len());
+        frame.extend_from_slice(&header);
+        frame.extend_from_slice(&body);
+        Ok(frame)
+    }
+
+    /// Decrypt a frame, rejecting anything not newer than the last one
+    pub fn open(&mut self, frame: &[u8]) -> Result<Message, SessionError> {
+        if frame.len() < SEQUENCE_SIZE + AES_256_GCM.tag_len() {
+            return Err(SessionError::Truncated);
+        }
+
+        let (header, ciphertext) = frame.split_at(SEQUENCE_SIZE);
+        let header: [u8; SEQUENCE_SIZE] = header.try_into().map_err(|_| SessionError::Truncated)?;
+        let sequence = u64::from_be_bytes(header);
+        if sequence <= self.last_received {
+            return Err(SessionError::Replay {
+                sequence,
+                last: self.last_received,
+            });
+        }
+
+        let mut body = ciphertext.to_vec();
+        let plaintext = self
+            .opener
+            .open_in_place(nonce(sequence), Aad::from(header), &mut body)
+            .map_err(|_| SessionError::Decrypt)?;
+        let message = codec::decode(plaintext)?;
+        if message.sequence != sequence {
+            return Err(SessionError::SequenceMismatch {
+                header: sequence,
+                message: message.sequence,
+            });
+        }
+
+        self.last_received = sequence;
+        Ok(message)
+    }
+}
+
+fn nonce(sequence: u64) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[NONCE_LEN - SEQUENCE_SIZE..].copy_from_slice(&sequence.to_be_bytes());
+    Nonce::assume_unique_for_key(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::protocol::{HandshakePayload, MessagePayload, MessageType};
+    use uuid::Uuid;
+
+    fn sessions() -> (SessionCipher, SessionCipher) {
+        let handshake = |peer_id| HandshakePayload {
+            version: "1.0.0".to_string(),
+            peer_id,
+            capabilities: vec![],
+            parameters: Default::default(),
+        };
+        let mut context = AuthContext::new(&handshake(Uuid::new_v4()), &handshake(Uuid::new_v4()));
+
+        let client = KeyShare::generate().unwrap();
+        let server = KeyShare::generate().unwrap();
+        let (client_public, server_public) =
+            (client.public_key().to_vec(), server.public_key().to_vec());
+        context.bind_key_exchange(&client_public, &server_public);
+
+        (
+            client
+                .agree(AuthRole::Client, &server_public, &context)
+                .unwrap(),
+            server
+                .agree(AuthRole::Server, &client_public, &context)
+                .unwrap(),
+        )
+    }
+
+    fn message(sequence: u64) -> Message {
+        Message::new(MessageType::KeepAlive, MessagePayload::KeepAlive).with_sequence(sequence)
+    }
+
+    #[test]
+    fn test_session_roundtrip() {
+        let (mut client, mut server) = sessions();
+
+        let sent = message(1);
+        let frame = client.seal(&sent).unwrap();
+        assert_eq!(server.open(&frame).unwrap(), sent);
+
+        // Each direction has its own key
+        let reply = server.seal(&message(1)).unwrap();
+        assert_ne!(frame[SEQUENCE_SIZE..], reply[SEQUENCE_SIZE..]);
+        assert!(client.open(&reply).is_ok());
+    }
+
+    #[test]
+    fn test_session_rejects_replay_and_tampering() {
+        let (mut client, mut server) = sessions();
+
+        let first = client.seal(&message(1)).unwrap();
+        let second = client.seal(&message(5)).unwrap();
+        assert!(matches!(
+            client.seal(&message(5)),
+            Err(SessionError::StaleSequence { .. })
+        ));
+
+        // Gaps are fine, going backwards is not
+        server.open(&second).unwrap();
+        assert!(matches!(
+            server.open(&first),
+            Err(SessionError::Replay { .. })
+        ));
+        assert!(matches!(
+            server.open(&second),
+            Err(SessionError::Replay { .. })
+        ));
+
+        // A frame cannot be moved to another sequence number
+        let mut moved = client.seal(&message(6)).unwrap();
+        moved[..SEQUENCE_SIZE].copy_from_slice(&7u64.to_be_bytes());
+        assert!(matches!(server.open(&moved), Err(SessionError::Decrypt)));
+
+        // Frames cannot be reflected back to their sender
+        let own = client.seal(&message(8)).unwrap();
+        assert!(matches!(client.open(&own), Err(SessionError::Decrypt)));
+
+        // Unrelated sessions cannot read each other's frames
+        let (_, mut other) = sessions();
+        assert!(other.open(&client.seal(&message(9)).unwrap()).is_err());
+        assert!(matches!(
+            server.open(&[0u8; 4]),
+            Err(SessionError::Truncated)
+        ));
+    }
+}
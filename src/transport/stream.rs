@@ -2,9 +2,19 @@
 //!
 //! This module handles efficient streaming of large clipboard data
 //! using chunked transfer with progress tracking and flow control.
-
-use crate::transport::{protocol::*, Connection, PeerInfo, Result, TransportError};
+//!
+//! Stream state outlives the connection it started on: when a peer
+//! reconnects, [`StreamingTransport::replace_connection`] announces each
+//! unfinished outbound stream again and carries on from the first chunk the
+//! peer has not acknowledged.
+
+use crate::sync::clock::HybridTimestamp;
+use crate::transport::{
+    protocol::*, Connection, NegotiatedCapabilities, PeerInfo, Result, TransportError,
+};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, info, warn};
@@ -16,6 +26,9 @@ pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
 /// Maximum number of in-flight chunks
 pub const MAX_IN_FLIGHT_CHUNKS: usize = 10;
 
+/// Clipboard payloads larger than this are streamed (1MB)
+pub const DEFAULT_STREAM_THRESHOLD: usize = 1024 * 1024;
+
 /// Stream chunk data
 #[derive(Debug, Clone)]
 pub struct StreamChunk {
@@ -35,12 +48,25 @@ pub struct StreamChunk {
     pub checksum: String,
 }
 
+/// Which way a stream flows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamDirection {
+    /// Sending to the peer
+    Outbound,
+    /// Receiving from the peer
+    Inbound,
+}
+
 /// Streaming progress update
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProgressUpdate {
     /// Stream identifier
     pub stream_id: Uuid,
 
+    /// Whether the stream is being sent or received
+    pub direction: StreamDirection,
+
     /// Bytes transferred so far
     pub bytes_transferred: u64,
 
@@ -102,6 +128,9 @@ pub struct StreamConfig {
 
 /// Outbound stream state
 struct OutboundStream {
+    /// Stream start message, sent again when the stream resumes
+    start: Message,
+
     /// Stream metadata
     metadata: StreamMetadata,
 
@@ -132,6 +161,15 @@ struct InboundStream {
     /// Stream metadata
     metadata: StreamMetadata,
 
+    /// Time the streamed clipboard message was created
+    timestamp: DateTime<Utc>,
+
+    /// Hybrid logical clock stamp of the streamed clipboard message
+    hlc: Option<HybridTimestamp>,
+
+    /// Sync group the streamed clipboard message is shared in
+    group: Option<String>,
+
     /// Received chunks
     chunks: HashMap<u64, Vec<u8>>,
 
@@ -188,9 +226,32 @@ impl StreamingTransport {
         &mut self,
         data: ClipboardData,
     ) -> Result<oneshot::Receiver<Result<()>>> {
+        self.send_clipboard_message(Message::new(
+            MessageType::ClipboardData,
+            MessagePayload::Clipboard(data),
+        ))
+        .await
+    }
+
+    /// Send a clipboard message, streaming its payload if it is large
+    ///
+    /// The receiver rebuilds the message with the original timestamp and
+    /// clock stamp once the stream completes.
+    pub async fn send_clipboard_message(
+        &mut self,
+        message: Message,
+    ) -> Result<oneshot::Receiver<Result<()>>> {
+        let data = match &message.payload {
+            MessagePayload::Clipboard(data) => data,
+            _ => {
+                return Err(TransportError::Streaming {
+                    message: "Only clipboard messages can be streamed".to_string(),
+                })
+            }
+        };
+
         if data.data.len() <= self.config.chunk_size {
             // Small payload, send directly
-            let message = Message::new(MessageType::ClipboardData, MessagePayload::Clipboard(data));
             self.connection.send(message).await?;
 
             let (tx, rx) = oneshot::channel();
@@ -201,14 +262,16 @@ impl StreamingTransport {
         info!("Starting clipboard stream of {} bytes", data.data.len());
 
         let stream_id = Uuid::new_v4();
-        let total_chunks = (data.data.len() + self.config.chunk_size - 1) / self.config.chunk_size;
 
-        // Compress data if enabled
-        let compressed_data = if self.config.enable_compression {
-            self.compress_data(&data.data)?
-        } else {
-            data.data.clone()
+        // Compress with the method negotiated with the peer, if enabled
+        let compression = match self.connection.capabilities() {
+            _ if !self.config.enable_compression => CompressionMethod::None,
+            Some(capabilities) => capabilities.compression.clone(),
+            None => self.config.compression_method.clone(),
         };
+        let compressed_data = self.compress_data(&data.data, &compression)?;
+        let total_chunks =
+            (compressed_data.len() + self.config.chunk_size - 1) / self.config.chunk_size;
 
         // Create stream metadata
         let metadata = StreamMetadata {
@@ -216,12 +279,10 @@ impl StreamingTransport {
             total_chunks: total_chunks as u64,
             chunk_size: self.config.chunk_size,
             content_type: data.format.clone(),
-            compression: if self.config.enable_compression {
-                self.config.compression_method.clone()
-            } else {
-                CompressionMethod::None
-            },
+            compression,
             checksum: self.calculate_checksum(&compressed_data),
+            content_checksum: data.checksum.clone(),
+            attributes: data.metadata.clone(),
         };
 
         // Send stream start message
@@ -234,18 +295,22 @@ impl StreamingTransport {
             completion: None,
         };
 
-        let start_message = Message::new(
+        let mut start_message = Message::new(
             MessageType::StreamStart,
             MessagePayload::Stream(stream_payload),
         );
+        start_message.timestamp = message.timestamp;
+        start_message.hlc = message.hlc;
+        start_message.group = message.group.clone();
 
-        self.connection.send(start_message).await?;
+        self.connection.send(start_message.clone()).await?;
 
         // Create completion channel
         let (completion_tx, completion_rx) = oneshot::channel();
 
         // Create outbound stream
         let outbound_stream = OutboundStream {
+            start: start_message,
             metadata,
             data: compressed_data,
             position: 0,
@@ -258,20 +323,29 @@ impl StreamingTransport {
 
         self.outbound_streams.insert(stream_id, outbound_stream);
 
-        // Start sending chunks
-        self.send_next_chunks(stream_id).await?;
+        // Start sending chunks; a stream cut off from here on resumes when
+        // the peer reconnects
+        if let Err(e) = self.send_next_chunks(stream_id).await {
+            if !e.is_connection_lost() {
+                return Err(e);
+            }
+            warn!("Stream {} interrupted, will resume: {}", stream_id, e);
+        }
 
         Ok(completion_rx)
     }
 
     /// Handle incoming stream messages
-    pub async fn handle_stream_message(
-        &mut self,
-        message: Message,
-    ) -> Result<Option<ClipboardData>> {
+    ///
+    /// Returns the reassembled clipboard message once a stream completes.
+    pub async fn handle_stream_message(&mut self, message: Message) -> Result<Option<Message>> {
+        let (timestamp, hlc, group) = (message.timestamp, message.hlc, message.group);
         if let MessagePayload::Stream(payload) = message.payload {
             match payload.operation {
-                StreamOperation::Start => self.handle_stream_start(payload).await,
+                StreamOperation::Start => {
+                    self.handle_stream_start(payload, timestamp, hlc, group)
+                        .await
+                }
                 StreamOperation::Chunk => self.handle_stream_chunk(payload).await,
                 StreamOperation::End => self.handle_stream_end(payload).await,
                 StreamOperation::Ack => {
@@ -290,6 +364,65 @@ impl StreamingTransport {
         }
     }
 
+    /// Continue over a new connection to the same peer
+    ///
+    /// Streams older than the configured timeout are dropped. Every other
+    /// outbound stream is announced again and resent from its first
+    /// unacknowledged chunk; the peer keeps what it already received.
+    pub async fn replace_connection(&mut self, connection: Box<dyn Connection>) -> Result<()> {
+        self.connection = connection;
+
+        let timeout = self.config.timeout;
+        self.inbound_streams
+            .retain(|_, stream| stream.start_time.elapsed() < timeout);
+
+        let expired: Vec<Uuid> = self
+            .outbound_streams
+            .iter()
+            .filter(|(_, stream)| stream.start_time.elapsed() >= timeout)
+            .map(|(stream_id, _)| *stream_id)
+            .collect();
+        for stream_id in expired {
+            warn!("Stream {} expired before it could resume", stream_id);
+            if let Some(tx) = self
+                .outbound_streams
+                .remove(&stream_id)
+                .and_then(|stream| stream.completion_tx)
+            {
+                let _ = tx.send(Err(TransportError::Timeout));
+            }
+        }
+
+        let stream_ids: Vec<Uuid> = self.outbound_streams.keys().copied().collect();
+        for stream_id in stream_ids {
+            let start = match self.outbound_streams.get_mut(&stream_id) {
+                Some(stream) => {
+                    stream.rewind();
+                    stream.start.clone()
+                }
+                None => continue,
+            };
+
+            info!("Resuming stream {}", stream_id);
+            self.connection.send(start).await?;
+            self.send_next_chunks(stream_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Progress of every unfinished stream
+    pub fn progress(&self) -> Vec<ProgressUpdate> {
+        self.outbound_streams
+            .iter()
+            .map(|(stream_id, stream)| stream.progress(*stream_id))
+            .chain(
+                self.inbound_streams
+                    .iter()
+                    .map(|(stream_id, stream)| stream.progress(*stream_id)),
+            )
+            .collect()
+    }
     /// Send next available chunks for a stream
     async fn send_next_chunks(&mut self, stream_id: Uuid) -> Result<()> {
         let stream =
@@ -366,11 +499,19 @@ impl StreamingTransport {
     async fn handle_stream_start(
         &mut self,
         payload: StreamPayload,
-    ) -> Result<Option<ClipboardData>> {
+        timestamp: DateTime<Utc>,
+        hlc: Option<HybridTimestamp>,
+        group: Option<String>,
+    ) -> Result<Option<Message>> {
         let metadata = payload.metadata.ok_or_else(|| TransportError::Streaming {
             message: "Stream start missing metadata".to_string(),
         })?;
 
+        if self.inbound_streams.contains_key(&payload.stream_id) {
+            info!("Resuming stream {}", payload.stream_id);
+            return Ok(None);
+        }
+
         info!(
             "Receiving stream {} of {} bytes",
             payload.stream_id, metadata.total_size
@@ -378,6 +519,9 @@ impl StreamingTransport {
 
         let inbound_stream = InboundStream {
             metadata,
+            timestamp,
+            hlc,
+            group,
             chunks: HashMap::new(),
             next_expected: 1,
             assembled_data: Vec::new(),
@@ -393,10 +537,7 @@ impl StreamingTransport {
     }
 
     /// Handle stream chunk message
-    async fn handle_stream_chunk(
-        &mut self,
-        payload: StreamPayload,
-    ) -> Result<Option<ClipboardData>> {
+    async fn handle_stream_chunk(&mut self, payload: StreamPayload) -> Result<Option<Message>> {
         let stream_id = payload.stream_id;
         let chunk_data = payload.data.ok_or_else(|| TransportError::Streaming {
             message: "Stream chunk missing data".to_string(),
@@ -414,8 +555,10 @@ impl StreamingTransport {
                     message: "Stream not found".to_string(),
                 })?;
 
-        // Store chunk
-        stream.chunks.insert(sequence, chunk_data);
+        // A resumed stream may repeat chunks whose ack was lost
+        if sequence >= stream.next_expected {
+            stream.chunks.insert(sequence, chunk_data);
+        }
 
         // Assemble sequential chunks
         while let Some(chunk_data) = stream.chunks.remove(&stream.next_expected) {
@@ -423,24 +566,11 @@ impl StreamingTransport {
             stream.next_expected += 1;
         }
 
-        // Send progress update (clone needed to avoid borrowing issues)
-        let next_expected = stream.next_expected;
-        let assembled_len = stream.assembled_data.len();
-        let metadata = stream.metadata.clone();
-        let start_time = stream.start_time;
-        drop(stream); // Release mutable borrow
+        let progress = stream.progress(stream_id);
 
         // Send acknowledgment
         self.send_stream_ack(stream_id, sequence).await?;
-
-        // Send progress update using cloned data
-        self.send_progress_update_with_data(
-            stream_id,
-            assembled_len,
-            &metadata,
-            start_time,
-            next_expected,
-        )?;
+        self.report_progress(progress);
 
         debug!("Received chunk {} for stream {}", sequence, stream_id);
 
@@ -448,7 +578,7 @@ impl StreamingTransport {
     }
 
     /// Handle stream end message
-    async fn handle_stream_end(&mut self, payload: StreamPayload) -> Result<Option<ClipboardData>> {
+    async fn handle_stream_end(&mut self, payload: StreamPayload) -> Result<Option<Message>> {
         let stream_id = payload.stream_id;
 
         let stream =
@@ -464,37 +594,40 @@ impl StreamingTransport {
             stream.assembled_data.len()
         );
 
-        // Clone data to avoid move issues
-        let assembled_data = stream.assembled_data.clone();
-        let compression_method = stream.metadata.compression.clone();
-        let expected_checksum = stream.metadata.checksum.clone();
-        let content_type = stream.metadata.content_type.clone();
-
-        // Decompress data if needed
-        let final_data = if compression_method != CompressionMethod::None {
-            self.decompress_data(&assembled_data, &compression_method)?
-        } else {
-            assembled_data.clone()
-        };
-
         // Verify checksum
-        let calculated_checksum = self.calculate_checksum(&assembled_data);
-        if calculated_checksum != expected_checksum {
+        let calculated_checksum = self.calculate_checksum(&stream.assembled_data);
+        if calculated_checksum != stream.metadata.checksum {
             return Err(TransportError::Streaming {
                 message: "Stream checksum verification failed".to_string(),
             });
         }
 
-        // Create clipboard data
+        // Decompress data if needed
+        let metadata = stream.metadata;
+        let final_data = if metadata.compression != CompressionMethod::None {
+            self.decompress_data(&stream.assembled_data, &metadata.compression)?
+        } else {
+            stream.assembled_data
+        };
+
+        // Rebuild the clipboard message the sender streamed
         let clipboard_data = ClipboardData {
-            format: content_type,
+            format: metadata.content_type,
             data: final_data,
-            compression: Some(compression_method),
-            checksum: expected_checksum,
-            metadata: std::collections::HashMap::new(),
+            compression: None,
+            checksum: metadata.content_checksum,
+            metadata: metadata.attributes,
         };
 
-        Ok(Some(clipboard_data))
+        let mut message = Message::new(
+            MessageType::ClipboardData,
+            MessagePayload::Clipboard(clipboard_data),
+        );
+        message.timestamp = stream.timestamp;
+        message.hlc = stream.hlc;
+        message.group = stream.group;
+
+        Ok(Some(message))
     }
 
     /// Handle stream acknowledgment
@@ -512,22 +645,8 @@ impl StreamingTransport {
                 stream.bytes_acked += chunk.data.len() as u64;
                 debug!("Chunk {} acknowledged for stream {}", sequence, stream_id);
 
-                // Send progress update (clone data to avoid borrowing issues)
-                let bytes_acked = stream.bytes_acked;
-                let total_size = stream.metadata.total_size;
-                let total_chunks = stream.metadata.total_chunks;
-                let next_sequence = stream.next_sequence;
-                let start_time = stream.start_time;
-                drop(stream); // Release mutable borrow
-
-                self.send_outbound_progress_update_with_data(
-                    stream_id,
-                    bytes_acked,
-                    total_size,
-                    total_chunks,
-                    next_sequence,
-                    start_time,
-                )?;
+                let progress = stream.progress(stream_id);
+                self.report_progress(progress);
 
                 // Send more chunks if available
                 self.send_next_chunks(stream_id).await?;
@@ -536,7 +655,6 @@ impl StreamingTransport {
 
         Ok(())
     }
-
     /// Handle stream cancellation
     async fn handle_stream_cancel(&mut self, payload: StreamPayload) -> Result<()> {
         let stream_id = payload.stream_id;
@@ -604,160 +722,14 @@ impl StreamingTransport {
         self.connection.send(ack_message).await
     }
 
-    /// Send progress update for inbound stream with data
-    fn send_progress_update_with_data(
-        &self,
-        stream_id: Uuid,
-        assembled_len: usize,
-        metadata: &StreamMetadata,
-        start_time: std::time::Instant,
-        next_expected: u64,
-    ) -> Result<()> {
-        let now = std::time::Instant::now();
-        let elapsed = now.duration_since(start_time).as_secs_f64();
-
-        let bytes_transferred = assembled_len as u64;
-        let total_bytes = metadata.total_size;
-        let transfer_rate = if elapsed > 0.0 {
-            bytes_transferred as f64 / elapsed
-        } else {
-            0.0
-        };
-
-        let eta_seconds = if transfer_rate > 0.0 && bytes_transferred < total_bytes {
-            Some((total_bytes - bytes_transferred) as f64 / transfer_rate)
-        } else {
-            None
-        };
-
-        let progress = ProgressUpdate {
-            stream_id,
-            bytes_transferred,
-            total_bytes,
-            transfer_rate,
-            eta_seconds,
-            current_chunk: next_expected.saturating_sub(1),
-            total_chunks: metadata.total_chunks,
-        };
-
-        let _ = self.progress_tx.send(progress);
-        Ok(())
-    }
-
-    /// Send progress update for inbound stream
-    fn send_progress_update(&self, stream_id: Uuid, stream: &InboundStream) -> Result<()> {
-        let now = std::time::Instant::now();
-        let elapsed = now.duration_since(stream.start_time).as_secs_f64();
-
-        let bytes_transferred = stream.assembled_data.len() as u64;
-        let total_bytes = stream.metadata.total_size;
-        let transfer_rate = if elapsed > 0.0 {
-            bytes_transferred as f64 / elapsed
-        } else {
-            0.0
-        };
-
-        let eta_seconds = if transfer_rate > 0.0 && bytes_transferred < total_bytes {
-            Some((total_bytes - bytes_transferred) as f64 / transfer_rate)
-        } else {
-            None
-        };
-
-        let progress = ProgressUpdate {
-            stream_id,
-            bytes_transferred,
-            total_bytes,
-            transfer_rate,
-            eta_seconds,
-            current_chunk: stream.next_expected.saturating_sub(1),
-            total_chunks: stream.metadata.total_chunks,
-        };
-
-        let _ = self.progress_tx.send(progress);
-        Ok(())
-    }
-
-    /// Send progress update for outbound stream with data
-    fn send_outbound_progress_update_with_data(
-        &self,
-        stream_id: Uuid,
-        bytes_acked: u64,
-        total_size: u64,
-        total_chunks: u64,
-        next_sequence: u64,
-        start_time: std::time::Instant,
-    ) -> Result<()> {
-        let now = std::time::Instant::now();
-        let elapsed = now.duration_since(start_time).as_secs_f64();
-
-        let bytes_transferred = bytes_acked;
-        let total_bytes = total_size;
-        let transfer_rate = if elapsed > 0.0 {
-            bytes_transferred as f64 / elapsed
-        } else {
-            0.0
-        };
-
-        let eta_seconds = if transfer_rate > 0.0 && bytes_transferred < total_bytes {
-            Some((total_bytes - bytes_transferred) as f64 / transfer_rate)
-        } else {
-            None
-        };
-
-        let progress = ProgressUpdate {
-            stream_id,
-            bytes_transferred,
-            total_bytes,
-            transfer_rate,
-            eta_seconds,
-            current_chunk: next_sequence.saturating_sub(1),
-            total_chunks,
-        };
-
-        let _ = self.progress_tx.send(progress);
-        Ok(())
-    }
-
-    /// Send progress update for outbound stream
-    fn send_outbound_progress_update(
-        &self,
-        stream_id: Uuid,
-        stream: &OutboundStream,
-    ) -> Result<()> {
-        let now = std::time::Instant::now();
-        let elapsed = now.duration_since(stream.start_time).as_secs_f64();
-
-        let bytes_transferred = stream.bytes_acked;
-        let total_bytes = stream.metadata.total_size;
-        let transfer_rate = if elapsed > 0.0 {
-            bytes_transferred as f64 / elapsed
-        } else {
-            0.0
-        };
-
-        let eta_seconds = if transfer_rate > 0.0 && bytes_transferred < total_bytes {
-            Some((total_bytes - bytes_transferred) as f64 / transfer_rate)
-        } else {
-            None
-        };
-
-        let progress = ProgressUpdate {
-            stream_id,
-            bytes_transferred,
-            total_bytes,
-            transfer_rate,
-            eta_seconds,
-            current_chunk: stream.next_sequence.saturating_sub(1),
-            total_chunks: stream.metadata.total_chunks,
-        };
-
+    /// Publish a progress update
+    fn report_progress(&self, progress: ProgressUpdate) {
         let _ = self.progress_tx.send(progress);
-        Ok(())
     }
 
-    /// Compress data using configured method
-    fn compress_data(&self, data: &[u8]) -> Result<Vec<u8>> {
-        match self.config.compression_method {
+    /// Compress data using the given method
+    fn compress_data(&self, data: &[u8], method: &CompressionMethod) -> Result<Vec<u8>> {
+        match method {
             CompressionMethod::None => Ok(data.to_vec()),
             CompressionMethod::Zstd => {
                 zstd::bulk::compress(data, 3).map_err(|e| TransportError::Streaming {
@@ -795,6 +767,76 @@ impl StreamingTransport {
     }
 }
 
+impl OutboundStream {
+    /// Go back to the first unacknowledged chunk
+    fn rewind(&mut self) {
+        if let Some(first) = self.in_flight.keys().min().copied() {
+            self.position = (first as usize - 1) * self.metadata.chunk_size;
+            self.next_sequence = first;
+            self.bytes_acked = self.position as u64;
+            self.in_flight.clear();
+        }
+    }
+
+    fn progress(&self, stream_id: Uuid) -> ProgressUpdate {
+        progress_update(
+            stream_id,
+            StreamDirection::Outbound,
+            self.bytes_acked,
+            &self.metadata,
+            self.next_sequence.saturating_sub(1),
+            self.start_time,
+        )
+    }
+}
+
+impl InboundStream {
+    fn progress(&self, stream_id: Uuid) -> ProgressUpdate {
+        progress_update(
+            stream_id,
+            StreamDirection::Inbound,
+            self.assembled_data.len() as u64,
+            &self.metadata,
+            self.next_expected.saturating_sub(1),
+            self.start_time,
+        )
+    }
+}
+
+fn progress_update(
+    stream_id: Uuid,
+    direction: StreamDirection,
+    bytes_transferred: u64,
+    metadata: &StreamMetadata,
+    current_chunk: u64,
+    start_time: std::time::Instant,
+) -> ProgressUpdate {
+    let elapsed = start_time.elapsed().as_secs_f64();
+    let total_bytes = metadata.total_size;
+    let transfer_rate = if elapsed > 0.0 {
+        bytes_transferred as f64 / elapsed
+    } else {
+        0.0
+    };
+
+    let eta_seconds = if transfer_rate > 0.0 && bytes_transferred < total_bytes {
+        Some((total_bytes - bytes_transferred) as f64 / transfer_rate)
+    } else {
+        None
+    };
+
+    ProgressUpdate {
+        stream_id,
+        direction,
+        bytes_transferred,
+        total_bytes,
+        transfer_rate,
+        eta_seconds,
+        current_chunk,
+        total_chunks: metadata.total_chunks,
+    }
+}
+
 #[async_trait]
 impl Connection for StreamingTransport {
     async fn send(&mut self, message: Message) -> Result<()> {
@@ -817,6 +859,14 @@ impl Connection for StreamingTransport {
         self.connection.is_connected()
     }
 
+    fn capabilities(&self) -> Option<&NegotiatedCapabilities> {
+        self.connection.capabilities()
+    }
+
+    fn authenticated_fingerprint(&self) -> Option<&str> {
+        self.connection.authenticated_fingerprint()
+    }
+
     async fn close(&mut self) -> Result<()> {
         self.connection.close().await
     }
@@ -825,6 +875,33 @@ impl Connection for StreamingTransport {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::unit_tests::MockConnection;
+
+    /// Pass messages both ways until the receiver completes a stream or
+    /// nothing moves
+    async fn exchange(
+        sender: &mut StreamingTransport,
+        to_receiver: &mut mpsc::UnboundedReceiver<Message>,
+        receiver: &mut StreamingTransport,
+        to_sender: &mut mpsc::UnboundedReceiver<Message>,
+    ) -> Option<Message> {
+        loop {
+            let mut moved = false;
+            while let Ok(message) = to_receiver.try_recv() {
+                moved = true;
+                if let Some(done) = receiver.handle_stream_message(message).await.unwrap() {
+                    return Some(done);
+                }
+            }
+            while let Ok(message) = to_sender.try_recv() {
+                moved = true;
+                sender.handle_stream_message(message).await.unwrap();
+            }
+            if !moved {
+                return None;
+            }
+        }
+    }
 
     #[test]
     fn test_stream_config_default() {
@@ -840,6 +917,7 @@ mod tests {
         let stream_id = Uuid::new_v4();
         let progress = ProgressUpdate {
             stream_id,
+            direction: StreamDirection::Outbound,
             bytes_transferred: 1024,
             total_bytes: 2048,
             transfer_rate: 512.0,
@@ -852,6 +930,85 @@ mod tests {
         assert_eq!(progress.current_chunk * 2, progress.total_chunks);
     }
 
+    #[tokio::test]
+    async fn test_stream_resumes_after_lost_acks() {
+        let config = StreamConfig {
+            chunk_size: 1024,
+            max_in_flight: 4,
+            ..StreamConfig::default()
+        };
+        let (connection, _, mut to_receiver) = MockConnection::new();
+        let (mut sender, _) = StreamingTransport::new(Box::new(connection), config.clone());
+        let (connection, _, mut to_sender) = MockConnection::new();
+        let (mut receiver, _) = StreamingTransport::new(Box::new(connection), config);
+
+        // Noise, so compression leaves several chunks
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let data: Vec<u8> = (0..20_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let mut attributes = HashMap::new();
+        attributes.insert("selection".to_string(), "clipboard".to_string());
+        let hlc = HybridTimestamp::from_wall(42, Uuid::new_v4());
+        let message = Message::new(
+            MessageType::ClipboardData,
+            MessagePayload::Clipboard(ClipboardData {
+                format: ClipboardFormat::Image {
+                    mime_type: "image/png".to_string(),
+                },
+                data: data.clone(),
+                compression: None,
+                checksum: "content".to_string(),
+                metadata: attributes.clone(),
+            }),
+        )
+        .with_hlc(hlc)
+        .with_group("pairing");
+        sender
+            .send_clipboard_message(message.clone())
+            .await
+            .unwrap();
+
+        // The first window arrives, but its acks are lost with the connection
+        while let Ok(chunk) = to_receiver.try_recv() {
+            assert!(receiver
+                .handle_stream_message(chunk)
+                .await
+                .unwrap()
+                .is_none());
+        }
+        while to_sender.try_recv().is_ok() {}
+        assert_eq!(receiver.progress()[0].current_chunk, 4);
+
+        let (connection, _, mut to_receiver) = MockConnection::new();
+        sender
+            .replace_connection(Box::new(connection))
+            .await
+            .unwrap();
+        let resumed = exchange(&mut sender, &mut to_receiver, &mut receiver, &mut to_sender)
+            .await
+            .expect("stream completes after resuming");
+
+        assert_eq!(resumed.timestamp, message.timestamp);
+        assert_eq!(resumed.hlc, Some(hlc));
+        assert_eq!(resumed.group.as_deref(), Some("pairing"));
+        match resumed.payload {
+            MessagePayload::Clipboard(received) => {
+                assert_eq!(received.data, data);
+                assert_eq!(received.checksum, "content");
+                assert_eq!(received.metadata, attributes);
+            }
+            other => panic!("unexpected payload: {:?}", other),
+        }
+        assert!(sender.progress().is_empty());
+        assert!(receiver.progress().is_empty());
+    }
+
     #[test]
     fn test_stream_chunk_creation() {
         let stream_id = Uuid::new_v4();
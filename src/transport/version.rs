@@ -0,0 +1,186 @@
+//! Protocol version negotiation
+//!
+//! Each side announces the range of protocol versions it can speak in its
+//! handshake: [`HandshakePayload::version`] is the highest, and the
+//! `min_protocol_version` parameter the lowest. Peers from before version
+//! ranges only send `version`, which then stands for both ends. The
+//! connection uses the highest version both ranges contain, so a machine
+//! can be upgraded without breaking sync with the rest.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::transport::protocol::{HandshakePayload, PROTOCOL_VERSION};
+
+/// Oldest protocol version this node still speaks
+///
+/// This is the oldest wire format the build implements, so lower it only
+/// together with support for that format. 1.x has no authenticated,
+/// encrypted session, which every connection now needs; 1.x clients are
+/// answered with a handshake they report as a version mismatch.
+pub const MIN_PROTOCOL_VERSION: &str = "2.0.0";
+
+/// Handshake parameter carrying the lowest version a peer speaks
+pub const MIN_VERSION_PARAMETER: &str = "min_protocol_version";
+
+/// A `major.minor.patch` protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ProtocolVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid protocol version '{}'", s);
+
+        let mut parts = s.trim().splitn(3, '.');
+        let mut next = || -> Result<u32, String> {
+            match parts.next() {
+                Some(part) => part.parse().map_err(|_| invalid()),
+                None => Ok(0),
+            }
+        };
+
+        let major = next()?;
+        let minor = next()?;
+        let patch = next()?;
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Versions a node can speak, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: ProtocolVersion,
+    pub max: ProtocolVersion,
+}
+
+impl VersionRange {
+    /// Versions this node speaks
+    pub fn local() -> Self {
+        Self {
+            min: MIN_PROTOCOL_VERSION
+                .parse()
+                .expect("valid minimum protocol version"),
+            max: PROTOCOL_VERSION.parse().expect("valid protocol version"),
+        }
+    }
+
+    /// Versions announced in a peer's handshake
+    pub fn from_handshake(handshake: &HandshakePayload) -> Result<Self, String> {
+        let max: ProtocolVersion = handshake.version.parse()?;
+        let min = match handshake.parameters.get(MIN_VERSION_PARAMETER) {
+            Some(min) => min.parse()?,
+            None => max,
+        };
+
+        if min > max {
+            return Err(format!("empty protocol version range {}-{}", min, max));
+        }
+        Ok(Self { min, max })
+    }
+
+    /// Highest version both ranges contain
+    pub fn highest_common(&self, other: &Self) -> Option<ProtocolVersion> {
+        let max = self.max.min(other.max);
+        let min = self.min.max(other.min);
+        (min <= max).then_some(max)
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.max)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn range(min: &str, max: &str) -> VersionRange {
+        VersionRange {
+            min: min.parse().unwrap(),
+            max: max.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_and_order() {
+        let version: ProtocolVersion = "1.2.3".parse().unwrap();
+        assert_eq!(version, ProtocolVersion::new(1, 2, 3));
+        assert_eq!(version.to_string(), "1.2.3");
+        assert_eq!("2".parse(), Ok(ProtocolVersion::new(2, 0, 0)));
+        assert!("1.x".parse::<ProtocolVersion>().is_err());
+
+        assert!(ProtocolVersion::new(1, 10, 0) > ProtocolVersion::new(1, 9, 5));
+    }
+
+    #[test]
+    fn test_highest_common_version() {
+        let upgraded = range("1.0.0", "1.2.0");
+        assert_eq!(
+            upgraded.highest_common(&range("1.0.0", "1.1.0")),
+            Some(ProtocolVersion::new(1, 1, 0))
+        );
+        assert_eq!(
+            upgraded.highest_common(&range("1.2.0", "2.0.0")),
+            Some(ProtocolVersion::new(1, 2, 0))
+        );
+        assert_eq!(upgraded.highest_common(&range("2.0.0", "2.1.0")), None);
+    }
+
+    #[test]
+    fn test_range_from_handshake() {
+        let mut handshake = HandshakePayload {
+            version: "1.1.0".to_string(),
+            peer_id: Uuid::new_v4(),
+            capabilities: vec![],
+            parameters: Default::default(),
+        };
+
+        // Peers from before version ranges speak exactly one version
+        assert_eq!(
+            VersionRange::from_handshake(&handshake),
+            Ok(range("1.1.0", "1.1.0"))
+        );
+
+        handshake
+            .parameters
+            .insert(MIN_VERSION_PARAMETER.to_string(), "1.0.0".to_string());
+        assert_eq!(
+            VersionRange::from_handshake(&handshake),
+            Ok(range("1.0.0", "1.1.0"))
+        );
+
+        handshake
+            .parameters
+            .insert(MIN_VERSION_PARAMETER.to_string(), "1.2.0".to_string());
+        assert!(VersionRange::from_handshake(&handshake).is_err());
+    }
+}
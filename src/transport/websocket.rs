@@ -6,8 +6,12 @@
 use crate::auth::{Authenticator, PeerId};
 use crate::progress::ConnectionProgress;
 use crate::transport::{
-    protocol::*, Connection, ConnectionInfo, ConnectionState, Listener, PeerInfo, Result,
-    TransportError,
+    capabilities::{self, NegotiatedCapabilities},
+    codec::{WireEncoding, ENCODINGS_PARAMETER, ENCODING_PARAMETER},
+    protocol::*,
+    session::{KeyShare, SessionCipher, SessionError},
+    version::{ProtocolVersion, VersionRange, MIN_PROTOCOL_VERSION, MIN_VERSION_PARAMETER},
+    Connection, ConnectionInfo, ConnectionState, Listener, PeerInfo, Result, TransportError,
 };
 use async_trait::async_trait;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
@@ -69,6 +73,13 @@ pub struct WebSocketConfig {
 
     /// Enable TLS
     pub enable_tls: bool,
+
+    /// Clipboard formats this node can render, offered to peers
+    pub formats: Vec<ClipboardFormat>,
+
+    /// Message encodings to accept, most preferred first; `[Json]` keeps
+    /// traffic readable when debugging
+    pub encodings: Vec<WireEncoding>,
 }
 
 /// WebSocket connection wrapper
@@ -94,16 +105,43 @@ pub struct WebSocketConnection {
     /// Authenticated peer ID
     authenticated_peer: Option<PeerId>,
 
+    /// Handshake binding for authentication, set once the handshake completes
+    auth_context: Option<AuthContext>,
+
+    /// Encrypted session, established by authentication
+    session: Option<SessionCipher>,
+
+    /// Capabilities offered to the peer
+    local_capabilities: CapabilitiesPayload,
+
+    /// Capabilities agreed with the peer after authentication
+    capabilities: Option<NegotiatedCapabilities>,
+
+    /// Message encodings offered to the peer
+    encodings: Vec<WireEncoding>,
+
+    /// Message encoding agreed in the handshake
+    encoding: WireEncoding,
+
     /// Send channel for outgoing messages
-    send_tx: mpsc::UnboundedSender<Message>,
+    send_tx: mpsc::UnboundedSender<Frame>,
 
     /// Receive channel for incoming messages
-    recv_rx: Arc<Mutex<mpsc::UnboundedReceiver<Message>>>,
+    recv_rx: Arc<Mutex<mpsc::UnboundedReceiver<Frame>>>,
 
     /// Close notification
     close_tx: Option<tokio::sync::oneshot::Sender<()>>,
 }
 
+/// Message as carried between a connection and its socket tasks
+///
+/// Handshake and authentication messages are plain JSON text frames;
+/// everything after authentication is sealed by the session.
+enum Frame {
+    Plain(Message),
+    Sealed(Vec<u8>),
+}
+
 /// WebSocket listener for accepting connections
 pub struct WebSocketListener {
     /// TCP listener
@@ -133,6 +171,8 @@ impl Default for WebSocketConfig {
             send_buffer_size: 64 * 1024,
             recv_buffer_size: 64 * 1024,
             enable_tls: false, // TLS disabled for initial implementation
+            formats: capabilities::default_formats(),
+            encodings: WireEncoding::ALL.to_vec(),
         }
     }
 }
@@ -222,6 +262,7 @@ impl WebSocketTransport {
             })?;
 
         progress.finalizing_connection();
+        connection.negotiate_capabilities().await?;
 
         info!(
             "Successfully connected and authenticated with peer {}",
@@ -258,7 +299,7 @@ impl WebSocketConnection {
         ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
         peer_info: PeerInfo,
         remote_addr: SocketAddr,
-        _config: WebSocketConfig,
+        config: WebSocketConfig,
     ) -> Result<Self> {
         let local_addr = match ws_stream.get_ref() {
             MaybeTlsStream::Plain(tcp) => tcp
@@ -276,11 +317,12 @@ impl WebSocketConnection {
             bytes_received: 0,
             state: ConnectionState::Connecting,
             protocol_version: PROTOCOL_VERSION.to_string(),
+            encoding: WireEncoding::Json,
         };
 
         // Create message channels
-        let (send_tx, send_rx) = mpsc::unbounded_channel::<Message>();
-        let (recv_tx, recv_rx) = mpsc::unbounded_channel::<Message>();
+        let (send_tx, send_rx) = mpsc::unbounded_channel::<Frame>();
+        let (recv_tx, recv_rx) = mpsc::unbounded_channel::<Frame>();
 
         let mut connection = Self {
             id,
@@ -290,6 +332,17 @@ impl WebSocketConnection {
             sequence_counter: AtomicU64::new(1),
             state: ConnectionState::Connecting,
             authenticated_peer: None,
+            auth_context: None,
+            session: None,
+            local_capabilities: capabilities::local_capabilities(
+                config.formats,
+                config.max_message_size,
+                config.enable_compression,
+                config.keepalive_interval.as_secs(),
+            ),
+            capabilities: None,
+            encodings: config.encodings,
+            encoding: WireEncoding::Json,
             send_tx,
             recv_rx: Arc::new(Mutex::new(recv_rx)),
             close_tx: None,
@@ -304,8 +357,8 @@ impl WebSocketConnection {
     /// Start background tasks for message processing
     async fn start_message_tasks(
         &mut self,
-        mut send_rx: mpsc::UnboundedReceiver<Message>,
-        recv_tx: mpsc::UnboundedSender<Message>,
+        mut send_rx: mpsc::UnboundedReceiver<Frame>,
+        recv_tx: mpsc::UnboundedSender<Frame>,
     ) -> Result<()> {
         let (close_tx, close_rx) = tokio::sync::oneshot::channel();
         self.close_tx = Some(close_tx);
@@ -334,10 +387,14 @@ impl WebSocketConnection {
             let mut close_rx = close_rx;
             loop {
                 tokio::select! {
+                    // Frames queued before a close, such as a handshake
+                    // rejection, still go out
+                    biased;
+
                     // Process outbound messages
                     msg = send_rx.recv() => {
                         match msg {
-                            Some(message) => {
+                            Some(Frame::Plain(message)) => {
                                 let serialized = match serde_json::to_string(&message) {
                                     Ok(s) => s,
                                     Err(e) => {
@@ -353,8 +410,18 @@ impl WebSocketConnection {
 
                                 debug!("Sent {} message", message.message_type);
                             }
+                            Some(Frame::Sealed(data)) => {
+                                let len = data.len();
+                                if let Err(e) = ws_sink.send(WsMessage::Binary(data.into())).await {
+                                    error!("Failed to send WebSocket message: {}", e);
+                                    break;
+                                }
+
+                                debug!("Sent sealed frame of {} bytes", len);
+                            }
                             None => {
                                 debug!("Send channel closed");
+                                let _ = ws_sink.send(WsMessage::Close(None)).await;
                                 break;
                             }
                         }
@@ -393,7 +460,7 @@ impl WebSocketConnection {
                             // Set the source peer ID for tracking
                             message.source_peer_id = Some(peer_info_id);
 
-                            if recv_tx.send(message).is_err() {
+                            if recv_tx.send(Frame::Plain(message)).is_err() {
                                 debug!("Receive channel closed");
                                 break;
                             }
@@ -403,7 +470,10 @@ impl WebSocketConnection {
                         }
                     },
                     Ok(WsMessage::Binary(data)) => {
-                        warn!("Received unexpected binary message of {} bytes", data.len());
+                        if recv_tx.send(Frame::Sealed(data.to_vec())).is_err() {
+                            debug!("Receive channel closed");
+                            break;
+                        }
                     }
                     Ok(WsMessage::Close(_)) => {
                         info!("WebSocket connection closed by peer");
@@ -466,6 +536,14 @@ impl WebSocketConnection {
                     crate::MAX_PAYLOAD_SIZE.to_string(),
                 ),
                 ("protocol_version".to_string(), PROTOCOL_VERSION.to_string()),
+                (
+                    MIN_VERSION_PARAMETER.to_string(),
+                    MIN_PROTOCOL_VERSION.to_string(),
+                ),
+                (
+                    ENCODINGS_PARAMETER.to_string(),
+                    WireEncoding::format_list(&self.encodings),
+                ),
             ]
             .into_iter()
             .collect(),
@@ -474,11 +552,11 @@ impl WebSocketConnection {
         // Send handshake
         let handshake_msg = Message::new(
             MessageType::Handshake,
-            MessagePayload::Handshake(handshake_payload),
+            MessagePayload::Handshake(handshake_payload.clone()),
         );
 
         self.send_tx
-            .send(handshake_msg)
+            .send(Frame::Plain(handshake_msg))
             .map_err(|_| TransportError::Connection {
                 message: "Failed to send connection setup message. The connection may be closed."
                     .to_string(),
@@ -500,13 +578,15 @@ impl WebSocketConnection {
         match response.message_type {
             MessageType::HandshakeResponse => {
                 if let MessagePayload::Handshake(payload) = response.payload {
-                    if payload.version != PROTOCOL_VERSION {
-                        return Err(TransportError::VersionMismatch {
-                            expected: PROTOCOL_VERSION.to_string(),
-                            actual: payload.version,
-                        });
-                    }
-                    info!("Handshake completed with peer {}", payload.peer_id);
+                    let version = negotiate_version(&payload)?;
+                    self.connection_info.protocol_version = version.to_string();
+                    self.encoding = self.chosen_encoding(&payload)?;
+                    self.connection_info.encoding = self.encoding;
+                    info!(
+                        "Handshake completed with peer {} using protocol v{} ({})",
+                        payload.peer_id, version, self.encoding
+                    );
+                    self.auth_context = Some(AuthContext::new(&handshake_payload, &payload));
                     // Update peer info with the actual peer ID
                     self.peer_info.id = payload.peer_id;
                     self.state = ConnectionState::Connected;
@@ -518,8 +598,17 @@ impl WebSocketConnection {
                 }
             }
             MessageType::Error => {
+                // Before authentication, this is the remote refusing our
+                // protocol version
+                let reason = match response.payload {
+                    MessagePayload::Error(error) => error.message,
+                    _ => "no reason given".to_string(),
+                };
                 Err(TransportError::Connection {
-                    message: "Connection rejected by remote device. Check if this device is authorized.".to_string()
+                    message: format!(
+                        "Connection rejected by remote device: {}. If it runs an older ClipSync, update it.",
+                        reason
+                    ),
                 })
             }
             _ => {
@@ -530,9 +619,11 @@ impl WebSocketConnection {
         }
     }
 
-    /// Perform authentication
+    /// Perform mutual challenge-response authentication as the client
     async fn authenticate(&mut self, authenticator: &dyn Authenticator) -> Result<()> {
         self.state = ConnectionState::Authenticating;
+        let mut context = self.auth_context()?;
+        let key_share = KeyShare::generate()?;
 
         // Get our public key
         let public_key = authenticator
@@ -540,28 +631,178 @@ impl WebSocketConnection {
             .await
             .map_err(|e| TransportError::Authentication(e))?;
 
-        // Create authentication payload
-        let auth_payload = AuthPayload {
-            method: "ssh_public_key".to_string(),
-            data: BASE64.encode(public_key.to_openssh_format()),
-            step: 1,
-            result: None,
-        };
-
-        // Send authentication request
-        let auth_msg = Message::new(
+        // Step 1: offer our key and challenge the server
+        let client_nonce = generate_auth_nonce()?;
+        self.send_auth_step(
             MessageType::AuthChallenge,
-            MessagePayload::Auth(auth_payload),
+            1,
+            AuthExchange {
+                public_key: Some(BASE64.encode(public_key.to_openssh_format())),
+                nonce: Some(BASE64.encode(client_nonce)),
+                signature: None,
+                key_share: Some(BASE64.encode(key_share.public_key())),
+            },
+            None,
+        )?;
+
+        // Step 2: the server proves possession of its key and challenges us
+        let (result, exchange) = self.receive_auth_step(MessageType::AuthChallenge).await?;
+        if !matches!(result, Some(AuthResult::Continue)) {
+            return Err(auth_failed("Server skipped the authentication challenge"));
+        }
+
+        let server_key = decode_public_key(exchange.public_key.as_deref())?;
+        if !authenticator
+            .is_authorized(&server_key)
+            .await
+            .map_err(|e| TransportError::Authentication(e))?
+        {
+            self.send_auth_error("Server key not authorized").await?;
+            return Err(TransportError::Authentication(
+                crate::auth::AuthError::UnauthorizedPeer(server_key.fingerprint()),
+            ));
+        }
+
+        let server_share = decode_base64(exchange.key_share.as_deref(), "key share")?;
+        context.bind_key_exchange(key_share.public_key(), &server_share);
+
+        let server_signature = decode_base64(exchange.signature.as_deref(), "signature")?;
+        if !server_key
+            .verify(
+                &context.signing_input(AuthRole::Server, &client_nonce),
+                &server_signature,
+            )
+            .map_err(|e| TransportError::Authentication(e))?
+        {
+            self.send_auth_error("Invalid signature").await?;
+            return Err(auth_failed("Server failed to prove possession of its key"));
+        }
+
+        // Step 3: prove possession of our key
+        let server_nonce = decode_nonce(exchange.nonce.as_deref())?;
+        let signature = authenticator
+            .sign(&context.signing_input(AuthRole::Client, &server_nonce))
+            .await
+            .map_err(|e| TransportError::Authentication(e))?;
+        self.send_auth_step(
+            MessageType::AuthResponse,
+            3,
+            AuthExchange {
+                signature: Some(BASE64.encode(signature)),
+                ..AuthExchange::default()
+            },
+            None,
+        )?;
+
+        let session = key_share
+            .agree(AuthRole::Client, &server_share, &context)?
+            .with_encoding(self.encoding);
+
+        // Step 4: wait for the server's verdict
+        match self.receive_auth_step(MessageType::AuthResult).await?.0 {
+            Some(AuthResult::Success { token: _, peer_id }) => {
+                info!(
+                    "Authentication successful with server {} (authenticated as {})",
+                    server_key.fingerprint(),
+                    peer_id
+                );
+                self.authenticated_peer = Some(PeerId {
+                    fingerprint: server_key.fingerprint(),
+                    name: None,
+                });
+                self.session = Some(session);
+                self.state = ConnectionState::Ready;
+                Ok(())
+            }
+            _ => Err(TransportError::Connection {
+                message:
+                    "Authentication response missing result. The remote device may have an error."
+                        .to_string(),
+            }),
+        }
+    }
+
+    /// Exchange capabilities with the peer over the encrypted session
+    ///
+    /// Both sides send theirs first, so neither waits on the other.
+    async fn negotiate_capabilities(&mut self) -> Result<()> {
+        let offer = Message::new(
+            MessageType::Capabilities,
+            MessagePayload::Capabilities(self.local_capabilities.clone()),
         );
+        self.send(offer).await?;
+
+        let response =
+            tokio::time::timeout(std::time::Duration::from_secs(10), self.receive_message())
+                .await
+                .map_err(|_| TransportError::Timeout)??;
+
+        match response.payload {
+            MessagePayload::Capabilities(remote) => {
+                let negotiated =
+                    NegotiatedCapabilities::negotiate(&self.local_capabilities, &remote);
+                debug!(
+                    "Negotiated with peer {}: {} formats, {:?} compression, streaming {}",
+                    self.peer_info.id,
+                    negotiated.formats.len(),
+                    negotiated.compression,
+                    negotiated.streaming
+                );
+                self.capabilities = Some(negotiated);
+                Ok(())
+            }
+            _ => Err(TransportError::Connection {
+                message: format!(
+                    "Expected capabilities from the remote device but received {}. The remote device may be incompatible.",
+                    response.message_type
+                ),
+            }),
+        }
+    }
+
+    /// Handshake binding for authentication signatures
+    fn auth_context(&self) -> Result<AuthContext> {
+        self.auth_context
+            .clone()
+            .ok_or_else(|| TransportError::Connection {
+                message: "Authentication attempted before the connection setup completed."
+                    .to_string(),
+            })
+    }
+
+    /// Send one step of the challenge-response exchange
+    fn send_auth_step(
+        &self,
+        message_type: MessageType,
+        step: u32,
+        exchange: AuthExchange,
+        result: Option<AuthResult>,
+    ) -> Result<()> {
+        let auth_payload = AuthPayload {
+            method: AUTH_METHOD_CHALLENGE_RESPONSE.to_string(),
+            data: exchange.encode(),
+            step,
+            result,
+        };
 
         self.send_tx
-            .send(auth_msg)
+            .send(Frame::Plain(Message::new(
+                message_type,
+                MessagePayload::Auth(auth_payload),
+            )))
             .map_err(|_| TransportError::Connection {
-                message: "Failed to send authentication request. The connection may be closed."
+                message: "Failed to send authentication message. The connection may be closed."
                     .to_string(),
-            })?;
+            })
+    }
 
-        // Wait for authentication result
+    /// Wait for the next step of the challenge-response exchange
+    ///
+    /// A failure reported by the other side is returned as an error.
+    async fn receive_auth_step(
+        &mut self,
+        expected: MessageType,
+    ) -> Result<(Option<AuthResult>, AuthExchange)> {
         let response =
             tokio::time::timeout(std::time::Duration::from_secs(10), self.receive_message())
                 .await
@@ -573,76 +814,102 @@ impl WebSocketConnection {
                     ),
                 })?;
 
-        // Process authentication result
-        match response.message_type {
-            MessageType::AuthResult => {
-                if let MessagePayload::Auth(payload) = response.payload {
-                    match payload.result {
-                        Some(AuthResult::Success { token: _, peer_id }) => {
-                            info!("Authentication successful with peer {}", peer_id);
-                            // Convert Uuid to PeerId - in real implementation this would be properly handled
-                            self.authenticated_peer = Some(PeerId {
-                                fingerprint: peer_id.to_string(),
-                                name: None,
-                            });
-                            self.state = ConnectionState::Ready;
-                            Ok(())
-                        }
-                        Some(AuthResult::Failed { reason }) => {
-                            Err(TransportError::Authentication(
-                                crate::auth::AuthError::AuthenticationFailed(reason)
-                            ))
-                        }
-                        Some(AuthResult::Continue) => {
-                            Err(TransportError::Authentication(
-                                crate::auth::AuthError::AuthenticationFailed(
-                                    "Multi-step auth not yet supported".to_string()
-                                )
-                            ))
-                        }
-                        None => {
-                            Err(TransportError::Connection {
-                                message: "Authentication response missing result. The remote device may have an error.".to_string()
-                            })
-                        }
-                    }
-                } else {
-                    Err(TransportError::Connection {
-                        message: "Received invalid authentication response. The remote device may be incompatible.".to_string()
-                    })
-                }
-            }
-            MessageType::Error => {
-                Err(TransportError::Authentication(
-                    crate::auth::AuthError::AuthenticationFailed("Authentication rejected by remote device. Verify your SSH key is authorized.".to_string())
+        let payload = match (response.message_type, response.payload) {
+            (MessageType::Error, _) => {
+                return Err(auth_failed(
+                    "Authentication rejected by remote device. Verify your SSH key is authorized.",
                 ))
             }
+            (message_type, MessagePayload::Auth(payload))
+                if message_type == expected || message_type == MessageType::AuthResult =>
+            {
+                payload
+            }
             _ => {
-                Err(TransportError::Connection {
+                return Err(TransportError::Connection {
                     message: "Received unexpected response during authentication. The remote device may be incompatible.".to_string()
                 })
             }
+        };
+
+        if let Some(AuthResult::Failed { reason }) = payload.result {
+            return Err(auth_failed(&reason));
+        }
+        if payload.method != AUTH_METHOD_CHALLENGE_RESPONSE {
+            return Err(auth_failed(&format!(
+                "Unsupported authentication method '{}'. The remote device may need an upgrade.",
+                payload.method
+            )));
+        }
+
+        let exchange = AuthExchange::decode(&payload.data).ok_or_else(|| {
+            TransportError::Authentication(crate::auth::AuthError::InvalidKeyFormat(
+                "Invalid authentication data".to_string(),
+            ))
+        })?;
+
+        Ok((payload.result, exchange))
+    }
+
+    /// Encoding the server chose from those we offered
+    ///
+    /// Every peer speaks JSON, so it is the fallback.
+    fn chosen_encoding(&self, response: &HandshakePayload) -> Result<WireEncoding> {
+        let encoding = match response.parameters.get(ENCODING_PARAMETER) {
+            Some(encoding) => encoding
+                .parse()
+                .map_err(|message| TransportError::Connection { message })?,
+            None => WireEncoding::Json,
+        };
+
+        if encoding != WireEncoding::Json && !self.encodings.contains(&encoding) {
+            return Err(TransportError::Connection {
+                message: format!(
+                    "Remote device chose message encoding '{}', which was not offered.",
+                    encoding
+                ),
+            });
         }
+        Ok(encoding)
     }
 
     /// Receive a message from the connection
+    ///
+    /// Once the session is established only sealed frames are accepted.
     async fn receive_message(&mut self) -> Result<Message> {
-        let mut recv_rx = self.recv_rx.lock().await;
-        recv_rx
+        let frame = self
+            .recv_rx
+            .lock()
+            .await
             .recv()
             .await
-            .ok_or_else(|| TransportError::ConnectionClosed)
+            .ok_or_else(|| TransportError::ConnectionClosed)?;
+
+        match (frame, self.session.as_mut()) {
+            (Frame::Plain(message), None) => Ok(message),
+            (Frame::Sealed(data), Some(session)) => {
+                let mut message = session.open(&data)?;
+                message.source_peer_id = Some(self.peer_info.id);
+                Ok(message)
+            }
+            (Frame::Plain(_), Some(_)) => Err(SessionError::Unsealed.into()),
+            (Frame::Sealed(_), None) => Err(SessionError::NotEstablished.into()),
+        }
     }
 }
 
 #[async_trait]
 impl Connection for WebSocketConnection {
     async fn send(&mut self, mut message: Message) -> Result<()> {
-        // Set sequence number
+        // Set sequence number and the version agreed in the handshake
         message.sequence = self.sequence_counter.fetch_add(1, Ordering::SeqCst);
+        message.version = self.connection_info.protocol_version.clone();
+
+        let session = self.session.as_mut().ok_or(SessionError::NotEstablished)?;
+        let frame = session.seal(&message)?;
 
         self.send_tx
-            .send(message)
+            .send(Frame::Sealed(frame))
             .map_err(|_| TransportError::ConnectionClosed)
     }
 
@@ -665,6 +932,16 @@ impl Connection for WebSocketConnection {
         )
     }
 
+    fn capabilities(&self) -> Option<&NegotiatedCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    fn authenticated_fingerprint(&self) -> Option<&str> {
+        self.authenticated_peer
+            .as_ref()
+            .map(|peer| peer.fingerprint.as_str())
+    }
+
     async fn close(&mut self) -> Result<()> {
         info!("Closing connection {}", self.id);
 
@@ -731,6 +1008,7 @@ impl Listener for WebSocketListener {
         connection
             .handle_incoming_authentication(&*self.authenticator)
             .await?;
+        connection.negotiate_capabilities().await?;
 
         info!(
             "Successfully accepted and authenticated connection from {}",
@@ -770,27 +1048,56 @@ impl WebSocketConnection {
 
         // Process handshake
         if let MessagePayload::Handshake(payload) = handshake.payload {
-            if payload.version != PROTOCOL_VERSION {
-                // Send error response
-                let error_payload = ErrorPayload {
-                    code: ErrorCode::ProtocolError,
-                    message: format!(
-                        "Protocol version mismatch: expected {}, got {}",
-                        PROTOCOL_VERSION, payload.version
-                    ),
-                    details: None,
-                };
-
-                let error_msg =
-                    Message::new(MessageType::Error, MessagePayload::Error(error_payload));
+            let version = match negotiate_version(&payload) {
+                Ok(version) => version,
+                Err(e) => {
+                    let reply = if predates_local(&payload) {
+                        // Older clients take any error reply for a rejected
+                        // key, but report a handshake in a version they
+                        // don't speak as a version mismatch
+                        let response_payload = HandshakePayload {
+                            version: PROTOCOL_VERSION.to_string(),
+                            peer_id: node_id,
+                            capabilities: Vec::new(),
+                            parameters: [(
+                                MIN_VERSION_PARAMETER.to_string(),
+                                MIN_PROTOCOL_VERSION.to_string(),
+                            )]
+                            .into_iter()
+                            .collect(),
+                        };
+                        Message::new(
+                            MessageType::HandshakeResponse,
+                            MessagePayload::Handshake(response_payload),
+                        )
+                    } else {
+                        let error_payload = ErrorPayload {
+                            code: ErrorCode::ProtocolError,
+                            message: format!(
+                                "No common protocol version: this device speaks {}, the client {}",
+                                VersionRange::local(),
+                                peer_versions(&payload)
+                            ),
+                            details: None,
+                        };
+                        Message::new(MessageType::Error, MessagePayload::Error(error_payload))
+                    };
+
+                    let _ = self.send_tx.send(Frame::Plain(reply));
+
+                    return Err(e);
+                }
+            };
+            self.connection_info.protocol_version = version.to_string();
 
-                let _ = self.send_tx.send(error_msg);
-
-                return Err(TransportError::VersionMismatch {
-                    expected: PROTOCOL_VERSION.to_string(),
-                    actual: payload.version,
-                });
-            }
+            // Older peers without an encoding list only speak JSON
+            let client_encodings = payload
+                .parameters
+                .get(ENCODINGS_PARAMETER)
+                .map(|list| WireEncoding::parse_list(list))
+                .unwrap_or_default();
+            self.encoding = WireEncoding::negotiate(&self.encodings, &client_encodings);
+            self.connection_info.encoding = self.encoding;
 
             // Send handshake response
             let response_payload = HandshakePayload {
@@ -807,28 +1114,42 @@ impl WebSocketConnection {
                         crate::MAX_PAYLOAD_SIZE.to_string(),
                     ),
                     ("protocol_version".to_string(), PROTOCOL_VERSION.to_string()),
+                    (
+                        MIN_VERSION_PARAMETER.to_string(),
+                        MIN_PROTOCOL_VERSION.to_string(),
+                    ),
+                    (
+                        ENCODINGS_PARAMETER.to_string(),
+                        WireEncoding::format_list(&self.encodings),
+                    ),
+                    (ENCODING_PARAMETER.to_string(), self.encoding.to_string()),
                 ]
                 .into_iter()
                 .collect(),
             };
 
+            self.auth_context = Some(AuthContext::new(&payload, &response_payload));
+
             let response_msg = Message::new(
                 MessageType::HandshakeResponse,
                 MessagePayload::Handshake(response_payload),
             );
 
-            self.send_tx
-                .send(response_msg)
-                .map_err(|_| TransportError::Connection {
+            self.send_tx.send(Frame::Plain(response_msg)).map_err(|_| {
+                TransportError::Connection {
                     message:
                         "Failed to send connection setup response. The connection may be closed."
                             .to_string(),
-                })?;
+                }
+            })?;
 
             // Update peer info with the actual peer ID from handshake
             self.peer_info.id = payload.peer_id;
             self.state = ConnectionState::Connected;
-            info!("Handshake completed with client {}", payload.peer_id);
+            info!(
+                "Handshake completed with client {} using protocol v{} ({})",
+                payload.peer_id, version, self.encoding
+            );
 
             Ok(())
         } else {
@@ -839,91 +1160,122 @@ impl WebSocketConnection {
         }
     }
 
-    /// Handle incoming authentication from a client
+    /// Handle mutual challenge-response authentication from a client
     async fn handle_incoming_authentication(
         &mut self,
         authenticator: &dyn Authenticator,
     ) -> Result<()> {
-        // Wait for authentication request
-        let auth_msg = self.receive_message().await?;
+        let mut context = self.auth_context()?;
+
+        // Step 1: the client offers its key and challenges us
+        let offer = match self.receive_auth_step(MessageType::AuthChallenge).await {
+            Ok((_, offer)) => offer,
+            Err(e) => {
+                self.send_auth_error("Unsupported authentication request")
+                    .await?;
+                return Err(e);
+            }
+        };
+        let client_key = decode_public_key(offer.public_key.as_deref())?;
+        let client_nonce = decode_nonce(offer.nonce.as_deref())?;
+        let client_share = decode_base64(offer.key_share.as_deref(), "key share")?;
 
-        if auth_msg.message_type != MessageType::AuthChallenge {
-            return Err(TransportError::Connection {
-                message: "Expected authentication request but received something else. The client may be incompatible.".to_string()
-            });
+        // Turn away unknown keys before doing any signing work
+        if !authenticator
+            .is_authorized(&client_key)
+            .await
+            .map_err(|e| TransportError::Authentication(e))?
+        {
+            self.send_auth_error("Authentication failed").await?;
+            return Err(TransportError::Authentication(
+                crate::auth::AuthError::UnauthorizedPeer(client_key.fingerprint()),
+            ));
         }
 
-        // Process authentication
-        if let MessagePayload::Auth(payload) = auth_msg.payload {
-            // Decode public key
-            let key_data = BASE64.decode(&payload.data).map_err(|_| {
-                TransportError::Authentication(crate::auth::AuthError::InvalidKeyFormat(
-                    "Invalid base64".to_string(),
-                ))
-            })?;
+        // Step 2: prove possession of our key and challenge the client
+        let key_share = KeyShare::generate()?;
+        context.bind_key_exchange(&client_share, key_share.public_key());
+        let public_key = authenticator
+            .get_public_key()
+            .await
+            .map_err(|e| TransportError::Authentication(e))?;
+        let signature = authenticator
+            .sign(&context.signing_input(AuthRole::Server, &client_nonce))
+            .await
+            .map_err(|e| TransportError::Authentication(e))?;
+        let server_nonce = generate_auth_nonce()?;
+        self.send_auth_step(
+            MessageType::AuthChallenge,
+            2,
+            AuthExchange {
+                public_key: Some(BASE64.encode(public_key.to_openssh_format())),
+                nonce: Some(BASE64.encode(server_nonce)),
+                signature: Some(BASE64.encode(signature)),
+                key_share: Some(BASE64.encode(key_share.public_key())),
+            },
+            Some(AuthResult::Continue),
+        )?;
+        let session = key_share
+            .agree(AuthRole::Server, &client_share, &context)?
+            .with_encoding(self.encoding);
+
+        // Step 3: the client answers our challenge
+        let (_, answer) = self.receive_auth_step(MessageType::AuthResponse).await?;
+        let client_signature = decode_base64(answer.signature.as_deref(), "signature")?;
+        if !client_key
+            .verify(
+                &context.signing_input(AuthRole::Client, &server_nonce),
+                &client_signature,
+            )
+            .map_err(|e| TransportError::Authentication(e))?
+        {
+            self.send_auth_error("Invalid signature").await?;
+            return Err(auth_failed("Client failed to prove possession of its key"));
+        }
 
-            // Parse public key (simplified - would use proper SSH key parsing)
-            let public_key = crate::auth::PublicKey::from_openssh_format(&key_data)
-                .map_err(|e| TransportError::Authentication(e))?;
-
-            // Authenticate the peer
-            match authenticator.authenticate_peer(&public_key).await {
-                Ok(auth_token) => {
-                    // Verify token to get peer ID
-                    match authenticator.verify_token(&auth_token).await {
-                        Ok(peer_id) => {
-                            // Send success response
-                            let auth_response = AuthPayload {
-                                method: "ssh_public_key".to_string(),
-                                data: "".to_string(),
-                                step: 2,
-                                result: Some(AuthResult::Success {
-                                    token: auth_token.to_string(),
-                                    peer_id: peer_id.clone(),
-                                }),
-                            };
-
-                            let response_msg = Message::new(
-                                MessageType::AuthResult,
-                                MessagePayload::Auth(auth_response),
-                            );
-
-                            self.send_tx.send(response_msg)
-                                .map_err(|_| TransportError::Connection {
-                                    message: "Failed to send authentication result. The connection may be closed.".to_string()
-                                })?;
-
-                            self.authenticated_peer = Some(peer_id.clone());
-                            self.state = ConnectionState::Ready;
-
-                            info!("Authentication successful for peer {}", peer_id);
-                            Ok(())
-                        }
-                        Err(e) => {
-                            self.send_auth_error("Token verification failed").await?;
-                            Err(TransportError::Authentication(e))
-                        }
+        // Step 4: issue a token for the verified key
+        match authenticator.authenticate_peer(&client_key).await {
+            Ok(auth_token) => {
+                // Verify token to get peer ID
+                match authenticator.verify_token(&auth_token).await {
+                    Ok(peer_id) => {
+                        self.send_auth_step(
+                            MessageType::AuthResult,
+                            4,
+                            AuthExchange::default(),
+                            Some(AuthResult::Success {
+                                token: auth_token.to_string(),
+                                peer_id: peer_id.clone(),
+                            }),
+                        )?;
+
+                        // Everything after the verdict is sealed
+                        self.authenticated_peer = Some(peer_id.clone());
+                        self.session = Some(session);
+                        self.state = ConnectionState::Ready;
+
+                        info!("Authentication successful for peer {}", peer_id);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        self.send_auth_error("Token verification failed").await?;
+                        Err(TransportError::Authentication(e))
                     }
-                }
-                Err(e) => {
-                    self.send_auth_error("Authentication failed").await?;
-                    Err(TransportError::Authentication(e))
                 }
             }
-        } else {
-            Err(TransportError::Connection {
-                message: "Received invalid authentication data. The client may be incompatible."
-                    .to_string(),
-            })
+            Err(e) => {
+                self.send_auth_error("Authentication failed").await?;
+                Err(TransportError::Authentication(e))
+            }
         }
     }
 
     /// Send authentication error
     async fn send_auth_error(&mut self, reason: &str) -> Result<()> {
         let auth_response = AuthPayload {
-            method: "ssh_public_key".to_string(),
-            data: "".to_string(),
-            step: 2,
+            method: AUTH_METHOD_CHALLENGE_RESPONSE.to_string(),
+            data: AuthExchange::default().encode(),
+            step: 4,
             result: Some(AuthResult::Failed {
                 reason: reason.to_string(),
             }),
@@ -933,7 +1285,7 @@ impl WebSocketConnection {
             Message::new(MessageType::AuthResult, MessagePayload::Auth(auth_response));
 
         self.send_tx
-            .send(response_msg)
+            .send(Frame::Plain(response_msg))
             .map_err(|_| TransportError::Connection {
                 message:
                     "Failed to send authentication error response. The connection may be closed."
@@ -942,6 +1294,75 @@ impl WebSocketConnection {
     }
 }
 
+/// Fresh random nonce for the challenge-response exchange
+fn generate_auth_nonce() -> Result<[u8; AUTH_NONCE_SIZE]> {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let mut nonce = [0u8; AUTH_NONCE_SIZE];
+    SystemRandom::new().fill(&mut nonce).map_err(|_| {
+        TransportError::Authentication(crate::auth::AuthError::CryptoError(
+            "Failed to generate authentication nonce".to_string(),
+        ))
+    })?;
+    Ok(nonce)
+}
+
+fn auth_failed(reason: &str) -> TransportError {
+    TransportError::Authentication(crate::auth::AuthError::AuthenticationFailed(
+        reason.to_string(),
+    ))
+}
+
+/// Highest protocol version both sides of a handshake speak
+fn negotiate_version(peer: &HandshakePayload) -> Result<ProtocolVersion> {
+    let local = VersionRange::local();
+    VersionRange::from_handshake(peer)
+        .ok()
+        .and_then(|remote| local.highest_common(&remote))
+        .ok_or_else(|| TransportError::VersionMismatch {
+            expected: local.to_string(),
+            actual: peer_versions(peer),
+        })
+}
+
+/// Whether every version a peer speaks is older than the oldest we do
+fn predates_local(peer: &HandshakePayload) -> bool {
+    VersionRange::from_handshake(peer).is_ok_and(|remote| remote.max < VersionRange::local().min)
+}
+
+/// Versions a peer announced, for error messages
+fn peer_versions(peer: &HandshakePayload) -> String {
+    VersionRange::from_handshake(peer)
+        .map(|range| range.to_string())
+        .unwrap_or_else(|_| peer.version.clone())
+}
+
+fn decode_base64(value: Option<&str>, what: &str) -> Result<Vec<u8>> {
+    value
+        .and_then(|value| BASE64.decode(value).ok())
+        .ok_or_else(|| {
+            TransportError::Authentication(crate::auth::AuthError::InvalidKeyFormat(format!(
+                "Missing or invalid {}",
+                what
+            )))
+        })
+}
+
+fn decode_nonce(value: Option<&str>) -> Result<Vec<u8>> {
+    let nonce = decode_base64(value, "nonce")?;
+    if nonce.len() != AUTH_NONCE_SIZE {
+        return Err(auth_failed("Authentication nonce has the wrong length"));
+    }
+    Ok(nonce)
+}
+
+fn decode_public_key(value: Option<&str>) -> Result<crate::auth::PublicKey> {
+    let key_data = decode_base64(value, "public key")?;
+    // Parse public key (simplified - would use proper SSH key parsing)
+    crate::auth::PublicKey::from_openssh_format(&key_data)
+        .map_err(|e| TransportError::Authentication(e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -973,4 +1394,337 @@ mod tests {
         state = ConnectionState::Ready;
         assert_eq!(state, ConnectionState::Ready);
     }
+
+    use crate::auth::{AuthConfig, AuthToken, PublicKey, SshAuthenticator};
+    use tempfile::TempDir;
+
+    async fn authenticator(dir: &TempDir) -> Arc<SshAuthenticator> {
+        let config = AuthConfig {
+            private_key_path: dir.path().join("id_ed25519"),
+            authorized_keys_path: dir.path().join("authorized_keys"),
+            generate_if_missing: true,
+        };
+        Arc::new(SshAuthenticator::new(config).await.unwrap())
+    }
+
+    async fn trust(auth: &SshAuthenticator, peer: &dyn Authenticator) {
+        let key = peer.get_public_key().await.unwrap();
+        auth.add_trusted_peer(&key.to_openssh(), None)
+            .await
+            .unwrap();
+    }
+
+    /// Claims a trusted public key but can only sign with its own
+    struct Impostor {
+        claimed: PublicKey,
+        inner: Arc<SshAuthenticator>,
+    }
+
+    #[async_trait]
+    impl Authenticator for Impostor {
+        async fn authenticate_peer(
+            &self,
+            peer_key: &PublicKey,
+        ) -> std::result::Result<AuthToken, crate::auth::AuthError> {
+            self.inner.authenticate_peer(peer_key).await
+        }
+
+        async fn verify_token(
+            &self,
+            token: &AuthToken,
+        ) -> std::result::Result<PeerId, crate::auth::AuthError> {
+            self.inner.verify_token(token).await
+        }
+
+        async fn get_public_key(&self) -> std::result::Result<PublicKey, crate::auth::AuthError> {
+            Ok(self.claimed.clone())
+        }
+
+        async fn sign(
+            &self,
+            message: &[u8],
+        ) -> std::result::Result<Vec<u8>, crate::auth::AuthError> {
+            self.inner.sign(message).await
+        }
+
+        async fn is_authorized(
+            &self,
+            peer_key: &PublicKey,
+        ) -> std::result::Result<bool, crate::auth::AuthError> {
+            self.inner.is_authorized(peer_key).await
+        }
+    }
+
+    /// Connect `client` to a listener authenticating with `server`
+    async fn connect(
+        server: Arc<dyn Authenticator>,
+        client: Arc<dyn Authenticator>,
+    ) -> (Result<WebSocketConnection>, Result<Box<dyn Connection>>) {
+        connect_with(server, client, WebSocketConfig::default()).await
+    }
+
+    async fn connect_with(
+        server: Arc<dyn Authenticator>,
+        client: Arc<dyn Authenticator>,
+        client_config: WebSocketConfig,
+    ) -> (Result<WebSocketConnection>, Result<Box<dyn Connection>>) {
+        let transport = WebSocketTransport::new(
+            "127.0.0.1:0".parse().unwrap(),
+            server,
+            WebSocketConfig::default(),
+            Uuid::new_v4(),
+        );
+        let mut listener = transport.start_listener().await.unwrap();
+        let addr = listener.local_addr();
+        let server_task = tokio::spawn(async move { listener.accept().await });
+
+        let peer = PeerInfo {
+            id: Uuid::new_v4(),
+            name: "server".to_string(),
+            addresses: vec![addr],
+            port: addr.port(),
+            version: crate::VERSION.to_string(),
+            platform: "test".to_string(),
+            metadata: Default::default(),
+            last_seen: chrono::Utc::now().timestamp(),
+        };
+        let client_result =
+            WebSocketTransport::connect_to_peer(&peer, client, client_config, Uuid::new_v4()).await;
+
+        (client_result, server_task.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_mutual_authentication() {
+        let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let server = authenticator(&server_dir).await;
+        let client = authenticator(&client_dir).await;
+        trust(&server, &*client).await;
+        trust(&client, &*server).await;
+
+        let (client_result, server_result) = connect(server.clone(), client.clone()).await;
+        let mut client_conn = client_result.unwrap();
+        let mut server_conn = server_result.unwrap();
+
+        let server_key = server.get_public_key().await.unwrap();
+        assert_eq!(client_conn.state, ConnectionState::Ready);
+        assert_eq!(
+            client_conn.authenticated_peer.as_ref().unwrap().fingerprint,
+            server_key.fingerprint()
+        );
+        assert!(server_conn.is_connected());
+
+        // Traffic after authentication goes through the encrypted session
+        let payload = MessagePayload::Clipboard(ClipboardData {
+            format: ClipboardFormat::Text,
+            data: b"secret".to_vec(),
+            compression: None,
+            checksum: String::new(),
+            metadata: HashMap::new(),
+        });
+        client_conn
+            .send(Message::new(MessageType::ClipboardData, payload.clone()))
+            .await
+            .unwrap();
+        let received = server_conn.receive().await.unwrap();
+        assert_eq!(received.payload, payload);
+        // Sequence 1 carried the capabilities
+        assert_eq!(received.sequence, 2);
+        assert_eq!(received.version, PROTOCOL_VERSION);
+        assert_eq!(
+            client_conn.connection_info().protocol_version,
+            PROTOCOL_VERSION
+        );
+
+        server_conn
+            .send(Message::new(
+                MessageType::KeepAlive,
+                MessagePayload::KeepAlive,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            client_conn.receive().await.unwrap().message_type,
+            MessageType::KeepAlive
+        );
+    }
+
+    #[test]
+    fn test_negotiate_version() {
+        let handshake = |version: &str, min: Option<&str>| HandshakePayload {
+            version: version.to_string(),
+            peer_id: Uuid::new_v4(),
+            capabilities: vec![],
+            parameters: min
+                .map(|min| (MIN_VERSION_PARAMETER.to_string(), min.to_string()))
+                .into_iter()
+                .collect(),
+        };
+        let current: ProtocolVersion = PROTOCOL_VERSION.parse().unwrap();
+
+        // A newer peer that still speaks our version
+        let newer = handshake("9.0.0", Some(MIN_PROTOCOL_VERSION));
+        assert_eq!(negotiate_version(&newer).unwrap(), current);
+
+        // Peers on either side of our range
+        let older = handshake("1.0.0", None);
+        assert!(matches!(
+            negotiate_version(&older),
+            Err(TransportError::VersionMismatch { actual, .. }) if actual == "1.0.0"
+        ));
+        let too_new = handshake("9.0.0", Some("9.0.0"));
+        assert!(negotiate_version(&too_new).is_err());
+        assert!(negotiate_version(&handshake("latest", None)).is_err());
+
+        assert!(predates_local(&older));
+        assert!(!predates_local(&too_new));
+    }
+
+    #[tokio::test]
+    async fn test_older_client_is_told_to_upgrade() {
+        let dir = TempDir::new().unwrap();
+        let transport = WebSocketTransport::new(
+            "127.0.0.1:0".parse().unwrap(),
+            authenticator(&dir).await,
+            WebSocketConfig::default(),
+            Uuid::new_v4(),
+        );
+        let mut listener = transport.start_listener().await.unwrap();
+        let addr = listener.local_addr();
+        let server_task = tokio::spawn(async move { listener.accept().await });
+
+        // What a 1.0.0 client sends
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/clipsync", addr))
+            .await
+            .unwrap();
+        let handshake = include_str!("../../tests/fixtures/protocol/1.0.0/handshake.json");
+        ws.send(WsMessage::Text(handshake.into())).await.unwrap();
+
+        // Answered with a handshake the client reports as a version
+        // mismatch, rather than an error it takes for a rejected key
+        let reply = match ws.next().await.unwrap().unwrap() {
+            WsMessage::Text(text) => serde_json::from_str::<Message>(&text).unwrap(),
+            other => panic!("unexpected frame {:?}", other),
+        };
+        assert_eq!(reply.message_type, MessageType::HandshakeResponse);
+        match reply.payload {
+            MessagePayload::Handshake(payload) => assert_eq!(payload.version, PROTOCOL_VERSION),
+            other => panic!("unexpected payload {:?}", other),
+        }
+
+        assert!(matches!(
+            server_task.await.unwrap(),
+            Err(TransportError::VersionMismatch { actual, .. }) if actual == "1.0.0"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_negotiated_after_authentication() {
+        let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let server = authenticator(&server_dir).await;
+        let client = authenticator(&client_dir).await;
+        trust(&server, &*client).await;
+        trust(&client, &*server).await;
+
+        // A text-only client without compression
+        let client_config = WebSocketConfig {
+            formats: vec![ClipboardFormat::Text],
+            enable_compression: false,
+            max_message_size: 1024 * 1024,
+            ..WebSocketConfig::default()
+        };
+        let (client_result, server_result) = connect_with(server, client, client_config).await;
+        let client_conn = client_result.unwrap();
+        let server_conn = server_result.unwrap();
+
+        let seen_by_server = server_conn.capabilities().unwrap();
+        assert_eq!(seen_by_server.formats, vec![ClipboardFormat::Text]);
+        assert_eq!(seen_by_server.compression, CompressionMethod::None);
+        assert_eq!(seen_by_server.max_message_size, 1024 * 1024);
+        assert!(seen_by_server.streaming);
+
+        let seen_by_client = client_conn.capabilities().unwrap();
+        assert_eq!(seen_by_client.formats, capabilities::default_formats());
+        assert_eq!(seen_by_client.compression, CompressionMethod::None);
+        assert_eq!(seen_by_client.max_message_size, 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_encoding_negotiated_in_handshake() {
+        let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let server = authenticator(&server_dir).await;
+        let client = authenticator(&client_dir).await;
+        trust(&server, &*client).await;
+        trust(&client, &*server).await;
+
+        let (client_result, server_result) = connect(server.clone(), client.clone()).await;
+        assert_eq!(client_result.unwrap().encoding, WireEncoding::Cbor);
+        assert_eq!(
+            server_result.unwrap().connection_info().encoding,
+            WireEncoding::Cbor
+        );
+
+        // A client debugging its traffic asks for JSON
+        let client_config = WebSocketConfig {
+            encodings: vec![WireEncoding::Json],
+            ..WebSocketConfig::default()
+        };
+        let (client_result, server_result) = connect_with(server, client, client_config).await;
+        let mut client_conn = client_result.unwrap();
+        let mut server_conn = server_result.unwrap();
+        assert_eq!(client_conn.encoding, WireEncoding::Json);
+        assert_eq!(server_conn.connection_info().encoding, WireEncoding::Json);
+
+        client_conn
+            .send(Message::new(
+                MessageType::KeepAlive,
+                MessagePayload::KeepAlive,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            server_conn.receive().await.unwrap().message_type,
+            MessageType::KeepAlive
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authentication_rejects_untrusted_peers() {
+        let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let server = authenticator(&server_dir).await;
+        let client = authenticator(&client_dir).await;
+
+        // The server does not know the client
+        trust(&client, &*server).await;
+        let (client_result, server_result) = connect(server.clone(), client.clone()).await;
+        assert!(client_result.is_err());
+        assert!(server_result.is_err());
+
+        // The client does not know the server
+        let (server_dir, client_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let server = authenticator(&server_dir).await;
+        let client = authenticator(&client_dir).await;
+        trust(&server, &*client).await;
+        let (client_result, server_result) = connect(server, client).await;
+        assert!(client_result.is_err());
+        assert!(server_result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_authentication_rejects_impostor() {
+        let dirs: Vec<TempDir> = (0..3).map(|_| TempDir::new().unwrap()).collect();
+        let server = authenticator(&dirs[0]).await;
+        let trusted = authenticator(&dirs[1]).await;
+        let attacker = authenticator(&dirs[2]).await;
+        trust(&server, &*trusted).await;
+        trust(&attacker, &*server).await;
+
+        let impostor = Arc::new(Impostor {
+            claimed: trusted.get_public_key().await.unwrap(),
+            inner: attacker,
+        });
+        let (client_result, server_result) = connect(server, impostor).await;
+        assert!(client_result.is_err());
+        assert!(server_result.is_err());
+    }
 }
@@ -1,6 +1,7 @@
 //! Simple test to verify the project builds correctly
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn test_project_builds() {
     // This test simply ensures the project compiles
     assert!(true, "Project builds successfully");
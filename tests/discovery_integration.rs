@@ -151,6 +151,7 @@ async fn test_peer_capabilities() {
             ssh_public_key: None,
             capabilities: vec!["encryption".to_string(), "compression".to_string()],
             device_name: Some("My Device".to_string()),
+            groups: vec![],
         },
         last_seen: chrono::Utc::now().timestamp(),
     };
@@ -53,6 +53,7 @@ async fn test_clipboard_size_limit() {
         mime_type: "text/plain".to_string(),
         data: large_data,
         timestamp: 0,
+        concealed: false,
     };
 
     // Should fail with TooLarge error
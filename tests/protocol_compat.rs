@@ -0,0 +1,190 @@
+//! Wire compatibility with released protocol versions
+//!
+//! `tests/fixtures/protocol/<version>/` holds messages as that version
+//! encoded them. Files of a released version never change, and must keep
+//! decoding. The directory of the current version also pins today's
+//! encoding, so an accidental change to the wire format fails here.
+//! `newer/` holds messages from a later version that must still decode.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clipsync::transport::protocol::{
+    ClipboardFormat, Message, MessagePayload, MessageType, StreamOperation, PROTOCOL_VERSION,
+};
+use clipsync::transport::{ProtocolVersion, VersionRange, MIN_PROTOCOL_VERSION};
+use serde_json::Value;
+
+fn fixture_dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/protocol")
+        .join(name)
+}
+
+/// Name and contents of every fixture in a directory
+fn fixtures(dir: &Path) -> Vec<(String, String)> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|path| {
+            let json = fs::read_to_string(&path).unwrap();
+            (path.display().to_string(), json)
+        })
+        .collect()
+}
+
+fn fixture(dir: &str, name: &str) -> Message {
+    let path = fixture_dir(dir).join(format!("{}.json", name));
+    decode(
+        &path.display().to_string(),
+        &fs::read_to_string(&path).unwrap(),
+    )
+}
+
+fn decode(name: &str, json: &str) -> Message {
+    serde_json::from_str(json).unwrap_or_else(|e| panic!("{} does not decode: {}", name, e))
+}
+
+#[test]
+fn test_released_versions_decode() {
+    let mut versions = Vec::new();
+    for entry in fs::read_dir(fixture_dir("")).unwrap() {
+        let path = entry.unwrap().path();
+        let version = match path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<ProtocolVersion>().ok())
+        {
+            Some(version) => version,
+            None => continue,
+        };
+        versions.push(version);
+
+        for (name, json) in fixtures(&path) {
+            let message = decode(&name, &json);
+            assert_ne!(message.message_type, MessageType::Unknown, "{}", name);
+            assert!(
+                !matches!(message.payload, MessagePayload::Unknown(_)),
+                "{} decodes as an unknown payload",
+                name
+            );
+
+            // Whatever we decode, we encode back to the same message
+            let encoded = serde_json::to_string(&message).unwrap();
+            assert_eq!(decode(&name, &encoded), message, "{}", name);
+        }
+    }
+
+    assert!(versions.contains(&"1.0.0".parse().unwrap()));
+    assert!(versions.contains(&PROTOCOL_VERSION.parse().unwrap()));
+}
+
+#[test]
+fn test_current_encoding_is_pinned() {
+    let dir = fixture_dir(PROTOCOL_VERSION);
+    assert!(
+        dir.is_dir(),
+        "add golden messages for protocol {} to {}",
+        PROTOCOL_VERSION,
+        dir.display()
+    );
+
+    for (name, json) in fixtures(&dir) {
+        let message = decode(&name, &json);
+        assert_eq!(message.version, PROTOCOL_VERSION, "{}", name);
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::from_str::<Value>(&json).unwrap(),
+            "{} no longer encodes as pinned",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_version_1_0_0_messages() {
+    let message = fixture("1.0.0", "clipboard_data");
+    match message.payload {
+        MessagePayload::Clipboard(data) => {
+            assert_eq!(data.format, ClipboardFormat::Text);
+            assert_eq!(data.data, b"hello from 1.0.0");
+        }
+        other => panic!("unexpected payload {:?}", other),
+    }
+    assert!(message.hlc.is_none());
+
+    // Fields added since default to empty
+    match fixture("1.0.0", "stream_start").payload {
+        MessagePayload::Stream(stream) => {
+            assert_eq!(stream.operation, StreamOperation::Start);
+            let metadata = stream.metadata.unwrap();
+            assert!(metadata.content_checksum.is_empty());
+            assert!(metadata.attributes.is_empty());
+        }
+        other => panic!("unexpected payload {:?}", other),
+    }
+
+    // A 1.0.0 handshake announces exactly one version, which we no longer
+    // speak
+    match fixture("1.0.0", "handshake").payload {
+        MessagePayload::Handshake(handshake) => {
+            let range = VersionRange::from_handshake(&handshake).unwrap();
+            assert_eq!(range.min, range.max);
+            assert_eq!(VersionRange::local().highest_common(&range), None);
+        }
+        other => panic!("unexpected payload {:?}", other),
+    }
+}
+
+#[test]
+fn test_oldest_accepted_version() {
+    let oldest: ProtocolVersion = MIN_PROTOCOL_VERSION.parse().unwrap();
+    assert_eq!(VersionRange::local().min, oldest);
+
+    // A peer that only speaks the oldest version we accept still connects
+    match fixture(MIN_PROTOCOL_VERSION, "handshake").payload {
+        MessagePayload::Handshake(handshake) => {
+            let range = VersionRange::from_handshake(&handshake).unwrap();
+            assert_eq!(range.max, oldest);
+            assert_eq!(VersionRange::local().highest_common(&range), Some(oldest));
+        }
+        other => panic!("unexpected payload {:?}", other),
+    }
+
+    // Anything older is refused
+    let older = VersionRange {
+        min: ProtocolVersion::new(1, 0, 0),
+        max: ProtocolVersion::new(oldest.major - 1, u32::MAX, u32::MAX),
+    };
+    assert_eq!(VersionRange::local().highest_common(&older), None);
+}
+
+#[test]
+fn test_newer_messages_decode() {
+    // Message types and payloads we do not know yet are kept as unknown
+    let message = fixture("newer", "unknown_message");
+    assert_eq!(message.message_type, MessageType::Unknown);
+    match &message.payload {
+        MessagePayload::Unknown(payload) => {
+            assert_eq!(payload.kind, "Lease");
+            assert_eq!(payload.data["expires_ms"], 1767225600000u64);
+        }
+        other => panic!("unexpected payload {:?}", other),
+    }
+    assert_eq!(message.sequence, 12);
+
+    // Fields we do not know are ignored
+    let message = fixture("newer", "extra_fields");
+    assert_eq!(message.message_type, MessageType::ClipboardData);
+    match message.payload {
+        MessagePayload::Clipboard(data) => assert_eq!(data.data, b"from the future"),
+        other => panic!("unexpected payload {:?}", other),
+    }
+    assert_eq!(message.hlc.unwrap().wall_ms, 1767225600000);
+}
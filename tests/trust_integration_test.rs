@@ -40,6 +40,7 @@ async fn test_trust_on_first_use() {
             ssh_fingerprint: Some(public_key.fingerprint()),
             capabilities: vec![],
             device_name: Some("Test Device".to_string()),
+            groups: vec![],
         },
         last_seen: chrono::Utc::now().timestamp(),
     };
@@ -89,6 +90,7 @@ async fn test_reject_peer() {
             ssh_fingerprint: Some(public_key.fingerprint()),
             capabilities: vec![],
             device_name: Some("Untrusted Device".to_string()),
+            groups: vec![],
         },
         last_seen: chrono::Utc::now().timestamp(),
     };